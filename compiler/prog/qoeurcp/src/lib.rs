@@ -1,10 +1,16 @@
 pub mod converter {
-  pub use qoeurcp_converter::{compile, BackendKind};
+  pub use qoeurcp_converter::{compile, compile_ast, BackendKind};
 }
 
 pub mod tokenizer {
-  pub use qoeurcp_tokenizer::{parse, tokenize, Token, TreeBuilder, TreeSink};
+  pub use qoeurcp_tokenizer::{
+    ast, ast_from_json, ast_to_json, parse, tokenize, tokens_to_json, Token,
+    TreeBuilder, TreeSink, SCHEMA_VERSION,
+  };
 }
 
-pub use qoeurcp_converter::{compile, BackendKind};
-pub use qoeurcp_tokenizer::{parse, tokenize, Token, TreeBuilder, TreeSink};
+pub use qoeurcp_converter::{compile, compile_ast, BackendKind};
+pub use qoeurcp_tokenizer::{
+  ast_from_json, ast_to_json, parse, tokenize, tokens_to_json, Token,
+  TreeBuilder, TreeSink,
+};
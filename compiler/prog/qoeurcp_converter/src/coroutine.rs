@@ -0,0 +1,325 @@
+use qoeurcp_tokenizer::ast::visit::walk_expr;
+use qoeurcp_tokenizer::ast::*;
+
+use std::collections::HashMap;
+use std::mem;
+
+// the name of the field holding the value of an awaited tail expression, it
+// can not be written in a program
+static RESULT: &str = "await#result";
+
+// an async function is lowered to a state machine, its body is split at each
+// await point e.g `val a := f().await; a + 1` has the states
+// `0: poll f() into a` and `1: ret a + 1`
+//
+// the backends emit the state struct of a machine, which holds its state,
+// its return value, its fields and the futures it awaits, and a poll function
+// running the states until the machine suspends or returns
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateMachine {
+  pub name: String,
+  pub args: Vec<String>,
+  pub fields: Vec<String>,
+  // the types of the fields, `Unknown` when a local has no type and its
+  // value does not give one
+  pub tys: Vec<TyKind>,
+  pub ret_ty: TyKind,
+  pub states: Vec<State>,
+}
+
+// the statements of a state run once, then the machine either suspends on a
+// future or returns
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+  pub stmts: Vec<Box<Stmt>>,
+  pub transition: Transition,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transition {
+  // polls the future until it is ready, stores its value in the names and
+  // resumes at the next state
+  Await {
+    future: Box<Expr>,
+    names: Vec<String>,
+    next: usize,
+  },
+  Return(Option<Box<Expr>>),
+}
+
+// the future of `yield_now()` is pending once, it hands the thread back to
+// the executor
+pub static YIELD_NOW: &str = "yield_now";
+
+// lowers the async functions declared at the top-level, the return types of
+// the functions give the types of the locals bound to their calls
+pub fn lower_stmts(stmts: &[Box<Stmt>]) -> Result<Vec<StateMachine>, String> {
+  let mut rets = stmts
+    .iter()
+    .filter_map(|stmt| match stmt.kind {
+      StmtKind::Fun(ref fun) => {
+        Some((fun.name(), fun.ret_ty.kind().to_owned()))
+      }
+      _ => None,
+    })
+    .collect::<HashMap<String, TyKind>>();
+
+  rets.insert(YIELD_NOW.to_string(), TyKind::Void);
+
+  stmts
+    .iter()
+    .filter_map(|stmt| match stmt.kind {
+      StmtKind::Fun(ref fun) if is_async(fun) => Some(lower(fun, &rets)),
+      _ => None,
+    })
+    .collect()
+}
+
+pub fn is_async(fun: &Fun) -> bool {
+  fun.has_modifier(&FunModifier::Async)
+}
+
+// an await point is the value of a statement e.g `val a := f().await;`,
+// `f().await;`, `ret f().await;` or the tail expression `f().await`, only
+// the statements of the body are split, an await point inside an `if` or a
+// loop is rejected
+pub fn lower(
+  fun: &Fun,
+  rets: &HashMap<String, TyKind>,
+) -> Result<StateMachine, String> {
+  let args = fun
+    .args
+    .iter()
+    .map(|arg| arg.expr.text())
+    .collect::<Vec<String>>();
+
+  let mut tys = fun
+    .args
+    .iter()
+    .map(|arg| arg.ty.kind().to_owned())
+    .collect::<Vec<TyKind>>();
+
+  let body = fun
+    .block
+    .as_ref()
+    .map_or(vec![], |block| block.stmts.to_vec());
+  let count = body.len();
+
+  let mut fields = args.to_vec();
+  let mut states = vec![];
+  let mut stmts = vec![];
+  let mut ret = None;
+
+  for (index, stmt) in body.into_iter().enumerate() {
+    let is_last = index + 1 == count;
+
+    let (future, names) = match stmt.kind {
+      StmtKind::Mut(ref local) | StmtKind::Val(ref local) => {
+        let ty = match local.ty.as_ref().map(|ty| ty.kind()) {
+          Some(TyKind::Unknown) | None => {
+            infer_ty(&local.value, &fields, &tys, rets)
+          }
+          Some(ty) => ty.to_owned(),
+        };
+
+        for name in local.names() {
+          match fields.iter().position(|field| *field == name) {
+            Some(index) => tys[index] = ty.to_owned(),
+            None => {
+              fields.push(name);
+              tys.push(ty.to_owned());
+            }
+          }
+        }
+
+        match local.value.kind {
+          ExprKind::Await(ref future) => (Some(future), local.names()),
+          _ => (None, vec![]),
+        }
+      }
+      StmtKind::Expr(ref expr) => match expr.kind {
+        ExprKind::Await(ref future) if is_last => {
+          (Some(future), vec![RESULT.to_string()])
+        }
+        ExprKind::Await(ref future) => (Some(future), vec![]),
+        _ => (None, vec![]),
+      },
+      StmtKind::Ret(Some(ref value)) => match value.kind {
+        ExprKind::Await(ref future) => (Some(future), vec![RESULT.to_string()]),
+        _ => (None, vec![]),
+      },
+      _ => (None, vec![]),
+    };
+
+    match future {
+      Some(future) => {
+        if contains_await(future) {
+          return Err(format!(
+            "coroutine:fn:lower:error: nested `await` in `{}`",
+            fun.name
+          ));
+        }
+
+        if names.contains(&RESULT.to_string()) {
+          fields.push(RESULT.to_string());
+          tys.push(fun.ret_ty.kind().to_owned());
+          ret = Some(Some(make_ident_expr(RESULT)));
+        }
+
+        let next = states.len() + 1;
+
+        states.push(State {
+          stmts: mem::replace(&mut stmts, vec![]),
+          transition: Transition::Await {
+            future: future.to_owned(),
+            names,
+            next,
+          },
+        });
+      }
+      None if is_control_flow(&stmt) && contains_await_stmt(&stmt) => {
+        return Err(format!(
+          "coroutine:fn:lower:error: `await` inside an `if` or a loop is not \
+           lowered in `{}`",
+          fun.name
+        ))
+      }
+      None if contains_await_stmt(&stmt) => {
+        return Err(format!(
+          "coroutine:fn:lower:error: `await` must be the value of a \
+           statement in `{}`",
+          fun.name
+        ))
+      }
+      None => match stmt.kind {
+        StmtKind::Ret(ref value) => ret = Some(value.to_owned()),
+        StmtKind::Expr(ref expr) if is_last => {
+          ret = Some(Some(expr.to_owned()))
+        }
+        _ => stmts.push(stmt.to_owned()),
+      },
+    }
+
+    // the statements after a `ret` are never reached
+    if ret.is_some() {
+      break;
+    }
+  }
+
+  states.push(State {
+    stmts,
+    transition: Transition::Return(ret.unwrap_or(None)),
+  });
+
+  Ok(StateMachine {
+    name: fun.name(),
+    args,
+    fields,
+    tys,
+    ret_ty: fun.ret_ty.kind().to_owned(),
+    states,
+  })
+}
+
+// the type of the value of a local declared without a type e.g
+// `val a := fetch(x).await;` has the return type of `fetch`
+fn infer_ty(
+  expr: &Expr,
+  fields: &[String],
+  tys: &[TyKind],
+  rets: &HashMap<String, TyKind>,
+) -> TyKind {
+  match expr.kind {
+    ExprKind::Await(ref future) => infer_ty(future, fields, tys, rets),
+    ExprKind::BinOp { ref op, .. }
+      if match op {
+        BinOpKind::Lt
+        | BinOpKind::Gt
+        | BinOpKind::Le
+        | BinOpKind::Ge
+        | BinOpKind::Ne
+        | BinOpKind::EqEq
+        | BinOpKind::And
+        | BinOpKind::Or => true,
+        _ => false,
+      } =>
+    {
+      TyKind::NameRefTy("bool".into())
+    }
+    ExprKind::BinOp { ref lhs, .. } => infer_ty(lhs, fields, tys, rets),
+    ExprKind::Call { ref callee, .. } => rets
+      .get(&callee.text())
+      .map_or(TyKind::Unknown, |ty| ty.to_owned()),
+    ExprKind::Ident(ref name) => fields
+      .iter()
+      .position(|field| field == name)
+      .map_or(TyKind::Unknown, |index| tys[index].to_owned()),
+    ExprKind::Lit(ref lit) => match lit {
+      LitKind::Bool(_) => TyKind::NameRefTy("bool".into()),
+      LitKind::Int(_) => TyKind::NameRefTy("int".into()),
+      LitKind::Real(_) => TyKind::NameRefTy("real".into()),
+      LitKind::Str(_) => TyKind::NameRefTy("str".into()),
+      _ => TyKind::Unknown,
+    },
+    ExprKind::StructLit { ref name, .. } => TyKind::NameRefTy(name.text()),
+    _ => TyKind::Unknown,
+  }
+}
+
+// the function called by the future of an await point and its arguments e.g
+// `fetch` and `x` in `fetch(x).await`
+pub fn awaited_call(future: &Expr) -> Option<(String, &[Box<Expr>])> {
+  match future.kind {
+    ExprKind::Call {
+      ref callee,
+      ref args,
+      ..
+    } => match callee.kind {
+      ExprKind::Ident(ref name) => Some((name.to_owned(), &args[..])),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+fn is_control_flow(stmt: &Stmt) -> bool {
+  match stmt.kind {
+    StmtKind::IfBlock { .. } => true,
+    StmtKind::Expr(ref expr) => match expr.kind {
+      ExprKind::Loop(_) => true,
+      _ => false,
+    },
+    _ => false,
+  }
+}
+
+// finds an await point, the body of a closure is a function of its own so
+// its await points are not searched
+struct AwaitFinder {
+  found: bool,
+}
+
+impl<'ast> Visitor<'ast> for AwaitFinder {
+  fn visit_expr(&mut self, expr: &'ast Expr) {
+    match expr.kind {
+      ExprKind::Await(_) => self.found = true,
+      _ => walk_expr(self, expr),
+    }
+  }
+
+  fn visit_fun(&mut self, _fun: &'ast Fun) {}
+}
+
+fn contains_await(expr: &Expr) -> bool {
+  let mut finder = AwaitFinder { found: false };
+
+  finder.visit_expr(expr);
+  finder.found
+}
+
+fn contains_await_stmt(stmt: &Stmt) -> bool {
+  let mut finder = AwaitFinder { found: false };
+
+  finder.visit_stmt(stmt);
+  finder.found
+}
@@ -1,18 +1,43 @@
+use crate::coroutine::{self, StateMachine, Transition, YIELD_NOW};
+use crate::layout::{optional_layout, OptionalLayout};
+
+use qoeurcp_tokenizer::ast::Block;
 use qoeurcp_tokenizer::ast::*;
 
+use cranelift::frontend::Switch;
 use cranelift::prelude::*;
 use cranelift_jit::{JITBuilder, JITModule};
-use cranelift_module::{DataContext, Linkage, Module};
+use cranelift_module::{DataContext, FuncId, Linkage, Module};
 
 use std::collections::HashMap;
 use std::fs::File;
 use std::slice;
 
+// the state struct of an async function in memory, it starts with the state
+// and the return value followed by the fields, whose arguments come first, and
+// the futures it awaits, every cranelift value takes 8 bytes
+#[derive(Clone, Debug)]
+pub struct FrameLayout {
+  id: FuncId,
+  pub size: i32,
+  pub args: usize,
+  pub ret: (i32, TyKind),
+  pub fields: Vec<(String, TyKind, i32)>,
+  // the offset of the future awaited by each state
+  children: Vec<Option<i32>>,
+}
+
 pub struct Jit {
   builder_context: FunctionBuilderContext,
   ctx: codegen::Context,
   data_ctx: DataContext,
+  frames: HashMap<String, FrameLayout>,
+  funs: HashMap<String, FuncId>,
   module: JITModule,
+  params: HashMap<String, Vec<TyKind>>,
+  rets: HashMap<String, TyKind>,
+  strings: usize,
+  structs: HashMap<String, Vec<Field>>,
 }
 
 impl Jit {
@@ -24,17 +49,61 @@ impl Jit {
       builder_context: FunctionBuilderContext::new(),
       ctx: module.make_context(),
       data_ctx: DataContext::new(),
+      frames: HashMap::new(),
+      funs: HashMap::new(),
       module,
+      params: HashMap::new(),
+      rets: HashMap::new(),
+      strings: 0,
+      structs: HashMap::new(),
     }
   }
 
+  // the functions and the structs are declared before the top-level
+  // statements are compiled into `basics`, so a function can be called
+  // before its declaration, an async function is compiled into the poll
+  // function of its state struct
   pub fn compile(
     &mut self,
     stmts: Vec<Box<Stmt>>,
   ) -> Result<*const u8, String> {
     let name = "basics";
 
-    self.translate(vec![], String::new(), stmts)?;
+    let (items, stmts): (Vec<_>, Vec<_>) =
+      stmts.into_iter().partition(|stmt| is_item(stmt));
+
+    let machines = coroutine::lower_stmts(&items)?;
+
+    self.declare_structs(&items)?;
+    self.declare_funs(&items)?;
+
+    for machine in machines.iter() {
+      self.declare_frame(machine, &machines, &mut vec![])?;
+    }
+
+    for item in items.iter() {
+      match item.kind {
+        StmtKind::Fun(ref fun)
+          if !fun.is_foreign() && !coroutine::is_async(fun) =>
+        {
+          self.define_fun(fun)?
+        }
+        _ => {}
+      }
+    }
+
+    for machine in machines.iter() {
+      self.define_poll(machine)?;
+    }
+
+    let int = self.module.target_config().pointer_type();
+
+    self.ctx.func.signature.returns.push(AbiParam::new(int));
+
+    if let Err(error) = self.translate(&[], None, &stmts) {
+      self.module.clear_context(&mut self.ctx);
+      return Err(error);
+    }
 
     let id = self
       .module
@@ -83,19 +152,308 @@ impl Jit {
     Ok(unsafe { slice::from_raw_parts(buffer.0, buffer.1) })
   }
 
-  fn translate(
+  // the compiled code of a function, once `compile` has finalized the module
+  pub fn fun_code(&mut self, name: &str) -> Option<*const u8> {
+    let id = *self.funs.get(name)?;
+
+    Some(self.module.get_finalized_function(id))
+  }
+
+  // the state struct of an async function and the code of its poll function,
+  // which takes a pointer to the struct and gives 1 once the function has
+  // returned and 0 while it is pending
+  pub fn frame(&mut self, name: &str) -> Option<(FrameLayout, *const u8)> {
+    let frame = self.frames.get(name)?.to_owned();
+    let code = self.module.get_finalized_function(frame.id);
+
+    Some((frame, code))
+  }
+
+  // the linkage of a declared function e.g `Linkage::Import` for an
+  // `ext fun`
+  pub fn linkage(&self, name: &str) -> Option<Linkage> {
+    self
+      .funs
+      .get(name)
+      .map(|id| self.module.declarations().get_function_decl(*id).linkage)
+  }
+
+  // the state struct of an async function holds the state structs of the
+  // futures it awaits, so an async function can not await itself e.g
+  // `val a := fetch(x).await;` holds the struct of `fetch` and `yield_now()`
+  // a flag
+  fn declare_frame(
     &mut self,
-    params: Vec<String>,
-    the_return: String,
-    stmts: Vec<Box<Stmt>>,
-  ) -> Result<(), String> {
+    machine: &StateMachine,
+    machines: &[StateMachine],
+    awaiting: &mut Vec<String>,
+  ) -> Result<i32, String> {
+    if let Some(frame) = self.frames.get(&machine.name) {
+      return Ok(frame.size);
+    }
+
+    if awaiting.contains(&machine.name) {
+      return Err(format!(
+        "cranelift:fn:declare_frame:error: `{}` awaits itself",
+        machine.name
+      ));
+    }
+
+    awaiting.push(machine.name.to_owned());
+
     let int = self.module.target_config().pointer_type();
+    let width = |structs, ty| translate_tys(int, structs, ty).len() as i32 * 8;
 
-    params.iter().for_each(|_| {
-      self.ctx.func.signature.params.push(AbiParam::new(int));
-    });
+    let mut size = 8;
+    let ret = (size, machine.ret_ty.to_owned());
 
-    self.ctx.func.signature.returns.push(AbiParam::new(int));
+    size += width(&self.structs, &machine.ret_ty);
+
+    let mut fields = vec![];
+
+    for (name, ty) in machine.fields.iter().zip(machine.tys.iter()) {
+      if *ty == TyKind::Unknown {
+        return Err(format!(
+          "cranelift:fn:declare_frame:error: the type of `{}` is unknown in \
+           `{}`",
+          name, machine.name
+        ));
+      }
+
+      fields.push((name.to_owned(), ty.to_owned(), size));
+      size += width(&self.structs, ty);
+    }
+
+    let mut children = vec![];
+
+    for state in machine.states.iter() {
+      let future = match state.transition {
+        Transition::Await { ref future, .. } => future,
+        Transition::Return(_) => {
+          children.push(None);
+          continue;
+        }
+      };
+
+      let callee = match coroutine::awaited_call(future) {
+        Some((callee, _)) => callee,
+        None => {
+          return Err(format!(
+            "cranelift:fn:declare_frame:error: only a call can be awaited in \
+             `{}`",
+            machine.name
+          ))
+        }
+      };
+
+      children.push(Some(size));
+
+      if callee == YIELD_NOW {
+        size += 8;
+        continue;
+      }
+
+      match machines.iter().find(|machine| machine.name == callee) {
+        Some(child) => size += self.declare_frame(child, machines, awaiting)?,
+        None => {
+          return Err(format!(
+            "cranelift:fn:declare_frame:error: `{}` is not an async function, \
+             it can not be awaited in `{}`",
+            callee, machine.name
+          ))
+        }
+      }
+    }
+
+    awaiting.pop();
+
+    let id = self
+      .module
+      .declare_function(
+        &format!("{}.poll", machine.name),
+        Linkage::Local,
+        &self.poll_signature(),
+      )
+      .map_err(|e| e.to_string())?;
+
+    self.frames.insert(
+      machine.name.to_owned(),
+      FrameLayout {
+        id,
+        size,
+        args: machine.args.len(),
+        ret,
+        fields,
+        children,
+      },
+    );
+
+    Ok(size)
+  }
+
+  // the foreign functions are imported, the symbols are resolved when the
+  // module is finalized e.g `ext fun puts: (-> int | s: str);`
+  fn declare_funs(&mut self, stmts: &[Box<Stmt>]) -> Result<(), String> {
+    for stmt in stmts.iter() {
+      let fun = match stmt.kind {
+        StmtKind::Fun(ref fun) if !coroutine::is_async(fun) => fun,
+        _ => continue,
+      };
+
+      if fun.abi() == Some(Abi::JavaScript) {
+        return Err(format!(
+          "cranelift:fn:declare_funs:error: javascript function `{}` can not \
+           be imported",
+          fun.name
+        ));
+      }
+
+      let linkage = match fun.is_foreign() {
+        true => Linkage::Import,
+        false => Linkage::Local,
+      };
+
+      let sig = self.signature(fun);
+
+      let id = self
+        .module
+        .declare_function(&fun.name, linkage, &sig)
+        .map_err(|e| e.to_string())?;
+
+      self.funs.insert(fun.name(), id);
+      self.params.insert(
+        fun.name(),
+        fun
+          .args
+          .iter()
+          .map(|arg| arg.ty.kind().to_owned())
+          .collect(),
+      );
+      self.rets.insert(fun.name(), fun.ret_ty.kind().to_owned());
+    }
+
+    Ok(())
+  }
+
+  // only the fields of a struct are compiled, its methods are not
+  fn declare_structs(&mut self, stmts: &[Box<Stmt>]) -> Result<(), String> {
+    for stmt in stmts.iter() {
+      let structure = match stmt.kind {
+        StmtKind::Struct(ref structure) => structure,
+        _ => continue,
+      };
+
+      let mut fields = vec![];
+
+      for member in structure.members.iter() {
+        match member {
+          StructMember::Field(ref field) => fields.push(field.to_owned()),
+          _ => {
+            return Err(format!(
+              "cranelift:fn:declare_structs:error: the methods of `{}` can \
+               not be compiled",
+              structure.name
+            ))
+          }
+        }
+      }
+
+      self.structs.insert(structure.name.to_owned(), fields);
+    }
+
+    Ok(())
+  }
+
+  fn define_fun(&mut self, fun: &Fun) -> Result<(), String> {
+    self.ctx.func.signature = self.signature(fun);
+
+    let block = match fun.block {
+      Some(ref block) => block,
+      None => unreachable!(),
+    };
+
+    if let Err(error) =
+      self.translate(&fun.args, Some(fun.ret_ty.kind()), &block.stmts)
+    {
+      self.module.clear_context(&mut self.ctx);
+      return Err(error);
+    }
+
+    self
+      .module
+      .define_function(
+        self.funs[&fun.name],
+        &mut self.ctx,
+        &mut codegen::binemit::NullTrapSink {},
+      )
+      .map_err(|e| e.to_string())?;
+
+    self.module.clear_context(&mut self.ctx);
+
+    Ok(())
+  }
+
+  fn define_poll(&mut self, machine: &StateMachine) -> Result<(), String> {
+    let frame = self.frames[&machine.name].to_owned();
+
+    self.ctx.func.signature = self.poll_signature();
+
+    if let Err(error) = self.translate_poll(machine, &frame) {
+      self.module.clear_context(&mut self.ctx);
+      return Err(error);
+    }
+
+    self
+      .module
+      .define_function(
+        frame.id,
+        &mut self.ctx,
+        &mut codegen::binemit::NullTrapSink {},
+      )
+      .map_err(|e| e.to_string())?;
+
+    self.module.clear_context(&mut self.ctx);
+
+    Ok(())
+  }
+
+  fn poll_signature(&self) -> Signature {
+    let int = self.module.target_config().pointer_type();
+    let mut sig = self.module.make_signature();
+
+    sig.params.push(AbiParam::new(int));
+    sig.returns.push(AbiParam::new(types::I8));
+
+    sig
+  }
+
+  fn signature(&self, fun: &Fun) -> Signature {
+    let int = self.module.target_config().pointer_type();
+    let mut sig = self.module.make_signature();
+
+    for arg in fun.args.iter() {
+      for ty in translate_tys(int, &self.structs, arg.ty.kind()) {
+        sig.params.push(AbiParam::new(ty));
+      }
+    }
+
+    for ty in translate_tys(int, &self.structs, fun.ret_ty.kind()) {
+      sig.returns.push(AbiParam::new(ty));
+    }
+
+    sig
+  }
+
+  // the body of a function or the top-level statements, which have no return
+  // type and give their last value when it is an integer as in the llvm
+  // backend e.g the `f64` of `sqrt(4.0)` gives 0
+  fn translate(
+    &mut self,
+    args: &[Box<FunArg>],
+    ret_ty: Option<&TyKind>,
+    stmts: &[Box<Stmt>],
+  ) -> Result<(), String> {
+    let int = self.module.target_config().pointer_type();
 
     let mut builder =
       FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
@@ -105,30 +463,106 @@ impl Jit {
     builder.switch_to_block(entry_block);
     builder.seal_block(entry_block);
 
-    let variables = declare_variables(
-      int,
-      &mut builder,
-      &params,
-      &the_return,
-      &stmts,
-      entry_block,
-    );
+    let params = builder.block_params(entry_block).to_vec();
 
     let mut trans = FunctionTranslator {
       int,
       builder,
-      variables,
+      locals: HashMap::new(),
+      variables: 0,
+      frames: &self.frames,
+      funs: &self.funs,
       module: &mut self.module,
+      params: &self.params,
+      poll: None,
+      rets: &self.rets,
+      ret_ty,
+      strings: &mut self.strings,
+      structs: &self.structs,
     };
 
-    stmts.iter().for_each(|stmt| {
-      trans.translate_stmt(stmt);
-    });
+    let mut params = params.into_iter();
 
-    let return_variable = trans.variables.get(&the_return).unwrap();
-    let return_value = trans.builder.use_var(*return_variable);
+    for arg in args.iter() {
+      let ty = arg.ty.kind().to_owned();
+      let values = translate_tys(int, trans.structs, &ty)
+        .iter()
+        .filter_map(|_| params.next())
+        .collect();
 
-    trans.builder.ins().return_(&[return_value]);
+      trans.declare_local(&arg.expr.text(), Typed { ty, values });
+    }
+
+    let mut last_value = Typed::void();
+
+    for stmt in stmts.iter() {
+      last_value = trans.translate_stmt(stmt)?;
+    }
+
+    let return_values = match ret_ty {
+      // the last statement returns, the block following it is not reached
+      _ if trans.builder.is_unreachable() => None,
+      Some(ty) => Some(trans.coerce(last_value, ty)?),
+      None => match last_value.values[..] {
+        [value] if trans.builder.func.dfg.value_type(value) == int => {
+          Some(vec![value])
+        }
+        _ => Some(vec![trans.builder.ins().iconst(int, 0)]),
+      },
+    };
+
+    match return_values {
+      Some(values) => trans.builder.ins().return_(&values),
+      None => trans.builder.ins().trap(TrapCode::UnreachableCodeReached),
+    };
+
+    trans.builder.seal_all_blocks();
+    trans.builder.finalize();
+
+    Ok(())
+  }
+
+  // the poll function of an async function, its return value and its state
+  // are stored in the state struct when it returns
+  fn translate_poll(
+    &mut self,
+    machine: &StateMachine,
+    frame: &FrameLayout,
+  ) -> Result<(), String> {
+    let int = self.module.target_config().pointer_type();
+
+    let mut builder =
+      FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
+    let entry_block = builder.create_block();
+
+    builder.append_block_params_for_function_params(entry_block);
+    builder.switch_to_block(entry_block);
+    builder.seal_block(entry_block);
+
+    let pointer = builder.block_params(entry_block)[0];
+
+    let mut trans = FunctionTranslator {
+      int,
+      builder,
+      locals: HashMap::new(),
+      variables: 0,
+      frames: &self.frames,
+      funs: &self.funs,
+      module: &mut self.module,
+      params: &self.params,
+      poll: Some(PollFrame {
+        pointer,
+        ret: frame.ret.0,
+        done: 2 * machine.states.len() as i64,
+      }),
+      rets: &self.rets,
+      ret_ty: Some(&machine.ret_ty),
+      strings: &mut self.strings,
+      structs: &self.structs,
+    };
+
+    trans.translate_states(machine, frame, pointer)?;
+    trans.builder.seal_all_blocks();
     trans.builder.finalize();
 
     Ok(())
@@ -142,121 +576,1038 @@ impl Jit {
   // }
 }
 
+// a value and its type, a struct or a tagged optional is made of several
+// cranelift values e.g a `?int` is its tag followed by its payload
+#[derive(Clone)]
+struct Typed {
+  ty: TyKind,
+  values: Vec<Value>,
+}
+
+impl Typed {
+  // `nil` has no value until it is given the layout of an optional type
+  fn nil() -> Typed {
+    Self {
+      ty: TyKind::OptionalTy(box TyKind::Unknown),
+      values: vec![],
+    }
+  }
+
+  fn void() -> Typed {
+    Self {
+      ty: TyKind::Void,
+      values: vec![],
+    }
+  }
+
+  fn is_nil(&self) -> bool {
+    self.ty == TyKind::OptionalTy(box TyKind::Unknown)
+  }
+}
+
+// the state struct a poll function is given, the state of a machine that has
+// returned is `done`
+#[derive(Clone, Copy)]
+struct PollFrame {
+  pointer: Value,
+  ret: i32,
+  done: i64,
+}
+
 struct FunctionTranslator<'a> {
   int: types::Type,
   builder: FunctionBuilder<'a>,
-  variables: HashMap<String, Variable>,
+  // a local is held by a variable per cranelift value of its type
+  locals: HashMap<String, (TyKind, Vec<Variable>)>,
+  variables: usize,
+  frames: &'a HashMap<String, FrameLayout>,
+  funs: &'a HashMap<String, FuncId>,
   module: &'a mut JITModule,
+  params: &'a HashMap<String, Vec<TyKind>>,
+  poll: Option<PollFrame>,
+  rets: &'a HashMap<String, TyKind>,
+  ret_ty: Option<&'a TyKind>,
+  strings: &'a mut usize,
+  structs: &'a HashMap<String, Vec<Field>>,
 }
 
 impl<'a> FunctionTranslator<'a> {
+  // a value is given the layout of the type it is used as e.g `nil` is the
+  // null pointer for a `?str` and the tag 0 followed by a zero for a `?int`,
+  // an optional used as its payload has been narrowed by the validator
+  fn coerce(
+    &mut self,
+    value: Typed,
+    ty: &TyKind,
+  ) -> Result<Vec<Value>, String> {
+    match (&value.ty, ty) {
+      (from, to) if from == to => Ok(value.values),
+      (_, TyKind::Void) => Ok(vec![]),
+      (TyKind::Void, _) => Err(format!(
+        "cranelift:fn:coerce:error: a statement has no value of type `{}`",
+        ty.text()
+      )),
+      (_, TyKind::OptionalTy(ref payload)) if value.is_nil() => {
+        match optional_layout(payload) {
+          OptionalLayout::NullPointer => {
+            Ok(vec![self.builder.ins().iconst(self.int, 0)])
+          }
+          OptionalLayout::Tagged => {
+            let tag = self.builder.ins().iconst(types::I8, 0);
+
+            Ok([vec![tag], self.zeros(payload)].concat())
+          }
+        }
+      }
+      _ if value.is_nil() => Err(format!(
+        "cranelift:fn:coerce:error: `nil` can only be compiled as an \
+         optional value"
+      )),
+      (TyKind::OptionalTy(_), _) => {
+        let payload = self.unwrap(value);
+
+        self.coerce(payload, ty)
+      }
+      (_, TyKind::OptionalTy(ref payload)) => {
+        let values = self.coerce(value, payload)?;
+
+        match optional_layout(payload) {
+          OptionalLayout::NullPointer => Ok(values),
+          OptionalLayout::Tagged => {
+            let tag = self.builder.ins().iconst(types::I8, 1);
+
+            Ok([vec![tag], values].concat())
+          }
+        }
+      }
+      _ => Ok(value.values),
+    }
+  }
+
+  fn declare_local(&mut self, name: &str, value: Typed) {
+    let mut variables = vec![];
+
+    for value in value.values.iter() {
+      let variable = Variable::new(self.variables);
+      let ty = self.builder.func.dfg.value_type(*value);
+
+      self.variables += 1;
+      self.builder.declare_var(variable, ty);
+      self.builder.def_var(variable, *value);
+
+      variables.push(variable);
+    }
+
+    self.locals.insert(name.into(), (value.ty, variables));
+  }
+
+  // a value is tested with `if foo` or `foo != nil` as a bool, the tag of an
+  // optional or its pointer tells whether it holds a value
+  fn is_some(&mut self, value: &Typed) -> Result<Value, String> {
+    let payload = match value.ty {
+      _ if value.is_nil() => {
+        return Ok(self.builder.ins().iconst(types::I8, 0))
+      }
+      TyKind::OptionalTy(ref payload) => payload,
+      _ => return Ok(value.values[0]),
+    };
+
+    match optional_layout(payload) {
+      OptionalLayout::NullPointer => {
+        let is_some =
+          self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::NotEqual, value.values[0], 0);
+
+        Ok(self.builder.ins().bint(types::I8, is_some))
+      }
+      OptionalLayout::Tagged => Ok(value.values[0]),
+    }
+  }
+
+  // the values of a type stored at an offset of a state struct
+  fn load(&mut self, ty: &TyKind, pointer: Value, offset: i32) -> Vec<Value> {
+    translate_tys(self.int, self.structs, ty)
+      .into_iter()
+      .enumerate()
+      .map(|(index, ty)| {
+        self.builder.ins().load(
+          ty,
+          MemFlags::trusted(),
+          pointer,
+          offset + 8 * index as i32,
+        )
+      })
+      .collect()
+  }
+
+  // the variables of a local or of one of its fields e.g `foo.bar`
+  fn place(&mut self, expr: &Expr) -> Result<(TyKind, Vec<Variable>), String> {
+    match expr.kind() {
+      ExprKind::Ident(ref name) => match self.locals.get(name) {
+        Some(local) => Ok(local.to_owned()),
+        None => Err(format!(
+          "cranelift:fn:place:error: unknown local `{}`",
+          name
+        )),
+      },
+      ExprKind::MemberAccess {
+        ref from,
+        ref access,
+      } => {
+        let (ty, variables) = match self.place(from)? {
+          (TyKind::OptionalTy(box payload), variables) => {
+            match optional_layout(&payload) {
+              OptionalLayout::NullPointer => (payload, variables),
+              OptionalLayout::Tagged => (payload, variables[1..].to_vec()),
+            }
+          }
+          place => place,
+        };
+
+        let (ty, range) = self.field(&ty, access)?;
+
+        Ok((ty, variables[range].to_vec()))
+      }
+      _ => Err(format!(
+        "cranelift:fn:place:error: `{}` can not be assigned",
+        unparse_expr(expr)
+      )),
+    }
+  }
+
+  // the type of a field and the range of its values in the values of the
+  // struct
+  fn field(
+    &self,
+    ty: &TyKind,
+    name: &str,
+  ) -> Result<(TyKind, std::ops::Range<usize>), String> {
+    let fields = match ty {
+      TyKind::NameRefTy(ref name) if self.structs.contains_key(name) => {
+        &self.structs[name]
+      }
+      _ => {
+        return Err(format!(
+          "cranelift:fn:field:error: `{}` has no field `{}`",
+          ty.text(),
+          name
+        ))
+      }
+    };
+
+    let mut start = 0;
+
+    for field in fields.iter() {
+      let len = translate_tys(self.int, self.structs, field.ty.kind()).len();
+
+      if field.name == name {
+        return Ok((field.ty.kind().to_owned(), start..start + len));
+      }
+
+      start += len;
+    }
+
+    Err(format!(
+      "cranelift:fn:field:error: `{}` has no field `{}`",
+      ty.text(),
+      name
+    ))
+  }
+
+  // the payload of an optional, the validator only lets a narrowed optional
+  // be used as its payload
+  fn unwrap(&mut self, value: Typed) -> Typed {
+    let payload = match value.ty {
+      TyKind::OptionalTy(box ref payload) => payload.to_owned(),
+      _ => return value,
+    };
+
+    let values = match optional_layout(&payload) {
+      OptionalLayout::NullPointer => value.values,
+      OptionalLayout::Tagged => value.values[1..].to_vec(),
+    };
+
+    Typed {
+      ty: payload,
+      values,
+    }
+  }
+
+  fn store(&mut self, values: &[Value], pointer: Value, offset: i32) {
+    for (index, value) in values.iter().enumerate() {
+      self.builder.ins().store(
+        MemFlags::trusted(),
+        *value,
+        pointer,
+        offset + 8 * index as i32,
+      );
+    }
+  }
+
+  fn zeros(&mut self, ty: &TyKind) -> Vec<Value> {
+    translate_tys(self.int, self.structs, ty)
+      .into_iter()
+      .map(|ty| match ty {
+        types::F64 => self.builder.ins().f64const(0.0),
+        ty => self.builder.ins().iconst(ty, 0),
+      })
+      .collect()
+  }
+
+  // an argument is passed in the layout of the type of its parameter
+  fn translate_arg(
+    &mut self,
+    arg: &Box<Expr>,
+    ty: &TyKind,
+  ) -> Result<Vec<Value>, String> {
+    let value = self.translate_expr(arg)?;
+
+    self.coerce(value, ty)
+  }
+
+  fn translate_assign_expr(
+    &mut self,
+    lhs: &Box<Expr>,
+    rhs: &Box<Expr>,
+  ) -> Result<Typed, String> {
+    let (ty, variables) = self.place(lhs)?;
+    let value = self.translate_expr(rhs)?;
+    let values = self.coerce(value, &ty)?;
+
+    for (variable, value) in variables.iter().zip(values) {
+      self.builder.def_var(*variable, value);
+    }
+
+    Ok(Typed::void())
+  }
+
   fn translate_binop_expr(
     &mut self,
     lhs: &Box<Expr>,
     op: &BinOpKind,
     rhs: &Box<Expr>,
-  ) -> Value {
-    match op {
+  ) -> Result<Typed, String> {
+    let lhs_expr = self.translate_expr(lhs)?;
+    let rhs_expr = self.translate_expr(rhs)?;
+
+    // an optional is compared to `nil` by its tag or its pointer
+    if lhs_expr.is_nil() || rhs_expr.is_nil() {
+      let is_some = match lhs_expr.is_nil() {
+        true => self.is_some(&rhs_expr)?,
+        false => self.is_some(&lhs_expr)?,
+      };
+
+      let cc = match op {
+        BinOpKind::EqEq => IntCC::Equal,
+        BinOpKind::Ne => IntCC::NotEqual,
+        _ => {
+          return Err(format!(
+            "cranelift:fn:translate_binop_expr:error: `nil` can only be \
+             compared with `==` or `!=`"
+          ))
+        }
+      };
+
+      let is_nil = self.builder.ins().icmp_imm(cc, is_some, 0);
+
+      return Ok(Typed {
+        ty: make_bool_ty(),
+        values: vec![self.builder.ins().bint(types::I8, is_nil)],
+      });
+    }
+
+    let lhs_expr = self.unwrap(lhs_expr);
+    let rhs_expr = self.unwrap(rhs_expr);
+    let (lhs_value, rhs_value) = (lhs_expr.values[0], rhs_expr.values[0]);
+
+    let cc = match op {
       BinOpKind::Add => {
-        let lhs_expr = self.translate_expr_stmt(lhs);
-        let rhs_expr = self.translate_expr_stmt(rhs);
+        return Ok(Typed {
+          ty: lhs_expr.ty,
+          values: vec![make_translate_binop_add_expr(
+            &mut self.builder,
+            lhs_value,
+            rhs_value,
+          )],
+        })
+      }
+      BinOpKind::Sub => {
+        return Ok(Typed {
+          ty: lhs_expr.ty,
+          values: vec![self.builder.ins().isub(lhs_value, rhs_value)],
+        })
+      }
+      BinOpKind::Mul => {
+        return Ok(Typed {
+          ty: lhs_expr.ty,
+          values: vec![self.builder.ins().imul(lhs_value, rhs_value)],
+        })
+      }
+      BinOpKind::EqEq => IntCC::Equal,
+      BinOpKind::Ne => IntCC::NotEqual,
+      BinOpKind::Lt => IntCC::SignedLessThan,
+      BinOpKind::Le => IntCC::SignedLessThanOrEqual,
+      BinOpKind::Gt => IntCC::SignedGreaterThan,
+      BinOpKind::Ge => IntCC::SignedGreaterThanOrEqual,
+      _ => {
+        return Err(format!(
+          "cranelift:fn:translate_binop_expr:error: `{}` can not be compiled",
+          op.text()
+        ))
+      }
+    };
+
+    let value = self.builder.ins().icmp(cc, lhs_value, rhs_value);
 
-        make_translate_binop_add_expr(&mut self.builder, lhs_expr, rhs_expr)
+    Ok(Typed {
+      ty: make_bool_ty(),
+      values: vec![self.builder.ins().bint(types::I8, value)],
+    })
+  }
+
+  fn translate_call_expr(
+    &mut self,
+    callee: &Box<Expr>,
+    args: &[Box<Expr>],
+  ) -> Result<Typed, String> {
+    let name = match callee.kind() {
+      ExprKind::Ident(ref name) if self.funs.contains_key(name) => name,
+      ExprKind::Ident(ref name)
+        if self.frames.contains_key(name) || name == YIELD_NOW =>
+      {
+        return Err(format!(
+          "cranelift:fn:translate_call_expr:error: `{}` is async, it can only \
+           be awaited",
+          name
+        ))
+      }
+      _ => {
+        return Err(format!(
+          "cranelift:fn:translate_call_expr:error: unknown function `{}`",
+          unparse_expr(callee)
+        ))
+      }
+    };
+
+    let fun = self
+      .module
+      .declare_func_in_func(self.funs[name], &mut self.builder.func);
+
+    let params = self.params;
+    let mut values = vec![];
+
+    for (i, arg) in args.iter().enumerate() {
+      match params[name].get(i) {
+        Some(ty) => values.extend(self.translate_arg(arg, ty)?),
+        None => values.extend(self.translate_expr(arg)?.values),
       }
-      _ => unimplemented!(),
     }
+
+    let args = values;
+
+    let call = self.builder.ins().call(fun, &args);
+
+    Ok(Typed {
+      ty: self.rets[name].to_owned(),
+      values: self.builder.inst_results(call).to_vec(),
+    })
   }
 
-  fn translate_expr_stmt(&mut self, expr: &Box<Expr>) -> Value {
+  fn translate_expr(&mut self, expr: &Box<Expr>) -> Result<Typed, String> {
     match expr.kind() {
+      ExprKind::Assign { ref lhs, ref rhs } => {
+        self.translate_assign_expr(lhs, rhs)
+      }
       ExprKind::BinOp {
         ref lhs,
         ref op,
         ref rhs,
         ..
       } => self.translate_binop_expr(lhs, op, rhs),
+      ExprKind::Call {
+        ref callee,
+        ref args,
+        ..
+      } => self.translate_call_expr(callee, args),
+      ExprKind::Ident(ref name) => {
+        let (ty, variables) = self.place(expr)?;
+
+        Ok(Typed {
+          ty,
+          values: variables
+            .iter()
+            .map(|variable| self.builder.use_var(*variable))
+            .collect(),
+        })
+      }
       ExprKind::Lit(ref lit) => self.translate_lit_expr(lit),
-      _ => unreachable!(),
+      ExprKind::MemberAccess {
+        ref from,
+        ref access,
+      } => {
+        let value = self.translate_expr(from)?;
+        let value = self.unwrap(value);
+        let (ty, range) = self.field(&value.ty, access)?;
+
+        Ok(Typed {
+          ty,
+          values: value.values[range].to_vec(),
+        })
+      }
+      ExprKind::StructLit {
+        ref name,
+        ref fields,
+      } => self.translate_struct_lit_expr(name, fields),
+      ExprKind::Try(ref expr) => self.translate_try_expr(expr),
+      _ => Err(format!(
+        "cranelift:fn:translate_expr:error: `{}` can not be compiled",
+        unparse_expr(expr)
+      )),
+    }
+  }
+
+  // the conditions are tested in turn, a branch jumps to the end once it is
+  // run e.g `if foo { } else if bar { } else { }`
+  fn translate_if_block_stmt(
+    &mut self,
+    conditions: &[(Box<Expr>, Box<Block>)],
+    alternative: &Option<Box<Block>>,
+  ) -> Result<Typed, String> {
+    let merge_block = self.builder.create_block();
+
+    for (condition, block) in conditions.iter() {
+      let value = self.translate_expr(condition)?;
+      let is_some = self.is_some(&value)?;
+
+      let then_block = self.builder.create_block();
+      let else_block = self.builder.create_block();
+
+      self.builder.ins().brz(is_some, else_block, &[]);
+      self.builder.ins().jump(then_block, &[]);
+
+      self.builder.switch_to_block(then_block);
+      self.builder.seal_block(then_block);
+      self.translate_block(block)?;
+      self.builder.ins().jump(merge_block, &[]);
+
+      self.builder.switch_to_block(else_block);
+      self.builder.seal_block(else_block);
+    }
+
+    if let Some(ref block) = alternative {
+      self.translate_block(block)?;
+    }
+
+    self.builder.ins().jump(merge_block, &[]);
+    self.builder.switch_to_block(merge_block);
+    self.builder.seal_block(merge_block);
+
+    Ok(Typed::void())
+  }
+
+  fn translate_block(&mut self, block: &Block) -> Result<(), String> {
+    let locals = self.locals.to_owned();
+
+    for stmt in block.stmts.iter() {
+      self.translate_stmt(stmt)?;
+    }
+
+    self.locals = locals;
+
+    Ok(())
+  }
+
+  fn translate_lit_expr(&mut self, kind: &LitKind) -> Result<Typed, String> {
+    let (ty, value) = match kind {
+      LitKind::Bool(ref value) => (
+        make_bool_ty(),
+        self.builder.ins().iconst(types::I8, *value as i64),
+      ),
+      LitKind::Real(ref value) => (
+        TyKind::NameRefTy("real".into()),
+        make_codegen_lit_real_expr(&mut self.builder, value),
+      ),
+      LitKind::Int(ref value) => (
+        TyKind::NameRefTy("int".into()),
+        make_codegen_lit_int_expr(&mut self.builder, value),
+      ),
+      LitKind::Str(ref value) => (
+        TyKind::NameRefTy("str".into()),
+        self.translate_lit_str_expr(value)?,
+      ),
+      // the layout of `nil` is given by the optional type it is used as
+      LitKind::Nil => return Ok(Typed::nil()),
+      _ => {
+        return Err(format!(
+          "cranelift:fn:translate_lit_expr:error: `{}` can not be compiled",
+          kind.text()
+        ))
+      }
+    };
+
+    Ok(Typed {
+      ty,
+      values: vec![value],
+    })
+  }
+
+  // a string is stored as a nul-terminated data object, so it can be given
+  // to a c function as a pointer
+  fn translate_lit_str_expr(&mut self, value: &str) -> Result<Value, String> {
+    let mut data_ctx = DataContext::new();
+    let mut contents = value.as_bytes().to_vec();
+
+    contents.push(0);
+    data_ctx.define(contents.into_boxed_slice());
+
+    let name = format!("str.{}", self.strings);
+
+    *self.strings += 1;
+
+    let id = self
+      .module
+      .declare_data(&name, Linkage::Local, false, false)
+      .map_err(|e| e.to_string())?;
+
+    self
+      .module
+      .define_data(id, &data_ctx)
+      .map_err(|e| e.to_string())?;
+
+    let data = self.module.declare_data_in_func(id, &mut self.builder.func);
+
+    Ok(self.builder.ins().global_value(self.int, data))
+  }
+
+  // every name of a local holds the same value e.g `val x y: ?int = nil;`
+  fn translate_local_stmt(&mut self, local: &Local) -> Result<Typed, String> {
+    let value = self.translate_expr(&local.value)?;
+
+    let ty = match local.ty.as_ref().map(|ty| ty.kind()) {
+      Some(TyKind::Unknown) | None => value.ty.to_owned(),
+      Some(ty) => ty.to_owned(),
+    };
+
+    let values = self.coerce(value, &ty)?;
+
+    for name in local.names() {
+      self.declare_local(
+        &name,
+        Typed {
+          ty: ty.to_owned(),
+          values: values.to_vec(),
+        },
+      );
     }
+
+    Ok(Typed::void())
+  }
+
+  // the statements following a `ret` are compiled in a block that is not
+  // reached
+  fn translate_ret_stmt(
+    &mut self,
+    value: &Option<Box<Expr>>,
+  ) -> Result<Typed, String> {
+    let ty = match self.ret_ty {
+      Some(ty) => ty,
+      None => {
+        return Err(format!(
+          "cranelift:fn:translate_ret_stmt:error: `ret` can only be compiled \
+           in a function"
+        ))
+      }
+    };
+
+    let value = match value {
+      Some(ref value) => self.translate_expr(value)?,
+      None => Typed::void(),
+    };
+
+    let values = self.coerce(value, ty)?;
+
+    self.translate_return(&values);
+
+    let unreachable_block = self.builder.create_block();
+
+    self.builder.switch_to_block(unreachable_block);
+    self.builder.seal_block(unreachable_block);
+
+    Ok(Typed::void())
   }
 
-  fn translate_lit_expr(&mut self, kind: &LitKind) -> Value {
-    match kind {
-      // LitKind::Real(ref value) => make_codegen_lit_real_expr(&mut self.builder, value),
-      LitKind::Int(ref value) => {
-        make_codegen_lit_int_expr(&mut self.builder, value)
+  // a poll function stores the return value in the state struct and tells
+  // that the machine is ready
+  fn translate_return(&mut self, values: &[Value]) {
+    let poll = match self.poll {
+      Some(poll) => poll,
+      None => {
+        self.builder.ins().return_(values);
+        return;
+      }
+    };
+
+    let done = self.builder.ins().iconst(self.int, poll.done);
+    let ready = self.builder.ins().iconst(types::I8, 1);
+
+    self.store(values, poll.pointer, poll.ret);
+    self.store(&[done], poll.pointer, 0);
+    self.builder.ins().return_(&[ready]);
+  }
+
+  // the fields are loaded from the state struct on entry and stored back when
+  // the machine suspends, the state is the block to resume at e.g `2 * s`
+  // runs the statements of the state `s` and `2 * s + 1` polls the future it
+  // awaits
+  fn translate_states(
+    &mut self,
+    machine: &StateMachine,
+    frame: &FrameLayout,
+    pointer: Value,
+  ) -> Result<(), String> {
+    let frames = self.frames;
+    let mut fields = vec![];
+
+    for (name, ty, offset) in frame.fields.iter() {
+      let values = self.load(ty, pointer, *offset);
+
+      self.declare_local(
+        name,
+        Typed {
+          ty: ty.to_owned(),
+          values,
+        },
+      );
+
+      fields.push((name.to_owned(), self.locals[name].to_owned(), *offset));
+    }
+
+    let state =
+      self
+        .builder
+        .ins()
+        .load(self.int, MemFlags::trusted(), pointer, 0);
+
+    let run_blocks = machine
+      .states
+      .iter()
+      .map(|_| self.builder.create_block())
+      .collect::<Vec<_>>();
+
+    let poll_blocks = machine
+      .states
+      .iter()
+      .map(|state| match state.transition {
+        Transition::Await { .. } => Some(self.builder.create_block()),
+        Transition::Return(_) => None,
+      })
+      .collect::<Vec<_>>();
+
+    let done_block = self.builder.create_block();
+    let mut switch = Switch::new();
+
+    for index in 0..machine.states.len() {
+      switch.set_entry(2 * index as u128, run_blocks[index]);
+
+      if let Some(poll_block) = poll_blocks[index] {
+        switch.set_entry(2 * index as u128 + 1, poll_block);
       }
-      _ => unreachable!(),
     }
+
+    switch.emit(&mut self.builder, state, done_block);
+
+    // a machine is not polled once it has returned
+    self.builder.switch_to_block(done_block);
+    self.builder.ins().trap(TrapCode::UnreachableCodeReached);
+
+    for (index, state) in machine.states.iter().enumerate() {
+      self.builder.switch_to_block(run_blocks[index]);
+
+      for stmt in state.stmts.iter() {
+        self.translate_stmt(stmt)?;
+      }
+
+      // a field declared again by the statements is given back its
+      // variables
+      for (name, (ty, variables), _) in fields.iter() {
+        let (local_ty, local_variables) = self.locals[name].to_owned();
+
+        if local_variables == *variables {
+          continue;
+        }
+
+        if local_ty != *ty {
+          return Err(format!(
+            "cranelift:fn:translate_states:error: `{}` is declared with \
+             another type in `{}`",
+            name, machine.name
+          ));
+        }
+
+        for (variable, local_variable) in
+          variables.iter().zip(local_variables.iter())
+        {
+          let value = self.builder.use_var(*local_variable);
+
+          self.builder.def_var(*variable, value);
+        }
+
+        self
+          .locals
+          .insert(name.to_owned(), (ty.to_owned(), variables.to_vec()));
+      }
+
+      let (future, names, next) = match state.transition {
+        Transition::Await {
+          ref future,
+          ref names,
+          next,
+        } => (future, names, next),
+        Transition::Return(ref value) => {
+          let value = match value {
+            Some(ref value) => self.translate_expr(value)?,
+            None => Typed::void(),
+          };
+
+          let values = self.coerce(value, &machine.ret_ty)?;
+
+          self.translate_return(&values);
+          continue;
+        }
+      };
+
+      // the awaited future starts at its first state
+      let (callee, args) = coroutine::awaited_call(future).unwrap();
+      let offset = frame.children[index].unwrap();
+      let child = frames.get(&callee);
+
+      if let Some(child) = child {
+        for (arg, (_, ty, arg_offset)) in args.iter().zip(child.fields.iter()) {
+          let values = self.translate_arg(arg, ty)?;
+
+          self.store(&values, pointer, offset + arg_offset);
+        }
+      }
+
+      let zero = self.builder.ins().iconst(self.int, 0);
+
+      self.store(&[zero], pointer, offset);
+      let poll_block = poll_blocks[index].unwrap();
+
+      self.builder.ins().jump(poll_block, &[]);
+      self.builder.switch_to_block(poll_block);
+
+      let ready = match child {
+        Some(child) => {
+          let fun = self
+            .module
+            .declare_func_in_func(child.id, &mut self.builder.func);
+          let child_pointer =
+            self.builder.ins().iadd_imm(pointer, offset as i64);
+          let call = self.builder.ins().call(fun, &[child_pointer]);
+
+          self.builder.inst_results(call)[0]
+        }
+        // `yield_now()` is ready once its flag is set
+        None => {
+          let flag = self.builder.ins().load(
+            self.int,
+            MemFlags::trusted(),
+            pointer,
+            offset,
+          );
+          let one = self.builder.ins().iconst(self.int, 1);
+          let is_set = self.builder.ins().icmp_imm(IntCC::NotEqual, flag, 0);
+
+          self.store(&[one], pointer, offset);
+          self.builder.ins().bint(types::I8, is_set)
+        }
+      };
+
+      let pending_block = self.builder.create_block();
+      let ready_block = self.builder.create_block();
+
+      self.builder.ins().brz(ready, pending_block, &[]);
+      self.builder.ins().jump(ready_block, &[]);
+
+      self.builder.switch_to_block(pending_block);
+
+      for (_, (_, variables), field_offset) in fields.iter() {
+        let values = variables
+          .iter()
+          .map(|variable| self.builder.use_var(*variable))
+          .collect::<Vec<Value>>();
+
+        self.store(&values, pointer, *field_offset);
+      }
+
+      let state = self.builder.ins().iconst(self.int, 2 * index as i64 + 1);
+      let pending = self.builder.ins().iconst(types::I8, 0);
+
+      self.store(&[state], pointer, 0);
+      self.builder.ins().return_(&[pending]);
+
+      self.builder.switch_to_block(ready_block);
+
+      let value = match child {
+        Some(child) => Typed {
+          ty: child.ret.1.to_owned(),
+          values: self.load(&child.ret.1, pointer, offset + child.ret.0),
+        },
+        None => Typed::void(),
+      };
+
+      for name in names.iter() {
+        let (_, (ty, variables), _) =
+          fields.iter().find(|(field, ..)| field == name).unwrap();
+        let values = self.coerce(value.to_owned(), ty)?;
+
+        for (variable, value) in variables.iter().zip(values) {
+          self.builder.def_var(*variable, value);
+        }
+      }
+
+      self.builder.ins().jump(run_blocks[next], &[]);
+    }
+
+    Ok(())
   }
 
-  fn translate_stmt(&mut self, stmt: &Box<Stmt>) -> Value {
+  fn translate_stmt(&mut self, stmt: &Box<Stmt>) -> Result<Typed, String> {
     match stmt.kind() {
-      StmtKind::Expr(ref expr) => self.translate_expr_stmt(expr),
-      _ => unreachable!(),
+      StmtKind::Expr(ref expr) => self.translate_expr(expr),
+      StmtKind::IfBlock {
+        ref conditions,
+        ref alternative,
+      } => self.translate_if_block_stmt(conditions, alternative),
+      StmtKind::Mut(ref local) | StmtKind::Val(ref local) => {
+        self.translate_local_stmt(local)
+      }
+      StmtKind::Ret(ref value) => self.translate_ret_stmt(value),
+      _ => Err(format!(
+        "cranelift:fn:translate_stmt:error: `{}` can not be compiled",
+        unparse_stmt(stmt)
+      )),
     }
   }
-}
 
-fn declare_variable(
-  int: types::Type,
-  builder: &mut FunctionBuilder,
-  variables: &mut HashMap<String, Variable>,
-  index: &mut usize,
-  name: &str,
-) -> Variable {
-  let var = Variable::new(*index);
+  // the fields are given in the order of the struct e.g `A { .bar = 0 }`
+  fn translate_struct_lit_expr(
+    &mut self,
+    name: &Box<Expr>,
+    fields: &[(String, Box<Expr>)],
+  ) -> Result<Typed, String> {
+    let ty = TyKind::NameRefTy(name.text());
+
+    let declared = match self.structs.get(&name.text()) {
+      Some(declared) => declared,
+      None => {
+        return Err(format!(
+          "cranelift:fn:translate_struct_lit_expr:error: unknown struct `{}`",
+          name.text()
+        ))
+      }
+    };
 
-  if !variables.contains_key(name) {
-    variables.insert(name.into(), var);
-    builder.declare_var(var, int);
-    *index += 1;
+    let mut values = vec![];
+
+    for field in declared.iter() {
+      let value = match fields.iter().find(|(name, _)| *name == field.name) {
+        Some((_, value)) => self.translate_expr(value)?,
+        None => {
+          return Err(format!(
+            "cranelift:fn:translate_struct_lit_expr:error: missing field `{}`",
+            field.name
+          ))
+        }
+      };
+
+      values.extend(self.coerce(value, field.ty.kind())?);
+    }
+
+    Ok(Typed { ty, values })
   }
 
-  var
-}
+  // `foo?` returns `nil` from the function when `foo` is `nil` and gives its
+  // payload otherwise
+  fn translate_try_expr(&mut self, expr: &Box<Expr>) -> Result<Typed, String> {
+    let ty = match self.ret_ty {
+      Some(ty @ TyKind::OptionalTy(_)) => ty,
+      // only the top-level statements are compiled, there is no function to
+      // return `nil` from
+      None => {
+        return Err(format!(
+          "cranelift:fn:translate_try_expr:error: `?` can only be compiled \
+           in a function"
+        ))
+      }
+      Some(_) => {
+        return Err(format!(
+          "cranelift:fn:translate_try_expr:error: `?` in a function that \
+           does not return an optional"
+        ))
+      }
+    };
 
-fn declare_variables(
-  int: types::Type,
-  builder: &mut FunctionBuilder,
-  params: &[String],
-  the_return: &str,
-  stmts: &[Box<Stmt>],
-  entry_block: cranelift::prelude::Block,
-) -> HashMap<String, Variable> {
-  let mut variables = HashMap::new();
-  let mut index = 0;
+    let value = self.translate_expr(expr)?;
+    let is_some = self.is_some(&value)?;
 
-  params.iter().enumerate().for_each(|(i, name)| {
-    let val = builder.block_params(entry_block)[i];
-    let var = declare_variable(int, builder, &mut variables, &mut index, name);
+    let nil_block = self.builder.create_block();
+    let some_block = self.builder.create_block();
 
-    builder.def_var(var, val);
-  });
+    self.builder.ins().brz(is_some, nil_block, &[]);
+    self.builder.ins().jump(some_block, &[]);
 
-  let zero = builder.ins().iconst(int, 0);
+    self.builder.switch_to_block(nil_block);
+    self.builder.seal_block(nil_block);
 
-  let return_variable =
-    declare_variable(int, builder, &mut variables, &mut index, the_return);
+    let nil = self.coerce(Typed::nil(), ty)?;
 
-  builder.def_var(return_variable, zero);
+    self.translate_return(&nil);
 
-  stmts.iter().for_each(|stmt| {
-    declare_variables_in_stmt(int, builder, &mut variables, &mut index, stmt);
-  });
+    self.builder.switch_to_block(some_block);
+    self.builder.seal_block(some_block);
 
-  variables
+    Ok(self.unwrap(value))
+  }
 }
 
-fn declare_variables_in_stmt(
+fn is_item(stmt: &Box<Stmt>) -> bool {
+  match stmt.kind {
+    StmtKind::Fun(_) | StmtKind::Struct(_) => true,
+    _ => false,
+  }
+}
+
+fn make_bool_ty() -> TyKind {
+  TyKind::NameRefTy("bool".into())
+}
+
+// `real` is a float, `bool` a byte and the other types are passed as
+// integers or pointers, a struct is passed as its fields and a tagged
+// optional as its tag followed by its payload
+fn translate_tys(
   int: types::Type,
-  builder: &mut FunctionBuilder,
-  variables: &mut HashMap<String, Variable>,
-  index: &mut usize,
-  expr: &Box<Stmt>,
-) {
-  match expr.kind {
-    _ => (),
+  structs: &HashMap<String, Vec<Field>>,
+  kind: &TyKind,
+) -> Vec<types::Type> {
+  match kind {
+    TyKind::Void => vec![],
+    TyKind::NameRefTy(ref name) if name == "bool" => vec![types::I8],
+    TyKind::NameRefTy(ref name) if name == "real" => vec![types::F64],
+    TyKind::NameRefTy(ref name) if structs.contains_key(name) => structs[name]
+      .iter()
+      .flat_map(|field| translate_tys(int, structs, field.ty.kind()))
+      .collect(),
+    TyKind::OptionalTy(ref ty) => match optional_layout(ty) {
+      OptionalLayout::NullPointer => vec![int],
+      OptionalLayout::Tagged => {
+        [vec![types::I8], translate_tys(int, structs, ty)].concat()
+      }
+    },
+    _ => vec![int],
   }
 }
 
@@ -268,6 +1619,13 @@ pub fn make_translate_binop_add_expr<'a>(
   builder.ins().iadd(lhs, rhs)
 }
 
+pub fn make_codegen_lit_real_expr<'a>(
+  builder: &mut FunctionBuilder<'a>,
+  real: &f64,
+) -> Value {
+  builder.ins().f64const(*real)
+}
+
 pub fn make_codegen_lit_int_expr<'a>(
   builder: &mut FunctionBuilder<'a>,
   int: &i64,
@@ -1,9 +1,11 @@
 mod jit;
 
-pub use self::jit::Jit;
+pub use self::jit::{FrameLayout, Jit};
 
-pub fn compile(file_name: &str, input: &str) {
-  let tree = qoeurcp_tokenizer::parse(input);
+use qoeurcp_tokenizer::ast::Ast;
+
+pub fn compile(file_name: &str, ast: &Ast) -> Result<(), String> {
   let mut compiler = Jit::new();
-  let _ = compiler.compile(tree.ast.nodes);
+
+  compiler.compile(ast.nodes.to_vec()).map(|_| ())
 }
@@ -0,0 +1,18 @@
+use qoeurcp_tokenizer::ast::*;
+
+// how `nil` is told apart from a value of an optional type
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OptionalLayout {
+  // `nil` is the null pointer e.g `?str` or `?ref int`
+  NullPointer,
+  // a tag is stored before the payload e.g `?int` is `{ i1, i64 }`
+  Tagged,
+}
+
+pub fn optional_layout(payload: &TyKind) -> OptionalLayout {
+  match payload {
+    TyKind::RefTy(_) => OptionalLayout::NullPointer,
+    TyKind::NameRefTy(ref name) if name == "str" => OptionalLayout::NullPointer,
+    _ => OptionalLayout::Tagged,
+  }
+}
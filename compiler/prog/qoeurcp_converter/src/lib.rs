@@ -8,19 +8,47 @@
 #![feature(decl_macro)]
 #![recursion_limit = "256"]
 
+mod coroutine;
 mod cranelift;
 mod interface;
+mod layout;
 mod llvm;
+mod runtime;
 mod scope;
 
 #[cfg(test)]
 mod test;
 
+pub use self::coroutine::{lower, State, StateMachine, Transition};
 pub use self::interface::BackendKind::{self, *};
+pub use self::layout::{optional_layout, OptionalLayout};
+pub use self::runtime::{Executor, Poll, Value};
+
+use qoeurcp_tokenizer::ast::{Ast, Target};
+
+pub fn compile(
+  file_name: &str,
+  input: &str,
+  mode: &BackendKind,
+  target: Target,
+) -> Result<(), String> {
+  let tree = qoeurcp_tokenizer::parse(input);
+
+  compile_ast(file_name, &tree.ast, mode, target)
+}
+
+// the tree can come from another tool e.g a code generator writing json, the
+// target blocks of the other targets are left out
+pub fn compile_ast(
+  file_name: &str,
+  ast: &Ast,
+  mode: &BackendKind,
+  target: Target,
+) -> Result<(), String> {
+  let ast = ast.for_target(target);
 
-pub fn compile(file_name: &str, input: &str, mode: &BackendKind) {
   match mode {
-    Cranelift => cranelift::compile(file_name, input),
-    Llvm => llvm::compile(file_name, input),
-  };
+    Cranelift => cranelift::compile(file_name, &ast),
+    Llvm => llvm::compile(file_name, &ast),
+  }
 }
@@ -2,10 +2,11 @@ use super::util::{cstring, cstring_mut, cstring_mut_mut};
 
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_uint};
 use std::ptr;
 
+use llvm_sys::analysis::*;
 use llvm_sys::core::*;
 use llvm_sys::prelude::*;
 use llvm_sys::target::*;
@@ -104,6 +105,60 @@ pub fn dispose_message(msg: &str) {
   unsafe { LLVMDisposeMessage(cstring_mut!(msg)) }
 }
 
+pub fn print_module_to_string(module: LLVMModuleRef) -> String {
+  unsafe {
+    let ir = LLVMPrintModuleToString(module);
+    let text = CStr::from_ptr(ir).to_string_lossy().into_owned();
+
+    LLVMDisposeMessage(ir);
+    text
+  }
+}
+
+pub fn print_module_to_file(
+  module: LLVMModuleRef,
+  name: &str,
+) -> Result<(), String> {
+  unsafe {
+    let mut msg = ptr::null_mut();
+    let failed = LLVMPrintModuleToFile(module, cstring!(name), &mut msg);
+
+    if failed == 0 {
+      return Ok(());
+    }
+
+    let text = CStr::from_ptr(msg).to_string_lossy().into_owned();
+
+    LLVMDisposeMessage(msg);
+    Err(text)
+  }
+}
+
+// the messages of the failed checks e.g `Call parameter type does not match
+// function signature!`
+pub fn verify_module(module: LLVMModuleRef) -> Result<(), String> {
+  unsafe {
+    let mut msg = ptr::null_mut();
+    let failed = LLVMVerifyModule(
+      module,
+      LLVMVerifierFailureAction::LLVMReturnStatusAction,
+      &mut msg,
+    );
+
+    let text = match msg.is_null() {
+      true => String::new(),
+      false => CStr::from_ptr(msg).to_string_lossy().into_owned(),
+    };
+
+    LLVMDisposeMessage(msg);
+
+    match failed {
+      0 => Ok(()),
+      _ => Err(text),
+    }
+  }
+}
+
 pub fn print_file_to(
   module: LLVMModuleRef,
   name: &str,
@@ -122,6 +177,10 @@ pub fn add_fun(
   unsafe { LLVMAddFunction(module, cstring!(name), ty) }
 }
 
+pub fn fun_param(value: LLVMValueRef, index: c_uint) -> LLVMValueRef {
+  unsafe { LLVMGetParam(value, index) }
+}
+
 pub fn make_context_module_with_name(
   context: LLVMContextRef,
   name: &str,
@@ -137,6 +196,19 @@ pub fn last_basic_block(value: LLVMValueRef) -> LLVMBasicBlockRef {
   unsafe { LLVMGetLastBasicBlock(value) }
 }
 
+pub fn entry_basic_block(value: LLVMValueRef) -> LLVMBasicBlockRef {
+  unsafe { LLVMGetEntryBasicBlock(value) }
+}
+
+pub fn insert_basic_block(builder: LLVMBuilderRef) -> LLVMBasicBlockRef {
+  unsafe { LLVMGetInsertBlock(builder) }
+}
+
+// a block is branched to by its predecessors, they are the uses of the block
+pub fn has_predecessors(block: LLVMBasicBlockRef) -> bool {
+  unsafe { !LLVMGetFirstUse(LLVMBasicBlockAsValue(block)).is_null() }
+}
+
 pub fn make_context_append_basic_block(
   context: LLVMContextRef,
   value: LLVMValueRef,
@@ -241,6 +313,14 @@ pub fn make_build_global_string(
   unsafe { LLVMBuildGlobalString(builder, cstring!(value), cstring!(name)) }
 }
 
+pub fn make_build_global_string_ptr(
+  builder: LLVMBuilderRef,
+  value: &str,
+  name: &str,
+) -> LLVMValueRef {
+  unsafe { LLVMBuildGlobalStringPtr(builder, cstring!(value), cstring!(name)) }
+}
+
 pub fn make_build_lt_cmp(
   builder: LLVMBuilderRef,
   lhs: LLVMValueRef,
@@ -337,6 +417,22 @@ pub fn make_build_ne_cmp(
   }
 }
 
+pub fn make_build_int_cmp(
+  builder: LLVMBuilderRef,
+  predicate: LLVMIntPredicate,
+  lhs: LLVMValueRef,
+  rhs: LLVMValueRef,
+) -> LLVMValueRef {
+  unsafe { LLVMBuildICmp(builder, predicate, lhs, rhs, cstring!("cmptmp")) }
+}
+
+pub fn make_build_is_not_null(
+  builder: LLVMBuilderRef,
+  value: LLVMValueRef,
+) -> LLVMValueRef {
+  unsafe { LLVMBuildIsNotNull(builder, value, cstring!("notnulltmp")) }
+}
+
 pub fn make_build_phi_value(
   builder: LLVMBuilderRef,
   ty: LLVMTypeRef,
@@ -376,6 +472,23 @@ pub fn make_build_call(
   }
 }
 
+// a call to a void function can not be named
+pub fn make_build_void_call(
+  builder: LLVMBuilderRef,
+  llvm_fun: LLVMValueRef,
+  param_tys: &mut Vec<LLVMValueRef>,
+) -> LLVMValueRef {
+  unsafe {
+    LLVMBuildCall(
+      builder,
+      llvm_fun,
+      param_tys.as_mut_ptr(),
+      param_tys.len() as u32,
+      cstring!(""),
+    )
+  }
+}
+
 pub fn make_build_extract_elmt(
   builder: LLVMBuilderRef,
   values: LLVMValueRef,
@@ -385,6 +498,15 @@ pub fn make_build_extract_elmt(
   unsafe { LLVMBuildExtractElement(builder, values, index, cstring!(name)) }
 }
 
+pub fn make_build_extract_value(
+  builder: LLVMBuilderRef,
+  aggregate: LLVMValueRef,
+  index: c_uint,
+  name: &str,
+) -> LLVMValueRef {
+  unsafe { LLVMBuildExtractValue(builder, aggregate, index, cstring!(name)) }
+}
+
 pub fn make_build_insert_elmt(
   builder: LLVMBuilderRef,
   arg_value: LLVMValueRef,
@@ -412,6 +534,14 @@ pub fn make_build_store(
   unsafe { LLVMBuildStore(builder, value, pointer) }
 }
 
+pub fn make_build_struct_gep(
+  builder: LLVMBuilderRef,
+  pointer: LLVMValueRef,
+  index: c_uint,
+) -> LLVMValueRef {
+  unsafe { LLVMBuildStructGEP(builder, pointer, index, cstring!("geptmp")) }
+}
+
 pub fn make_build_binop_add_value(
   builder: LLVMBuilderRef,
   lhs: LLVMValueRef,
@@ -474,6 +604,27 @@ pub fn make_build_ret_void(
   unsafe { LLVMBuildRetVoid(builder) }
 }
 
+pub fn make_build_switch(
+  builder: LLVMBuilderRef,
+  value: LLVMValueRef,
+  otherwise: LLVMBasicBlockRef,
+  count: c_uint,
+) -> LLVMValueRef {
+  unsafe { LLVMBuildSwitch(builder, value, otherwise, count) }
+}
+
+pub fn add_case(
+  switch: LLVMValueRef,
+  value: LLVMValueRef,
+  dest: LLVMBasicBlockRef,
+) {
+  unsafe { LLVMAddCase(switch, value, dest) }
+}
+
+pub fn make_build_unreachable(builder: LLVMBuilderRef) -> LLVMValueRef {
+  unsafe { LLVMBuildUnreachable(builder) }
+}
+
 pub fn make_const_int(ty: LLVMTypeRef, int: &i64) -> LLVMValueRef {
   unsafe { LLVMConstInt(ty, *int as u64, 0) }
 }
@@ -488,6 +639,10 @@ pub fn make_const_int_value(
   }
 }
 
+pub fn make_const_null(ty: LLVMTypeRef) -> LLVMValueRef {
+  unsafe { LLVMConstNull(ty) }
+}
+
 pub fn make_const_real(ty: LLVMTypeRef, val: &f64) -> LLVMValueRef {
   unsafe { LLVMConstReal(ty, *val) }
 }
@@ -497,7 +652,7 @@ pub fn make_const_real_value(
   real: &f64,
 ) -> LLVMValueRef {
   unsafe {
-    let real_ty = LLVMFloatTypeInContext(context);
+    let real_ty = LLVMDoubleTypeInContext(context);
     LLVMConstReal(real_ty, *real)
   }
 }
@@ -530,6 +685,19 @@ pub fn make_context_int64_ty(context: LLVMContextRef) -> LLVMTypeRef {
   unsafe { LLVMInt64TypeInContext(context) }
 }
 
+// a named struct is printed as a type of the module e.g
+// `%load.frame = type { i64, i64 }`, its body is set once its fields are known
+pub fn make_context_named_struct_ty(
+  context: LLVMContextRef,
+  name: &str,
+) -> LLVMTypeRef {
+  unsafe { LLVMStructCreateNamed(context, cstring!(name)) }
+}
+
+pub fn set_struct_body(ty: LLVMTypeRef, tys: &mut Vec<LLVMTypeRef>) {
+  unsafe { LLVMStructSetBody(ty, tys.as_mut_ptr(), tys.len() as c_uint, 0) }
+}
+
 pub fn make_context_struct_ty(
   context: LLVMContextRef,
   tys: &mut Vec<LLVMTypeRef>,
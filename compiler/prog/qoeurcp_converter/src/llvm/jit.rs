@@ -1,5 +1,8 @@
+use super::interface::make_fun_ty;
 use super::interface::*;
 use super::util::cstring;
+use crate::coroutine::{self, StateMachine, Transition, YIELD_NOW};
+use crate::layout::{optional_layout, OptionalLayout};
 
 use qoeurcp_tokenizer::ast::*;
 
@@ -20,9 +23,41 @@ pub struct Jit {
   pub context: LLVMContextRef,
   pub module: LLVMModuleRef,
   pub builder: LLVMBuilderRef,
+  pub funs: HashMap<String, LLVMValueRef>,
+  pub params: HashMap<String, Vec<TyKind>>,
+  pub rets: HashMap<String, TyKind>,
+  pub structs: HashMap<String, Vec<Field>>,
   pub target: RefCell<LLVMTargetRef>,
   pub target_machine: RefCell<LLVMTargetMachineRef>,
   pub target_data: RefCell<LLVMTargetDataRef>,
+  // the function being compiled, a local is stored in the memory allocated
+  // for it and `main` has no return type
+  fun: LLVMValueRef,
+  locals: HashMap<String, (TyKind, LLVMValueRef)>,
+  ret_ty: Option<TyKind>,
+  frames: HashMap<String, Frame>,
+  poll: Option<PollFrame>,
+}
+
+// the state struct of an async function, it holds the state, the return
+// value, the fields, whose arguments come first, and the futures it awaits
+// e.g `%load.frame = type { i64, i64, i64, i64, %fetch.frame }`
+#[derive(Clone)]
+struct Frame {
+  ty: LLVMTypeRef,
+  fun: LLVMValueRef,
+  ret_ty: TyKind,
+  fields: Vec<(String, TyKind, c_uint)>,
+  // the index of the future awaited by each state
+  children: Vec<Option<c_uint>>,
+}
+
+// the state struct a poll function is given, the state of a machine that has
+// returned is `done`
+#[derive(Clone, Copy)]
+struct PollFrame {
+  pointer: LLVMValueRef,
+  done: i64,
 }
 
 impl Drop for Jit {
@@ -33,29 +68,105 @@ impl Drop for Jit {
   }
 }
 
+// a value and its type, `nil` and the value of a statement are null until
+// they are given a layout
+#[derive(Clone)]
+struct Typed {
+  ty: TyKind,
+  value: LLVMValueRef,
+}
+
+impl Typed {
+  fn nil() -> Typed {
+    Self {
+      ty: TyKind::OptionalTy(box TyKind::Unknown),
+      value: ptr::null_mut(),
+    }
+  }
+
+  fn void() -> Typed {
+    Self {
+      ty: TyKind::Void,
+      value: ptr::null_mut(),
+    }
+  }
+
+  fn is_nil(&self) -> bool {
+    self.ty == TyKind::OptionalTy(box TyKind::Unknown)
+  }
+}
+
 impl Jit {
   pub fn new() -> Jit {
     unsafe {
       let context = LLVMContextCreate();
-      let module = LLVMModuleCreateWithName(cstring!(""));
+      let module = make_context_module_with_name(context, "basics");
       let builder = LLVMCreateBuilderInContext(context);
 
       Self {
         context,
         builder,
         module,
+        funs: HashMap::new(),
+        params: HashMap::new(),
+        rets: HashMap::new(),
+        structs: HashMap::new(),
         target: RefCell::new(ptr::null_mut()),
         target_machine: RefCell::new(ptr::null_mut()),
         target_data: RefCell::new(ptr::null_mut()),
+        fun: ptr::null_mut(),
+        locals: HashMap::new(),
+        ret_ty: None,
+        frames: HashMap::new(),
+        poll: None,
       }
     }
   }
 
-  pub fn codegen(&mut self, stmts: Vec<Box<Stmt>>) {
+  // the functions and the structs are declared before the top-level
+  // statements are compiled into `main`, so a function can be called before
+  // its declaration, an async function is compiled into the poll function of
+  // its state struct
+  pub fn codegen(&mut self, stmts: Vec<Box<Stmt>>) -> Result<(), String> {
     unsafe {
-      let context = LLVMContextCreate();
-      let module = LLVMModuleCreateWithName(cstring!("basics"));
-      let builder = LLVMCreateBuilderInContext(context);
+      let context = self.context;
+      let module = self.module;
+      let builder = self.builder;
+
+      let (items, stmts): (Vec<_>, Vec<_>) =
+        stmts.into_iter().partition(|stmt| is_item(stmt));
+
+      let machines = coroutine::lower_stmts(&items)?;
+
+      self.declare_structs(&items)?;
+
+      for stmt in items.iter() {
+        match stmt.kind {
+          StmtKind::Fun(ref fun) if !coroutine::is_async(fun) => {
+            self.declare_fun(fun)?
+          }
+          _ => {}
+        }
+      }
+
+      for machine in machines.iter() {
+        self.declare_frame(machine, &machines, &mut vec![])?;
+      }
+
+      for stmt in items.iter() {
+        match stmt.kind {
+          StmtKind::Fun(ref fun)
+            if !fun.is_foreign() && !coroutine::is_async(fun) =>
+          {
+            self.codegen_fun(fun)?
+          }
+          _ => {}
+        }
+      }
+
+      for machine in machines.iter() {
+        self.codegen_poll(machine)?;
+      }
 
       let int_ty = LLVMInt64TypeInContext(context);
       let fun_ty = LLVMFunctionType(int_ty, ptr::null_mut(), 0, 0);
@@ -67,107 +178,1218 @@ impl Jit {
 
       LLVMPositionBuilderAtEnd(builder, bb);
 
-      let mut names = HashMap::new();
-      insert_allocations(context, builder, &mut names, &stmts);
+      self.fun = fun;
+      self.locals.clear();
+      self.ret_ty = None;
 
-      let int_ty = LLVMInt64TypeInContext(context);
       let zero = LLVMConstInt(int_ty, 0, 0);
 
       let mut ret_value = zero; // return value on empty program
 
-      stmts.iter().for_each(|stmt| {
-        ret_value = self.codegen_stmt(context, builder, fun, &mut names, stmt);
-      });
+      for stmt in stmts.iter() {
+        ret_value = self.codegen_stmt(stmt)?.value;
+      }
+
+      // `main` returns the last value only when it is an `i64` e.g the
+      // `double` of `sqrt(4.0)` gives 0
+      if ret_value.is_null() || type_of(ret_value) != int_ty {
+        ret_value = zero;
+      }
 
       LLVMBuildRet(builder, ret_value);
 
-      let out_file = cstring!("out/test.ll");
-      LLVMPrintModuleToFile(module, out_file, ptr::null_mut());
+      Ok(())
+    }
+  }
+
+  // a value is given the layout of the type it is used as e.g `nil` is the
+  // null pointer for a `?str` and `{ i1 false, i64 0 }` for a `?int`, an
+  // optional used as its payload has been narrowed by the validator
+  fn coerce(
+    &mut self,
+    value: Typed,
+    ty: &TyKind,
+  ) -> Result<LLVMValueRef, String> {
+    match (&value.ty, ty) {
+      (from, to) if from == to => Ok(value.value),
+      (_, TyKind::Void) => Ok(ptr::null_mut()),
+      (TyKind::Void, _) => Err(format!(
+        "llvm:fn:coerce:error: a statement has no value of type `{}`",
+        ty.text()
+      )),
+      (_, TyKind::OptionalTy(_)) if value.is_nil() => {
+        Ok(make_const_null(codegen_ty(self.context, &self.structs, ty)))
+      }
+      _ if value.is_nil() => Err(format!(
+        "llvm:fn:coerce:error: `nil` can only be compiled as an optional value"
+      )),
+      (TyKind::OptionalTy(_), _) => {
+        let payload = self.unwrap(value);
+
+        self.coerce(payload, ty)
+      }
+      (_, TyKind::OptionalTy(ref payload)) => {
+        let value = self.coerce(value, payload)?;
+
+        match optional_layout(payload) {
+          OptionalLayout::NullPointer => Ok(value),
+          OptionalLayout::Tagged => {
+            let optional_ty = codegen_ty(self.context, &self.structs, ty);
+            let tag = make_const_int(make_context_int1_ty(self.context), &1);
+            let tagged = make_const_null(optional_ty);
+            let tagged =
+              make_build_insert_elmt(self.builder, tagged, tag, 0, "");
 
-      LLVMDisposeBuilder(builder);
-      LLVMDisposeModule(module);
-      LLVMContextDispose(context);
+            Ok(make_build_insert_elmt(self.builder, tagged, value, 1, ""))
+          }
+        }
+      }
+      _ => Ok(value.value),
     }
   }
 
+  // an argument is passed in the layout of the type of its parameter
+  fn codegen_arg(
+    &mut self,
+    arg: &Box<Expr>,
+    ty: &TyKind,
+  ) -> Result<LLVMValueRef, String> {
+    let value = self.codegen_expr(arg)?;
+
+    self.coerce(value, ty)
+  }
+
+  fn codegen_assign_expr(
+    &mut self,
+    lhs: &Box<Expr>,
+    rhs: &Box<Expr>,
+  ) -> Result<Typed, String> {
+    let (ty, pointer) = self.place(lhs)?;
+    let value = self.codegen_expr(rhs)?;
+    let value = self.coerce(value, &ty)?;
+
+    make_build_store(self.builder, value, pointer);
+
+    Ok(Typed::void())
+  }
+
   fn codegen_binop_expr(
     &mut self,
     lhs: &Box<Expr>,
     op: &BinOpKind,
     rhs: &Box<Expr>,
-  ) -> LLVMValueRef {
-    match op {
+  ) -> Result<Typed, String> {
+    let lhs_expr = self.codegen_expr(lhs)?;
+    let rhs_expr = self.codegen_expr(rhs)?;
+
+    // an optional is compared to `nil` by its tag or its pointer
+    if lhs_expr.is_nil() || rhs_expr.is_nil() {
+      let is_some = match lhs_expr.is_nil() {
+        true => self.is_some(&rhs_expr)?,
+        false => self.is_some(&lhs_expr)?,
+      };
+
+      let predicate = match op {
+        BinOpKind::EqEq => LLVMIntPredicate::LLVMIntEQ,
+        BinOpKind::Ne => LLVMIntPredicate::LLVMIntNE,
+        _ => {
+          return Err(format!(
+            "llvm:fn:codegen_binop_expr:error: `nil` can only be compared \
+             with `==` or `!=`"
+          ))
+        }
+      };
+
+      let no = make_const_int(make_context_int1_ty(self.context), &0);
+
+      return Ok(Typed {
+        ty: make_bool_ty(),
+        value: make_build_int_cmp(self.builder, predicate, is_some, no),
+      });
+    }
+
+    let lhs_expr = self.unwrap(lhs_expr);
+    let rhs_expr = self.unwrap(rhs_expr);
+    let (lhs_value, rhs_value) = (lhs_expr.value, rhs_expr.value);
+
+    let predicate = match op {
       BinOpKind::Add => {
-        let lhs_expr = self.codegen_expr_stmt(lhs);
-        let rhs_expr = self.codegen_expr_stmt(rhs);
+        return Ok(Typed {
+          ty: lhs_expr.ty,
+          value: make_build_binop_add_value(self.builder, lhs_value, rhs_value),
+        })
+      }
+      BinOpKind::Sub => {
+        return Ok(Typed {
+          ty: lhs_expr.ty,
+          value: make_build_binop_sub_value(self.builder, lhs_value, rhs_value),
+        })
+      }
+      BinOpKind::Mul => {
+        return Ok(Typed {
+          ty: lhs_expr.ty,
+          value: make_build_binop_mul_value(self.builder, lhs_value, rhs_value),
+        })
+      }
+      BinOpKind::EqEq => LLVMIntPredicate::LLVMIntEQ,
+      BinOpKind::Ne => LLVMIntPredicate::LLVMIntNE,
+      BinOpKind::Lt => LLVMIntPredicate::LLVMIntSLT,
+      BinOpKind::Le => LLVMIntPredicate::LLVMIntSLE,
+      BinOpKind::Gt => LLVMIntPredicate::LLVMIntSGT,
+      BinOpKind::Ge => LLVMIntPredicate::LLVMIntSGE,
+      _ => {
+        return Err(format!(
+          "llvm:fn:codegen_binop_expr:error: `{}` can not be compiled",
+          op.text()
+        ))
+      }
+    };
+
+    Ok(Typed {
+      ty: make_bool_ty(),
+      value: make_build_int_cmp(self.builder, predicate, lhs_value, rhs_value),
+    })
+  }
+
+  fn codegen_block(&mut self, block: &Block) -> Result<(), String> {
+    let locals = self.locals.to_owned();
+
+    for stmt in block.stmts.iter() {
+      self.codegen_stmt(stmt)?;
+    }
 
-        make_build_binop_add_value(self.builder, lhs_expr, rhs_expr)
+    self.locals = locals;
+
+    Ok(())
+  }
+
+  fn codegen_call_expr(
+    &mut self,
+    callee: &Box<Expr>,
+    args: &[Box<Expr>],
+  ) -> Result<Typed, String> {
+    let name = match callee.kind() {
+      ExprKind::Ident(ref name) if self.funs.contains_key(name) => name,
+      ExprKind::Ident(ref name)
+        if self.frames.contains_key(name) || name == YIELD_NOW =>
+      {
+        return Err(format!(
+          "llvm:fn:codegen_call_expr:error: `{}` is async, it can only be \
+           awaited",
+          name
+        ))
+      }
+      _ => {
+        return Err(format!(
+          "llvm:fn:codegen_call_expr:error: unknown function `{}`",
+          unparse_expr(callee)
+        ))
+      }
+    };
+
+    let fun = self.funs[name];
+    let params = self.params[name].to_vec();
+    let mut values = vec![];
+
+    for (i, arg) in args.iter().enumerate() {
+      match params.get(i) {
+        Some(ty) => values.push(self.codegen_arg(arg, ty)?),
+        None => values.push(self.codegen_expr(arg)?.value),
       }
-      _ => unimplemented!(),
+    }
+
+    let mut args = values;
+
+    let ret_ty = unsafe { LLVMGetReturnType(LLVMGetElementType(type_of(fun))) };
+
+    match get_ty_kind(ret_ty) {
+      LLVMTypeKind::LLVMVoidTypeKind => {
+        make_build_void_call(self.builder, fun, &mut args);
+        Ok(Typed::void())
+      }
+      _ => Ok(Typed {
+        ty: self.rets[name].to_owned(),
+        value: make_build_call(self.builder, fun, &mut args),
+      }),
     }
   }
 
-  fn codegen_expr_stmt(&mut self, expr: &Box<Expr>) -> LLVMValueRef {
+  fn codegen_expr(&mut self, expr: &Box<Expr>) -> Result<Typed, String> {
     match expr.kind() {
+      ExprKind::Assign { ref lhs, ref rhs } => {
+        self.codegen_assign_expr(lhs, rhs)
+      }
       ExprKind::BinOp {
         ref lhs,
         ref op,
         ref rhs,
         ..
       } => self.codegen_binop_expr(lhs, op, rhs),
+      ExprKind::Call {
+        ref callee,
+        ref args,
+        ..
+      } => self.codegen_call_expr(callee, args),
+      ExprKind::Ident(_) => {
+        let (ty, pointer) = self.place(expr)?;
+
+        Ok(Typed {
+          ty,
+          value: make_build_load(self.builder, pointer),
+        })
+      }
       ExprKind::Lit(ref lit) => self.codegen_lit_expr(lit),
-      _ => unimplemented!(),
+      ExprKind::MemberAccess {
+        ref from,
+        ref access,
+      } => {
+        let value = self.codegen_expr(from)?;
+        let value = self.unwrap(value);
+        let (ty, index) = self.field(&value.ty, access)?;
+
+        Ok(Typed {
+          ty,
+          value: make_build_extract_value(self.builder, value.value, index, ""),
+        })
+      }
+      ExprKind::StructLit {
+        ref name,
+        ref fields,
+      } => self.codegen_struct_lit_expr(name, fields),
+      ExprKind::Try(ref expr) => self.codegen_try_expr(expr),
+      _ => Err(format!(
+        "llvm:fn:codegen_expr:error: `{}` can not be compiled",
+        unparse_expr(expr)
+      )),
+    }
+  }
+
+  // a foreign function is added without a body, so it is printed as a
+  // `declare` and resolved by the linker e.g `declare i64 @puts(i8*)`
+  fn declare_fun(&mut self, fun: &Fun) -> Result<(), String> {
+    if fun.abi() == Some(Abi::JavaScript) {
+      return Err(format!(
+        "llvm:fn:declare_fun:error: javascript function `{}` can not be \
+         declared",
+        fun.name
+      ));
+    }
+
+    let mut param_tys = fun
+      .args
+      .iter()
+      .map(|arg| codegen_ty(self.context, &self.structs, arg.ty.kind()))
+      .collect::<Vec<LLVMTypeRef>>();
+
+    let ret_ty = codegen_ty(self.context, &self.structs, fun.ret_ty.kind());
+
+    let fun_ty =
+      make_fun_ty(ret_ty, param_tys.as_mut_ptr(), param_tys.len() as c_uint, 0);
+
+    let value = add_fun(self.module, &fun.name, fun_ty);
+
+    self.funs.insert(fun.name(), value);
+    self.params.insert(
+      fun.name(),
+      fun
+        .args
+        .iter()
+        .map(|arg| arg.ty.kind().to_owned())
+        .collect(),
+    );
+    self.rets.insert(fun.name(), fun.ret_ty.kind().to_owned());
+
+    Ok(())
+  }
+
+  // the state struct of an async function holds the state structs of the
+  // futures it awaits, so an async function can not await itself e.g
+  // `val a := fetch(x).await;` holds the struct of `fetch` and `yield_now()`
+  // a flag
+  fn declare_frame(
+    &mut self,
+    machine: &StateMachine,
+    machines: &[StateMachine],
+    awaiting: &mut Vec<String>,
+  ) -> Result<LLVMTypeRef, String> {
+    if let Some(frame) = self.frames.get(&machine.name) {
+      return Ok(frame.ty);
+    }
+
+    if awaiting.contains(&machine.name) {
+      return Err(format!(
+        "llvm:fn:declare_frame:error: `{}` awaits itself",
+        machine.name
+      ));
+    }
+
+    awaiting.push(machine.name.to_owned());
+
+    let int_ty = make_context_int64_ty(self.context);
+    let ty = make_context_named_struct_ty(
+      self.context,
+      &format!("{}.frame", machine.name),
+    );
+
+    let mut tys = vec![int_ty, self.field_ty(&machine.ret_ty)];
+    let mut fields = vec![];
+
+    for (name, ty) in machine.fields.iter().zip(machine.tys.iter()) {
+      if *ty == TyKind::Unknown {
+        return Err(format!(
+          "llvm:fn:declare_frame:error: the type of `{}` is unknown in `{}`",
+          name, machine.name
+        ));
+      }
+
+      fields.push((name.to_owned(), ty.to_owned(), tys.len() as c_uint));
+      tys.push(self.field_ty(ty));
+    }
+
+    let mut children = vec![];
+
+    for state in machine.states.iter() {
+      let future = match state.transition {
+        Transition::Await { ref future, .. } => future,
+        Transition::Return(_) => {
+          children.push(None);
+          continue;
+        }
+      };
+
+      let callee = match coroutine::awaited_call(future) {
+        Some((callee, _)) => callee,
+        None => {
+          return Err(format!(
+            "llvm:fn:declare_frame:error: only a call can be awaited in `{}`",
+            machine.name
+          ))
+        }
+      };
+
+      children.push(Some(tys.len() as c_uint));
+
+      if callee == YIELD_NOW {
+        tys.push(int_ty);
+        continue;
+      }
+
+      match machines.iter().find(|machine| machine.name == callee) {
+        Some(child) => {
+          let child_ty = self.declare_frame(child, machines, awaiting)?;
+
+          tys.push(child_ty);
+        }
+        None => {
+          return Err(format!(
+            "llvm:fn:declare_frame:error: `{}` is not an async function, it \
+             can not be awaited in `{}`",
+            callee, machine.name
+          ))
+        }
+      }
+    }
+
+    awaiting.pop();
+    set_struct_body(ty, &mut tys);
+
+    let mut param_tys = vec![make_pointer_ty(ty, 0)];
+    let fun_ty = make_fun_ty(
+      make_context_int1_ty(self.context),
+      param_tys.as_mut_ptr(),
+      1,
+      0,
+    );
+
+    let fun = add_fun(self.module, &format!("{}.poll", machine.name), fun_ty);
+
+    self.frames.insert(
+      machine.name.to_owned(),
+      Frame {
+        ty,
+        fun,
+        ret_ty: machine.ret_ty.to_owned(),
+        fields,
+        children,
+      },
+    );
+
+    Ok(ty)
+  }
+
+  // only the fields of a struct are compiled, its methods are not
+  fn declare_structs(&mut self, stmts: &[Box<Stmt>]) -> Result<(), String> {
+    for stmt in stmts.iter() {
+      let structure = match stmt.kind {
+        StmtKind::Struct(ref structure) => structure,
+        _ => continue,
+      };
+
+      let mut fields = vec![];
+
+      for member in structure.members.iter() {
+        match member {
+          StructMember::Field(ref field) => fields.push(field.to_owned()),
+          _ => {
+            return Err(format!(
+              "llvm:fn:declare_structs:error: the methods of `{}` can not be \
+               compiled",
+              structure.name
+            ))
+          }
+        }
+      }
+
+      self.structs.insert(structure.name.to_owned(), fields);
+    }
+
+    Ok(())
+  }
+
+  // the arguments are stored as locals, the last statement gives the return
+  // value unless it returns
+  fn codegen_fun(&mut self, fun: &Fun) -> Result<(), String> {
+    let value = self.funs[&fun.name];
+    let entry = unsafe {
+      LLVMAppendBasicBlockInContext(self.context, value, cstring!("entry"))
+    };
+
+    position_at_end(self.builder, entry);
+
+    self.fun = value;
+    self.locals.clear();
+    self.ret_ty = Some(fun.ret_ty.kind().to_owned());
+
+    for (i, arg) in fun.args.iter().enumerate() {
+      let ty = arg.ty.kind().to_owned();
+      let pointer = make_build_alloca(
+        self.builder,
+        codegen_ty(self.context, &self.structs, &ty),
+        &arg.expr.text(),
+      );
+
+      make_build_store(self.builder, fun_param(value, i as c_uint), pointer);
+      self.locals.insert(arg.expr.text(), (ty, pointer));
+    }
+
+    let mut last_value = Typed::void();
+
+    if let Some(ref block) = fun.block {
+      for stmt in block.stmts.iter() {
+        last_value = self.codegen_stmt(stmt)?;
+      }
+    }
+
+    if self.is_unreachable() {
+      make_build_unreachable(self.builder);
+    } else if *fun.ret_ty.kind() == TyKind::Void {
+      make_build_ret_void(self.builder, ptr::null_mut());
+    } else {
+      let value = self.coerce(last_value, fun.ret_ty.kind())?;
+
+      make_build_ret(self.builder, value);
+    }
+
+    Ok(())
+  }
+
+  // the conditions are tested in turn, a branch jumps to the end once it is
+  // run e.g `if foo { } else if bar { } else { }`
+  fn codegen_if_block_stmt(
+    &mut self,
+    conditions: &[(Box<Expr>, Box<Block>)],
+    alternative: &Option<Box<Block>>,
+  ) -> Result<Typed, String> {
+    let merge_block = make_context_append_basic_block(self.context, self.fun);
+
+    for (condition, block) in conditions.iter() {
+      let value = self.codegen_expr(condition)?;
+      let is_some = self.is_some(&value)?;
+
+      let then_block = make_context_append_basic_block(self.context, self.fun);
+      let else_block = make_context_append_basic_block(self.context, self.fun);
+
+      make_build_condition_block(self.builder, is_some, then_block, else_block);
+
+      position_at_end(self.builder, then_block);
+      self.codegen_block(block)?;
+      make_build_block(self.builder, merge_block);
+
+      position_at_end(self.builder, else_block);
+    }
+
+    if let Some(ref block) = alternative {
+      self.codegen_block(block)?;
     }
+
+    make_build_block(self.builder, merge_block);
+    position_at_end(self.builder, merge_block);
+
+    Ok(Typed::void())
   }
 
-  fn codegen_lit_expr(&mut self, kind: &LitKind) -> LLVMValueRef {
-    match kind {
-      // LitKind::Bool(ref value) => make_codegen_lit_bool_expr(value),
+  fn codegen_lit_expr(&mut self, kind: &LitKind) -> Result<Typed, String> {
+    let (ty, value) = match kind {
+      LitKind::Bool(ref value) => (
+        make_bool_ty(),
+        make_const_int(make_context_int1_ty(self.context), &(*value as i64)),
+      ),
       // LitKind::Char(ref value) => make_codegen_lit_char_expr(value),
-      LitKind::Real(ref value) => make_const_real_value(self.context, value),
-      LitKind::Int(ref value) => make_const_int_value(self.context, value),
-      // LitKind::Str(ref value) => make_codegen_lit_str_expr(value),
-      _ => unreachable!(),
+      LitKind::Real(ref value) => (
+        TyKind::NameRefTy("real".into()),
+        make_const_real_value(self.context, value),
+      ),
+      LitKind::Int(ref value) => (
+        TyKind::NameRefTy("int".into()),
+        make_const_int_value(self.context, value),
+      ),
+      LitKind::Str(ref value) => (
+        TyKind::NameRefTy("str".into()),
+        make_build_global_string_ptr(self.builder, value, "str"),
+      ),
+      // the layout of `nil` is given by the optional type it is used as
+      LitKind::Nil => return Ok(Typed::nil()),
+      _ => {
+        return Err(format!(
+          "llvm:fn:codegen_lit_expr:error: `{}` can not be compiled",
+          kind.text()
+        ))
+      }
+    };
+
+    Ok(Typed { ty, value })
+  }
+
+  // every name of a local holds the same value e.g `val x y: ?int = nil;`
+  fn codegen_local_stmt(&mut self, local: &Local) -> Result<Typed, String> {
+    let value = self.codegen_expr(&local.value)?;
+
+    let ty = match local.ty.as_ref().map(|ty| ty.kind()) {
+      Some(TyKind::Unknown) | None => value.ty.to_owned(),
+      Some(ty) => ty.to_owned(),
+    };
+
+    let value = self.coerce(value, &ty)?;
+
+    for name in local.names() {
+      let pointer = make_build_alloca(
+        self.builder,
+        codegen_ty(self.context, &self.structs, &ty),
+        &name,
+      );
+
+      make_build_store(self.builder, value, pointer);
+      self.locals.insert(name, (ty.to_owned(), pointer));
     }
+
+    Ok(Typed::void())
   }
 
-  fn codegen_stmt(
+  // the statements following a `ret` are compiled in a block that is not
+  // reached
+  fn codegen_ret_stmt(
     &mut self,
-    context: LLVMContextRef,
-    builder: LLVMBuilderRef,
-    fun: LLVMValueRef,
-    names: &mut HashMap<String, LLVMValueRef>,
-    stmt: &Box<Stmt>,
-  ) -> LLVMValueRef {
+    value: &Option<Box<Expr>>,
+  ) -> Result<Typed, String> {
+    let ty = match self.ret_ty {
+      Some(ref ty) => ty.to_owned(),
+      None => {
+        return Err(format!(
+          "llvm:fn:codegen_ret_stmt:error: `ret` can only be compiled in a \
+           function"
+        ))
+      }
+    };
+
+    let value = match value {
+      Some(ref value) => self.codegen_expr(value)?,
+      None => Typed::void(),
+    };
+
+    let value = self.coerce(value, &ty)?;
+
+    self.codegen_return(&ty, value);
+
+    let unreachable_block =
+      make_context_append_basic_block(self.context, self.fun);
+
+    position_at_end(self.builder, unreachable_block);
+
+    Ok(Typed::void())
+  }
+
+  // a poll function stores the return value in the state struct and tells
+  // that the machine is ready
+  fn codegen_return(&mut self, ty: &TyKind, value: LLVMValueRef) {
+    let poll = match self.poll {
+      Some(poll) => poll,
+      None => {
+        match ty {
+          TyKind::Void => make_build_ret_void(self.builder, value),
+          _ => make_build_ret(self.builder, value),
+        };
+
+        return;
+      }
+    };
+
+    let int_ty = make_context_int64_ty(self.context);
+    let bool_ty = make_context_int1_ty(self.context);
+
+    self.store(
+      ty,
+      value,
+      make_build_struct_gep(self.builder, poll.pointer, 1),
+    );
+
+    make_build_store(
+      self.builder,
+      make_const_int(int_ty, &poll.done),
+      make_build_struct_gep(self.builder, poll.pointer, 0),
+    );
+
+    make_build_ret(self.builder, make_const_int(bool_ty, &1));
+  }
+
+  // the fields are the members of the state struct, the state is the block
+  // to resume at e.g `2 * s` runs the statements of the state `s` and
+  // `2 * s + 1` polls the future it awaits
+  fn codegen_poll(&mut self, machine: &StateMachine) -> Result<(), String> {
+    let frame = self.frames[&machine.name].to_owned();
+    let entry = unsafe {
+      LLVMAppendBasicBlockInContext(self.context, frame.fun, cstring!("entry"))
+    };
+
+    position_at_end(self.builder, entry);
+
+    let int_ty = make_context_int64_ty(self.context);
+    let bool_ty = make_context_int1_ty(self.context);
+    let pointer = fun_param(frame.fun, 0);
+
+    self.fun = frame.fun;
+    self.locals.clear();
+    self.ret_ty = Some(machine.ret_ty.to_owned());
+    self.poll = Some(PollFrame {
+      pointer,
+      done: 2 * machine.states.len() as i64,
+    });
+
+    let mut fields = vec![];
+
+    for (name, ty, index) in frame.fields.iter() {
+      let field = make_build_struct_gep(self.builder, pointer, *index);
+
+      self.locals.insert(name.to_owned(), (ty.to_owned(), field));
+      fields.push((name.to_owned(), ty.to_owned(), field));
+    }
+
+    let state = make_build_load(
+      self.builder,
+      make_build_struct_gep(self.builder, pointer, 0),
+    );
+
+    let run_blocks = machine
+      .states
+      .iter()
+      .map(|_| make_context_append_basic_block(self.context, self.fun))
+      .collect::<Vec<_>>();
+
+    let poll_blocks = machine
+      .states
+      .iter()
+      .map(|state| match state.transition {
+        Transition::Await { .. } => {
+          Some(make_context_append_basic_block(self.context, self.fun))
+        }
+        Transition::Return(_) => None,
+      })
+      .collect::<Vec<_>>();
+
+    let done_block = make_context_append_basic_block(self.context, self.fun);
+    let switch = make_build_switch(
+      self.builder,
+      state,
+      done_block,
+      2 * machine.states.len() as c_uint,
+    );
+
+    for index in 0..machine.states.len() {
+      let run = make_const_int(int_ty, &(2 * index as i64));
+
+      add_case(switch, run, run_blocks[index]);
+
+      if let Some(poll_block) = poll_blocks[index] {
+        let poll = make_const_int(int_ty, &(2 * index as i64 + 1));
+
+        add_case(switch, poll, poll_block);
+      }
+    }
+
+    // a machine is not polled once it has returned
+    position_at_end(self.builder, done_block);
+    make_build_unreachable(self.builder);
+
+    for (index, state) in machine.states.iter().enumerate() {
+      position_at_end(self.builder, run_blocks[index]);
+
+      for stmt in state.stmts.iter() {
+        self.codegen_stmt(stmt)?;
+      }
+
+      // a field declared again by the statements is stored back in the
+      // state struct
+      for (name, ty, field) in fields.iter() {
+        let (local_ty, local) = self.locals[name].to_owned();
+
+        if local == *field {
+          continue;
+        }
+
+        if local_ty != *ty {
+          return Err(format!(
+            "llvm:fn:codegen_poll:error: `{}` is declared with another type \
+             in `{}`",
+            name, machine.name
+          ));
+        }
+
+        let value = make_build_load(self.builder, local);
+
+        self.store(ty, value, *field);
+        self.locals.insert(name.to_owned(), (ty.to_owned(), *field));
+      }
+
+      let (future, names, next) = match state.transition {
+        Transition::Await {
+          ref future,
+          ref names,
+          next,
+        } => (future, names, next),
+        Transition::Return(ref value) => {
+          let value = match value {
+            Some(ref value) => self.codegen_expr(value)?,
+            None => Typed::void(),
+          };
+
+          let value = self.coerce(value, &machine.ret_ty)?;
+
+          self.codegen_return(&machine.ret_ty, value);
+          continue;
+        }
+      };
+
+      // the awaited future starts at its first state
+      let (callee, args) = coroutine::awaited_call(future).unwrap();
+      let child = self.frames.get(&callee).cloned();
+      let child_pointer = make_build_struct_gep(
+        self.builder,
+        pointer,
+        frame.children[index].unwrap(),
+      );
+
+      match child {
+        Some(ref child) => {
+          for (arg, (_, ty, arg_index)) in args.iter().zip(child.fields.iter())
+          {
+            let value = self.codegen_arg(arg, ty)?;
+            let field =
+              make_build_struct_gep(self.builder, child_pointer, *arg_index);
+
+            self.store(ty, value, field);
+          }
+
+          let child_state =
+            make_build_struct_gep(self.builder, child_pointer, 0);
+
+          make_build_store(
+            self.builder,
+            make_const_int(int_ty, &0),
+            child_state,
+          );
+        }
+        None => {
+          make_build_store(
+            self.builder,
+            make_const_int(int_ty, &0),
+            child_pointer,
+          );
+        }
+      }
+
+      let poll_block = poll_blocks[index].unwrap();
+
+      make_build_block(self.builder, poll_block);
+      position_at_end(self.builder, poll_block);
+
+      // the pointer is computed again, the poll block is also entered when
+      // the machine resumes
+      let child_pointer = make_build_struct_gep(
+        self.builder,
+        pointer,
+        frame.children[index].unwrap(),
+      );
+
+      let ready = match child {
+        Some(ref child) => {
+          make_build_call(self.builder, child.fun, &mut vec![child_pointer])
+        }
+        // `yield_now()` is ready once its flag is set
+        None => {
+          let flag = make_build_load(self.builder, child_pointer);
+
+          make_build_store(
+            self.builder,
+            make_const_int(int_ty, &1),
+            child_pointer,
+          );
+
+          make_build_int_cmp(
+            self.builder,
+            LLVMIntPredicate::LLVMIntNE,
+            flag,
+            make_const_int(int_ty, &0),
+          )
+        }
+      };
+
+      let pending_block =
+        make_context_append_basic_block(self.context, self.fun);
+      let ready_block = make_context_append_basic_block(self.context, self.fun);
+
+      make_build_condition_block(
+        self.builder,
+        ready,
+        ready_block,
+        pending_block,
+      );
+
+      position_at_end(self.builder, pending_block);
+
+      make_build_store(
+        self.builder,
+        make_const_int(int_ty, &(2 * index as i64 + 1)),
+        make_build_struct_gep(self.builder, pointer, 0),
+      );
+
+      make_build_ret(self.builder, make_const_int(bool_ty, &0));
+      position_at_end(self.builder, ready_block);
+
+      let value = match child {
+        Some(ref child) if child.ret_ty != TyKind::Void => {
+          let ret = make_build_struct_gep(self.builder, child_pointer, 1);
+
+          Typed {
+            ty: child.ret_ty.to_owned(),
+            value: make_build_load(self.builder, ret),
+          }
+        }
+        _ => Typed::void(),
+      };
+
+      for name in names.iter() {
+        let (_, ty, field) =
+          fields.iter().find(|(field, ..)| field == name).unwrap();
+        let value = self.coerce(value.to_owned(), ty)?;
+
+        self.store(ty, value, *field);
+      }
+
+      make_build_block(self.builder, run_blocks[next]);
+    }
+
+    self.poll = None;
+
+    Ok(())
+  }
+
+  fn codegen_stmt(&mut self, stmt: &Box<Stmt>) -> Result<Typed, String> {
     match stmt.kind() {
-      StmtKind::Expr(ref expr) => self.codegen_expr_stmt(expr),
-      _ => unreachable!(),
+      StmtKind::Expr(ref expr) => self.codegen_expr(expr),
+      StmtKind::IfBlock {
+        ref conditions,
+        ref alternative,
+      } => self.codegen_if_block_stmt(conditions, alternative),
+      StmtKind::Mut(ref local) | StmtKind::Val(ref local) => {
+        self.codegen_local_stmt(local)
+      }
+      StmtKind::Ret(ref value) => self.codegen_ret_stmt(value),
+      _ => Err(format!(
+        "llvm:fn:codegen_stmt:error: `{}` can not be compiled",
+        unparse_stmt(stmt)
+      )),
+    }
+  }
+
+  // the fields are given in the order of the struct e.g `A { .bar = 0 }`
+  fn codegen_struct_lit_expr(
+    &mut self,
+    name: &Box<Expr>,
+    fields: &[(String, Box<Expr>)],
+  ) -> Result<Typed, String> {
+    let ty = TyKind::NameRefTy(name.text());
+
+    let declared = match self.structs.get(&name.text()) {
+      Some(declared) => declared.to_vec(),
+      None => {
+        return Err(format!(
+          "llvm:fn:codegen_struct_lit_expr:error: unknown struct `{}`",
+          name.text()
+        ))
+      }
+    };
+
+    let mut value =
+      make_const_null(codegen_ty(self.context, &self.structs, &ty));
+
+    for (index, field) in declared.iter().enumerate() {
+      let field_value =
+        match fields.iter().find(|(name, _)| *name == field.name) {
+          Some((_, field_value)) => self.codegen_expr(field_value)?,
+          None => {
+            return Err(format!(
+              "llvm:fn:codegen_struct_lit_expr:error: missing field `{}`",
+              field.name
+            ))
+          }
+        };
+
+      let field_value = self.coerce(field_value, field.ty.kind())?;
+
+      value = make_build_insert_elmt(
+        self.builder,
+        value,
+        field_value,
+        index as c_uint,
+        "",
+      );
+    }
+
+    Ok(Typed { ty, value })
+  }
+
+  // `foo?` returns `nil` from the function when `foo` is `nil` and gives its
+  // payload otherwise
+  fn codegen_try_expr(&mut self, expr: &Box<Expr>) -> Result<Typed, String> {
+    let ty = match self.ret_ty {
+      Some(ref ty @ TyKind::OptionalTy(_)) => ty.to_owned(),
+      // only the top-level statements are compiled, there is no function to
+      // return `nil` from
+      None => {
+        return Err(format!(
+          "llvm:fn:codegen_try_expr:error: `?` can only be compiled in a \
+           function"
+        ))
+      }
+      Some(_) => {
+        return Err(format!(
+          "llvm:fn:codegen_try_expr:error: `?` in a function that does not \
+           return an optional"
+        ))
+      }
+    };
+
+    let value = self.codegen_expr(expr)?;
+    let is_some = self.is_some(&value)?;
+
+    let nil_block = make_context_append_basic_block(self.context, self.fun);
+    let some_block = make_context_append_basic_block(self.context, self.fun);
+
+    make_build_condition_block(self.builder, is_some, some_block, nil_block);
+
+    position_at_end(self.builder, nil_block);
+
+    let nil = self.coerce(Typed::nil(), &ty)?;
+
+    self.codegen_return(&ty, nil);
+    position_at_end(self.builder, some_block);
+
+    Ok(self.unwrap(value))
+  }
+
+  // the type of a field and its index in the struct
+  fn field(&self, ty: &TyKind, name: &str) -> Result<(TyKind, c_uint), String> {
+    let fields = match ty {
+      TyKind::NameRefTy(ref name) if self.structs.contains_key(name) => {
+        &self.structs[name]
+      }
+      _ => {
+        return Err(format!(
+          "llvm:fn:field:error: `{}` has no field `{}`",
+          ty.text(),
+          name
+        ))
+      }
+    };
+
+    match fields.iter().position(|field| field.name == name) {
+      Some(index) => Ok((fields[index].ty.kind().to_owned(), index as c_uint)),
+      None => Err(format!(
+        "llvm:fn:field:error: `{}` has no field `{}`",
+        ty.text(),
+        name
+      )),
     }
   }
+
+  // a value is tested with `if foo` or `foo != nil` as a bool, the tag of an
+  // optional or its pointer tells whether it holds a value
+  fn is_some(&mut self, value: &Typed) -> Result<LLVMValueRef, String> {
+    let bool_ty = make_context_int1_ty(self.context);
+
+    let payload = match value.ty {
+      _ if value.is_nil() => return Ok(make_const_int(bool_ty, &0)),
+      TyKind::OptionalTy(ref payload) => payload,
+      _ if type_of(value.value) == bool_ty => return Ok(value.value),
+      _ => {
+        let zero = make_const_null(type_of(value.value));
+
+        return Ok(make_build_int_cmp(
+          self.builder,
+          LLVMIntPredicate::LLVMIntNE,
+          value.value,
+          zero,
+        ));
+      }
+    };
+
+    match optional_layout(payload) {
+      OptionalLayout::NullPointer => {
+        Ok(make_build_is_not_null(self.builder, value.value))
+      }
+      OptionalLayout::Tagged => {
+        Ok(make_build_extract_value(self.builder, value.value, 0, ""))
+      }
+    }
+  }
+
+  // the block following a `ret` or an `if` whose branches all return has no
+  // predecessor
+  fn is_unreachable(&self) -> bool {
+    let block = insert_basic_block(self.builder);
+
+    block != entry_basic_block(self.fun) && !has_predecessors(block)
+  }
+
+  // a member of a state struct, the return value of a void function takes no
+  // memory
+  fn field_ty(&self, ty: &TyKind) -> LLVMTypeRef {
+    match ty {
+      TyKind::Void => make_context_struct_ty(self.context, &mut vec![]),
+      _ => codegen_ty(self.context, &self.structs, ty),
+    }
+  }
+
+  // the memory of a local or of one of its fields e.g `foo.bar`
+  fn place(&mut self, expr: &Expr) -> Result<(TyKind, LLVMValueRef), String> {
+    match expr.kind() {
+      ExprKind::Ident(ref name) => match self.locals.get(name) {
+        Some(local) => Ok(local.to_owned()),
+        None => Err(format!("llvm:fn:place:error: unknown local `{}`", name)),
+      },
+      ExprKind::MemberAccess {
+        ref from,
+        ref access,
+      } => {
+        let (ty, pointer) = match self.place(from)? {
+          (TyKind::OptionalTy(box payload), pointer) => {
+            match optional_layout(&payload) {
+              OptionalLayout::NullPointer => (payload, pointer),
+              OptionalLayout::Tagged => {
+                (payload, make_build_struct_gep(self.builder, pointer, 1))
+              }
+            }
+          }
+          place => place,
+        };
+
+        let (ty, index) = self.field(&ty, access)?;
+
+        Ok((ty, make_build_struct_gep(self.builder, pointer, index)))
+      }
+      _ => Err(format!(
+        "llvm:fn:place:error: `{}` can not be assigned",
+        unparse_expr(expr)
+      )),
+    }
+  }
+
+  fn store(&mut self, ty: &TyKind, value: LLVMValueRef, pointer: LLVMValueRef) {
+    if *ty != TyKind::Void {
+      make_build_store(self.builder, value, pointer);
+    }
+  }
+
+  // the payload of an optional, the validator only lets a narrowed optional
+  // be used as its payload
+  fn unwrap(&mut self, value: Typed) -> Typed {
+    let payload = match value.ty {
+      TyKind::OptionalTy(box ref payload) => payload.to_owned(),
+      _ => return value,
+    };
+
+    let value = match optional_layout(&payload) {
+      OptionalLayout::NullPointer => value.value,
+      OptionalLayout::Tagged => {
+        make_build_extract_value(self.builder, value.value, 1, "")
+      }
+    };
+
+    Typed { ty: payload, value }
+  }
 }
 
-fn insert_allocations(
+// `real` is a double, `str` a pointer to its bytes, a struct is the struct
+// of its fields and the other types are integers, an optional is the pointer
+// itself or a `{ i1, T }` pair
+fn codegen_ty(
   context: LLVMContextRef,
-  builder: LLVMBuilderRef,
-  names: &mut HashMap<String, LLVMValueRef>,
-  stmts: &[Box<Stmt>],
-) {
-  // let mut variable_names = HashSet::new();
-
-  // for stmt in stmts {
-  //   match stmt.kind {
-  //     StmtKind::Val
-  //     _ => unreachable!(),
-  //   }
-  // }
-
-  // for variable_name in variable_names {
-  // unsafe {
-  // let int_ty = LLVMInt64TypeInContext(context);
-  // let name = CString::new(variable_name.as_bytes()).unwrap();
-  // let pointer = LLVMBuildAlloca(builder, int_ty, name.as_ptr());
-
-  // names.insert(variable_name.to_owned(), pointer);
-  // }
-  // }
+  structs: &HashMap<String, Vec<Field>>,
+  kind: &TyKind,
+) -> LLVMTypeRef {
+  match kind {
+    TyKind::Void => make_context_void_ty(context),
+    TyKind::OptionalTy(ref ty) => match optional_layout(ty) {
+      OptionalLayout::NullPointer => codegen_ty(context, structs, ty),
+      OptionalLayout::Tagged => make_context_struct_ty(
+        context,
+        &mut vec![
+          make_context_int1_ty(context),
+          codegen_ty(context, structs, ty),
+        ],
+      ),
+    },
+    TyKind::RefTy(ref ty) => {
+      make_pointer_ty(codegen_ty(context, structs, ty), 0)
+    }
+    TyKind::NameRefTy(ref name) if name == "bool" => {
+      make_context_int1_ty(context)
+    }
+    TyKind::NameRefTy(ref name) if name == "real" => {
+      make_context_double_ty(context)
+    }
+    TyKind::NameRefTy(ref name) if name == "str" => {
+      make_pointer_ty(make_context_int8_ty(context), 0)
+    }
+    TyKind::NameRefTy(ref name) if structs.contains_key(name) => {
+      make_context_struct_ty(
+        context,
+        &mut structs[name]
+          .iter()
+          .map(|field| codegen_ty(context, structs, field.ty.kind()))
+          .collect(),
+      )
+    }
+    _ => make_context_int64_ty(context),
+  }
+}
+
+fn is_item(stmt: &Box<Stmt>) -> bool {
+  match stmt.kind {
+    StmtKind::Fun(_) | StmtKind::Struct(_) => true,
+    _ => false,
+  }
+}
+
+fn make_bool_ty() -> TyKind {
+  TyKind::NameRefTy("bool".into())
 }
@@ -5,6 +5,8 @@ mod util;
 pub use self::interface::*;
 pub use self::jit::Jit;
 
+use qoeurcp_tokenizer::ast::Ast;
+
 use std::process::Command;
 
 // just for testing to see if it's working
@@ -20,10 +22,12 @@ fn make_exe() {
     .expect("failed to execute process");
 }
 
-pub fn compile(file_name: &str, input: &str) {
-  let tree = qoeurcp_tokenizer::parse(input);
+pub fn compile(file_name: &str, ast: &Ast) -> Result<(), String> {
   let mut compiler = Jit::new();
-  let _ = compiler.codegen(tree.ast.nodes);
 
-  make_exe()
+  compiler.codegen(ast.nodes.to_vec())?;
+  print_module_to_file(compiler.module, "out/test.ll")?;
+  make_exe();
+
+  Ok(())
 }
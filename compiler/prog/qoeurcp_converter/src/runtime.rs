@@ -0,0 +1,217 @@
+use super::cranelift::{FrameLayout, Jit};
+use super::layout::{optional_layout, OptionalLayout};
+
+use qoeurcp_tokenizer::ast::*;
+
+use std::collections::VecDeque;
+use std::mem;
+
+pub type RuntimeResult<T> = Result<T, String>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+  Bool(bool),
+  Int(i64),
+  Nil,
+  Real(f64),
+  Void,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Poll {
+  Pending,
+  Ready(Value),
+}
+
+// a call of an async function, its state struct is polled by the compiled
+// poll function until the function has returned
+struct Task {
+  frame: FrameLayout,
+  memory: Vec<u64>,
+  poll: fn(*mut u8) -> i8,
+  value: Option<Value>,
+}
+
+// a single-threaded executor, the spawned tasks are polled in turn until
+// every one of them is ready, the async functions are compiled by the
+// cranelift backend into poll functions
+pub struct Executor {
+  jit: Jit,
+  tasks: Vec<Task>,
+  pub polls: usize,
+}
+
+impl Executor {
+  pub fn new(ast: &Ast) -> RuntimeResult<Executor> {
+    let mut jit = Jit::new();
+
+    jit.compile(ast.nodes.to_vec())?;
+
+    Ok(Self {
+      jit,
+      tasks: vec![],
+      polls: 0,
+    })
+  }
+
+  // runs an async function to completion
+  pub fn block_on(
+    &mut self,
+    name: &str,
+    args: Vec<Value>,
+  ) -> RuntimeResult<Value> {
+    let task = self.spawn(name, args)?;
+
+    Ok(self.run()?.remove(task))
+  }
+
+  // polls the tasks in the order of their spawn and returns their values
+  pub fn run(&mut self) -> RuntimeResult<Vec<Value>> {
+    let mut queue = (0..self.tasks.len()).collect::<VecDeque<usize>>();
+
+    while let Some(task) = queue.pop_front() {
+      if let Poll::Pending = self.poll(task)? {
+        queue.push_back(task);
+      }
+    }
+
+    Ok(
+      mem::replace(&mut self.tasks, vec![])
+        .into_iter()
+        .filter_map(|task| task.value)
+        .collect(),
+    )
+  }
+
+  // the arguments are stored in the state struct of the call, whose state is
+  // the first one
+  pub fn spawn(
+    &mut self,
+    name: &str,
+    args: Vec<Value>,
+  ) -> RuntimeResult<usize> {
+    let (frame, code) = match self.jit.frame(name) {
+      Some(frame) => frame,
+      None => {
+        return Err(format!(
+          "runtime:fn:spawn:error: `{}` is not an async function",
+          name
+        ))
+      }
+    };
+
+    if args.len() != frame.args {
+      return Err(format!(
+        "runtime:fn:spawn:error: `{}` takes {} arguments, {} are given",
+        name,
+        frame.args,
+        args.len()
+      ));
+    }
+
+    let mut memory = vec![0; frame.size as usize / 8];
+
+    for ((_, ty, offset), value) in frame.fields.iter().zip(args) {
+      write(&mut memory, *offset as usize, ty, value)?;
+    }
+
+    self.tasks.push(Task {
+      frame,
+      memory,
+      poll: unsafe { mem::transmute::<_, fn(*mut u8) -> i8>(code) },
+      value: None,
+    });
+
+    Ok(self.tasks.len() - 1)
+  }
+
+  fn poll(&mut self, id: usize) -> RuntimeResult<Poll> {
+    let task = &mut self.tasks[id];
+
+    if let Some(ref value) = task.value {
+      return Ok(Poll::Ready(value.to_owned()));
+    }
+
+    self.polls += 1;
+
+    if (task.poll)(task.memory.as_mut_ptr() as *mut u8) == 0 {
+      return Ok(Poll::Pending);
+    }
+
+    let (offset, ref ty) = task.frame.ret;
+    let value = read(&task.memory, offset as usize, ty)?;
+
+    task.value = Some(value.to_owned());
+
+    Ok(Poll::Ready(value))
+  }
+}
+
+// a value is stored as its cranelift values, each of them takes 8 bytes e.g
+// `?int` is its tag followed by its payload
+fn write(
+  memory: &mut [u64],
+  offset: usize,
+  ty: &TyKind,
+  value: Value,
+) -> RuntimeResult<()> {
+  let slot = offset / 8;
+
+  match (ty, value) {
+    (TyKind::OptionalTy(_), Value::Nil) => {}
+    (TyKind::OptionalTy(ref payload), value) => {
+      match optional_layout(payload) {
+        OptionalLayout::NullPointer => write(memory, offset, payload, value)?,
+        OptionalLayout::Tagged => {
+          memory[slot] = 1;
+          write(memory, offset + 8, payload, value)?;
+        }
+      }
+    }
+    (TyKind::NameRefTy(ref name), Value::Bool(value)) if name == "bool" => {
+      memory[slot] = value as u64
+    }
+    (TyKind::NameRefTy(ref name), Value::Real(value)) if name == "real" => {
+      memory[slot] = value.to_bits()
+    }
+    (TyKind::NameRefTy(ref name), Value::Int(value)) if name == "int" => {
+      memory[slot] = value as u64
+    }
+    (ty, value) => {
+      return Err(format!(
+        "runtime:fn:write:error: {:?} is not a value of type `{}`",
+        value,
+        ty.text()
+      ))
+    }
+  }
+
+  Ok(())
+}
+
+fn read(memory: &[u64], offset: usize, ty: &TyKind) -> RuntimeResult<Value> {
+  let slot = offset / 8;
+
+  match ty {
+    TyKind::Void => Ok(Value::Void),
+    TyKind::OptionalTy(ref payload) => match optional_layout(payload) {
+      OptionalLayout::Tagged if memory[slot] as u8 == 0 => Ok(Value::Nil),
+      OptionalLayout::Tagged => read(memory, offset + 8, payload),
+      OptionalLayout::NullPointer if memory[slot] == 0 => Ok(Value::Nil),
+      OptionalLayout::NullPointer => read(memory, offset, payload),
+    },
+    TyKind::NameRefTy(ref name) if name == "bool" => {
+      Ok(Value::Bool(memory[slot] as u8 != 0))
+    }
+    TyKind::NameRefTy(ref name) if name == "real" => {
+      Ok(Value::Real(f64::from_bits(memory[slot])))
+    }
+    TyKind::NameRefTy(ref name) if name == "int" => {
+      Ok(Value::Int(memory[slot] as i64))
+    }
+    _ => Err(format!(
+      "runtime:fn:read:error: a value of type `{}` can not be read",
+      ty.text()
+    )),
+  }
+}
@@ -33,12 +33,16 @@ impl Scope {
   }
 
   pub fn add_variable(&mut self, local: Local) -> ScopeResult<()> {
-    match self.get_variable(&local.name()) {
+    let names = local.names();
+
+    match names.iter().find(|name| self.get_variable(name).is_some()) {
       Some(_) => Err(format!(
         "scope:fn:add_variable:error: variable already exist"
       )),
       None => Ok({
-        self.variables.insert(local.name(), local);
+        for name in names {
+          self.variables.insert(name, local.to_owned());
+        }
       }),
     }
   }
@@ -1 +1,409 @@
+use super::coroutine::{lower, Transition};
+use super::cranelift::Jit as CraneliftJit;
+use super::layout::{optional_layout, OptionalLayout};
+use super::llvm::{print_module_to_string, verify_module, Jit as LlvmJit};
+use super::runtime::{Executor, Value};
+use super::{compile, Cranelift};
 
+use qoeurcp_tokenizer::ast::*;
+
+use cranelift_module::Linkage;
+
+use std::collections::HashMap;
+use std::mem;
+
+fn async_fun(input: &str) -> Fun {
+  let tree = qoeurcp_tokenizer::parse(input);
+
+  match tree.ast.nodes[0].kind {
+    StmtKind::Fun(ref fun) => *fun.to_owned(),
+    _ => unreachable!(),
+  }
+}
+
+#[test]
+fn lower_async_fun_at_await_points() {
+  let fun = async_fun(
+    "async fun load: (-> int | x: int) = (x) {
+      val a := fetch(x).await;
+      val b := a + 1;
+      fetch(b).await;
+      ret fetch(b).await;
+    }",
+  );
+
+  let machine = lower(&fun, &HashMap::new()).unwrap();
+
+  let transitions = machine
+    .states
+    .iter()
+    .map(|state| match state.transition {
+      Transition::Await {
+        ref names, next, ..
+      } => {
+        format!("await {:?} {}", names, next)
+      }
+      Transition::Return(ref value) => format!("ret {}", value.is_some()),
+    })
+    .collect::<Vec<String>>();
+
+  assert_eq!(
+    transitions,
+    [
+      "await [\"a\"] 1",
+      "await [] 2",
+      "await [\"await#result\"] 3",
+      "ret true"
+    ]
+  );
+
+  assert_eq!(machine.states[1].stmts.len(), 1);
+  assert_eq!(machine.fields, ["x", "a", "b", "await#result"]);
+
+  let fun = async_fun("async fun f: () = () { g(h().await) }");
+
+  assert!(lower(&fun, &HashMap::new()).is_err());
+
+  for src in [
+    "async fun f: (-> void | x: bool) = (x) { if x { h().await; } }",
+    "async fun f: () = () { loop { h().await; } }",
+  ]
+  .iter()
+  {
+    let error = lower(&async_fun(src), &HashMap::new()).unwrap_err();
+
+    assert!(error.contains("inside an `if` or a loop"), "{}", error);
+  }
+}
+
+static ASYNC_FUNS: &str = "fun double: (-> int | x: int) = (x) { x * 2 }
+async fun fetch: (-> int | x: int) = (x) {
+  yield_now().await;
+  double(x)
+}
+async fun load: (-> int | x: int) = (x) {
+  val a := fetch(x).await;
+  val b := fetch(a + 1).await;
+  a + b
+}";
+
+#[test]
+fn run_async_funs_to_completion() {
+  let tree = qoeurcp_tokenizer::parse(ASYNC_FUNS);
+
+  let mut executor = Executor::new(&tree.ast).unwrap();
+
+  assert_eq!(
+    executor.block_on("load", vec![Value::Int(1)]),
+    Ok(Value::Int(8))
+  );
+
+  // `load` is pending while each `fetch` yields
+  assert_eq!(executor.polls, 3);
+
+  let mut executor = Executor::new(&tree.ast).unwrap();
+
+  executor.spawn("load", vec![Value::Int(1)]).unwrap();
+  executor.spawn("fetch", vec![Value::Int(5)]).unwrap();
+
+  assert_eq!(executor.run(), Ok(vec![Value::Int(8), Value::Int(10)]));
+}
+
+#[test]
+fn emit_state_structs_in_llvm() {
+  let tree = qoeurcp_tokenizer::parse(ASYNC_FUNS);
+  let mut jit = LlvmJit::new();
+
+  jit.codegen(tree.ast.nodes.to_vec()).unwrap();
+
+  let ir = print_module_to_string(jit.module);
+
+  assert_eq!(verify_module(jit.module), Ok(()), "{}", ir);
+  assert!(
+    ir.contains("%fetch.frame = type { i64, i64, i64, i64 }"),
+    "{}",
+    ir
+  );
+  assert!(
+    ir.contains(
+      "%load.frame = type { i64, i64, i64, i64, i64, %fetch.frame, \
+       %fetch.frame }"
+    ),
+    "{}",
+    ir
+  );
+  assert!(
+    ir.contains("define i1 @load.poll(%load.frame* %0)"),
+    "{}",
+    ir
+  );
+  assert!(ir.contains("call i1 @fetch.poll(%fetch.frame*"), "{}", ir);
+}
+
+#[test]
+fn reject_async_calls_that_are_not_awaited() {
+  for (src, error) in [
+    (
+      "async fun spin: (-> int | x: int) = (x) { spin(x).await }",
+      "`spin` awaits itself",
+    ),
+    (
+      "async fun fetch: (-> int | x: int) = (x) { x }\nfetch(1);",
+      "`fetch` is async, it can only be awaited",
+    ),
+  ]
+  .iter()
+  {
+    let tree = qoeurcp_tokenizer::parse(src);
+    let mut jit = CraneliftJit::new();
+
+    let result = jit.compile(tree.ast.nodes.to_vec());
+
+    assert!(result.unwrap_err().contains(error));
+
+    let mut jit = LlvmJit::new();
+
+    let result = jit.codegen(tree.ast.nodes.to_vec());
+
+    assert!(result.unwrap_err().contains(error));
+  }
+}
+
+#[test]
+fn lay_out_optionals() {
+  let int = TyKind::NameRefTy("int".into());
+  let string = TyKind::NameRefTy("str".into());
+
+  assert_eq!(optional_layout(&int), OptionalLayout::Tagged);
+  assert_eq!(optional_layout(&string), OptionalLayout::NullPointer);
+  assert_eq!(
+    optional_layout(&TyKind::RefTy(box int)),
+    OptionalLayout::NullPointer
+  );
+
+  let tree = qoeurcp_tokenizer::parse(
+    "async fun check: (-> bool | x: ?int) = (x) { x != nil }",
+  );
+
+  let mut executor = Executor::new(&tree.ast).unwrap();
+
+  assert_eq!(
+    executor.block_on("check", vec![Value::Nil]),
+    Ok(Value::Bool(false))
+  );
+}
+
+// the jit looks `sqrt` up among the loaded libraries, the test binary only
+// loads the math library once it calls into it
+#[link(name = "m")]
+extern "C" {
+  fn sqrt(x: f64) -> f64;
+}
+
+static FOREIGN_FUNS: &str = "ext fun puts: (-> int | s: str);
+ext fun sqrt: (-> real | x: real);
+puts(\"hi\");
+sqrt(4.0);";
+
+#[test]
+fn import_foreign_funs_in_cranelift() {
+  let tree = qoeurcp_tokenizer::parse(FOREIGN_FUNS);
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+
+  assert_eq!(unsafe { sqrt(4.0) }, 2.0);
+
+  let mut jit = CraneliftJit::new();
+  let code = jit.compile(tree.ast.nodes.to_vec()).unwrap();
+
+  assert_eq!(jit.linkage("puts"), Some(Linkage::Import));
+  assert_eq!(jit.linkage("sqrt"), Some(Linkage::Import));
+
+  // the calls only run once `puts` and `sqrt` are resolved
+  let main = unsafe { mem::transmute::<_, fn() -> i64>(code) };
+
+  assert_eq!(main(), 0);
+
+  let tree = qoeurcp_tokenizer::parse("nope(1);");
+  let mut jit = CraneliftJit::new();
+
+  assert_eq!(
+    jit.compile(tree.ast.nodes.to_vec()),
+    Err(
+      "cranelift:fn:translate_call_expr:error: unknown function `nope`".into()
+    )
+  );
+  assert_eq!(
+    compile("nope", "nope(1);", &Cranelift, Target::Native),
+    Err(
+      "cranelift:fn:translate_call_expr:error: unknown function `nope`".into()
+    )
+  );
+}
+
+#[test]
+fn declare_foreign_funs_in_llvm() {
+  let tree = qoeurcp_tokenizer::parse(FOREIGN_FUNS);
+  let mut jit = LlvmJit::new();
+
+  jit.codegen(tree.ast.nodes.to_vec()).unwrap();
+
+  let ir = print_module_to_string(jit.module);
+
+  assert_eq!(verify_module(jit.module), Ok(()), "{}", ir);
+  assert!(ir.contains("declare i64 @puts(i8*)"), "{}", ir);
+  assert!(ir.contains("declare double @sqrt(double)"), "{}", ir);
+
+  let tree = qoeurcp_tokenizer::parse("nope(1);");
+  let mut jit = LlvmJit::new();
+
+  assert_eq!(
+    jit.codegen(tree.ast.nodes.to_vec()),
+    Err("llvm:fn:codegen_call_expr:error: unknown function `nope`".into())
+  );
+}
+
+// the c calling convention leaves the arguments a function does not read, so
+// `getpid` stands for a function taking optionals
+static OPTIONALS: &str = "ext fun getpid: (-> int | x: ?int, y: ?real);
+ext fun time: (-> int | t: ?ref int);
+getpid(nil, nil);
+getpid(1, 2.0);
+time(nil);";
+
+#[test]
+fn pass_optionals_in_cranelift() {
+  let tree = qoeurcp_tokenizer::parse(OPTIONALS);
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+
+  let mut jit = CraneliftJit::new();
+  let code = jit.compile(tree.ast.nodes.to_vec()).unwrap();
+  let main = unsafe { mem::transmute::<_, fn() -> i64>(code) };
+
+  assert!(main() > 0);
+
+  for (src, error) in [
+    (
+      "val x: int = nil;",
+      "`nil` can only be compiled as an optional value",
+    ),
+    ("time(nil)?;", "`?` can only be compiled in a function"),
+  ]
+  .iter()
+  {
+    let tree = qoeurcp_tokenizer::parse(&format!("{}\n{}", OPTIONALS, src));
+    let mut jit = CraneliftJit::new();
+
+    let result = jit.compile(tree.ast.nodes.to_vec());
+
+    assert!(result.unwrap_err().contains(error));
+  }
+}
+
+#[test]
+fn pass_optionals_in_llvm() {
+  let tree = qoeurcp_tokenizer::parse(OPTIONALS);
+  let mut jit = LlvmJit::new();
+
+  jit.codegen(tree.ast.nodes.to_vec()).unwrap();
+
+  let ir = print_module_to_string(jit.module);
+
+  assert_eq!(verify_module(jit.module), Ok(()), "{}", ir);
+  assert!(ir.contains("{ i1, i64 } zeroinitializer"), "{}", ir);
+  assert!(ir.contains("{ i1, i64 } { i1 true, i64 1 }"), "{}", ir);
+  assert!(ir.contains("call i64 @time(i64* null)"), "{}", ir);
+  assert!(ir.contains("ret i64 %calltmp2"), "{}", ir);
+
+  for (src, error) in [
+    (
+      "val x: int = nil;",
+      "`nil` can only be compiled as an optional value",
+    ),
+    ("time(nil)?;", "`?` can only be compiled in a function"),
+  ]
+  .iter()
+  {
+    let tree = qoeurcp_tokenizer::parse(&format!("{}\n{}", OPTIONALS, src));
+    let mut jit = LlvmJit::new();
+
+    let result = jit.codegen(tree.ast.nodes.to_vec());
+
+    assert!(result.unwrap_err().contains(error));
+  }
+}
+
+// the example of the guide, `foo` is narrowed to an `A` by `if foo` and the
+// other values are wrapped into or unwrapped from their optional layout
+static NARROWING: &str = "struct A { bar: int }
+fun do_something: (-> | foo: ?A) = (foo) {
+  if foo {
+    foo.bar = 0;
+  }
+}
+fun reset: (-> ?A | foo: ?A) = (foo) {
+  if foo {
+    foo.bar = 0;
+  }
+  foo
+}
+fun bar_or: (-> int | foo: ?A, default: int) = (foo, default) {
+  if foo == nil {
+    ret default;
+  }
+  foo.bar
+}
+fun incremented: (-> ?int | foo: ?A) = (foo) {
+  val a: A = foo?;
+  a.bar + 1
+}
+fun int_or: (-> int | x: ?int, default: int) = (x, default) {
+  if x {
+    ret x;
+  }
+  default
+}
+do_something(nil);
+do_something(A { .bar = 1 });
+bar_or(reset(A { .bar = 7 }), 1) + bar_or(nil, 20) + bar_or(A { .bar = 300 }, 0)
+  + int_or(incremented(nil), 4000) + int_or(incremented(A { .bar = 49999 }), 0);";
+
+#[test]
+fn narrow_optionals_in_cranelift() {
+  let tree = qoeurcp_tokenizer::parse(NARROWING);
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+
+  let mut jit = CraneliftJit::new();
+  let code = jit.compile(tree.ast.nodes.to_vec()).unwrap();
+  let main = unsafe { mem::transmute::<_, fn() -> i64>(code) };
+
+  assert_eq!(main(), 54320);
+}
+
+#[test]
+fn narrow_optionals_in_llvm() {
+  let tree = qoeurcp_tokenizer::parse(NARROWING);
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+
+  let mut jit = LlvmJit::new();
+
+  jit.codegen(tree.ast.nodes.to_vec()).unwrap();
+
+  let ir = print_module_to_string(jit.module);
+
+  assert_eq!(verify_module(jit.module), Ok(()), "{}", ir);
+  assert!(
+    ir.contains("define void @do_something({ i1, { i64 } }"),
+    "{}",
+    ir
+  );
+  assert!(ir.contains("define { i1, i64 } @incremented("), "{}", ir);
+  assert!(
+    ir.contains("call void @do_something({ i1, { i64 } } zeroinitializer)"),
+    "{}",
+    ir
+  );
+}
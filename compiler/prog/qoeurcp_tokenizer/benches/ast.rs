@@ -0,0 +1,139 @@
+// the cost of building and walking the tree of a large program
+//
+// run with `cargo bench -p qoeurcp_tokenizer`
+
+#![feature(test)]
+
+extern crate test;
+
+use qoeurcp_tokenizer::ast::*;
+use qoeurcp_tokenizer::parse;
+
+use test::{black_box, Bencher};
+
+// the number of functions of the synthetic program
+static FUNS: usize = 2000;
+
+// a program made of `len` functions, each one calls the previous one e.g
+// `fun f1: (-> int | a: int, b: int) = (a, b) { ... f0(x, b) ... }`
+fn synthetic_program(len: usize) -> String {
+  let mut src =
+    String::from("fun f0: (-> int | a: int, b: int) = (a, b) { a }\n");
+
+  for index in 1..len {
+    src.push_str(&format!(
+      "fun f{index}: (-> int | a: int, b: int) = (a, b) {{
+        val x := a * 2 + b;
+        mut y := x - 1;
+
+        if y > 0 {{
+          y = y + f{prev}(x, b);
+        }} else {{
+          y = -y;
+        }}
+
+        y
+      }}
+      ",
+      index = index,
+      prev = index - 1,
+    ));
+  }
+
+  src
+}
+
+struct Count {
+  nodes: usize,
+}
+
+impl<'ast> Visitor<'ast> for Count {
+  fn visit_expr(&mut self, expr: &'ast Expr) {
+    self.nodes += 1;
+    visit::walk_expr(self, expr);
+  }
+
+  fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+    self.nodes += 1;
+    visit::walk_stmt(self, stmt);
+  }
+}
+
+#[bench]
+fn parse_program(b: &mut Bencher) {
+  let src = synthetic_program(FUNS);
+
+  assert!(parse(&src).errors.is_empty());
+  b.iter(|| black_box(parse(&src)));
+}
+
+#[bench]
+fn walk_program(b: &mut Bencher) {
+  let tree = parse(&synthetic_program(FUNS));
+
+  b.iter(|| {
+    let mut count = Count { nodes: 0 };
+
+    count.visit_ast(&tree.ast);
+    black_box(count.nodes)
+  });
+}
+
+// the nodes of the arena are looked up by id, compare with `walk_program`
+#[bench]
+fn walk_arena(b: &mut Bencher) {
+  let nodes = Arena::new(*parse(&synthetic_program(FUNS)).ast);
+
+  b.iter(|| {
+    (0..nodes.len())
+      .filter_map(|index| nodes.get(NodeId::new(index)))
+      .count()
+  });
+}
+
+#[bench]
+fn clone_program(b: &mut Bencher) {
+  let tree = parse(&synthetic_program(FUNS));
+
+  b.iter(|| black_box(tree.ast.nodes.to_vec()));
+}
+
+// parsing and moving every node into an arena, compare with `parse_program`
+#[bench]
+fn allocate_program(b: &mut Bencher) {
+  let src = synthetic_program(FUNS);
+
+  b.iter(|| black_box(Arena::new(*parse(&src).ast).len()));
+}
+
+#[bench]
+fn number_program(b: &mut Bencher) {
+  let mut ast = *parse(&synthetic_program(FUNS)).ast;
+
+  b.iter(|| {
+    let mut ids = NodeIds::new();
+
+    ids.assign_ast(&mut ast);
+    black_box(ids.len())
+  });
+}
+
+#[bench]
+fn look_up_side_table(b: &mut Bencher) {
+  let nodes = Arena::new(*parse(&synthetic_program(FUNS)).ast);
+  let mut tys = NodeMap::with_capacity(nodes.len());
+
+  for index in 0..nodes.len() {
+    let id = NodeId::new(index);
+
+    if nodes.expr(id).is_some() {
+      tys.insert(id, TyKind::NameRefTy("int".into()));
+    }
+  }
+
+  b.iter(|| {
+    (0..nodes.len())
+      .filter_map(|index| tys.get(NodeId::new(index)))
+      .count()
+  });
+}
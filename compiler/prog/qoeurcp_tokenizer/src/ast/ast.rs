@@ -4,16 +4,23 @@ pub use self::LitKind::*;
 pub use self::StmtKind::*;
 pub use self::TyKind::*;
 
+use super::node::NodeId;
+
 use crate::token::*;
 use crate::tree_builder::TreePrinter;
 
 use qoeurcp_span::Span;
 
+use std::convert::TryFrom;
 use std::fmt;
+use std::iter;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Ast {
   pub nodes: Vec<Box<Stmt>>,
+  // the doc comments of the module, written at the top of its file
+  #[serde(default)]
+  pub docs: Vec<String>,
 }
 
 impl Default for Ast {
@@ -30,7 +37,10 @@ impl TreePrinter for Ast {
 
 impl Ast {
   pub fn new(nodes: Vec<Box<Stmt>>) -> Ast {
-    Self { nodes }
+    Self {
+      nodes,
+      docs: vec![],
+    }
   }
 
   pub fn add(&mut self, node: Box<Stmt>) {
@@ -47,9 +57,147 @@ impl Ast {
 
     format!("{}", nodes)
   }
+
+  // the tests, mocks and benches declared at the top-level or in a suite, in
+  // the order of their declaration
+  pub fn test_items(&self) -> Vec<&Stmt> {
+    let mut items = vec![];
+    let mut stmts = self.nodes.iter().rev().collect::<Vec<_>>();
+
+    while let Some(stmt) = stmts.pop() {
+      match stmt.kind {
+        StmtKind::Bench(_) | StmtKind::Mock(_) | StmtKind::Test(_) => {
+          items.push(&**stmt)
+        }
+        StmtKind::Suite(ref suite) => {
+          stmts.extend(suite.block.stmts.iter().rev())
+        }
+        _ => {}
+      }
+    }
+
+    items
+  }
+
+  // the body-less functions of a foreign language e.g `ext fun puts: ...;`
+  pub fn foreign_funs(&self) -> Vec<&Fun> {
+    self
+      .nodes
+      .iter()
+      .filter_map(|node| match node.kind {
+        StmtKind::Fun(ref fun) if fun.is_foreign() => Some(&**fun),
+        _ => None,
+      })
+      .collect()
+  }
+
+  // the blocks of the target are inlined and the blocks of the other targets
+  // are dropped e.g `wasm = { fun f... }` is `fun f...` on wasm only
+  pub fn for_target(&self, target: Target) -> Ast {
+    Self {
+      nodes: stmts_for_target(&self.nodes, target),
+      docs: self.docs.to_vec(),
+    }
+  }
+
+  // the top-level items marked by an attribute e.g the `|> cfg: test.` items
+  pub fn items_with_attr(&self, name: &str) -> Vec<&Stmt> {
+    self
+      .nodes
+      .iter()
+      .filter(|node| node.has_attr(name))
+      .map(|node| &**node)
+      .collect()
+  }
+}
+
+fn stmts_for_target(stmts: &[Box<Stmt>], target: Target) -> Vec<Box<Stmt>> {
+  let mut selected = vec![];
+
+  for stmt in stmts.iter() {
+    if let StmtKind::TargetBlock {
+      target: ref block_target,
+      ref body,
+    } = stmt.kind
+    {
+      if *block_target == target {
+        selected.extend(stmts_for_target(&body.stmts, target));
+      }
+
+      continue;
+    }
+
+    let mut stmt = stmt.to_owned();
+
+    match stmt.kind {
+      StmtKind::Bench(ref mut fun)
+      | StmtKind::Fun(ref mut fun)
+      | StmtKind::Mock(ref mut fun)
+      | StmtKind::Test(ref mut fun) => {
+        if let Some(ref mut block) = fun.block {
+          block.stmts = stmts_for_target(&block.stmts, target);
+        }
+      }
+      StmtKind::Suite(ref mut suite) => {
+        suite.block.stmts = stmts_for_target(&suite.block.stmts, target);
+      }
+      _ => {}
+    }
+
+    selected.push(stmt);
+  }
+
+  selected
+}
+
+// an attribute is written before an item e.g `|> derive: clone, debug.`, its
+// arguments are names, paths or literals
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attribute {
+  #[serde(default)]
+  pub id: NodeId,
+  pub name: String,
+  pub args: Vec<Box<Expr>>,
+  #[serde(default)]
+  pub span: Span,
+}
+
+impl TreePrinter for Attribute {
+  fn print(&self, _level: usize) {
+    println!("{} (span: {})", self.text(), self.span);
+  }
+}
+
+impl Attribute {
+  pub fn new(name: &str, args: Vec<Box<Expr>>, span: Span) -> Attribute {
+    Self {
+      id: NodeId::DUMMY,
+      name: name.into(),
+      args,
+      span,
+    }
+  }
+
+  pub fn has_arg(&self, name: &str) -> bool {
+    self.args.iter().any(|arg| arg.text() == name)
+  }
+
+  pub fn text(&self) -> String {
+    let args = self
+      .args
+      .iter()
+      .map(|arg| arg.text())
+      .collect::<Vec<String>>()
+      .join(", ");
+
+    match args.is_empty() {
+      true => format!("|> {}.", self.name),
+      false => format!("|> {}: {}.", self.name, args),
+    }
+  }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum BinOpKind {
   Add,
   Sub,
@@ -61,12 +209,20 @@ pub enum BinOpKind {
   Le,
   Ge,
   Ne,
-  Eq,
+  EqEq,
+  And,
+  Or,
+  BitAnd,
+  BitOr,
+  Shl,
+  Shr,
 }
 
-impl BinOpKind {
-  pub fn from(token: &Token) -> BinOpKind {
-    match token.kind() {
+impl TryFrom<&Token> for BinOpKind {
+  type Error = String;
+
+  fn try_from(token: &Token) -> Result<BinOpKind, String> {
+    Ok(match token.kind() {
       TokenKind::Binary(BinaryKind::Add) => BinOpKind::Add,
       TokenKind::Binary(BinaryKind::Sub) => BinOpKind::Sub,
       TokenKind::Binary(BinaryKind::Mul) => BinOpKind::Mul,
@@ -76,9 +232,29 @@ impl BinOpKind {
       TokenKind::Binary(BinaryKind::Le) => BinOpKind::Le,
       TokenKind::Binary(BinaryKind::Gt) => BinOpKind::Gt,
       TokenKind::Binary(BinaryKind::Ge) => BinOpKind::Ge,
-      TokenKind::Binary(BinaryKind::Eq) => BinOpKind::Eq,
+      TokenKind::Binary(BinaryKind::EqEq) => BinOpKind::EqEq,
       TokenKind::Binary(BinaryKind::Ne) => BinOpKind::Ne,
-      tkn => unimplemented!("{} is not a operator", tkn),
+      TokenKind::Binary(BinaryKind::AndAnd) => BinOpKind::And,
+      TokenKind::Binary(BinaryKind::OrOr) => BinOpKind::Or,
+      TokenKind::Binary(BinaryKind::And) => BinOpKind::BitAnd,
+      TokenKind::Binary(BinaryKind::Or) => BinOpKind::BitOr,
+      TokenKind::Binary(BinaryKind::Shl) => BinOpKind::Shl,
+      TokenKind::Binary(BinaryKind::Shr) => BinOpKind::Shr,
+      tkn => return Err(format!("`{}` is not a binary operator", tkn.text())),
+    })
+  }
+}
+
+impl BinOpKind {
+  // the operator of a compound assignment e.g `+=`, none for `=`
+  pub fn from_assign_op(token: &Token) -> Option<BinOpKind> {
+    match token.kind() {
+      TokenKind::AssignOp(BinaryKind::Add) => Some(BinOpKind::Add),
+      TokenKind::AssignOp(BinaryKind::Sub) => Some(BinOpKind::Sub),
+      TokenKind::AssignOp(BinaryKind::Mul) => Some(BinOpKind::Mul),
+      TokenKind::AssignOp(BinaryKind::Div) => Some(BinOpKind::Div),
+      TokenKind::AssignOp(BinaryKind::Mod) => Some(BinOpKind::Mod),
+      _ => None,
     }
   }
 
@@ -93,15 +269,24 @@ impl BinOpKind {
       Self::Le => "<=",
       Self::Gt => ">",
       Self::Ge => ">=",
-      Self::Eq => "==",
+      Self::EqEq => "==",
       Self::Ne => "!=",
+      Self::And => "&&",
+      Self::Or => "||",
+      Self::BitAnd => "&",
+      Self::BitOr => "|",
+      Self::Shl => "<<",
+      Self::Shr => ">>",
     }
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Block {
+  #[serde(default)]
+  pub id: NodeId,
   pub stmts: Vec<Box<Stmt>>,
+  #[serde(default)]
   pub span: Span,
 }
 
@@ -114,6 +299,7 @@ impl fmt::Display for Block {
 impl From<Vec<Box<Stmt>>> for Block {
   fn from(stmts: Vec<Box<Stmt>>) -> Block {
     Self {
+      id: NodeId::DUMMY,
       stmts,
       span: Span::zero(),
     }
@@ -147,8 +333,11 @@ impl Block {
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Capsule {
+  #[serde(default)]
+  pub id: NodeId,
+  #[serde(default)]
   pub span: Span,
   pub stmt: Option<Stmt>,
   pub with_traits: Vec<String>,
@@ -156,6 +345,8 @@ pub struct Capsule {
   pub args: Vec<FunArg>,
   pub members: Vec<TraitMember>,
   pub visibility: bool,
+  #[serde(default)]
+  pub docs: Vec<String>,
 }
 
 impl TreePrinter for Capsule {
@@ -174,6 +365,7 @@ impl Capsule {
     members: Vec<TraitMember>,
   ) -> Capsule {
     Self {
+      id: NodeId::DUMMY,
       span,
       stmt,
       with_traits,
@@ -181,6 +373,7 @@ impl Capsule {
       args,
       members,
       visibility: false,
+      docs: vec![],
     }
   }
 
@@ -199,28 +392,140 @@ impl Capsule {
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Enum {
+  #[serde(default)]
+  pub id: NodeId,
+  #[serde(default)]
+  pub span: Span,
+  pub name: String,
+  pub variants: Vec<EnumVariant>,
+  pub visibility: bool,
+}
+
+impl TreePrinter for Enum {
+  fn print(&self, _level: usize) {
+    println!("{} (span: {})", self.text(), self.span);
+  }
+}
+
+impl Enum {
+  pub fn new(span: Span, name: &str, variants: Vec<EnumVariant>) -> Enum {
+    Self {
+      id: NodeId::DUMMY,
+      span,
+      name: name.into(),
+      variants,
+      visibility: false,
+    }
+  }
+
+  pub fn variant(&self, name: &str) -> Option<&EnumVariant> {
+    self.variants.iter().find(|variant| variant.name == name)
+  }
+
+  pub fn text(&self) -> String {
+    let variants = self
+      .variants
+      .iter()
+      .map(|variant| variant.text())
+      .collect::<Vec<String>>()
+      .join(", ");
+
+    format!("enum {} {{ {} }}", self.name, variants)
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EnumVariant {
+  #[serde(default)]
+  pub id: NodeId,
+  pub name: String,
+  pub kind: VariantKind,
+  pub discriminant: Option<Box<Expr>>,
+  #[serde(default)]
+  pub span: Span,
+}
+
+impl TreePrinter for EnumVariant {
+  fn print(&self, _level: usize) {
+    println!("{} (span: {})", self.text(), self.span);
+  }
+}
+
+impl EnumVariant {
+  pub fn new(
+    name: &str,
+    kind: VariantKind,
+    discriminant: Option<Box<Expr>>,
+    span: Span,
+  ) -> EnumVariant {
+    Self {
+      id: NodeId::DUMMY,
+      name: name.into(),
+      kind,
+      discriminant,
+      span,
+    }
+  }
+
+  pub fn text(&self) -> String {
+    let kind = self.kind.text();
+
+    match self.discriminant {
+      Some(ref discriminant) => {
+        format!("{}{} = {}", self.name, kind, discriminant.text())
+      }
+      None => format!("{}{}", self.name, kind),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ExprKind {
   Empty,
+  Error,
   Closure(Box<Fun>),
   Ident(String),
   Lit(LitKind),
   Loop(LoopKind),
+  LoopVar(String),
   Array {
     data: Vec<Box<Expr>>,
+    #[serde(default)]
     span: Span,
   },
+  ArrayRepeat {
+    value: Box<Expr>,
+    len: Box<Expr>,
+  },
+  Assign {
+    lhs: Box<Expr>,
+    rhs: Box<Expr>,
+  },
+  Await(Box<Expr>),
   BinOp {
     lhs: Box<Expr>,
     op: BinOpKind,
     rhs: Box<Expr>,
+    #[serde(default)]
     span: Span,
   },
   Call {
     callee: Box<Expr>,
     args: Vec<Box<Expr>>,
+    #[serde(default)]
     span: Span,
   },
+  Cast {
+    expr: Box<Expr>,
+    ty: Ty,
+  },
+  CompoundAssign {
+    lhs: Box<Expr>,
+    op: BinOpKind,
+    rhs: Box<Expr>,
+  },
   Hash {
     data: Vec<(Box<HashKind>, Box<Expr>)>,
   },
@@ -236,6 +541,28 @@ pub enum ExprKind {
     from: Box<Expr>,
     access: String,
   },
+  MethodCall {
+    receiver: Box<Expr>,
+    method: String,
+    args: Vec<Box<Expr>>,
+  },
+  Path(Box<Path>),
+  Range {
+    start: Option<Box<Expr>>,
+    end: Option<Box<Expr>>,
+    inclusive: bool,
+  },
+  StructLit {
+    name: Box<Expr>,
+    fields: Vec<(String, Box<Expr>)>,
+  },
+  // propagates nil to the caller e.g `foo?`
+  Try(Box<Expr>),
+  Tuple(Vec<Box<Expr>>),
+  TupleIndex {
+    from: Box<Expr>,
+    index: usize,
+  },
   UnOp {
     operand: UnOpKind,
     rhs: Box<Expr>,
@@ -272,15 +599,46 @@ impl ExprKind {
 
   pub fn text(&self) -> String {
     match *self {
+      Self::Await(ref expr) => format!("{}.await", expr.text()),
       Self::Ident(ref ident) => format!("{}", ident),
+      Self::Lit(ref lit) => format!("{}", lit.text()),
+      Self::LoopVar(ref name) => format!("${}", name),
+      Self::Path(ref path) => format!("{}", path.text()),
+      Self::Range {
+        ref start,
+        ref end,
+        inclusive,
+      } => {
+        let start = start.as_ref().map_or(String::new(), |start| start.text());
+        let end = end.as_ref().map_or(String::new(), |end| end.text());
+        let op = if inclusive { "..=" } else { ".." };
+
+        format!("{}{}{}", start, op, end)
+      }
+      Self::Try(ref expr) => format!("{}?", expr.text()),
+      Self::Tuple(ref exprs) if exprs.len() == 1 => {
+        format!("({},)", exprs[0].text())
+      }
+      Self::Tuple(ref exprs) => {
+        let exprs = exprs
+          .iter()
+          .map(|expr| expr.text())
+          .collect::<Vec<String>>()
+          .join(", ");
+
+        format!("({})", exprs)
+      }
       _ => format!(""),
     }
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Expr {
+  #[serde(default)]
+  pub id: NodeId,
   pub kind: ExprKind,
+  #[serde(default)]
   pub span: Span,
 }
 
@@ -292,11 +650,26 @@ impl TreePrinter for Expr {
 
 impl Expr {
   pub fn new(kind: ExprKind, span: Span) -> Expr {
-    Self { kind, span }
+    Self {
+      id: NodeId::DUMMY,
+      kind,
+      span,
+    }
+  }
+
+  pub fn kind(&self) -> &ExprKind {
+    &self.kind
   }
 
-  pub fn kind(&self) -> ExprKind {
-    self.kind.to_owned()
+  // a place can be the target of an assignment e.g `x`, `out[0]`, `v.x`
+  pub fn is_place(&self) -> bool {
+    match self.kind {
+      ExprKind::Ident(_)
+      | ExprKind::Index { .. }
+      | ExprKind::MemberAccess { .. }
+      | ExprKind::TupleIndex { .. } => true,
+      _ => false,
+    }
   }
 
   pub fn text(&self) -> String {
@@ -304,12 +677,17 @@ impl Expr {
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Field {
+  #[serde(default)]
+  pub id: NodeId,
   pub name: String,
   pub ty: Ty,
   pub expr: Option<Expr>,
+  #[serde(default)]
   pub span: Span,
+  #[serde(default)]
+  pub docs: Vec<String>,
 }
 
 impl TreePrinter for Field {
@@ -321,10 +699,12 @@ impl TreePrinter for Field {
 impl Field {
   pub fn new(name: &str, ty: Ty, expr: Option<Expr>, span: Span) -> Field {
     Self {
+      id: NodeId::DUMMY,
       name: name.into(),
       expr,
       ty,
       span,
+      docs: vec![],
     }
   }
 
@@ -333,13 +713,20 @@ impl Field {
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Fun {
+  #[serde(default)]
+  pub id: NodeId,
+  #[serde(default)]
   pub span: Span,
   pub name: String,
   pub args: Vec<Box<FunArg>>,
   pub ret_ty: Ty,
   pub block: Option<Block>,
+  pub modifiers: Vec<FunModifier>,
+  pub visibility: bool,
+  #[serde(default)]
+  pub docs: Vec<String>,
 }
 
 impl TreePrinter for Fun {
@@ -357,39 +744,85 @@ impl Fun {
     block: Box<Block>,
   ) -> Fun {
     Self {
+      id: NodeId::DUMMY,
       span,
       name: name.into(),
       args,
       ret_ty,
       block: Some(*block),
+      modifiers: vec![],
+      visibility: false,
+      docs: vec![],
     }
   }
 
+  // the calling convention of a foreign function, set by its modifier
+  pub fn abi(&self) -> Option<Abi> {
+    self.modifiers.iter().find_map(|modifier| modifier.abi())
+  }
+
+  pub fn has_modifier(&self, modifier: &FunModifier) -> bool {
+    self.modifiers.contains(modifier)
+  }
+
+  pub fn is_foreign(&self) -> bool {
+    self.abi().is_some()
+  }
+
   pub fn name(&self) -> String {
     self.name.to_owned()
   }
 
+  pub fn sig(&self) -> FunSig {
+    let args = self.args.iter().map(|arg| *arg.to_owned()).collect();
+
+    FunSig::new(
+      &self.name,
+      args,
+      self.ret_ty.to_owned(),
+      self.span.to_owned(),
+    )
+  }
+
   pub fn text(&self) -> String {
+    let modifiers = self
+      .modifiers
+      .iter()
+      .map(|modifier| format!("{} ", modifier.text()))
+      .collect::<String>();
+
     let args = self
       .args
       .iter()
       .map(|arg| arg.text())
       .collect::<Vec<String>>()
-      .join("\n");
-
-    let block = &self.block.as_ref().unwrap().text();
-
-    format!("fun {}: {} = ({}) {{ {} }}", self.name, "", args, block)
+      .join(", ");
+
+    match self.block {
+      Some(ref block) => format!(
+        "{}fun {}: {} = ({}) {{ {} }}",
+        modifiers,
+        self.name,
+        self.ret_ty.text(),
+        args,
+        block.text(),
+      ),
+      None => format!("{}{};", modifiers, self.sig().text()),
+    }
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct FunArg {
+  #[serde(default)]
+  pub id: NodeId,
   pub name: Option<String>,
   pub immutable: bool,
   pub expr: Box<Expr>,
+  #[serde(default)]
   pub span: Span,
   pub ty: Ty,
+  pub default: Option<Box<Expr>>,
 }
 
 impl TreePrinter for FunArg {
@@ -401,27 +834,86 @@ impl TreePrinter for FunArg {
 impl FunArg {
   pub fn new(name: Option<String>, expr: Box<Expr>) -> FunArg {
     Self {
+      id: NodeId::DUMMY,
       name,
       immutable: false,
       expr,
       span: Span::zero(),
       ty: Ty::unknown(),
+      default: None,
     }
   }
 
   pub fn text(&self) -> String {
-    let name = self.name.as_ref().unwrap();
     let ty = self.ty.text();
 
-    format!("{}: {}", name, ty)
+    let arg = match self.name {
+      Some(ref name) => format!("{}: {}", name, ty),
+      None => ty,
+    };
+
+    match self.default {
+      Some(ref default) => format!("{} = {}", arg, default.text()),
+      None => arg,
+    }
+  }
+}
+
+// `ext` declares a c function, `exp` a javascript function and `mod` a rust
+// function
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Abi {
+  C,
+  JavaScript,
+  Rust,
+}
+
+impl Abi {
+  pub fn text(&self) -> &'static str {
+    match self {
+      Self::C => "c",
+      Self::JavaScript => "javascript",
+      Self::Rust => "rust",
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FunModifier {
+  Async,
+  Exp,
+  Ext,
+  Mod,
+  Static,
+}
+
+impl FunModifier {
+  pub fn abi(&self) -> Option<Abi> {
+    match self {
+      Self::Exp => Some(Abi::JavaScript),
+      Self::Ext => Some(Abi::C),
+      Self::Mod => Some(Abi::Rust),
+      _ => None,
+    }
+  }
+
+  pub fn text(&self) -> &'static str {
+    match self {
+      Self::Async => "async",
+      Self::Exp => "exp",
+      Self::Ext => "ext",
+      Self::Mod => "mod",
+      Self::Static => "static",
+    }
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct FunSig {
   pub name: String,
   pub args: Vec<FunArg>,
   pub ret_ty: Ty,
+  #[serde(default)]
   pub span: Span,
   pub ty: Ty,
 }
@@ -433,21 +925,40 @@ impl TreePrinter for FunSig {
 }
 
 impl FunSig {
+  pub fn new(name: &str, args: Vec<FunArg>, ret_ty: Ty, span: Span) -> FunSig {
+    let ty = Ty::new(
+      FunTy {
+        param_tys: args.iter().map(|arg| arg.ty.kind().to_owned()).collect(),
+        ret_ty: box ret_ty.kind().to_owned(),
+      },
+      span.to_owned(),
+    );
+
+    Self {
+      name: name.into(),
+      args,
+      ret_ty,
+      span,
+      ty,
+    }
+  }
+
   pub fn text(&self) -> String {
     let args = self
       .args
       .iter()
       .map(|arg| arg.text())
       .collect::<Vec<String>>()
-      .join("\n");
+      .join(", ");
 
-    format!("fun {}: {} = ({})", self.name, self.ty.text(), args)
+    format!("fun {}: (-> {} | {})", self.name, self.ret_ty.text(), args)
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum HashKind {
   Bool(bool),
+  Ident(String),
   Int(i64),
   Str(String),
 }
@@ -460,11 +971,12 @@ impl fmt::Display for HashKind {
 
 impl From<Box<Expr>> for HashKind {
   fn from(expr: Box<Expr>) -> HashKind {
-    match expr.kind() {
+    match expr.kind {
+      ExprKind::Ident(name) => Self::Ident(name),
       Lit(LitKind::Bool(value)) => Self::Bool(value),
       Lit(LitKind::Int(value)) => Self::Int(value),
       Lit(LitKind::Str(value)) => Self::Str(value),
-      _ => unreachable!(), // TODO: handle an error instead
+      _ => unreachable!(), // the parser only accepts keys via `is_key`
     }
   }
 }
@@ -476,20 +988,33 @@ impl TreePrinter for HashKind {
 }
 
 impl HashKind {
+  // a key is a name or a bool, int or str literal e.g `.{ x = 0, "y" = 1 }`
+  pub fn is_key(expr: &Expr) -> bool {
+    match expr.kind {
+      ExprKind::Ident(_)
+      | Lit(LitKind::Bool(_))
+      | Lit(LitKind::Int(_))
+      | Lit(LitKind::Str(_)) => true,
+      _ => false,
+    }
+  }
+
   pub fn text(&self) -> String {
     match self {
       Self::Bool(value) => format!("{}", value),
+      Self::Ident(value) => format!("{}", value),
       Self::Int(value) => format!("{}", value),
       Self::Str(value) => format!("{}", value),
     }
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum LitKind {
   Bool(bool),
   Char(char),
   Int(i64),
+  Nil,
   Real(f64),
   Str(String),
 }
@@ -500,18 +1025,22 @@ impl LitKind {
       Self::Bool(ref b) => format!("{}", b),
       Self::Char(ref c) => format!("'{}'", c),
       Self::Int(ref int) => format!("{}", int),
+      Self::Nil => format!("nil"),
       Self::Real(ref real) => format!("{}", real),
       Self::Str(ref string) => format!("\"{}\"", string),
     }
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Local {
-  pub name: String,
+  #[serde(default)]
+  pub id: NodeId,
+  pub names: Vec<String>,
   pub immutable: bool,
-  pub ty: Ty,
+  pub ty: Option<Ty>,
   pub value: Box<Expr>,
+  #[serde(default)]
   pub span: Span,
 }
 
@@ -522,92 +1051,295 @@ impl TreePrinter for Local {
 }
 
 impl Local {
-  pub fn name(&self) -> String {
-    self.name.to_owned()
+  pub fn names(&self) -> Vec<String> {
+    self.names.to_vec()
+  }
+
+  // every name of the binding is initialized by the same value
+  // e.g `val x y z: int = 0;`
+  pub fn is_shared(&self) -> bool {
+    self.names.len() > 1
+  }
+
+  pub fn is_inferred(&self) -> bool {
+    self.ty.is_none()
   }
 
   pub fn text(&self) -> String {
-    let ty = self.ty.text();
+    let kw = if self.immutable { "val" } else { "mut" };
+    let names = self.names.join(" ");
     let value = self.value.text();
 
-    if self.immutable {
-      return format!("mut {}: {} = {}", self.name, ty, value);
+    match self.ty {
+      Some(ref ty) => {
+        format!("{} {}: {} = {}", kw, names, ty.text(), value)
+      }
+      None => format!("{} {} := {}", kw, names, value),
     }
-
-    format!("val {}: {} = {}", self.name, ty, value)
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum LoopKind {
   LoopFor {
     iterable: Box<Expr>,
     iterator: Box<Expr>,
+    // the iterator is not written by the user e.g `$it`, `$elmt`
+    implicit: bool,
     block: Box<Block>,
+    label: Option<String>,
   },
   LoopLoop {
     block: Box<Block>,
+    label: Option<String>,
   },
   LoopWhile {
     condition: Box<Expr>,
     block: Box<Block>,
+    label: Option<String>,
   },
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum StmtKind {
-  Empty,
-  Capsule(Box<Capsule>),
-  Expr(Box<Expr>),
-  Fun(Box<Fun>),
-  Mut(Box<Local>),
-  Ret(Option<Box<Expr>>),
-  Struct(Box<Struct>),
-  Use(Box<Use>),
-  Val(Box<Local>),
-  IfBlock {
-    conditions: Vec<(Box<Expr>, Box<Block>)>,
-    alternative: Option<Box<Block>>,
-  },
-}
+impl LoopKind {
+  pub fn label(&self) -> Option<String> {
+    match *self {
+      Self::LoopFor { ref label, .. }
+      | Self::LoopLoop { ref label, .. }
+      | Self::LoopWhile { ref label, .. } => label.to_owned(),
+    }
+  }
 
-impl StmtKind {
-  pub fn text(&self) -> String {
+  pub fn set_label(&mut self, name: &str) {
     match *self {
-      Self::Empty => format!("Empty"),
-      _ => format!(""),
+      Self::LoopFor { ref mut label, .. }
+      | Self::LoopLoop { ref mut label, .. }
+      | Self::LoopWhile { ref mut label, .. } => *label = Some(name.into()),
     }
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct Stmt {
-  pub kind: StmtKind,
+// a declarative macro is a list of rules, the first rule whose pattern
+// matches the arguments of an invocation is expanded e.g
+// `macro twice { ($x:expr) => { $x * 2 } }`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MacroDef {
+  #[serde(default)]
+  pub id: NodeId,
+  pub name: String,
+  pub rules: Vec<MacroRule>,
+  #[serde(default)]
   pub span: Span,
 }
 
-impl TreePrinter for Stmt {
+impl TreePrinter for MacroDef {
   fn print(&self, _level: usize) {
     println!("{} (span: {})", self.text(), self.span);
   }
 }
 
-impl Stmt {
-  pub fn new(kind: StmtKind, span: Span) -> Stmt {
-    Self { kind, span }
-  }
+impl MacroDef {
+  pub fn text(&self) -> String {
+    let rules = self
+      .rules
+      .iter()
+      .map(|rule| rule.text())
+      .collect::<Vec<String>>()
+      .join(" ");
 
-  pub fn kind(&self) -> StmtKind {
-    self.kind.to_owned()
+    format!("macro {} {{ {} }}", self.name, rules)
   }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MacroRule {
+  pub pattern: Vec<TokenTree>,
+  pub template: Vec<TokenTree>,
+}
 
+impl MacroRule {
   pub fn text(&self) -> String {
-    format!("{}", self.kind.text())
+    format!(
+      "({}) => {{ {} }}",
+      TokenTree::text_of(&self.pattern),
+      TokenTree::text_of(&self.template)
+    )
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum StructMember {
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Path {
+  #[serde(default)]
+  pub id: NodeId,
+  pub root: PathRoot,
+  pub segments: Vec<String>,
+  #[serde(default)]
+  pub span: Span,
+}
+
+impl TreePrinter for Path {
+  fn print(&self, _level: usize) {
+    println!("{} (span: {})", self.text(), self.span);
+  }
+}
+
+impl Path {
+  pub fn new(root: PathRoot, segments: Vec<String>, span: Span) -> Path {
+    Self {
+      id: NodeId::DUMMY,
+      root,
+      segments,
+      span,
+    }
+  }
+
+  pub fn text(&self) -> String {
+    let mut segments = vec![];
+
+    match self.root {
+      PathRoot::Relative => {}
+      PathRoot::Package(ref name) => segments.push(format!("@{}", name)),
+      PathRoot::Current => segments.push(format!("self")),
+      PathRoot::Super(depth) => {
+        segments.extend(iter::repeat(format!("super")).take(depth))
+      }
+    }
+
+    segments.extend(self.segments.iter().cloned());
+
+    format!("{}", segments.join("::"))
+  }
+}
+
+// the module from which a path is resolved e.g `@std::gl`, `self::add`,
+// `super::super::add`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PathRoot {
+  Relative,
+  Package(String),
+  Current,
+  Super(usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StmtKind {
+  Empty,
+  Error,
+  Bench(Box<Fun>),
+  Capsule(Box<Capsule>),
+  Continue(Option<String>),
+  Enum(Box<Enum>),
+  Expr(Box<Expr>),
+  Fun(Box<Fun>),
+  Macro(Box<MacroDef>),
+  Mock(Box<Fun>),
+  Mut(Box<Local>),
+  Ret(Option<Box<Expr>>),
+  Struct(Box<Struct>),
+  Suite(Box<Suite>),
+  Test(Box<Fun>),
+  TypeAlias(Box<TypeAlias>),
+  Use(Box<Use>),
+  Val(Box<Local>),
+  Break {
+    label: Option<String>,
+    value: Option<Box<Expr>>,
+  },
+  IfBlock {
+    conditions: Vec<(Box<Expr>, Box<Block>)>,
+    alternative: Option<Box<Block>>,
+  },
+  TargetBlock {
+    target: Target,
+    body: Block,
+  },
+}
+
+impl StmtKind {
+  pub fn text(&self) -> String {
+    match *self {
+      Self::Empty => format!("Empty"),
+      Self::Error => format!("Error"),
+      _ => format!(""),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stmt {
+  #[serde(default)]
+  pub id: NodeId,
+  pub kind: StmtKind,
+  #[serde(default)]
+  pub attrs: Vec<Attribute>,
+  #[serde(default)]
+  pub span: Span,
+}
+
+impl TreePrinter for Stmt {
+  fn print(&self, _level: usize) {
+    println!("{} (span: {})", self.text(), self.span);
+  }
+}
+
+impl Stmt {
+  pub fn new(kind: StmtKind, span: Span) -> Stmt {
+    Self {
+      id: NodeId::DUMMY,
+      kind,
+      attrs: vec![],
+      span,
+    }
+  }
+
+  pub fn attr(&self, name: &str) -> Option<&Attribute> {
+    self.attrs.iter().find(|attr| attr.name == name)
+  }
+
+  pub fn attrs_by_name(&self, name: &str) -> Vec<&Attribute> {
+    self.attrs.iter().filter(|attr| attr.name == name).collect()
+  }
+
+  // the doc comments of an item e.g `# adds two vec2` above `fun add`
+  pub fn docs(&self) -> Vec<String> {
+    match self.kind {
+      StmtKind::Bench(ref fun)
+      | StmtKind::Fun(ref fun)
+      | StmtKind::Mock(ref fun)
+      | StmtKind::Test(ref fun) => fun.docs.to_vec(),
+      StmtKind::Capsule(ref capsule) => capsule.docs.to_vec(),
+      StmtKind::Struct(ref strukt) => strukt.docs.to_vec(),
+      _ => vec![],
+    }
+  }
+
+  // the docs of a statement that can not be documented are dropped
+  pub fn set_docs(&mut self, docs: Vec<String>) {
+    match self.kind {
+      StmtKind::Bench(ref mut fun)
+      | StmtKind::Fun(ref mut fun)
+      | StmtKind::Mock(ref mut fun)
+      | StmtKind::Test(ref mut fun) => fun.docs = docs,
+      StmtKind::Capsule(ref mut capsule) => capsule.docs = docs,
+      StmtKind::Struct(ref mut strukt) => strukt.docs = docs,
+      _ => {}
+    }
+  }
+
+  pub fn has_attr(&self, name: &str) -> bool {
+    self.attr(name).is_some()
+  }
+
+  pub fn kind(&self) -> &StmtKind {
+    &self.kind
+  }
+
+  pub fn text(&self) -> String {
+    format!("{}", self.kind.text())
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StructMember {
   Field(Field),
   Method(Fun),
   StaticMethod(Fun),
@@ -625,14 +1357,21 @@ impl StructMember {
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Struct {
+  #[serde(default)]
+  pub id: NodeId,
+  #[serde(default)]
   pub span: Span,
   pub node: Option<Stmt>,
   pub parents: Vec<String>,
   pub name: String,
   pub param_tys: Vec<FunArg>,
   pub members: Vec<StructMember>,
+  #[serde(default)]
+  pub visibility: bool,
+  #[serde(default)]
+  pub docs: Vec<String>,
 }
 
 impl TreePrinter for Struct {
@@ -651,12 +1390,15 @@ impl Struct {
     members: Vec<StructMember>,
   ) -> Struct {
     Self {
+      id: NodeId::DUMMY,
       span,
       node,
       parents,
       name: name.into(),
       param_tys,
       members,
+      visibility: false,
+      docs: vec![],
     }
   }
 
@@ -672,7 +1414,7 @@ impl Struct {
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum TraitMember {
   Field(Field),
   Method(Fun),
@@ -700,7 +1442,7 @@ impl TraitMember {
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum UnOpKind {
   Deref,
   Not,
@@ -735,72 +1477,343 @@ impl UnOpKind {
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+// the arguments of a macro are kept as tokens grouped by their delimiters
+// e.g `(a, [b; 2])`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TokenTree {
+  Token(Token),
+  Delimited {
+    open: Token,
+    trees: Vec<TokenTree>,
+    close: Token,
+  },
+}
+
+impl TokenTree {
+  pub fn text_of(trees: &[TokenTree]) -> String {
+    trees
+      .iter()
+      .map(|tree| tree.text())
+      .collect::<Vec<String>>()
+      .join(" ")
+  }
+
+  pub fn text(&self) -> String {
+    match *self {
+      Self::Token(ref token) => token.text(),
+      Self::Delimited {
+        ref open,
+        ref trees,
+        ref close,
+      } => format!("{}{}{}", open.text(), Self::text_of(trees), close.text()),
+    }
+  }
+
+  // the tokens of the trees in the source order
+  pub fn flatten(trees: Vec<TokenTree>) -> Vec<Token> {
+    let mut tokens = vec![];
+
+    for tree in trees {
+      match tree {
+        Self::Token(token) => tokens.push(token),
+        Self::Delimited { open, trees, close } => {
+          tokens.push(open);
+          tokens.extend(Self::flatten(trees));
+          tokens.push(close);
+        }
+      }
+    }
+
+    tokens
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TypeAlias {
+  #[serde(default)]
+  pub id: NodeId,
+  pub name: String,
+  pub ty: Ty,
+  pub visibility: bool,
+  #[serde(default)]
+  pub span: Span,
+}
+
+impl TreePrinter for TypeAlias {
+  fn print(&self, _level: usize) {
+    println!("{} (span: {})", self.text(), self.span);
+  }
+}
+
+impl TypeAlias {
+  pub fn new(name: &str, ty: Ty, span: Span) -> TypeAlias {
+    Self {
+      id: NodeId::DUMMY,
+      name: name.into(),
+      ty,
+      visibility: false,
+      span,
+    }
+  }
+
+  pub fn text(&self) -> String {
+    format!("type {} = {};", self.name, self.ty.text())
+  }
+}
+
+// a suite groups the tests, mocks and benches of a unit e.g `suite { }`,
+// `suite lexer { }`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Suite {
+  #[serde(default)]
+  pub id: NodeId,
+  pub name: Option<String>,
+  pub block: Block,
+  #[serde(default)]
+  pub span: Span,
+}
+
+impl TreePrinter for Suite {
+  fn print(&self, _level: usize) {
+    println!("{} (span: {})", self.text(), self.span);
+  }
+}
+
+impl Suite {
+  pub fn text(&self) -> String {
+    match self.name {
+      Some(ref name) => format!("suite {}", name),
+      None => format!("suite"),
+    }
+  }
+}
+
+// the platform a program is compiled for, the code of a `wasm = { }` block
+// is only compiled for the web assembly target
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Target {
+  Native,
+  Wasm,
+}
+
+impl Target {
+  pub fn from(name: &str) -> Option<Target> {
+    match name {
+      "native" => Some(Self::Native),
+      "wasm" => Some(Self::Wasm),
+      _ => None,
+    }
+  }
+
+  pub fn text(&self) -> &'static str {
+    match self {
+      Self::Native => "native",
+      Self::Wasm => "wasm",
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Use {
+  #[serde(default)]
+  pub id: NodeId,
   pub name: String,
   pub stmts: Vec<Box<Stmt>>,
+  #[serde(default)]
   pub span: Span,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum VariantKind {
+  Unit,
+  Tuple(Vec<Ty>),
+  Struct(Vec<Field>),
+}
+
+impl VariantKind {
+  pub fn text(&self) -> String {
+    match *self {
+      Self::Unit => format!(""),
+      Self::Tuple(ref tys) => {
+        let tys = tys
+          .iter()
+          .map(|ty| ty.text())
+          .collect::<Vec<String>>()
+          .join(", ");
+
+        format!("({})", tys)
+      }
+      Self::Struct(ref fields) => {
+        let fields = fields
+          .iter()
+          .map(|field| format!(".{}: {}", field.name, field.ty.text()))
+          .collect::<Vec<String>>()
+          .join(", ");
+
+        format!(" {{ {} }}", fields)
+      }
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum TyKind {
   Unknown,
+  Void,
   NameRefTy(String),
+  FunTy {
+    param_tys: Vec<TyKind>,
+    ret_ty: Box<TyKind>,
+  },
   GenericTy {
     name: String,
     param_tys: Vec<TyKind>,
   },
+  PathTy(Vec<String>),
+  ArrayTy {
+    ty: Box<TyKind>,
+    len: Box<Expr>,
+  },
+  SliceTy(Box<TyKind>),
+  TupleTy(Vec<TyKind>),
+  OptionalTy(Box<TyKind>),
+  RefTy(Box<TyKind>),
 }
 
 impl TyKind {
   pub fn text(&self) -> String {
     match *self {
       Self::NameRefTy(ref name) => format!("{}", name),
-      Self::GenericTy { ref name, .. } => format!("{}", name),
+      Self::FunTy {
+        ref param_tys,
+        ref ret_ty,
+      } => {
+        let param_tys = param_tys
+          .iter()
+          .map(|param_ty| param_ty.text())
+          .collect::<Vec<String>>()
+          .join(", ");
+
+        format!("({} -> {})", param_tys, ret_ty.text())
+      }
+      Self::GenericTy {
+        ref name,
+        ref param_tys,
+      } => {
+        let param_tys = param_tys
+          .iter()
+          .map(|param_ty| param_ty.text())
+          .collect::<Vec<String>>()
+          .join(", ");
+
+        format!("{}<{}>", name, param_tys)
+      }
+      Self::PathTy(ref segments) => format!("{}", segments.join("::")),
+      Self::ArrayTy { ref ty, ref len } => {
+        format!("[{}; {}]", ty.text(), len.text())
+      }
+      Self::SliceTy(ref ty) => format!("[{}]", ty.text()),
+      Self::TupleTy(ref tys) if tys.len() == 1 => {
+        format!("({},)", tys[0].text())
+      }
+      Self::TupleTy(ref tys) => {
+        let tys = tys
+          .iter()
+          .map(|ty| ty.text())
+          .collect::<Vec<String>>()
+          .join(", ");
+
+        format!("({})", tys)
+      }
+      Self::OptionalTy(ref ty) => format!("?{}", ty.text()),
+      Self::RefTy(ref ty) => format!("ref {}", ty.text()),
       Self::Unknown => format!("Unknown"),
+      Self::Void => format!("void"),
     }
   }
 
   pub fn generics(&self) -> Vec<TyKind> {
     match *self {
-      Self::NameRefTy(_) | Self::Unknown => vec![],
       Self::GenericTy { ref param_tys, .. } => param_tys.to_vec(),
+      _ => vec![],
     }
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Ty {
+  #[serde(default)]
+  id: NodeId,
   kind: Box<TyKind>,
+  #[serde(default)]
   span: Span,
 }
 
 impl From<Box<Expr>> for Ty {
-  fn from(ident: Box<Expr>) -> Ty {
-    match ident.text() {
-      _ => *make_unknown_ty(TyKind::Unknown),
-    }
+  fn from(expr: Box<Expr>) -> Ty {
+    let kind = match expr.kind {
+      ExprKind::Ident(ref name) if name == "void" => TyKind::Void,
+      ExprKind::Ident(ref name) => TyKind::NameRefTy(name.into()),
+      ExprKind::Path(ref path) => TyKind::PathTy(path.segments.to_vec()),
+      _ => TyKind::Unknown,
+    };
+
+    Ty::new(kind, expr.span)
   }
 }
 
 impl Ty {
   pub fn new(kind: TyKind, span: Span) -> Ty {
     Self {
+      id: NodeId::DUMMY,
       kind: box kind,
       span,
     }
   }
 
+  pub fn id(&self) -> NodeId {
+    self.id
+  }
+
+  pub(crate) fn set_id(&mut self, id: NodeId) {
+    self.id = id;
+  }
+
+  pub fn kind(&self) -> &TyKind {
+    &self.kind
+  }
+
+  pub fn kind_mut(&mut self) -> &mut TyKind {
+    &mut self.kind
+  }
+
+  pub fn into_kind(self) -> TyKind {
+    *self.kind
+  }
+
+  pub fn span(&self) -> Span {
+    self.span.to_owned()
+  }
+
   pub fn text(&self) -> String {
     format!("{}", self.kind.text())
   }
 
   pub fn unknown() -> Ty {
     Self {
+      id: NodeId::DUMMY,
       kind: box TyKind::Unknown,
       span: Span::zero(),
     }
   }
+
+  pub fn void() -> Ty {
+    Self {
+      id: NodeId::DUMMY,
+      kind: box TyKind::Void,
+      span: Span::zero(),
+    }
+  }
 }
 
 pub fn make_array_expr(data: Vec<Box<Expr>>) -> Box<Expr> {
@@ -813,6 +1826,18 @@ pub fn make_array_expr(data: Vec<Box<Expr>>) -> Box<Expr> {
   )
 }
 
+pub fn make_array_repeat_expr(value: Box<Expr>, len: Box<Expr>) -> Box<Expr> {
+  box Expr::new(ExprKind::ArrayRepeat { value, len }, Span::zero())
+}
+
+pub fn make_assign_expr(lhs: Box<Expr>, rhs: Box<Expr>) -> Box<Expr> {
+  box Expr::new(ExprKind::Assign { lhs, rhs }, Span::zero())
+}
+
+pub fn make_await_expr(expr: Box<Expr>) -> Box<Expr> {
+  box Expr::new(ExprKind::Await(expr), Span::zero())
+}
+
 pub fn make_binop_expr(
   lhs: Box<Expr>,
   op: BinOpKind,
@@ -848,6 +1873,39 @@ pub fn make_call_expr(callee: Box<Expr>, args: Vec<Box<Expr>>) -> Box<Expr> {
   )
 }
 
+pub fn make_cast_expr(expr: Box<Expr>, ty: Box<Ty>) -> Box<Expr> {
+  box Expr::new(ExprKind::Cast { expr, ty: *ty }, Span::zero())
+}
+
+pub fn make_compound_assign_expr(
+  lhs: Box<Expr>,
+  op: BinOpKind,
+  rhs: Box<Expr>,
+) -> Box<Expr> {
+  box Expr::new(ExprKind::CompoundAssign { lhs, op, rhs }, Span::zero())
+}
+
+pub fn make_closure_expr(
+  args: Vec<Box<FunArg>>,
+  block: Box<Block>,
+) -> Box<Expr> {
+  box Expr::new(
+    ExprKind::Closure(box Fun::new(
+      Span::zero(),
+      "",
+      args,
+      Ty::unknown(),
+      block,
+    )),
+    Span::zero(),
+  )
+}
+
+// an error node stands for an expression that could not be parsed
+pub fn make_error_expr(span: Span) -> Box<Expr> {
+  box Expr::new(ExprKind::Error, span)
+}
+
 pub fn make_hash_expr(data: Vec<(Box<HashKind>, Box<Expr>)>) -> Box<Expr> {
   box Expr::new(ExprKind::Hash { data }, Span::zero())
 }
@@ -878,6 +1936,20 @@ pub fn make_ident_expr(id: &str) -> Box<Expr> {
 //   )
 // }
 
+// the branches of an `if` statement e.g `if a { } else if b { } else { }`
+pub fn make_if_block_stmt(
+  conditions: Vec<(Box<Expr>, Box<Block>)>,
+  alternative: Option<Box<Block>>,
+) -> Box<Stmt> {
+  box Stmt::new(
+    StmtKind::IfBlock {
+      conditions,
+      alternative,
+    },
+    Span::zero(),
+  )
+}
+
 pub fn make_index_expr(data: Box<Expr>, index: Box<Expr>) -> Box<Expr> {
   box Expr::new(ExprKind::Index { data, index }, Span::zero())
 }
@@ -886,6 +1958,10 @@ pub fn make_lit_int_expr(int: i64) -> Box<Expr> {
   box Expr::new(ExprKind::Lit(LitKind::Int(int)), Span::zero())
 }
 
+pub fn make_lit_nil_expr() -> Box<Expr> {
+  box Expr::new(ExprKind::Lit(LitKind::Nil), Span::zero())
+}
+
 pub fn make_lit_real_expr(real: f64) -> Box<Expr> {
   box Expr::new(ExprKind::Lit(LitKind::Real(real)), Span::zero())
 }
@@ -897,20 +1973,30 @@ pub fn make_lit_str_expr(expr: String) -> Box<Expr> {
 pub fn make_loop_for_expr(
   iterable: Box<Expr>,
   iterator: Box<Expr>,
+  implicit: bool,
   block: Box<Block>,
 ) -> Box<Expr> {
   box Expr::new(
     ExprKind::Loop(LoopKind::LoopFor {
       iterable,
       iterator,
+      implicit,
       block,
+      label: None,
     }),
     Span::zero(),
   )
 }
 
 pub fn make_loop_loop_expr(block: Box<Block>) -> Box<Expr> {
-  box Expr::new(ExprKind::Loop(LoopKind::LoopLoop { block }), Span::zero())
+  box Expr::new(
+    ExprKind::Loop(LoopKind::LoopLoop { block, label: None }),
+    Span::zero(),
+  )
+}
+
+pub fn make_loop_var_expr(name: &str) -> Box<Expr> {
+  box Expr::new(ExprKind::LoopVar(name.into()), Span::zero())
 }
 
 pub fn make_loop_while_expr(
@@ -918,7 +2004,11 @@ pub fn make_loop_while_expr(
   block: Box<Block>,
 ) -> Box<Expr> {
   box Expr::new(
-    ExprKind::Loop(LoopKind::LoopWhile { condition, block }),
+    ExprKind::Loop(LoopKind::LoopWhile {
+      condition,
+      block,
+      label: None,
+    }),
     Span::zero(),
   )
 }
@@ -927,6 +2017,35 @@ pub fn make_expr(kind: ExprKind) -> Box<Expr> {
   box Expr::new(kind, Span::zero())
 }
 
+pub fn make_range_expr(
+  start: Option<Box<Expr>>,
+  end: Option<Box<Expr>>,
+  inclusive: bool,
+) -> Box<Expr> {
+  box Expr::new(
+    ExprKind::Range {
+      start,
+      end,
+      inclusive,
+    },
+    Span::zero(),
+  )
+}
+
+pub fn make_path_expr(root: PathRoot, segments: Vec<String>) -> Box<Expr> {
+  box Expr::new(
+    ExprKind::Path(box Path::new(root, segments, Span::zero())),
+    Span::zero(),
+  )
+}
+
+pub fn make_struct_lit_expr(
+  name: Box<Expr>,
+  fields: Vec<(String, Box<Expr>)>,
+) -> Box<Expr> {
+  box Expr::new(ExprKind::StructLit { name, fields }, Span::zero())
+}
+
 pub fn make_member_access_expr(from: Expr, access: &str) -> Box<Expr> {
   box Expr::new(
     MemberAccess {
@@ -937,6 +2056,33 @@ pub fn make_member_access_expr(from: Expr, access: &str) -> Box<Expr> {
   )
 }
 
+pub fn make_method_call_expr(
+  receiver: Box<Expr>,
+  method: &str,
+  args: Vec<Box<Expr>>,
+) -> Box<Expr> {
+  box Expr::new(
+    ExprKind::MethodCall {
+      receiver,
+      method: method.into(),
+      args,
+    },
+    Span::zero(),
+  )
+}
+
+pub fn make_try_expr(expr: Box<Expr>) -> Box<Expr> {
+  box Expr::new(ExprKind::Try(expr), Span::zero())
+}
+
+pub fn make_tuple_expr(exprs: Vec<Box<Expr>>) -> Box<Expr> {
+  box Expr::new(ExprKind::Tuple(exprs), Span::zero())
+}
+
+pub fn make_tuple_index_expr(from: Box<Expr>, index: usize) -> Box<Expr> {
+  box Expr::new(ExprKind::TupleIndex { from, index }, Span::zero())
+}
+
 pub fn make_unop_expr(operand: &str, rhs: Box<Expr>) -> Box<Expr> {
   box Expr::new(
     ExprKind::UnOp {
@@ -947,79 +2093,259 @@ pub fn make_unop_expr(operand: &str, rhs: Box<Expr>) -> Box<Expr> {
   )
 }
 
+// an error node stands for a statement that could not be parsed
+pub fn make_error_stmt(span: Span) -> Box<Stmt> {
+  box Stmt::new(StmtKind::Error, span)
+}
+
 pub fn make_expr_stmt(expr: Box<Expr>) -> Box<Stmt> {
   box Stmt::new(StmtKind::Expr(expr), Span::zero())
 }
 
+pub fn make_break_stmt(
+  label: Option<String>,
+  value: Option<Box<Expr>>,
+) -> Box<Stmt> {
+  box Stmt::new(StmtKind::Break { label, value }, Span::zero())
+}
+
+pub fn make_capsule_stmt(
+  name: Box<Expr>,
+  members: Vec<TraitMember>,
+) -> Box<Stmt> {
+  let capsule =
+    Capsule::new(Span::zero(), None, vec![], &name.text(), vec![], members);
+
+  box Stmt::new(StmtKind::Capsule(box capsule), Span::zero())
+}
+
+pub fn make_continue_stmt(label: Option<String>) -> Box<Stmt> {
+  box Stmt::new(StmtKind::Continue(label), Span::zero())
+}
+
+pub fn make_enum_stmt(
+  name: Box<Expr>,
+  variants: Vec<EnumVariant>,
+) -> Box<Stmt> {
+  box Stmt::new(
+    StmtKind::Enum(box Enum::new(Span::zero(), &name.text(), variants)),
+    Span::zero(),
+  )
+}
+
+pub fn make_enum_variant(
+  name: Box<Expr>,
+  kind: VariantKind,
+  discriminant: Option<Box<Expr>>,
+) -> EnumVariant {
+  EnumVariant::new(&name.text(), kind, discriminant, Span::zero())
+}
+
+pub fn make_field(name: Box<Expr>, ty: Box<Ty>) -> Field {
+  Field::new(&name.text(), *ty, None, Span::zero())
+}
+
+// the types and the default values missing from the arguments are taken
+// from the signature e.g `fun mul: (x: int, y: int) = (x, y) { x * y }`
 pub fn make_fun_stmt(
   name: Box<Expr>,
+  modifiers: Vec<FunModifier>,
+  sig: Box<FunSig>,
   args: Vec<Box<FunArg>>,
-  ret_ty: Box<Expr>,
-  block: Box<Block>,
+  block: Option<Box<Block>>,
 ) -> Box<Stmt> {
+  let args = args
+    .into_iter()
+    .enumerate()
+    .map(|(index, mut arg)| {
+      if let Some(sig_arg) = sig.args.get(index) {
+        if *arg.ty.kind() == TyKind::Unknown {
+          arg.ty = sig_arg.ty.to_owned();
+        }
+
+        if arg.default.is_none() {
+          arg.default = sig_arg.default.to_owned();
+        }
+      }
+
+      arg
+    })
+    .collect();
+
   box Stmt::new(
     StmtKind::Fun(box Fun {
+      id: NodeId::DUMMY,
       name: name.text(),
       args,
-      block: Some(*block),
-      ret_ty: Ty::from(ret_ty),
+      block: block.map(|block| *block),
+      ret_ty: sig.ret_ty,
+      modifiers,
+      visibility: false,
       span: Span::zero(),
+      docs: vec![],
     }),
     Span::zero(),
   )
 }
 
-pub fn make_fun_arg(expr: Box<Expr>, ty: Box<Expr>) -> Box<FunArg> {
+pub fn make_fun_sig(args: Vec<FunArg>, ret_ty: Box<Ty>) -> Box<FunSig> {
+  box FunSig::new("", args, *ret_ty, Span::zero())
+}
+
+pub fn make_fun_sig_arg(
+  name: Option<String>,
+  ty: Box<Ty>,
+  default: Option<Box<Expr>>,
+) -> FunArg {
+  let expr = match name {
+    Some(ref name) => make_ident_expr(name),
+    None => make_expr(ExprKind::Empty),
+  };
+
+  FunArg {
+    default,
+    ty: *ty,
+    ..FunArg::new(name, expr)
+  }
+}
+
+pub fn make_fun_arg(expr: Box<Expr>, ty: Box<Ty>) -> Box<FunArg> {
   box FunArg {
+    id: NodeId::DUMMY,
     immutable: true,
     expr: expr.to_owned(),
     name: Some(expr.text()),
-    ty: Ty::from(ty),
+    ty: *ty,
     span: Span::zero(),
+    default: None,
   }
 }
 
 pub fn make_mut_stmt(
-  name: Box<Expr>,
-  ty: Box<Expr>,
+  names: Vec<Box<Expr>>,
+  ty: Option<Box<Ty>>,
   value: Box<Expr>,
 ) -> Box<Stmt> {
   box Stmt::new(
     StmtKind::Mut(box Local {
+      id: NodeId::DUMMY,
       immutable: false,
-      name: name.text(),
+      names: names.iter().map(|name| name.text()).collect(),
       value,
-      ty: Ty::from(ty),
+      ty: ty.map(|ty| *ty),
       span: Span::zero(),
     }),
     Span::zero(),
   )
 }
 
-pub fn make_ret_stmt(expr: Box<Expr>) -> Box<Stmt> {
-  box Stmt::new(StmtKind::Ret(Some(expr)), Span::zero())
+pub fn make_ret_stmt(expr: Option<Box<Expr>>) -> Box<Stmt> {
+  box Stmt::new(StmtKind::Ret(expr), Span::zero())
 }
 
 pub fn make_val_stmt(
-  name: Box<Expr>,
-  ty: Box<Expr>,
+  names: Vec<Box<Expr>>,
+  ty: Option<Box<Ty>>,
   value: Box<Expr>,
 ) -> Box<Stmt> {
   box Stmt::new(
     StmtKind::Val(box Local {
+      id: NodeId::DUMMY,
       immutable: true,
-      name: name.text(),
+      names: names.iter().map(|name| name.text()).collect(),
       value,
-      ty: Ty::from(ty),
+      ty: ty.map(|ty| *ty),
+      span: Span::zero(),
+    }),
+    Span::zero(),
+  )
+}
+
+pub fn make_type_alias_stmt(name: Box<Expr>, ty: Box<Ty>) -> Box<Stmt> {
+  box Stmt::new(
+    StmtKind::TypeAlias(box TypeAlias::new(&name.text(), *ty, Span::zero())),
+    Span::zero(),
+  )
+}
+
+pub fn make_bench_stmt(
+  name: &str,
+  args: Vec<Box<FunArg>>,
+  block: Box<Block>,
+) -> Box<Stmt> {
+  let fun = Fun::new(Span::zero(), name, args, Ty::void(), block);
+
+  box Stmt::new(StmtKind::Bench(box fun), Span::zero())
+}
+
+pub fn make_macro_stmt(name: &str, rules: Vec<MacroRule>) -> Box<Stmt> {
+  box Stmt::new(
+    StmtKind::Macro(box MacroDef {
+      id: NodeId::DUMMY,
+      name: name.into(),
+      rules,
+      span: Span::zero(),
+    }),
+    Span::zero(),
+  )
+}
+
+pub fn make_mock_stmt(
+  name: &str,
+  args: Vec<Box<FunArg>>,
+  block: Box<Block>,
+) -> Box<Stmt> {
+  let fun = Fun::new(Span::zero(), name, args, Ty::void(), block);
+
+  box Stmt::new(StmtKind::Mock(box fun), Span::zero())
+}
+
+pub fn make_target_block_stmt(target: Target, body: Box<Block>) -> Box<Stmt> {
+  box Stmt::new(
+    StmtKind::TargetBlock {
+      target,
+      body: *body,
+    },
+    Span::zero(),
+  )
+}
+
+pub fn make_struct_stmt(
+  name: Box<Expr>,
+  members: Vec<StructMember>,
+) -> Box<Stmt> {
+  let structure =
+    Struct::new(Span::zero(), None, vec![], &name.text(), vec![], members);
+
+  box Stmt::new(StmtKind::Struct(box structure), Span::zero())
+}
+
+pub fn make_suite_stmt(name: Option<String>, block: Box<Block>) -> Box<Stmt> {
+  box Stmt::new(
+    StmtKind::Suite(box Suite {
+      id: NodeId::DUMMY,
+      name,
+      block: *block,
       span: Span::zero(),
     }),
     Span::zero(),
   )
 }
 
+pub fn make_test_stmt(
+  name: &str,
+  args: Vec<Box<FunArg>>,
+  block: Box<Block>,
+) -> Box<Stmt> {
+  let fun = Fun::new(Span::zero(), name, args, Ty::void(), block);
+
+  box Stmt::new(StmtKind::Test(box fun), Span::zero())
+}
+
 pub fn make_use_stmt(name: &str, ast: Box<Ast>) -> Box<Stmt> {
   box Stmt::new(
     StmtKind::Use(box self::Use {
+      id: NodeId::DUMMY,
       name: name.into(),
       stmts: ast.nodes,
       span: Span::zero(),
@@ -1032,16 +2358,50 @@ pub fn make_name_ref_ty(name: &str) -> Box<Ty> {
   box Ty::new(NameRefTy(name.into()), Span::zero())
 }
 
-pub fn make_generics_ty() -> Box<Ty> {
+pub fn make_fun_ty(param_tys: Vec<TyKind>, ret_ty: TyKind) -> Box<Ty> {
+  box Ty::new(
+    FunTy {
+      param_tys,
+      ret_ty: box ret_ty,
+    },
+    Span::zero(),
+  )
+}
+
+pub fn make_generics_ty(name: &str, param_tys: Vec<TyKind>) -> Box<Ty> {
   box Ty::new(
     GenericTy {
-      name: "".into(),
-      param_tys: vec![],
+      name: name.into(),
+      param_tys,
     },
     Span::zero(),
   )
 }
 
+pub fn make_path_ty(segments: Vec<String>) -> Box<Ty> {
+  box Ty::new(PathTy(segments), Span::zero())
+}
+
+pub fn make_array_ty(ty: TyKind, len: Box<Expr>) -> Box<Ty> {
+  box Ty::new(ArrayTy { ty: box ty, len }, Span::zero())
+}
+
+pub fn make_slice_ty(ty: TyKind) -> Box<Ty> {
+  box Ty::new(SliceTy(box ty), Span::zero())
+}
+
+pub fn make_tuple_ty(tys: Vec<TyKind>) -> Box<Ty> {
+  box Ty::new(TupleTy(tys), Span::zero())
+}
+
+pub fn make_optional_ty(ty: TyKind) -> Box<Ty> {
+  box Ty::new(OptionalTy(box ty), Span::zero())
+}
+
+pub fn make_ref_ty(ty: TyKind) -> Box<Ty> {
+  box Ty::new(RefTy(box ty), Span::zero())
+}
+
 pub fn make_unknown_ty(kind: TyKind) -> Box<Ty> {
   box Ty::new(kind, Span::zero())
 }
@@ -0,0 +1,549 @@
+use super::ast::*;
+
+// rebuilds the tree by value, a pass overrides the `fold_*` methods of the
+// nodes it rewrites and calls the matching `walk_*` function to fold their
+// children e.g desugaring `x += 1` into `x = x + 1`
+//
+// ```
+// impl Fold for Desugar {
+//   fn fold_expr(&mut self, expr: Expr) -> Expr {
+//     match walk_expr(self, expr) {
+//       Expr { kind: ExprKind::CompoundAssign { lhs, op, rhs }, span } => ...
+//       expr => expr,
+//     }
+//   }
+// }
+// ```
+pub trait Fold: Sized {
+  fn fold_ast(&mut self, ast: Ast) -> Ast {
+    walk_ast(self, ast)
+  }
+
+  fn fold_attr(&mut self, attr: Attribute) -> Attribute {
+    walk_attr(self, attr)
+  }
+
+  fn fold_block(&mut self, block: Block) -> Block {
+    walk_block(self, block)
+  }
+
+  fn fold_capsule(&mut self, capsule: Capsule) -> Capsule {
+    walk_capsule(self, capsule)
+  }
+
+  fn fold_enum(&mut self, enumeration: Enum) -> Enum {
+    walk_enum(self, enumeration)
+  }
+
+  fn fold_enum_variant(&mut self, variant: EnumVariant) -> EnumVariant {
+    walk_enum_variant(self, variant)
+  }
+
+  fn fold_expr(&mut self, expr: Expr) -> Expr {
+    walk_expr(self, expr)
+  }
+
+  fn fold_field(&mut self, field: Field) -> Field {
+    walk_field(self, field)
+  }
+
+  fn fold_fun(&mut self, fun: Fun) -> Fun {
+    walk_fun(self, fun)
+  }
+
+  fn fold_fun_arg(&mut self, arg: FunArg) -> FunArg {
+    walk_fun_arg(self, arg)
+  }
+
+  fn fold_lit(&mut self, lit: LitKind) -> LitKind {
+    lit
+  }
+
+  fn fold_local(&mut self, local: Local) -> Local {
+    walk_local(self, local)
+  }
+
+  fn fold_loop(&mut self, kind: LoopKind) -> LoopKind {
+    walk_loop(self, kind)
+  }
+
+  fn fold_macro_def(&mut self, def: MacroDef) -> MacroDef {
+    def
+  }
+
+  fn fold_path(&mut self, path: Path) -> Path {
+    path
+  }
+
+  fn fold_stmt(&mut self, stmt: Stmt) -> Stmt {
+    walk_stmt(self, stmt)
+  }
+
+  fn fold_struct(&mut self, structure: Struct) -> Struct {
+    walk_struct(self, structure)
+  }
+
+  fn fold_suite(&mut self, suite: Suite) -> Suite {
+    walk_suite(self, suite)
+  }
+
+  fn fold_ty(&mut self, ty: Ty) -> Ty {
+    walk_ty(self, ty)
+  }
+
+  fn fold_ty_kind(&mut self, kind: TyKind) -> TyKind {
+    walk_ty_kind(self, kind)
+  }
+
+  fn fold_type_alias(&mut self, alias: TypeAlias) -> TypeAlias {
+    walk_type_alias(self, alias)
+  }
+
+  fn fold_use(&mut self, import: Use) -> Use {
+    walk_use(self, import)
+  }
+}
+
+pub fn walk_ast<F: Fold>(folder: &mut F, ast: Ast) -> Ast {
+  Ast {
+    nodes: fold_stmts(folder, ast.nodes),
+    ..ast
+  }
+}
+
+pub fn walk_attr<F: Fold>(folder: &mut F, attr: Attribute) -> Attribute {
+  Attribute {
+    args: fold_exprs(folder, attr.args),
+    ..attr
+  }
+}
+
+pub fn walk_block<F: Fold>(folder: &mut F, block: Block) -> Block {
+  Block {
+    stmts: fold_stmts(folder, block.stmts),
+    ..block
+  }
+}
+
+pub fn walk_capsule<F: Fold>(folder: &mut F, capsule: Capsule) -> Capsule {
+  let members = capsule
+    .members
+    .into_iter()
+    .map(|member| match member {
+      TraitMember::Field(field) => TraitMember::Field(folder.fold_field(field)),
+      TraitMember::Method(method) => {
+        TraitMember::Method(folder.fold_fun(method))
+      }
+    })
+    .collect();
+
+  Capsule {
+    stmt: capsule.stmt.map(|stmt| folder.fold_stmt(stmt)),
+    args: capsule
+      .args
+      .into_iter()
+      .map(|arg| folder.fold_fun_arg(arg))
+      .collect(),
+    members,
+    ..capsule
+  }
+}
+
+pub fn walk_enum<F: Fold>(folder: &mut F, enumeration: Enum) -> Enum {
+  Enum {
+    variants: enumeration
+      .variants
+      .into_iter()
+      .map(|variant| folder.fold_enum_variant(variant))
+      .collect(),
+    ..enumeration
+  }
+}
+
+pub fn walk_enum_variant<F: Fold>(
+  folder: &mut F,
+  variant: EnumVariant,
+) -> EnumVariant {
+  let kind = match variant.kind {
+    VariantKind::Unit => VariantKind::Unit,
+    VariantKind::Tuple(tys) => {
+      VariantKind::Tuple(tys.into_iter().map(|ty| folder.fold_ty(ty)).collect())
+    }
+    VariantKind::Struct(fields) => VariantKind::Struct(
+      fields
+        .into_iter()
+        .map(|field| folder.fold_field(field))
+        .collect(),
+    ),
+  };
+
+  EnumVariant {
+    kind,
+    discriminant: fold_opt_expr(folder, variant.discriminant),
+    ..variant
+  }
+}
+
+pub fn walk_expr<F: Fold>(folder: &mut F, expr: Expr) -> Expr {
+  let kind = match expr.kind {
+    kind @ ExprKind::Empty
+    | kind @ ExprKind::Error
+    | kind @ ExprKind::Ident(_)
+    | kind @ ExprKind::LoopVar(_) => kind,
+    ExprKind::Closure(fun) => ExprKind::Closure(box folder.fold_fun(*fun)),
+    ExprKind::Lit(lit) => ExprKind::Lit(folder.fold_lit(lit)),
+    ExprKind::Loop(kind) => ExprKind::Loop(folder.fold_loop(kind)),
+    ExprKind::Path(path) => ExprKind::Path(box folder.fold_path(*path)),
+    ExprKind::Array { data, span } => ExprKind::Array {
+      data: fold_exprs(folder, data),
+      span,
+    },
+    ExprKind::ArrayRepeat { value, len } => ExprKind::ArrayRepeat {
+      value: fold_box_expr(folder, value),
+      len: fold_box_expr(folder, len),
+    },
+    ExprKind::Assign { lhs, rhs } => ExprKind::Assign {
+      lhs: fold_box_expr(folder, lhs),
+      rhs: fold_box_expr(folder, rhs),
+    },
+    ExprKind::Await(expr) => ExprKind::Await(fold_box_expr(folder, expr)),
+    ExprKind::BinOp { lhs, op, rhs, span } => ExprKind::BinOp {
+      lhs: fold_box_expr(folder, lhs),
+      op,
+      rhs: fold_box_expr(folder, rhs),
+      span,
+    },
+    ExprKind::Call { callee, args, span } => ExprKind::Call {
+      callee: fold_box_expr(folder, callee),
+      args: fold_exprs(folder, args),
+      span,
+    },
+    ExprKind::Cast { expr, ty } => ExprKind::Cast {
+      expr: fold_box_expr(folder, expr),
+      ty: folder.fold_ty(ty),
+    },
+    ExprKind::CompoundAssign { lhs, op, rhs } => ExprKind::CompoundAssign {
+      lhs: fold_box_expr(folder, lhs),
+      op,
+      rhs: fold_box_expr(folder, rhs),
+    },
+    ExprKind::Hash { data } => ExprKind::Hash {
+      data: data
+        .into_iter()
+        .map(|(key, value)| (key, fold_box_expr(folder, value)))
+        .collect(),
+    },
+    ExprKind::IfElse {
+      conditions,
+      alternative,
+    } => ExprKind::IfElse {
+      conditions: fold_exprs(folder, conditions),
+      alternative: alternative.map(|block| box folder.fold_block(*block)),
+    },
+    ExprKind::Index { index, data } => ExprKind::Index {
+      index: fold_box_expr(folder, index),
+      data: fold_box_expr(folder, data),
+    },
+    ExprKind::MemberAccess { from, access } => ExprKind::MemberAccess {
+      from: fold_box_expr(folder, from),
+      access,
+    },
+    ExprKind::MethodCall {
+      receiver,
+      method,
+      args,
+    } => ExprKind::MethodCall {
+      receiver: fold_box_expr(folder, receiver),
+      method,
+      args: fold_exprs(folder, args),
+    },
+    ExprKind::Range {
+      start,
+      end,
+      inclusive,
+    } => ExprKind::Range {
+      start: fold_opt_expr(folder, start),
+      end: fold_opt_expr(folder, end),
+      inclusive,
+    },
+    ExprKind::StructLit { name, fields } => ExprKind::StructLit {
+      name: fold_box_expr(folder, name),
+      fields: fields
+        .into_iter()
+        .map(|(field, value)| (field, fold_box_expr(folder, value)))
+        .collect(),
+    },
+    ExprKind::Try(expr) => ExprKind::Try(fold_box_expr(folder, expr)),
+    ExprKind::Tuple(exprs) => ExprKind::Tuple(fold_exprs(folder, exprs)),
+    ExprKind::TupleIndex { from, index } => ExprKind::TupleIndex {
+      from: fold_box_expr(folder, from),
+      index,
+    },
+    ExprKind::UnOp { operand, rhs } => ExprKind::UnOp {
+      operand,
+      rhs: fold_box_expr(folder, rhs),
+    },
+  };
+
+  Expr {
+    id: expr.id,
+    kind,
+    span: expr.span,
+  }
+}
+
+pub fn walk_field<F: Fold>(folder: &mut F, field: Field) -> Field {
+  Field {
+    ty: folder.fold_ty(field.ty),
+    expr: field.expr.map(|expr| folder.fold_expr(expr)),
+    ..field
+  }
+}
+
+pub fn walk_fun<F: Fold>(folder: &mut F, fun: Fun) -> Fun {
+  Fun {
+    args: fun
+      .args
+      .into_iter()
+      .map(|arg| box folder.fold_fun_arg(*arg))
+      .collect(),
+    ret_ty: folder.fold_ty(fun.ret_ty),
+    block: fun.block.map(|block| folder.fold_block(block)),
+    ..fun
+  }
+}
+
+pub fn walk_fun_arg<F: Fold>(folder: &mut F, arg: FunArg) -> FunArg {
+  FunArg {
+    expr: fold_box_expr(folder, arg.expr),
+    ty: folder.fold_ty(arg.ty),
+    default: fold_opt_expr(folder, arg.default),
+    ..arg
+  }
+}
+
+pub fn walk_local<F: Fold>(folder: &mut F, local: Local) -> Local {
+  Local {
+    ty: local.ty.map(|ty| folder.fold_ty(ty)),
+    value: fold_box_expr(folder, local.value),
+    ..local
+  }
+}
+
+pub fn walk_loop<F: Fold>(folder: &mut F, kind: LoopKind) -> LoopKind {
+  match kind {
+    LoopKind::LoopFor {
+      iterable,
+      iterator,
+      implicit,
+      block,
+      label,
+    } => LoopKind::LoopFor {
+      iterable: fold_box_expr(folder, iterable),
+      iterator: fold_box_expr(folder, iterator),
+      implicit,
+      block: box folder.fold_block(*block),
+      label,
+    },
+    LoopKind::LoopLoop { block, label } => LoopKind::LoopLoop {
+      block: box folder.fold_block(*block),
+      label,
+    },
+    LoopKind::LoopWhile {
+      condition,
+      block,
+      label,
+    } => LoopKind::LoopWhile {
+      condition: fold_box_expr(folder, condition),
+      block: box folder.fold_block(*block),
+      label,
+    },
+  }
+}
+
+pub fn walk_stmt<F: Fold>(folder: &mut F, stmt: Stmt) -> Stmt {
+  let attrs = stmt
+    .attrs
+    .into_iter()
+    .map(|attr| folder.fold_attr(attr))
+    .collect();
+
+  let kind = match stmt.kind {
+    kind @ StmtKind::Empty
+    | kind @ StmtKind::Error
+    | kind @ StmtKind::Continue(_) => kind,
+    StmtKind::Bench(fun) => StmtKind::Bench(box folder.fold_fun(*fun)),
+    StmtKind::Fun(fun) => StmtKind::Fun(box folder.fold_fun(*fun)),
+    StmtKind::Mock(fun) => StmtKind::Mock(box folder.fold_fun(*fun)),
+    StmtKind::Test(fun) => StmtKind::Test(box folder.fold_fun(*fun)),
+    StmtKind::Capsule(capsule) => {
+      StmtKind::Capsule(box folder.fold_capsule(*capsule))
+    }
+    StmtKind::Enum(enumeration) => {
+      StmtKind::Enum(box folder.fold_enum(*enumeration))
+    }
+    StmtKind::Expr(expr) => StmtKind::Expr(fold_box_expr(folder, expr)),
+    StmtKind::Macro(def) => StmtKind::Macro(box folder.fold_macro_def(*def)),
+    StmtKind::Mut(local) => StmtKind::Mut(box folder.fold_local(*local)),
+    StmtKind::Val(local) => StmtKind::Val(box folder.fold_local(*local)),
+    StmtKind::Ret(value) => StmtKind::Ret(fold_opt_expr(folder, value)),
+    StmtKind::Struct(structure) => {
+      StmtKind::Struct(box folder.fold_struct(*structure))
+    }
+    StmtKind::Suite(suite) => StmtKind::Suite(box folder.fold_suite(*suite)),
+    StmtKind::TypeAlias(alias) => {
+      StmtKind::TypeAlias(box folder.fold_type_alias(*alias))
+    }
+    StmtKind::Use(import) => StmtKind::Use(box folder.fold_use(*import)),
+    StmtKind::Break { label, value } => StmtKind::Break {
+      label,
+      value: fold_opt_expr(folder, value),
+    },
+    StmtKind::IfBlock {
+      conditions,
+      alternative,
+    } => StmtKind::IfBlock {
+      conditions: conditions
+        .into_iter()
+        .map(|(condition, block)| {
+          let condition = fold_box_expr(folder, condition);
+
+          (condition, box folder.fold_block(*block))
+        })
+        .collect(),
+      alternative: alternative.map(|block| box folder.fold_block(*block)),
+    },
+    StmtKind::TargetBlock { target, body } => StmtKind::TargetBlock {
+      target,
+      body: folder.fold_block(body),
+    },
+  };
+
+  Stmt {
+    id: stmt.id,
+    kind,
+    attrs,
+    span: stmt.span,
+  }
+}
+
+pub fn walk_struct<F: Fold>(folder: &mut F, structure: Struct) -> Struct {
+  let members = structure
+    .members
+    .into_iter()
+    .map(|member| match member {
+      StructMember::Field(field) => {
+        StructMember::Field(folder.fold_field(field))
+      }
+      StructMember::Method(method) => {
+        StructMember::Method(folder.fold_fun(method))
+      }
+      StructMember::StaticMethod(method) => {
+        StructMember::StaticMethod(folder.fold_fun(method))
+      }
+    })
+    .collect();
+
+  Struct {
+    node: structure.node.map(|node| folder.fold_stmt(node)),
+    param_tys: structure
+      .param_tys
+      .into_iter()
+      .map(|param_ty| folder.fold_fun_arg(param_ty))
+      .collect(),
+    members,
+    ..structure
+  }
+}
+
+pub fn walk_suite<F: Fold>(folder: &mut F, suite: Suite) -> Suite {
+  Suite {
+    block: folder.fold_block(suite.block),
+    ..suite
+  }
+}
+
+pub fn walk_ty<F: Fold>(folder: &mut F, ty: Ty) -> Ty {
+  let (id, span) = (ty.id(), ty.span());
+  let mut ty = Ty::new(folder.fold_ty_kind(ty.into_kind()), span);
+
+  ty.set_id(id);
+  ty
+}
+
+pub fn walk_ty_kind<F: Fold>(folder: &mut F, kind: TyKind) -> TyKind {
+  match kind {
+    kind @ TyKind::Unknown
+    | kind @ TyKind::Void
+    | kind @ TyKind::NameRefTy(_)
+    | kind @ TyKind::PathTy(_) => kind,
+    TyKind::FunTy { param_tys, ret_ty } => TyKind::FunTy {
+      param_tys: fold_ty_kinds(folder, param_tys),
+      ret_ty: box folder.fold_ty_kind(*ret_ty),
+    },
+    TyKind::GenericTy { name, param_tys } => TyKind::GenericTy {
+      name,
+      param_tys: fold_ty_kinds(folder, param_tys),
+    },
+    TyKind::ArrayTy { ty, len } => TyKind::ArrayTy {
+      ty: box folder.fold_ty_kind(*ty),
+      len: fold_box_expr(folder, len),
+    },
+    TyKind::SliceTy(ty) => TyKind::SliceTy(box folder.fold_ty_kind(*ty)),
+    TyKind::TupleTy(tys) => TyKind::TupleTy(fold_ty_kinds(folder, tys)),
+    TyKind::OptionalTy(ty) => TyKind::OptionalTy(box folder.fold_ty_kind(*ty)),
+    TyKind::RefTy(ty) => TyKind::RefTy(box folder.fold_ty_kind(*ty)),
+  }
+}
+
+pub fn walk_type_alias<F: Fold>(folder: &mut F, alias: TypeAlias) -> TypeAlias {
+  TypeAlias {
+    ty: folder.fold_ty(alias.ty),
+    ..alias
+  }
+}
+
+pub fn walk_use<F: Fold>(folder: &mut F, import: Use) -> Use {
+  Use {
+    stmts: fold_stmts(folder, import.stmts),
+    ..import
+  }
+}
+
+fn fold_box_expr<F: Fold>(folder: &mut F, expr: Box<Expr>) -> Box<Expr> {
+  box folder.fold_expr(*expr)
+}
+
+fn fold_exprs<F: Fold>(
+  folder: &mut F,
+  exprs: Vec<Box<Expr>>,
+) -> Vec<Box<Expr>> {
+  exprs
+    .into_iter()
+    .map(|expr| fold_box_expr(folder, expr))
+    .collect()
+}
+
+fn fold_opt_expr<F: Fold>(
+  folder: &mut F,
+  expr: Option<Box<Expr>>,
+) -> Option<Box<Expr>> {
+  expr.map(|expr| fold_box_expr(folder, expr))
+}
+
+fn fold_stmts<F: Fold>(
+  folder: &mut F,
+  stmts: Vec<Box<Stmt>>,
+) -> Vec<Box<Stmt>> {
+  stmts
+    .into_iter()
+    .map(|stmt| box folder.fold_stmt(*stmt))
+    .collect()
+}
+
+fn fold_ty_kinds<F: Fold>(folder: &mut F, kinds: Vec<TyKind>) -> Vec<TyKind> {
+  kinds
+    .into_iter()
+    .map(|kind| folder.fold_ty_kind(kind))
+    .collect()
+}
@@ -1,3 +1,15 @@
 mod ast;
+pub mod fold;
+mod node;
+mod unparse;
+mod validator;
+pub mod visit;
+pub mod visit_mut;
 
 pub use ast::*;
+pub use fold::Fold;
+pub use node::{Arena, Node, NodeId, NodeIds, NodeMap};
+pub use unparse::{unparse, unparse_expr, unparse_stmt, unparse_ty};
+pub use validator::{validate, validate_item, Validator};
+pub use visit::Visitor;
+pub use visit_mut::VisitorMut;
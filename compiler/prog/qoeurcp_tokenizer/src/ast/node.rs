@@ -0,0 +1,827 @@
+use super::ast::*;
+use super::visit_mut::{self, VisitorMut};
+
+use qoeurcp_span::Span;
+
+use std::fmt;
+use std::mem;
+
+// the stable id of a node, the nodes of an item are numbered in the order of
+// the tree once the item is parsed, so parsing the same source twice gives
+// the same ids e.g `#12`
+//
+// a node built outside of the parser has the `NodeId::DUMMY` id until it is
+// numbered by `NodeIds::assign`
+#[derive(
+  Clone,
+  Copy,
+  Debug,
+  PartialEq,
+  Eq,
+  Hash,
+  PartialOrd,
+  Ord,
+  Serialize,
+  Deserialize,
+)]
+pub struct NodeId(u32);
+
+impl NodeId {
+  pub const DUMMY: NodeId = NodeId(u32::MAX);
+
+  pub fn new(index: usize) -> NodeId {
+    NodeId(index as u32)
+  }
+
+  pub fn index(self) -> usize {
+    self.0 as usize
+  }
+
+  pub fn is_dummy(self) -> bool {
+    self == Self::DUMMY
+  }
+}
+
+impl Default for NodeId {
+  fn default() -> NodeId {
+    Self::DUMMY
+  }
+}
+
+impl fmt::Display for NodeId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "#{}", self.0)
+  }
+}
+
+// numbers the nodes of the items of a module, the numbering goes on from one
+// item to the next
+#[derive(Clone, Debug, Default)]
+pub struct NodeIds {
+  next: u32,
+}
+
+impl NodeIds {
+  pub fn new() -> NodeIds {
+    Self { next: 0 }
+  }
+
+  pub fn assign(&mut self, stmt: &mut Stmt) {
+    self.visit_stmt_mut(stmt);
+  }
+
+  pub fn assign_ast(&mut self, ast: &mut Ast) {
+    self.visit_ast_mut(ast);
+  }
+
+  pub fn len(&self) -> usize {
+    self.next as usize
+  }
+
+  fn next_id(&mut self) -> NodeId {
+    let id = NodeId(self.next);
+
+    self.next += 1;
+    id
+  }
+}
+
+impl VisitorMut for NodeIds {
+  fn visit_attr_mut(&mut self, attr: &mut Attribute) {
+    attr.id = self.next_id();
+    visit_mut::walk_attr_mut(self, attr);
+  }
+
+  fn visit_block_mut(&mut self, block: &mut Block) {
+    block.id = self.next_id();
+    visit_mut::walk_block_mut(self, block);
+  }
+
+  fn visit_capsule_mut(&mut self, capsule: &mut Capsule) {
+    capsule.id = self.next_id();
+    visit_mut::walk_capsule_mut(self, capsule);
+  }
+
+  fn visit_enum_mut(&mut self, enumeration: &mut Enum) {
+    enumeration.id = self.next_id();
+    visit_mut::walk_enum_mut(self, enumeration);
+  }
+
+  fn visit_enum_variant_mut(&mut self, variant: &mut EnumVariant) {
+    variant.id = self.next_id();
+    visit_mut::walk_enum_variant_mut(self, variant);
+  }
+
+  fn visit_expr_mut(&mut self, expr: &mut Expr) {
+    expr.id = self.next_id();
+    visit_mut::walk_expr_mut(self, expr);
+  }
+
+  fn visit_field_mut(&mut self, field: &mut Field) {
+    field.id = self.next_id();
+    visit_mut::walk_field_mut(self, field);
+  }
+
+  fn visit_fun_mut(&mut self, fun: &mut Fun) {
+    fun.id = self.next_id();
+    visit_mut::walk_fun_mut(self, fun);
+  }
+
+  fn visit_fun_arg_mut(&mut self, arg: &mut FunArg) {
+    arg.id = self.next_id();
+    visit_mut::walk_fun_arg_mut(self, arg);
+  }
+
+  fn visit_local_mut(&mut self, local: &mut Local) {
+    local.id = self.next_id();
+    visit_mut::walk_local_mut(self, local);
+  }
+
+  fn visit_macro_def_mut(&mut self, def: &mut MacroDef) {
+    def.id = self.next_id();
+  }
+
+  fn visit_path_mut(&mut self, path: &mut Path) {
+    path.id = self.next_id();
+  }
+
+  fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+    stmt.id = self.next_id();
+    visit_mut::walk_stmt_mut(self, stmt);
+  }
+
+  fn visit_struct_mut(&mut self, structure: &mut Struct) {
+    structure.id = self.next_id();
+    visit_mut::walk_struct_mut(self, structure);
+  }
+
+  fn visit_suite_mut(&mut self, suite: &mut Suite) {
+    suite.id = self.next_id();
+    visit_mut::walk_suite_mut(self, suite);
+  }
+
+  fn visit_ty_mut(&mut self, ty: &mut Ty) {
+    let id = self.next_id();
+
+    ty.set_id(id);
+    visit_mut::walk_ty_mut(self, ty);
+  }
+
+  fn visit_type_alias_mut(&mut self, alias: &mut TypeAlias) {
+    alias.id = self.next_id();
+    visit_mut::walk_type_alias_mut(self, alias);
+  }
+
+  fn visit_use_mut(&mut self, import: &mut Use) {
+    import.id = self.next_id();
+    visit_mut::walk_use_mut(self, import);
+  }
+}
+
+// a side table maps the ids of the nodes to what a pass knows about them
+// e.g the resolved names or the inferred types, the ids are dense so the
+// table is a vector, the count of the entries is kept so `len` does not
+// scan the slots
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeMap<T> {
+  values: Vec<Option<T>>,
+  len: usize,
+}
+
+impl<T> NodeMap<T> {
+  pub fn new() -> NodeMap<T> {
+    Self {
+      values: vec![],
+      len: 0,
+    }
+  }
+
+  pub fn with_capacity(len: usize) -> NodeMap<T> {
+    Self {
+      values: Vec::with_capacity(len),
+      len: 0,
+    }
+  }
+
+  pub fn contains(&self, id: NodeId) -> bool {
+    self.get(id).is_some()
+  }
+
+  pub fn get(&self, id: NodeId) -> Option<&T> {
+    self.values.get(id.index()).and_then(|value| value.as_ref())
+  }
+
+  pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+    self
+      .values
+      .get_mut(id.index())
+      .and_then(|value| value.as_mut())
+  }
+
+  // a node without an id can not be a key
+  pub fn insert(&mut self, id: NodeId, value: T) -> Option<T> {
+    assert!(!id.is_dummy(), "a node must be numbered to be a key");
+
+    if id.index() >= self.values.len() {
+      self.values.resize_with(id.index() + 1, || None);
+    }
+
+    let previous = self.values[id.index()].replace(value);
+
+    if previous.is_none() {
+      self.len += 1;
+    }
+
+    previous
+  }
+
+  pub fn remove(&mut self, id: NodeId) -> Option<T> {
+    let previous = self
+      .values
+      .get_mut(id.index())
+      .and_then(|value| value.take());
+
+    if previous.is_some() {
+      self.len -= 1;
+    }
+
+    previous
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (NodeId, &T)> {
+    self.values.iter().enumerate().filter_map(|(index, value)| {
+      value.as_ref().map(|value| (NodeId::new(index), value))
+    })
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+impl<T> Default for NodeMap<T> {
+  fn default() -> NodeMap<T> {
+    Self::new()
+  }
+}
+
+// a node of the arena borrowed from it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Node<'ast> {
+  Attribute(&'ast Attribute),
+  Block(&'ast Block),
+  Capsule(&'ast Capsule),
+  Enum(&'ast Enum),
+  EnumVariant(&'ast EnumVariant),
+  Expr(&'ast Expr),
+  Field(&'ast Field),
+  Fun(&'ast Fun),
+  FunArg(&'ast FunArg),
+  Local(&'ast Local),
+  MacroDef(&'ast MacroDef),
+  Path(&'ast Path),
+  Stmt(&'ast Stmt),
+  Struct(&'ast Struct),
+  Suite(&'ast Suite),
+  Ty(&'ast Ty),
+  TypeAlias(&'ast TypeAlias),
+  Use(&'ast Use),
+}
+
+// a node owned by the arena
+#[derive(Clone, Debug, PartialEq)]
+enum Slot {
+  Attribute(Attribute),
+  Block(Block),
+  Capsule(Capsule),
+  Enum(Enum),
+  EnumVariant(EnumVariant),
+  Expr(Expr),
+  Field(Field),
+  Fun(Fun),
+  FunArg(FunArg),
+  Local(Local),
+  MacroDef(MacroDef),
+  Path(Path),
+  Stmt(Stmt),
+  Struct(Struct),
+  Suite(Suite),
+  Ty(Ty),
+  TypeAlias(TypeAlias),
+  Use(Use),
+}
+
+impl Slot {
+  fn node(&self) -> Node<'_> {
+    match *self {
+      Self::Attribute(ref attr) => Node::Attribute(attr),
+      Self::Block(ref block) => Node::Block(block),
+      Self::Capsule(ref capsule) => Node::Capsule(capsule),
+      Self::Enum(ref enumeration) => Node::Enum(enumeration),
+      Self::EnumVariant(ref variant) => Node::EnumVariant(variant),
+      Self::Expr(ref expr) => Node::Expr(expr),
+      Self::Field(ref field) => Node::Field(field),
+      Self::Fun(ref fun) => Node::Fun(fun),
+      Self::FunArg(ref arg) => Node::FunArg(arg),
+      Self::Local(ref local) => Node::Local(local),
+      Self::MacroDef(ref def) => Node::MacroDef(def),
+      Self::Path(ref path) => Node::Path(path),
+      Self::Stmt(ref stmt) => Node::Stmt(stmt),
+      Self::Struct(ref structure) => Node::Struct(structure),
+      Self::Suite(ref suite) => Node::Suite(suite),
+      Self::Ty(ref ty) => Node::Ty(ty),
+      Self::TypeAlias(ref alias) => Node::TypeAlias(alias),
+      Self::Use(ref import) => Node::Use(import),
+    }
+  }
+}
+
+// the empty node left in the place of a node moved into the arena, it keeps
+// the id of the node so its parent still points to it e.g the `lhs` of a
+// binary expression of the arena is an empty expression with the id of the
+// left operand
+trait Hole {
+  fn hole(id: NodeId) -> Self;
+}
+
+impl Hole for Attribute {
+  fn hole(id: NodeId) -> Attribute {
+    Self {
+      id,
+      name: String::new(),
+      args: vec![],
+      span: Span::zero(),
+    }
+  }
+}
+
+impl Hole for Block {
+  fn hole(id: NodeId) -> Block {
+    Self {
+      id,
+      stmts: vec![],
+      span: Span::zero(),
+    }
+  }
+}
+
+impl Hole for Capsule {
+  fn hole(id: NodeId) -> Capsule {
+    Self {
+      id,
+      span: Span::zero(),
+      stmt: None,
+      with_traits: vec![],
+      name: String::new(),
+      args: vec![],
+      members: vec![],
+      visibility: false,
+      docs: vec![],
+    }
+  }
+}
+
+impl Hole for Enum {
+  fn hole(id: NodeId) -> Enum {
+    Self {
+      id,
+      span: Span::zero(),
+      name: String::new(),
+      variants: vec![],
+      visibility: false,
+    }
+  }
+}
+
+impl Hole for EnumVariant {
+  fn hole(id: NodeId) -> EnumVariant {
+    Self {
+      id,
+      name: String::new(),
+      kind: VariantKind::Unit,
+      discriminant: None,
+      span: Span::zero(),
+    }
+  }
+}
+
+impl Hole for Expr {
+  fn hole(id: NodeId) -> Expr {
+    Self {
+      id,
+      kind: ExprKind::Empty,
+      span: Span::zero(),
+    }
+  }
+}
+
+impl Hole for Field {
+  fn hole(id: NodeId) -> Field {
+    Self {
+      id,
+      name: String::new(),
+      ty: Ty::hole(NodeId::DUMMY),
+      expr: None,
+      span: Span::zero(),
+      docs: vec![],
+    }
+  }
+}
+
+impl Hole for Fun {
+  fn hole(id: NodeId) -> Fun {
+    Self {
+      id,
+      span: Span::zero(),
+      name: String::new(),
+      args: vec![],
+      ret_ty: Ty::hole(NodeId::DUMMY),
+      block: None,
+      modifiers: vec![],
+      visibility: false,
+      docs: vec![],
+    }
+  }
+}
+
+impl Hole for FunArg {
+  fn hole(id: NodeId) -> FunArg {
+    Self {
+      id,
+      name: None,
+      immutable: false,
+      expr: box Expr::hole(NodeId::DUMMY),
+      span: Span::zero(),
+      ty: Ty::hole(NodeId::DUMMY),
+      default: None,
+    }
+  }
+}
+
+impl Hole for Local {
+  fn hole(id: NodeId) -> Local {
+    Self {
+      id,
+      names: vec![],
+      immutable: false,
+      ty: None,
+      value: box Expr::hole(NodeId::DUMMY),
+      span: Span::zero(),
+    }
+  }
+}
+
+impl Hole for MacroDef {
+  fn hole(id: NodeId) -> MacroDef {
+    Self {
+      id,
+      name: String::new(),
+      rules: vec![],
+      span: Span::zero(),
+    }
+  }
+}
+
+impl Hole for Path {
+  fn hole(id: NodeId) -> Path {
+    Self {
+      id,
+      root: PathRoot::Relative,
+      segments: vec![],
+      span: Span::zero(),
+    }
+  }
+}
+
+impl Hole for Stmt {
+  fn hole(id: NodeId) -> Stmt {
+    Self {
+      id,
+      kind: StmtKind::Empty,
+      attrs: vec![],
+      span: Span::zero(),
+    }
+  }
+}
+
+impl Hole for Struct {
+  fn hole(id: NodeId) -> Struct {
+    Self {
+      id,
+      span: Span::zero(),
+      node: None,
+      parents: vec![],
+      name: String::new(),
+      param_tys: vec![],
+      members: vec![],
+      visibility: false,
+      docs: vec![],
+    }
+  }
+}
+
+impl Hole for Suite {
+  fn hole(id: NodeId) -> Suite {
+    Self {
+      id,
+      name: None,
+      block: Block::hole(NodeId::DUMMY),
+      span: Span::zero(),
+    }
+  }
+}
+
+impl Hole for Ty {
+  fn hole(id: NodeId) -> Ty {
+    let mut ty = Ty::new(TyKind::Unknown, Span::zero());
+
+    ty.set_id(id);
+    ty
+  }
+}
+
+impl Hole for TypeAlias {
+  fn hole(id: NodeId) -> TypeAlias {
+    Self {
+      id,
+      name: String::new(),
+      ty: Ty::hole(NodeId::DUMMY),
+      visibility: false,
+      span: Span::zero(),
+    }
+  }
+}
+
+impl Hole for Use {
+  fn hole(id: NodeId) -> Use {
+    Self {
+      id,
+      name: String::new(),
+      stmts: vec![],
+      span: Span::zero(),
+    }
+  }
+}
+
+// the nodes of a tree allocated in slots keyed by their ids, the tree is
+// taken apart once and every node is moved into its slot, a node of the
+// arena points to its children by the ids of the holes left in their place
+// e.g `arena.expr(lhs.id)` for the `lhs` of a binary expression
+//
+// the accessors borrow the nodes from the arena so a pass walks it without
+// cloning a subtree, a node without an id stays in its parent
+#[derive(Clone, Debug)]
+pub struct Arena {
+  slots: NodeMap<Slot>,
+  parents: NodeMap<NodeId>,
+  items: Vec<NodeId>,
+  docs: Vec<String>,
+}
+
+impl Arena {
+  pub fn new(mut ast: Ast) -> Arena {
+    let mut alloc = Alloc {
+      arena: Self {
+        slots: NodeMap::new(),
+        parents: NodeMap::new(),
+        items: vec![],
+        docs: mem::replace(&mut ast.docs, vec![]),
+      },
+      stack: vec![],
+    };
+
+    alloc.visit_ast_mut(&mut ast);
+    alloc.arena.items = ast.nodes.iter().map(|stmt| stmt.id).collect();
+    alloc.arena
+  }
+
+  pub fn get(&self, id: NodeId) -> Option<Node<'_>> {
+    self.slots.get(id).map(|slot| slot.node())
+  }
+
+  pub fn block(&self, id: NodeId) -> Option<&Block> {
+    match self.get(id) {
+      Some(Node::Block(block)) => Some(block),
+      _ => None,
+    }
+  }
+
+  pub fn expr(&self, id: NodeId) -> Option<&Expr> {
+    match self.get(id) {
+      Some(Node::Expr(expr)) => Some(expr),
+      _ => None,
+    }
+  }
+
+  pub fn fun(&self, id: NodeId) -> Option<&Fun> {
+    match self.get(id) {
+      Some(Node::Fun(fun)) => Some(fun),
+      _ => None,
+    }
+  }
+
+  pub fn stmt(&self, id: NodeId) -> Option<&Stmt> {
+    match self.get(id) {
+      Some(Node::Stmt(stmt)) => Some(stmt),
+      _ => None,
+    }
+  }
+
+  pub fn ty(&self, id: NodeId) -> Option<&Ty> {
+    match self.get(id) {
+      Some(Node::Ty(ty)) => Some(ty),
+      _ => None,
+    }
+  }
+
+  // the ids of the items of the module in the order of the source
+  pub fn items(&self) -> &[NodeId] {
+    &self.items
+  }
+
+  pub fn docs(&self) -> &[String] {
+    &self.docs
+  }
+
+  // the closest numbered node holding the node e.g the statement of an
+  // expression
+  pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+    self.parents.get(id).copied()
+  }
+
+  pub fn len(&self) -> usize {
+    self.slots.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.slots.is_empty()
+  }
+}
+
+// moves the nodes of a tree into an arena, a node is moved once its children
+// are, so the children of a node of the arena are holes
+struct Alloc {
+  arena: Arena,
+  stack: Vec<NodeId>,
+}
+
+impl Alloc {
+  fn enter(&mut self, id: NodeId) -> bool {
+    if id.is_dummy() {
+      return false;
+    }
+
+    if let Some(&parent) = self.stack.last() {
+      self.arena.parents.insert(id, parent);
+    }
+
+    self.stack.push(id);
+    true
+  }
+
+  fn exit<T: Hole>(
+    &mut self,
+    entered: bool,
+    node: &mut T,
+    slot: fn(T) -> Slot,
+  ) {
+    if entered {
+      let id = self.stack.pop().unwrap();
+
+      self
+        .arena
+        .slots
+        .insert(id, slot(mem::replace(node, T::hole(id))));
+    }
+  }
+}
+
+impl VisitorMut for Alloc {
+  fn visit_attr_mut(&mut self, attr: &mut Attribute) {
+    let entered = self.enter(attr.id);
+
+    visit_mut::walk_attr_mut(self, attr);
+    self.exit(entered, attr, Slot::Attribute);
+  }
+
+  fn visit_block_mut(&mut self, block: &mut Block) {
+    let entered = self.enter(block.id);
+
+    visit_mut::walk_block_mut(self, block);
+    self.exit(entered, block, Slot::Block);
+  }
+
+  fn visit_capsule_mut(&mut self, capsule: &mut Capsule) {
+    let entered = self.enter(capsule.id);
+
+    visit_mut::walk_capsule_mut(self, capsule);
+    self.exit(entered, capsule, Slot::Capsule);
+  }
+
+  fn visit_enum_mut(&mut self, enumeration: &mut Enum) {
+    let entered = self.enter(enumeration.id);
+
+    visit_mut::walk_enum_mut(self, enumeration);
+    self.exit(entered, enumeration, Slot::Enum);
+  }
+
+  fn visit_enum_variant_mut(&mut self, variant: &mut EnumVariant) {
+    let entered = self.enter(variant.id);
+
+    visit_mut::walk_enum_variant_mut(self, variant);
+    self.exit(entered, variant, Slot::EnumVariant);
+  }
+
+  fn visit_expr_mut(&mut self, expr: &mut Expr) {
+    let entered = self.enter(expr.id);
+
+    visit_mut::walk_expr_mut(self, expr);
+    self.exit(entered, expr, Slot::Expr);
+  }
+
+  fn visit_field_mut(&mut self, field: &mut Field) {
+    let entered = self.enter(field.id);
+
+    visit_mut::walk_field_mut(self, field);
+    self.exit(entered, field, Slot::Field);
+  }
+
+  fn visit_fun_mut(&mut self, fun: &mut Fun) {
+    let entered = self.enter(fun.id);
+
+    visit_mut::walk_fun_mut(self, fun);
+    self.exit(entered, fun, Slot::Fun);
+  }
+
+  fn visit_fun_arg_mut(&mut self, arg: &mut FunArg) {
+    let entered = self.enter(arg.id);
+
+    visit_mut::walk_fun_arg_mut(self, arg);
+    self.exit(entered, arg, Slot::FunArg);
+  }
+
+  fn visit_local_mut(&mut self, local: &mut Local) {
+    let entered = self.enter(local.id);
+
+    visit_mut::walk_local_mut(self, local);
+    self.exit(entered, local, Slot::Local);
+  }
+
+  fn visit_macro_def_mut(&mut self, def: &mut MacroDef) {
+    let entered = self.enter(def.id);
+
+    self.exit(entered, def, Slot::MacroDef);
+  }
+
+  fn visit_path_mut(&mut self, path: &mut Path) {
+    let entered = self.enter(path.id);
+
+    self.exit(entered, path, Slot::Path);
+  }
+
+  fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+    let entered = self.enter(stmt.id);
+
+    visit_mut::walk_stmt_mut(self, stmt);
+    self.exit(entered, stmt, Slot::Stmt);
+  }
+
+  fn visit_struct_mut(&mut self, structure: &mut Struct) {
+    let entered = self.enter(structure.id);
+
+    visit_mut::walk_struct_mut(self, structure);
+    self.exit(entered, structure, Slot::Struct);
+  }
+
+  fn visit_suite_mut(&mut self, suite: &mut Suite) {
+    let entered = self.enter(suite.id);
+
+    visit_mut::walk_suite_mut(self, suite);
+    self.exit(entered, suite, Slot::Suite);
+  }
+
+  fn visit_ty_mut(&mut self, ty: &mut Ty) {
+    let entered = self.enter(ty.id());
+
+    visit_mut::walk_ty_mut(self, ty);
+    self.exit(entered, ty, Slot::Ty);
+  }
+
+  fn visit_type_alias_mut(&mut self, alias: &mut TypeAlias) {
+    let entered = self.enter(alias.id);
+
+    visit_mut::walk_type_alias_mut(self, alias);
+    self.exit(entered, alias, Slot::TypeAlias);
+  }
+
+  fn visit_use_mut(&mut self, import: &mut Use) {
+    let entered = self.enter(import.id);
+
+    visit_mut::walk_use_mut(self, import);
+    self.exit(entered, import, Slot::Use);
+  }
+}
@@ -0,0 +1,1357 @@
+use super::ast::*;
+use super::visit::{walk_expr, walk_fun, walk_fun_arg, walk_local, Visitor};
+
+use crate::token::{
+  BinaryKind, LiteralKind, PrecedenceKind, Token, TokenKind, UnaryKind,
+};
+
+use std::collections::{HashMap, HashSet};
+
+// writes a tree back as canonical source, the parentheses are only written
+// where the precedence of the operators requires them e.g
+// `(a + b) * c`, `a - (b - c)`, `(x as int) < y`
+//
+// the written source is parsed back into the same tree, except for what the
+// source can not spell e.g the names renamed by a macro expansion `x#1`
+// become `x_1`, or `x_1_` when `x_1` is taken, a negative literal is read
+// as a negation, `true` and `false` are read as names and an error node is
+// dropped. the structs, capsules and imports have no syntax yet, they are
+// written as they are documented
+pub fn unparse(ast: &Ast) -> String {
+  let mut names = Names::default();
+
+  names.visit_ast(ast);
+
+  let mut printer = Printer::with_names(names.0);
+  let mut sections = vec![];
+
+  if !ast.docs.is_empty() {
+    sections.push(printer.docs(&ast.docs).join("\n"));
+  }
+
+  let items = omit_empty(&ast.nodes);
+
+  for (index, stmt) in items.iter().enumerate() {
+    sections.push(printer.stmt(stmt, false, items.get(index + 1).copied()));
+  }
+
+  match sections.is_empty() {
+    true => String::new(),
+    false => format!("{}\n", sections.join("\n\n")),
+  }
+}
+
+pub fn unparse_expr(expr: &Expr) -> String {
+  let mut names = Names::default();
+
+  names.visit_expr(expr);
+  Printer::with_names(names.0).expr(expr)
+}
+
+pub fn unparse_stmt(stmt: &Stmt) -> String {
+  let mut names = Names::default();
+
+  names.visit_stmt(stmt);
+  Printer::with_names(names.0).stmt(stmt, false, None)
+}
+
+pub fn unparse_ty(ty: &Ty) -> String {
+  Printer::new().ty(ty.kind())
+}
+
+// the names bound or used by a tree
+#[derive(Default)]
+struct Names(HashSet<String>);
+
+impl<'ast> Visitor<'ast> for Names {
+  fn visit_expr(&mut self, expr: &'ast Expr) {
+    if let ExprKind::Ident(ref name) = expr.kind {
+      self.0.insert(name.to_owned());
+    }
+
+    walk_expr(self, expr)
+  }
+
+  fn visit_fun(&mut self, fun: &'ast Fun) {
+    self.0.insert(fun.name.to_owned());
+    walk_fun(self, fun)
+  }
+
+  fn visit_fun_arg(&mut self, arg: &'ast FunArg) {
+    self.0.extend(arg.name.to_owned());
+    walk_fun_arg(self, arg)
+  }
+
+  fn visit_local(&mut self, local: &'ast Local) {
+    self.0.extend(local.names());
+    walk_local(self, local)
+  }
+}
+
+struct Printer {
+  indent: usize,
+  // the spelling of the names renamed by a macro expansion
+  renames: HashMap<String, String>,
+}
+
+impl Printer {
+  fn new() -> Printer {
+    Self {
+      indent: 0,
+      renames: HashMap::new(),
+    }
+  }
+
+  // a renamed name is spelled with underscores until the spelling is not
+  // taken by another name e.g `x#1` is written `x_1` or `x_1_`
+  fn with_names(names: HashSet<String>) -> Printer {
+    let mut renamed = names
+      .iter()
+      .filter(|name| name.contains('#'))
+      .cloned()
+      .collect::<Vec<String>>();
+    let mut taken = names
+      .into_iter()
+      .filter(|name| !name.contains('#'))
+      .collect::<HashSet<String>>();
+    let mut renames = HashMap::new();
+
+    renamed.sort();
+
+    for name in renamed {
+      let mut spelling = name.replace('#', "_");
+
+      while taken.contains(&spelling) {
+        spelling.push('_');
+      }
+
+      taken.insert(spelling.to_owned());
+      renames.insert(name, spelling);
+    }
+
+    Self { indent: 0, renames }
+  }
+
+  fn ident(&self, name: &str) -> String {
+    match self.renames.get(name) {
+      Some(spelling) => spelling.to_owned(),
+      None => name.to_owned(),
+    }
+  }
+
+  fn pad(&self) -> String {
+    "  ".repeat(self.indent)
+  }
+
+  // e.g `|> derive: clone, debug.`, a number is followed by a space to not be
+  // read as a real e.g `|> align: 16 .`
+  fn attr(&mut self, attr: &Attribute) -> String {
+    let args = self.exprs(&attr.args);
+
+    match attr.args.last().map(|arg| &arg.kind) {
+      None => format!("|> {}.", attr.name),
+      Some(ExprKind::Lit(LitKind::Int(_)))
+      | Some(ExprKind::Lit(LitKind::Real(_))) => {
+        format!("|> {}: {} .", attr.name, args)
+      }
+      Some(_) => format!("|> {}: {}.", attr.name, args),
+    }
+  }
+
+  // a value block ends with the expression it evaluates to, which is written
+  // without semicolon e.g `fun zero: int = () { 0 }`
+  fn block(&mut self, block: &Block, is_value: bool) -> String {
+    let stmts = omit_empty(&block.stmts);
+
+    if stmts.is_empty() {
+      return format!("{{}}");
+    }
+
+    self.indent += 1;
+
+    let mut lines = vec![];
+
+    for (index, stmt) in stmts.iter().enumerate() {
+      let is_tail = is_value && index + 1 == stmts.len();
+      let next = stmts.get(index + 1).copied();
+
+      lines.push(format!("{}{}", self.pad(), self.stmt(stmt, is_tail, next)));
+    }
+
+    self.indent -= 1;
+
+    format!("{{\n{}\n{}}}", lines.join("\n"), self.pad())
+  }
+
+  // the docs of several lines are written as a block e.g `#!+ ... #!-`
+  fn docs(&self, docs: &[String]) -> Vec<String> {
+    let mut lines = vec![];
+
+    for doc in docs.iter() {
+      if !doc.contains('\n') {
+        lines.push(format!("# {}", doc).trim_end().to_string());
+        continue;
+      }
+
+      lines.push(format!("#!+"));
+      lines.extend(
+        doc
+          .lines()
+          .map(|line| format!("#! {}", line).trim_end().to_string()),
+      );
+      lines.push(format!("#!-"));
+    }
+
+    lines
+  }
+
+  fn stmt(
+    &mut self,
+    stmt: &Stmt,
+    is_tail: bool,
+    next: Option<&Stmt>,
+  ) -> String {
+    let mut lines = self.docs(&stmt.docs());
+
+    for attr in stmt.attrs.iter() {
+      lines.push(self.attr(attr));
+    }
+
+    lines.push(self.stmt_kind(stmt, is_tail, next));
+    lines.join(&format!("\n{}", self.pad()))
+  }
+
+  fn stmt_kind(
+    &mut self,
+    stmt: &Stmt,
+    is_tail: bool,
+    next: Option<&Stmt>,
+  ) -> String {
+    match stmt.kind {
+      StmtKind::Empty | StmtKind::Error => String::new(),
+      StmtKind::Bench(ref fun) => self.test("bench", fun),
+      StmtKind::Capsule(ref capsule) => self.capsule(capsule),
+      StmtKind::Continue(ref label) => {
+        format!("continue{};", label_text(label))
+      }
+      StmtKind::Enum(ref enumeration) => self.enumeration(enumeration),
+      StmtKind::Expr(ref expr) => {
+        let text = self.expr(expr);
+
+        match expr.kind {
+          _ if is_tail => text,
+          ExprKind::Loop(_)
+            if !next.map_or(false, |next| self.continues(next)) =>
+          {
+            text
+          }
+          _ => format!("{};", text),
+        }
+      }
+      StmtKind::Fun(ref fun) => self.fun(fun),
+      StmtKind::Macro(ref def) => self.macro_def(def),
+      StmtKind::Mock(ref fun) => self.test("mock", fun),
+      StmtKind::Mut(ref local) => self.local("mut", local),
+      StmtKind::Ret(None) => format!("ret;"),
+      StmtKind::Ret(Some(ref value)) => format!("ret {};", self.expr(value)),
+      StmtKind::Struct(ref structure) => self.structure(structure),
+      StmtKind::Suite(ref suite) => match suite.name {
+        Some(ref name) => {
+          format!("suite {} {}", name, self.block(&suite.block, false))
+        }
+        None => format!("suite {}", self.block(&suite.block, false)),
+      },
+      StmtKind::Test(ref fun) => self.test("test", fun),
+      StmtKind::TypeAlias(ref alias) => format!(
+        "{}type {} = {};",
+        visibility_text(alias.visibility),
+        alias.name,
+        self.ty(alias.ty.kind())
+      ),
+      StmtKind::Use(ref import) => format!("use @{};", import.name),
+      StmtKind::Val(ref local) => self.local("val", local),
+      StmtKind::Break {
+        ref label,
+        ref value,
+      } => {
+        let value = match value {
+          Some(ref value) => {
+            let value = self.expr(value);
+
+            // a label starting the value would be read as the label of the
+            // `break` e.g `break (\l loop { })`
+            match label.is_none() && value.starts_with('\\') {
+              true => format!(" ({})", value),
+              false => format!(" {}", value),
+            }
+          }
+          None => String::new(),
+        };
+
+        format!("break{}{};", label_text(label), value)
+      }
+      StmtKind::IfBlock {
+        ref conditions,
+        ref alternative,
+      } => {
+        let mut branches = vec![];
+
+        for (condition, block) in conditions.iter() {
+          branches.push(format!(
+            "if {} {}",
+            self.condition(condition, block),
+            self.block(block, is_tail)
+          ));
+        }
+
+        if let Some(ref alternative) = alternative {
+          branches.push(self.block(alternative, is_tail));
+        }
+
+        branches.join(" else ")
+      }
+      StmtKind::TargetBlock {
+        ref target,
+        ref body,
+      } => format!("{} = {}", target.text(), self.block(body, false)),
+    }
+  }
+
+  // a loop is not followed by a semicolon, unless the next statement would
+  // continue it e.g `loop { }; (a, b);`, `loop { }; -x;`
+  fn continues(&mut self, next: &Stmt) -> bool {
+    match next.kind {
+      StmtKind::Expr(ref expr) if next.attrs.is_empty() => {
+        match self.expr(expr).chars().next() {
+          Some('(') | Some('[') | Some('-') | Some('!') | Some('.') => true,
+          _ => false,
+        }
+      }
+      _ => false,
+    }
+  }
+
+  // a condition is followed by a block, which must not be read as the body
+  // of a closure or as the fields of a struct literal e.g `if ((a, b)) { }`
+  fn condition(&mut self, condition: &Expr, block: &Block) -> String {
+    let text = self.expr(condition);
+
+    match self.is_block_ambiguous(condition, block) {
+      true => parenthesize_condition(&text),
+      false => text,
+    }
+  }
+
+  fn is_block_ambiguous(&mut self, expr: &Expr, block: &Block) -> bool {
+    let is_hash_ahead =
+      omit_empty(&block.stmts).first().map_or(false, |stmt| {
+        stmt.attrs.is_empty()
+          && self.stmt_kind(stmt, false, None).starts_with(".{")
+      });
+
+    // a group starting with a dot is read as parameters e.g `a * (.{} + b)`
+    ends_with(expr, &|expr| match expr.kind {
+      ExprKind::Tuple(_) => true,
+      ExprKind::Path(ref path) => {
+        is_hash_ahead
+          && (path.root != PathRoot::Relative || path.segments.len() > 1)
+      }
+      _ => last_group(expr)
+        .map_or(false, |group| Printer::new().expr(group).starts_with('.')),
+    })
+  }
+
+  // e.g `fun add: int = (x: int, y: int) { x + y }`, `fun add := (x) { }`,
+  // a declaration writes its signature e.g `ext fun sqrt: (-> int | x: int);`
+  fn fun(&mut self, fun: &Fun) -> String {
+    let mut head = visibility_text(fun.visibility).to_string();
+
+    for modifier in fun.modifiers.iter() {
+      head.push_str(&format!("{} ", modifier.text()));
+    }
+
+    head.push_str(&format!("fun {}", self.ident(&fun.name)));
+
+    let block = match fun.block {
+      Some(ref block) => block,
+      None => return format!("{}: {};", head, self.fun_sig(fun)),
+    };
+
+    let sig = match fun.ret_ty.kind() {
+      TyKind::Void => format!(" = "),
+      TyKind::Unknown => format!(" := "),
+      ty => format!(": {} = ", self.ret_ty(ty)),
+    };
+
+    let is_value = *fun.ret_ty.kind() != TyKind::Void;
+
+    format!(
+      "{}{}({}) {}",
+      head,
+      sig,
+      self.fun_args(&fun.args),
+      self.block(block, is_value)
+    )
+  }
+
+  fn fun_arg(&mut self, arg: &FunArg) -> String {
+    let name = match arg.name {
+      Some(ref name) => self.ident(name),
+      None => self.expr(&arg.expr),
+    };
+
+    let mut text = match arg.ty.kind() {
+      TyKind::Unknown => name,
+      ty => format!("{}: {}", name, self.ty(ty)),
+    };
+
+    if let Some(ref default) = arg.default {
+      text.push_str(&format!(" = {}", self.expr(default)));
+    }
+
+    text
+  }
+
+  fn fun_args(&mut self, args: &[Box<FunArg>]) -> String {
+    args
+      .iter()
+      .map(|arg| self.fun_arg(arg))
+      .collect::<Vec<String>>()
+      .join(", ")
+  }
+
+  // the signature of a declaration names its arguments e.g `(x: int)`,
+  // `(-> int | x: int, \y)`, `int`
+  fn fun_sig(&mut self, fun: &Fun) -> String {
+    let mut args = vec![];
+
+    for arg in fun.args.iter() {
+      let mut text = match (arg.name.as_ref(), arg.ty.kind()) {
+        (Some(name), TyKind::Unknown) => format!("\\{}", self.ident(name)),
+        (Some(name), ty) => format!("{}: {}", self.ident(name), self.ty(ty)),
+        (None, ty) => self.ty(ty),
+      };
+
+      if let Some(ref default) = arg.default {
+        text.push_str(&format!(" = {}", self.expr(default)));
+      }
+
+      args.push(text);
+    }
+
+    match (args.is_empty(), fun.ret_ty.kind()) {
+      (true, TyKind::Void) => format!("()"),
+      (true, ty) => self.ret_ty(ty),
+      (false, TyKind::Void) => format!("({})", args.join(", ")),
+      (false, ty) => format!("(-> {} | {})", self.ty(ty), args.join(", ")),
+    }
+  }
+
+  // a parenthesized type would be read as the signature e.g `(-> (int, int))`
+  fn ret_ty(&mut self, ty: &TyKind) -> String {
+    let text = self.ty(ty);
+
+    match text.starts_with('(') {
+      true => format!("(-> {})", text),
+      false => text,
+    }
+  }
+
+  fn local(&mut self, keyword: &str, local: &Local) -> String {
+    let names = local
+      .names
+      .iter()
+      .map(|name| self.ident(name))
+      .collect::<Vec<String>>()
+      .join(" ");
+
+    let value = self.expr(&local.value);
+
+    match local.ty {
+      Some(ref ty) => {
+        format!("{} {}: {} = {};", keyword, names, self.ty(ty.kind()), value)
+      }
+      None => format!("{} {} := {};", keyword, names, value),
+    }
+  }
+
+  fn test(&mut self, keyword: &str, fun: &Fun) -> String {
+    let block = match fun.block {
+      Some(ref block) => self.block(block, false),
+      None => format!("{{}}"),
+    };
+
+    format!(
+      "{} {} = ({}) {}",
+      keyword,
+      fun.name,
+      self.fun_args(&fun.args),
+      block
+    )
+  }
+
+  fn enumeration(&mut self, enumeration: &Enum) -> String {
+    let head = format!(
+      "{}enum {}",
+      visibility_text(enumeration.visibility),
+      enumeration.name
+    );
+
+    if enumeration.variants.is_empty() {
+      return format!("{} {{}}", head);
+    }
+
+    self.indent += 1;
+
+    let mut lines = vec![];
+
+    for variant in enumeration.variants.iter() {
+      let mut text = variant.name.to_owned();
+
+      match variant.kind {
+        VariantKind::Unit => {}
+        VariantKind::Tuple(ref tys) => {
+          let tys = tys
+            .iter()
+            .map(|ty| self.ty(ty.kind()))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+          text.push_str(&format!("({})", tys));
+        }
+        VariantKind::Struct(ref fields) => {
+          text.push_str(&format!(" {}", self.fields(fields)));
+        }
+      }
+
+      if let Some(ref discriminant) = variant.discriminant {
+        text.push_str(&format!(" = {}", self.expr(discriminant)));
+      }
+
+      lines.push(format!("{}{},", self.pad(), text));
+    }
+
+    self.indent -= 1;
+
+    format!("{} {{\n{}\n{}}}", head, lines.join("\n"), self.pad())
+  }
+
+  fn field(&mut self, field: &Field) -> String {
+    format!("{}: {}", field.name, self.ty(field.ty.kind()))
+  }
+
+  // the fields are written on one line unless one of them is documented e.g
+  // `{ w: int, h: int }`
+  fn fields(&mut self, fields: &[Field]) -> String {
+    if fields.is_empty() {
+      return format!("{{}}");
+    }
+
+    if fields.iter().all(|field| field.docs.is_empty()) {
+      let fields = fields
+        .iter()
+        .map(|field| self.field(field))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+      return format!("{{ {} }}", fields);
+    }
+
+    self.indent += 1;
+
+    let mut lines = vec![];
+
+    for field in fields.iter() {
+      for doc in self.docs(&field.docs) {
+        lines.push(format!("{}{}", self.pad(), doc));
+      }
+
+      lines.push(format!("{}{},", self.pad(), self.field(field)));
+    }
+
+    self.indent -= 1;
+
+    format!("{{\n{}\n{}}}", lines.join("\n"), self.pad())
+  }
+
+  fn members(&mut self, head: String, members: Vec<String>) -> String {
+    match members.is_empty() {
+      true => format!("{} {{}}", head),
+      false => format!("{} {{\n{}\n{}}}", head, members.join("\n"), self.pad()),
+    }
+  }
+
+  fn capsule(&mut self, capsule: &Capsule) -> String {
+    let head = format!(
+      "{}capsule {}",
+      visibility_text(capsule.visibility),
+      capsule.name
+    );
+
+    self.indent += 1;
+
+    let mut members = vec![];
+
+    for member in capsule.members.iter() {
+      let text = match *member {
+        TraitMember::Field(ref field) => format!("{},", self.field(field)),
+        TraitMember::Method(ref method) => self.fun(method),
+      };
+
+      members.push(format!("{}{}", self.pad(), text));
+    }
+
+    self.indent -= 1;
+    self.members(head, members)
+  }
+
+  fn structure(&mut self, structure: &Struct) -> String {
+    let head = format!(
+      "{}struct {}",
+      visibility_text(structure.visibility),
+      structure.name
+    );
+
+    self.indent += 1;
+
+    let mut members = vec![];
+
+    for member in structure.members.iter() {
+      let text = match *member {
+        StructMember::Field(ref field) => format!("{},", self.field(field)),
+        StructMember::Method(ref method) => self.fun(method),
+        StructMember::StaticMethod(ref method) => {
+          format!("static {}", self.fun(method))
+        }
+      };
+
+      members.push(format!("{}{}", self.pad(), text));
+    }
+
+    self.indent -= 1;
+    self.members(head, members)
+  }
+
+  // e.g `macro twice { ($x:expr) => { $x * 2 } }`
+  fn macro_def(&mut self, def: &MacroDef) -> String {
+    if def.rules.is_empty() {
+      return format!("macro {} {{}}", def.name);
+    }
+
+    self.indent += 1;
+
+    let mut rules = vec![];
+
+    for rule in def.rules.iter() {
+      let template = match token_trees_text(&rule.template) {
+        template if template.is_empty() => format!("{{}}"),
+        template => format!("{{ {} }}", template),
+      };
+
+      rules.push(format!(
+        "{}({}) => {}",
+        self.pad(),
+        token_trees_text(&rule.pattern),
+        template
+      ));
+    }
+
+    self.indent -= 1;
+
+    format!(
+      "macro {} {{\n{}\n{}}}",
+      def.name,
+      rules.join("\n"),
+      self.pad()
+    )
+  }
+
+  fn exprs(&mut self, exprs: &[Box<Expr>]) -> String {
+    exprs
+      .iter()
+      .map(|expr| self.expr(expr))
+      .collect::<Vec<String>>()
+      .join(", ")
+  }
+
+  // writes an operand between parentheses when it binds less tightly than
+  // the operator it belongs to
+  fn operand(&mut self, expr: &Expr, precedence: PrecedenceKind) -> String {
+    let text = self.expr(expr);
+
+    match expr_precedence(expr) < precedence {
+      true => format!("({})", text),
+      false => text,
+    }
+  }
+
+  // the receiver of a dot, a number would be read as a real and a tuple
+  // index as a nested tuple index e.g `(1).len()`, `(t.0).x`
+  fn receiver(&mut self, expr: &Expr, is_tuple_index: bool) -> String {
+    match expr.kind {
+      ExprKind::Lit(LitKind::Int(_)) | ExprKind::Lit(LitKind::Real(_)) => {
+        format!("({})", self.expr(expr))
+      }
+      ExprKind::TupleIndex { .. } if !is_tuple_index => {
+        format!("({})", self.expr(expr))
+      }
+      _ => self.operand(expr, PrecedenceKind::Calling),
+    }
+  }
+
+  fn expr(&mut self, expr: &Expr) -> String {
+    match expr.kind {
+      ExprKind::Empty | ExprKind::Error => String::new(),
+      ExprKind::Closure(ref fun) => self.closure(fun),
+      ExprKind::Ident(ref name) => self.ident(name),
+      ExprKind::Lit(ref lit) => lit_text(lit),
+      ExprKind::Loop(ref kind) => self.loop_kind(kind),
+      ExprKind::LoopVar(ref name) => format!("${}", name),
+      ExprKind::Array { ref data, .. } => format!("[{}]", self.exprs(data)),
+      ExprKind::ArrayRepeat { ref value, ref len } => {
+        format!("[{}; {}]", self.expr(value), self.expr(len))
+      }
+      ExprKind::Assign { ref lhs, ref rhs } => format!(
+        "{} = {}",
+        self.operand(lhs, PrecedenceKind::Range),
+        self.expr(rhs)
+      ),
+      ExprKind::Await(ref expr) => {
+        format!("{}.await", self.receiver(expr, false))
+      }
+      ExprKind::BinOp {
+        ref lhs,
+        ref op,
+        ref rhs,
+        ..
+      } => {
+        let precedence = binop_precedence(op);
+
+        // a type followed by `<` would be read as generic e.g `(x as t) < y`
+        let lhs = match op {
+          BinOpKind::Lt if ends_with(lhs, &|expr| is_cast(expr)) => {
+            format!("({})", self.expr(lhs))
+          }
+          _ => self.operand(lhs, precedence),
+        };
+
+        let rhs = self.operand(rhs, above(precedence));
+
+        format!("{} {} {}", lhs, op.text(), rhs)
+      }
+      ExprKind::Call {
+        ref callee,
+        ref args,
+        ..
+      } => {
+        // a called member would be read as a method call e.g `(a.f)(x)`
+        let callee = match callee.kind {
+          ExprKind::MemberAccess { .. } => format!("({})", self.expr(callee)),
+          _ => self.operand(callee, PrecedenceKind::Calling),
+        };
+
+        format!("{}({})", callee, self.exprs(args))
+      }
+      ExprKind::Cast { ref expr, ref ty } => format!(
+        "{} as {}",
+        self.operand(expr, PrecedenceKind::Cast),
+        self.ty(ty.kind())
+      ),
+      ExprKind::CompoundAssign {
+        ref lhs,
+        ref op,
+        ref rhs,
+      } => format!(
+        "{} {}= {}",
+        self.operand(lhs, PrecedenceKind::Range),
+        op.text(),
+        self.expr(rhs)
+      ),
+      ExprKind::Hash { ref data } if data.is_empty() => format!(".{{}}"),
+      ExprKind::Hash { ref data } => {
+        let data = data
+          .iter()
+          .map(|(key, value)| {
+            format!("{} = {}", hash_key_text(key), self.expr(value))
+          })
+          .collect::<Vec<String>>()
+          .join(", ");
+
+        format!(".{{ {} }}", data)
+      }
+      ExprKind::IfElse {
+        ref conditions,
+        ref alternative,
+      } => {
+        let mut branches = conditions
+          .iter()
+          .map(|condition| format!("if {} {{}}", self.expr(condition)))
+          .collect::<Vec<String>>();
+
+        if let Some(ref alternative) = alternative {
+          branches.push(self.block(alternative, true));
+        }
+
+        branches.join(" else ")
+      }
+      ExprKind::Index {
+        ref index,
+        ref data,
+      } => format!(
+        "{}[{}]",
+        self.operand(data, PrecedenceKind::Calling),
+        self.expr(index)
+      ),
+      ExprKind::MemberAccess {
+        ref from,
+        ref access,
+      } => format!("{}.{}", self.receiver(from, false), access),
+      ExprKind::MethodCall {
+        ref receiver,
+        ref method,
+        ref args,
+      } => format!(
+        "{}.{}({})",
+        self.receiver(receiver, false),
+        method,
+        self.exprs(args)
+      ),
+      ExprKind::Path(ref path) => path.text(),
+      ExprKind::Range {
+        ref start,
+        ref end,
+        inclusive,
+      } => {
+        let start = match start {
+          Some(ref start) => self.operand(start, above(PrecedenceKind::Range)),
+          None => String::new(),
+        };
+
+        // a type would be glued to the operator e.g `(x as vec<int>)..y`
+        let start = match start.ends_with('>') {
+          true => format!("({})", start),
+          false => start,
+        };
+
+        let end = match end {
+          Some(ref end) => self.operand(end, above(PrecedenceKind::Range)),
+          None => String::new(),
+        };
+
+        let op = if inclusive { "..=" } else { ".." };
+
+        // the symbols would be glued to the operator e.g `a.. -b`, `a.. .{}`
+        match end.starts_with(|c| c == '-' || c == '!' || c == '.') {
+          true => format!("{}{} {}", start, op, end),
+          false => format!("{}{}{}", start, op, end),
+        }
+      }
+      ExprKind::StructLit {
+        ref name,
+        ref fields,
+      } => {
+        let fields = fields
+          .iter()
+          .map(|(field, value)| format!(".{} = {}", field, self.expr(value)))
+          .collect::<Vec<String>>()
+          .join(", ");
+
+        format!("{} {{ {} }}", self.expr(name), fields)
+      }
+      ExprKind::Try(ref expr) => {
+        format!("{}?", self.operand(expr, PrecedenceKind::Calling))
+      }
+      ExprKind::Tuple(ref exprs) if exprs.len() == 1 => {
+        format!("({},)", self.expr(&exprs[0]))
+      }
+      ExprKind::Tuple(ref exprs) => format!("({})", self.exprs(exprs)),
+      ExprKind::TupleIndex { ref from, index } => {
+        format!("{}.{}", self.receiver(from, true), index)
+      }
+      ExprKind::UnOp {
+        ref operand,
+        ref rhs,
+      } => {
+        let op = UnOpKind::text(operand.to_owned());
+
+        // the prefix operators are spaced to not be glued e.g `- -a`, `! -a`,
+        // `! .{}`
+        if is_prefix(rhs) {
+          return format!("{} {}", op, self.expr(rhs));
+        }
+
+        let rhs = self.operand(rhs, PrecedenceKind::Calling);
+
+        match rhs.starts_with('.') {
+          true => format!("{} {}", op, rhs),
+          false => format!("{}{}", op, rhs),
+        }
+      }
+    }
+  }
+
+  // the body of a closure is written after an arrow when it is a single
+  // expression e.g `(x) -> x * x`, the parameters are followed by an arrow
+  // when the first one could not be told apart from an assignment e.g
+  // `(x = 1) -> { }`
+  fn closure(&mut self, fun: &Fun) -> String {
+    let args = self.fun_args(&fun.args);
+
+    if let Some(body) = arrow_body(fun) {
+      return format!("({}) -> {}", args, self.expr(body));
+    }
+
+    let block = match fun.block {
+      Some(ref block) => self.block(block, *fun.ret_ty.kind() != TyKind::Void),
+      None => format!("{{}}"),
+    };
+
+    match fun.args.first() {
+      Some(arg)
+        if *arg.ty.kind() == TyKind::Unknown && arg.default.is_some() =>
+      {
+        format!("({}) -> {}", args, block)
+      }
+      _ => format!("({}) {}", args, block),
+    }
+  }
+
+  // e.g `\outer for 0..3 { }`, `for xs $x { }`, `while x < 3 { }`
+  fn loop_kind(&mut self, kind: &LoopKind) -> String {
+    let label = match kind.label() {
+      Some(label) => format!("\\{} ", label),
+      None => String::new(),
+    };
+
+    let text = match *kind {
+      LoopKind::LoopFor {
+        ref iterable,
+        ref iterator,
+        implicit,
+        ref block,
+        ..
+      } => {
+        let mut iterable_text = self.operand(iterable, PrecedenceKind::Range);
+
+        // an open range would take the iterator as its end e.g `for (0..) $x`
+        // and so would a block e.g `for (.{}) $x`, `for (loop { }) $x`
+        let is_iterator_ambiguous = !implicit
+          && match iterable.kind {
+            ExprKind::Range { end: None, .. } => true,
+            _ => iterable_text.ends_with('}'),
+          };
+
+        if is_iterator_ambiguous {
+          iterable_text = format!("({})", iterable_text);
+        } else if iterable_text.starts_with("(.")
+          || self.is_block_ambiguous(iterable, block)
+        {
+          iterable_text = parenthesize_condition(&iterable_text);
+        }
+
+        match implicit {
+          true => format!("for {} {}", iterable_text, self.block(block, false)),
+          false => format!(
+            "for {} {} {}",
+            iterable_text,
+            self.expr(iterator),
+            self.block(block, false)
+          ),
+        }
+      }
+      LoopKind::LoopLoop { ref block, .. } => {
+        format!("loop {}", self.block(block, false))
+      }
+      LoopKind::LoopWhile {
+        ref condition,
+        ref block,
+        ..
+      } => format!(
+        "while {} {}",
+        self.condition(condition, block),
+        self.block(block, false)
+      ),
+    };
+
+    format!("{}{}", label, text)
+  }
+
+  // e.g `(int, \x -> int)`, `(-> int)`, `(-> | int)`, `(->)`
+  fn ty(&mut self, ty: &TyKind) -> String {
+    match *ty {
+      TyKind::Unknown => format!("_"),
+      TyKind::Void => format!("void"),
+      TyKind::NameRefTy(ref name) => name.to_owned(),
+      TyKind::FunTy {
+        ref param_tys,
+        ref ret_ty,
+      } => {
+        let param_tys = param_tys
+          .iter()
+          .map(|param_ty| match param_ty {
+            TyKind::Unknown => format!("\\x"),
+            param_ty => self.ty(param_ty),
+          })
+          .collect::<Vec<String>>()
+          .join(", ");
+
+        match (param_tys.is_empty(), ret_ty.as_ref()) {
+          (true, TyKind::Void) => format!("(->)"),
+          (true, ret_ty) => format!("(-> {})", self.ty(ret_ty)),
+          (false, TyKind::Void) => format!("(-> | {})", param_tys),
+          (false, ret_ty) => format!("({} -> {})", param_tys, self.ty(ret_ty)),
+        }
+      }
+      TyKind::GenericTy {
+        ref name,
+        ref param_tys,
+      } => {
+        let param_tys = self.tys(param_tys);
+
+        // a `>>>` is not split e.g `vec<vec<vec<int>> >`
+        match param_tys.ends_with(">>") {
+          true => format!("{}<{} >", name, param_tys),
+          false => format!("{}<{}>", name, param_tys),
+        }
+      }
+      TyKind::PathTy(ref segments) => segments.join("::"),
+      TyKind::ArrayTy { ref ty, ref len } => {
+        format!("[{}; {}]", self.ty(ty), self.expr(len))
+      }
+      TyKind::SliceTy(ref ty) => format!("[{}]", self.ty(ty)),
+      TyKind::TupleTy(ref tys) if tys.len() == 1 => {
+        format!("({},)", self.ty(&tys[0]))
+      }
+      TyKind::TupleTy(ref tys) => format!("({})", self.tys(tys)),
+      TyKind::OptionalTy(ref ty) => format!("?{}", self.ty(ty)),
+      TyKind::RefTy(ref ty) => format!("ref {}", self.ty(ty)),
+    }
+  }
+
+  fn tys(&mut self, tys: &[TyKind]) -> String {
+    tys
+      .iter()
+      .map(|ty| self.ty(ty))
+      .collect::<Vec<String>>()
+      .join(", ")
+  }
+}
+
+// the statements that are not written e.g an error node
+fn omit_empty(stmts: &[Box<Stmt>]) -> Vec<&Stmt> {
+  stmts
+    .iter()
+    .map(|stmt| stmt.as_ref())
+    .filter(|stmt| match stmt.kind {
+      StmtKind::Empty | StmtKind::Error => false,
+      StmtKind::Expr(ref expr) => match expr.kind {
+        ExprKind::Empty | ExprKind::Error => false,
+        _ => true,
+      },
+      _ => true,
+    })
+    .collect()
+}
+
+fn above(precedence: PrecedenceKind) -> PrecedenceKind {
+  match precedence {
+    PrecedenceKind::Lowest => PrecedenceKind::Assignement,
+    PrecedenceKind::Assignement => PrecedenceKind::Range,
+    PrecedenceKind::Range => PrecedenceKind::LogicalOr,
+    PrecedenceKind::LogicalOr => PrecedenceKind::LogicalAnd,
+    PrecedenceKind::LogicalAnd => PrecedenceKind::Conditional,
+    PrecedenceKind::Conditional => PrecedenceKind::BitOr,
+    PrecedenceKind::BitOr => PrecedenceKind::BitAnd,
+    PrecedenceKind::BitAnd => PrecedenceKind::Shift,
+    PrecedenceKind::Shift => PrecedenceKind::Sum,
+    PrecedenceKind::Sum => PrecedenceKind::Exponent,
+    PrecedenceKind::Exponent => PrecedenceKind::Cast,
+    PrecedenceKind::Cast => PrecedenceKind::Unary,
+    PrecedenceKind::Unary => PrecedenceKind::Calling,
+    PrecedenceKind::Calling => PrecedenceKind::Index,
+    PrecedenceKind::Index | PrecedenceKind::Access => PrecedenceKind::Access,
+  }
+}
+
+fn binop_precedence(op: &BinOpKind) -> PrecedenceKind {
+  match op {
+    BinOpKind::Add | BinOpKind::Sub => PrecedenceKind::Sum,
+    BinOpKind::Mul | BinOpKind::Div | BinOpKind::Mod => {
+      PrecedenceKind::Exponent
+    }
+    BinOpKind::Lt
+    | BinOpKind::Gt
+    | BinOpKind::Le
+    | BinOpKind::Ge
+    | BinOpKind::Ne
+    | BinOpKind::EqEq => PrecedenceKind::Conditional,
+    BinOpKind::And => PrecedenceKind::LogicalAnd,
+    BinOpKind::Or => PrecedenceKind::LogicalOr,
+    BinOpKind::BitAnd => PrecedenceKind::BitAnd,
+    BinOpKind::BitOr => PrecedenceKind::BitOr,
+    BinOpKind::Shl | BinOpKind::Shr => PrecedenceKind::Shift,
+  }
+}
+
+// the loosest operator of an expression, an operand is written between
+// parentheses when it is looser than its position allows
+fn expr_precedence(expr: &Expr) -> PrecedenceKind {
+  match expr.kind {
+    ExprKind::Assign { .. } | ExprKind::CompoundAssign { .. } => {
+      PrecedenceKind::Assignement
+    }
+    ExprKind::BinOp { ref op, .. } => binop_precedence(op),
+    ExprKind::Cast { .. } => PrecedenceKind::Cast,
+    ExprKind::Closure(ref fun) if arrow_body(fun).is_some() => {
+      PrecedenceKind::Lowest
+    }
+    ExprKind::IfElse { .. } => PrecedenceKind::Lowest,
+    ExprKind::Range { .. } => PrecedenceKind::Range,
+    _ if is_prefix(expr) => PrecedenceKind::Unary,
+    _ => PrecedenceKind::Access,
+  }
+}
+
+// the operand written last without parentheses, which is followed by what
+// comes after the expression
+fn last_operand(expr: &Expr) -> Option<&Expr> {
+  match expr.kind {
+    ExprKind::Assign { ref rhs, .. }
+    | ExprKind::CompoundAssign { ref rhs, .. } => Some(rhs),
+    ExprKind::BinOp {
+      ref op, ref rhs, ..
+    } if expr_precedence(rhs) >= above(binop_precedence(op)) => Some(rhs),
+    ExprKind::Closure(ref fun) => arrow_body(fun),
+    ExprKind::Range {
+      end: Some(ref end), ..
+    } if expr_precedence(end) >= above(PrecedenceKind::Range) => Some(end),
+    ExprKind::UnOp { ref rhs, .. }
+      if is_prefix(rhs) || expr_precedence(rhs) >= PrecedenceKind::Calling =>
+    {
+      Some(rhs)
+    }
+    _ => None,
+  }
+}
+
+// the last operand when it is written in parentheses e.g `b + c` in
+// `a * (b + c)`
+fn last_group(expr: &Expr) -> Option<&Expr> {
+  match expr.kind {
+    ExprKind::BinOp {
+      ref op, ref rhs, ..
+    } if expr_precedence(rhs) < above(binop_precedence(op)) => Some(rhs),
+    ExprKind::Range {
+      end: Some(ref end), ..
+    } if expr_precedence(end) < above(PrecedenceKind::Range) => Some(end),
+    ExprKind::UnOp { ref rhs, .. }
+      if !is_prefix(rhs) && expr_precedence(rhs) < PrecedenceKind::Calling =>
+    {
+      Some(rhs)
+    }
+    _ => None,
+  }
+}
+
+fn ends_with(expr: &Expr, is_last: &dyn Fn(&Expr) -> bool) -> bool {
+  is_last(expr)
+    || last_operand(expr).map_or(false, |last| ends_with(last, is_last))
+}
+
+fn arrow_body(fun: &Fun) -> Option<&Expr> {
+  let block = fun.block.as_ref()?;
+  let stmts = omit_empty(&block.stmts);
+
+  match stmts.as_slice() {
+    [stmt] if stmt.attrs.is_empty() => match stmt.kind {
+      StmtKind::Expr(ref expr) => Some(expr),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+// a condition starting with a dot would be read as the parameters of a
+// closure e.g `if ((.{} & ())) { }`
+fn parenthesize_condition(text: &str) -> String {
+  match text.starts_with('.') {
+    true => format!("(({}))", text),
+    false => format!("({})", text),
+  }
+}
+
+fn is_cast(expr: &Expr) -> bool {
+  match expr.kind {
+    ExprKind::Cast { .. } => true,
+    _ => false,
+  }
+}
+
+// an expression written with a leading operator e.g `-a`, `!a`, `-1`
+fn is_prefix(expr: &Expr) -> bool {
+  match expr.kind {
+    ExprKind::UnOp { .. } => true,
+    ExprKind::Lit(LitKind::Int(int)) => int < 0,
+    ExprKind::Lit(LitKind::Real(real)) => real.is_sign_negative(),
+    _ => false,
+  }
+}
+
+fn label_text(label: &Option<String>) -> String {
+  match label {
+    Some(ref label) => format!(" \\{}", label),
+    None => String::new(),
+  }
+}
+
+fn visibility_text(visibility: bool) -> &'static str {
+  match visibility {
+    true => "pub ",
+    false => "",
+  }
+}
+
+fn hash_key_text(key: &HashKind) -> String {
+  match key {
+    HashKind::Str(ref value) => format!("\"{}\"", value),
+    key => key.text(),
+  }
+}
+
+// a real is written with its fraction e.g `1.0`
+fn lit_text(lit: &LitKind) -> String {
+  match lit {
+    LitKind::Real(real) => format!("{:?}", real),
+    lit => lit.text(),
+  }
+}
+
+fn token_text(token: &Token) -> String {
+  match token.kind {
+    TokenKind::AssignOp(BinaryKind::Eq) => format!("="),
+    TokenKind::AssignOp(ref op) => format!("{}=", op),
+    TokenKind::Literal(LiteralKind::StrBuffer(ref buffer)) => {
+      format!("\"{}\"", buffer)
+    }
+    TokenKind::Literal(LiteralKind::CharAscii(c)) => {
+      format!("'{}'", c)
+    }
+    ref kind => kind.text(),
+  }
+}
+
+// the tokens of a macro rule are spaced as the source would be written e.g
+// `($format:expr, $($arg:expr),+)`, `@std::io::print($format)`
+fn token_trees_text(trees: &[TokenTree]) -> String {
+  let tokens = TokenTree::flatten(trees.to_vec())
+    .into_iter()
+    .filter(|token| match token.kind {
+      TokenKind::DocComment(_)
+      | TokenKind::Indent(_)
+      | TokenKind::EOF
+      | TokenKind::EOL => false,
+      _ => true,
+    })
+    .collect::<Vec<Token>>();
+
+  let mut text = String::new();
+  // the open parens of the repetitions e.g `$( ... ),+`
+  let mut groups = vec![];
+  let mut is_repetition_end = false;
+
+  for (index, token) in tokens.iter().enumerate() {
+    let kind = &token.kind;
+    let before =
+      |back: usize| index.checked_sub(back).map(|index| &tokens[index].kind);
+    let after = tokens.get(index + 1).map(|token| &token.kind);
+    let is_delim = |kind: Option<&TokenKind>| match kind {
+      Some(TokenKind::OpenParen)
+      | Some(TokenKind::OpenBracket)
+      | Some(TokenKind::OpenBrace) => true,
+      _ => false,
+    };
+    let is_end = |kind: Option<&TokenKind>| match kind {
+      Some(TokenKind::Ident(_))
+      | Some(TokenKind::CloseParen)
+      | Some(TokenKind::CloseBracket) => true,
+      _ => false,
+    };
+    let is_fragment = |back: usize| match (before(back), before(back + 1)) {
+      (Some(TokenKind::Ident(_)), Some(TokenKind::Dollar)) => true,
+      _ => false,
+    };
+
+    let is_tight = match (before(1), kind) {
+      (None, _) => true,
+      (Some(TokenKind::Comma), _)
+      | (Some(TokenKind::Semicolon), _)
+      | (Some(TokenKind::CloseParen), _)
+        if is_repetition_end =>
+      {
+        match kind {
+          TokenKind::Comma
+          | TokenKind::Semicolon
+          | TokenKind::QuestionMark
+          | TokenKind::Binary(BinaryKind::Add)
+          | TokenKind::Binary(BinaryKind::Mul)
+          | TokenKind::CloseParen
+          | TokenKind::CloseBracket => true,
+          _ => false,
+        }
+      }
+      (_, TokenKind::Comma)
+      | (_, TokenKind::Semicolon)
+      | (_, TokenKind::Colon)
+      | (_, TokenKind::CloseParen)
+      | (_, TokenKind::CloseBracket) => true,
+      (Some(TokenKind::OpenParen), _) | (Some(TokenKind::OpenBracket), _) => {
+        true
+      }
+      (Some(TokenKind::Dollar), _)
+      | (Some(TokenKind::At), _)
+      | (Some(TokenKind::BackSlash), _)
+      | (Some(TokenKind::ColonColon), _)
+      | (_, TokenKind::ColonColon) => true,
+      (Some(TokenKind::Colon), _) => is_fragment(2),
+      (prev, TokenKind::OpenParen) | (prev, TokenKind::OpenBracket) => {
+        is_end(prev) || prev == Some(&TokenKind::Unary(UnaryKind::Not))
+      }
+      (Some(TokenKind::Unary(UnaryKind::Not)), TokenKind::Ident(_)) => true,
+      (Some(TokenKind::Unary(UnaryKind::Not)), _) => is_delim(Some(kind)),
+      (Some(TokenKind::Ident(_)), TokenKind::Unary(_)) => is_delim(after),
+      (prev, TokenKind::Binary(BinaryKind::Dot)) => is_end(prev),
+      (Some(TokenKind::Binary(BinaryKind::Dot)), _) => match kind {
+        TokenKind::Ident(_) | TokenKind::Await | TokenKind::OpenBrace => true,
+        _ => false,
+      },
+      (prev, TokenKind::QuestionMark) => is_end(prev),
+      _ => false,
+    };
+
+    if !is_tight {
+      text.push(' ');
+    }
+
+    text.push_str(&token_text(token));
+
+    is_repetition_end = match kind {
+      TokenKind::OpenParen => {
+        groups.push(before(1) == Some(&TokenKind::Dollar));
+        false
+      }
+      TokenKind::CloseParen => groups.pop().unwrap_or(false),
+      TokenKind::Comma | TokenKind::Semicolon => is_repetition_end,
+      TokenKind::OpenBracket | TokenKind::OpenBrace => {
+        groups.push(false);
+        false
+      }
+      TokenKind::CloseBracket | TokenKind::CloseBrace => {
+        groups.pop();
+        false
+      }
+      _ => false,
+    };
+  }
+
+  text
+}
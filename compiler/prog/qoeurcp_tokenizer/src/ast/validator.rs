@@ -0,0 +1,504 @@
+use super::ast::*;
+use super::visit::{self, Visitor};
+
+use std::collections::HashMap;
+use std::mem;
+
+// checks the rules that the grammar alone does not enforce e.g a `break`
+// must be written inside a loop and its label must name an enclosing loop
+//
+// the blocks of the targets other than the selected one are not compiled, so
+// they are not checked, every block is checked when no target is selected
+pub fn validate(ast: &Ast, target: Option<Target>) -> Vec<String> {
+  let mut validator = Validator::new();
+
+  validator.selected = target;
+
+  // the functions of a target can be called before their declaration, the
+  // javascript functions are declared outside of a block or in `wasm = { }`
+  for fun in ast
+    .for_target(target.unwrap_or(Target::Wasm))
+    .foreign_funs()
+  {
+    validator.declare_target_fun(fun);
+  }
+
+  validator.visit_ast(ast);
+  validator.errors
+}
+
+// the rules hold within a top-level item, so an item can be checked as soon
+// as it is parsed
+pub fn validate_item(stmt: &Stmt) -> Vec<String> {
+  Validator::new().validate_item(stmt)
+}
+
+pub struct Validator {
+  errors: Vec<String>,
+  is_async: bool,
+  loops: Vec<Option<String>>,
+  narrowed: Vec<String>,
+  optionals: Vec<String>,
+  returns_optional: Option<bool>,
+  selected: Option<Target>,
+  target: Option<Target>,
+  target_funs: HashMap<String, Target>,
+  unresolved: HashMap<String, usize>,
+}
+
+impl Validator {
+  pub fn new() -> Validator {
+    Self {
+      errors: vec![],
+      is_async: false,
+      loops: vec![],
+      narrowed: vec![],
+      optionals: vec![],
+      returns_optional: Some(false),
+      selected: None,
+      target: None,
+      target_funs: HashMap::new(),
+      unresolved: HashMap::new(),
+    }
+  }
+
+  // the functions of a target declared by the previous items are remembered,
+  // so the items of a stream can be checked one by one, a function used
+  // before its declaration is reported once it is declared
+  pub fn validate_item(&mut self, stmt: &Stmt) -> Vec<String> {
+    self.visit_stmt(stmt);
+
+    mem::replace(&mut self.errors, vec![])
+  }
+
+  // a javascript function can only be called from web assembly
+  fn declare_target_fun(&mut self, fun: &Fun) {
+    if fun.abi() != Some(Abi::JavaScript)
+      || self.target_funs.insert(fun.name(), Target::Wasm).is_some()
+    {
+      return;
+    }
+
+    let enclosing = self.target.take();
+
+    for _ in 0..self.unresolved.remove(&fun.name).unwrap_or(0) {
+      self.validate_target(&fun.name, Some(Target::Wasm));
+    }
+
+    self.target = enclosing;
+  }
+
+  // the names are known to hold a value inside the block e.g `foo` inside
+  // `if foo != nil { }`
+  fn validate_narrowed_block(&mut self, block: &Block, names: Vec<String>) {
+    let narrowed = self.narrowed.to_vec();
+
+    self.narrowed.extend(names);
+    self.visit_block(block);
+    self.narrowed = narrowed;
+  }
+
+  // a local is optional when its type is optional or its value may be `nil`
+  // e.g `val foo: ?int = 0;` or `mut foo := nil;`
+  fn declare_local(&mut self, local: &Local) {
+    let is_nil = self.may_be_nil(&local.value);
+
+    let is_optional = match local.ty.as_ref().map(|ty| ty.kind()) {
+      Some(TyKind::OptionalTy(_)) => true,
+      Some(TyKind::Unknown) | None => is_nil,
+      Some(ty) => {
+        if local.value.kind == ExprKind::Lit(LitKind::Nil) {
+          self.errors.push(format!(
+            "validator:fn:declare_local:error: `nil` can not be assigned to \
+             `{}` of type `{}`",
+            local.names.join(" "),
+            ty.text()
+          ));
+        }
+
+        false
+      }
+    };
+
+    for name in local.names() {
+      self.optionals.retain(|optional| *optional != name);
+      self.narrowed.retain(|narrowed| *narrowed != name);
+
+      if is_optional {
+        self.optionals.push(name);
+      }
+    }
+  }
+
+  fn may_be_nil(&self, expr: &Expr) -> bool {
+    match expr.kind {
+      ExprKind::Lit(LitKind::Nil) => true,
+      ExprKind::Ident(ref name) => {
+        self.optionals.contains(name) && !self.narrowed.contains(name)
+      }
+      _ => false,
+    }
+  }
+
+  // the value of an optional is only used once it is narrowed e.g
+  // `if foo { foo.bar; }` or propagated e.g `foo?.bar`
+  fn validate_unwrapped(&mut self, expr: &Expr) {
+    let operands: Vec<&Expr> = match expr.kind {
+      ExprKind::BinOp {
+        ref lhs,
+        ref op,
+        ref rhs,
+        ..
+      } => match op {
+        BinOpKind::And | BinOpKind::EqEq | BinOpKind::Ne | BinOpKind::Or => {
+          vec![]
+        }
+        _ => vec![lhs, rhs],
+      },
+      ExprKind::Call { ref callee, .. } => vec![callee],
+      ExprKind::Index { ref data, .. }
+      | ExprKind::MemberAccess { from: ref data, .. }
+      | ExprKind::MethodCall {
+        receiver: ref data, ..
+      }
+      | ExprKind::TupleIndex { from: ref data, .. } => vec![data],
+      ExprKind::UnOp {
+        operand: UnOpKind::Neg,
+        ref rhs,
+      }
+      | ExprKind::UnOp {
+        operand: UnOpKind::Deref,
+        ref rhs,
+      } => vec![rhs],
+      _ => vec![],
+    };
+
+    for operand in operands {
+      if let ExprKind::Ident(ref name) = operand.kind {
+        if self.may_be_nil(operand) {
+          self.errors.push(format!(
+            "validator:fn:validate_unwrapped:error: `{}` may be nil, narrow \
+             it with `if {} {{ }}` or propagate it with `{}?`",
+            name, name, name
+          ));
+        }
+      }
+    }
+  }
+
+  // a foreign function is only declared, its signature must be written out
+  // since there is no body to infer it from
+  fn validate_foreign_fun(&mut self, fun: &Fun) {
+    let abis = fun
+      .modifiers
+      .iter()
+      .filter(|modifier| modifier.abi().is_some())
+      .count();
+
+    if abis > 1 {
+      self.errors.push(format!(
+        "validator:fn:validate_foreign_fun:error: `{}` has more than one abi",
+        fun.name
+      ));
+    }
+
+    if !fun.is_foreign() {
+      return;
+    }
+
+    self.declare_target_fun(fun);
+
+    if fun.block.is_some() {
+      self.errors.push(format!(
+        "validator:fn:validate_foreign_fun:error: foreign function `{}` has a \
+         body",
+        fun.name
+      ));
+    }
+
+    let is_unknown = |ty: &Ty| *ty.kind() == TyKind::Unknown;
+
+    if is_unknown(&fun.ret_ty) || fun.args.iter().any(|arg| is_unknown(&arg.ty))
+    {
+      self.errors.push(format!(
+        "validator:fn:validate_foreign_fun:error: foreign function `{}` has \
+         an inferred signature",
+        fun.name
+      ));
+    }
+  }
+
+  fn validate_jump(&mut self, jump: &str, label: &Option<String>) {
+    match label {
+      _ if self.loops.is_empty() => self.errors.push(format!(
+        "validator:fn:validate_jump:error: `{}` outside of a loop",
+        jump
+      )),
+      Some(ref label)
+        if !self.loops.iter().any(|name| name.as_ref() == Some(label)) =>
+      {
+        self.errors.push(format!(
+          "validator:fn:validate_jump:error: `{}` to an unknown label `{}`",
+          jump, label
+        ))
+      }
+      _ => {}
+    }
+  }
+
+  // the api of a target is only used inside a block of the target e.g a
+  // `exp fun` or a `wasm::` path is used inside `wasm = { }`
+  fn validate_target(&mut self, name: &str, target: Option<Target>) {
+    match target {
+      Some(target) if self.target != Some(target) => self.errors.push(format!(
+        "validator:fn:validate_target:error: `{}` is only available in a \
+           `{} = {{ }}` block",
+        name,
+        target.text()
+      )),
+      _ => {}
+    }
+  }
+}
+
+// the rules are checked on the way down the tree, the nodes they are not
+// about are walked
+impl<'ast> Visitor<'ast> for Validator {
+  // an attribute is not code, its arguments are names e.g `|> derive: clone.`
+  fn visit_attr(&mut self, _attr: &'ast Attribute) {}
+
+  // the locals of a block and what is narrowed inside it are forgotten at
+  // the end of the block
+  fn visit_block(&mut self, block: &'ast Block) {
+    let optionals = self.optionals.to_vec();
+    let narrowed = self.narrowed.to_vec();
+
+    visit::walk_block(self, block);
+
+    self.optionals = optionals;
+    self.narrowed = narrowed;
+  }
+
+  fn visit_expr(&mut self, expr: &'ast Expr) {
+    self.validate_unwrapped(expr);
+
+    match expr.kind {
+      ExprKind::Await(_) if !self.is_async => self.errors.push(format!(
+        "validator:fn:visit_expr:error: `await` outside of an async function"
+      )),
+      ExprKind::Assign { ref lhs, ref rhs } => {
+        // `foo = nil;` undoes the narrowing of `foo`
+        if let ExprKind::Ident(ref name) = lhs.kind {
+          if self.may_be_nil(rhs) {
+            self.narrowed.retain(|narrowed| narrowed != name);
+          }
+        }
+      }
+      ExprKind::Try(_) if self.returns_optional == Some(false) => {
+        self.errors.push(format!(
+          "validator:fn:visit_expr:error: `?` in a function that does not \
+           return an optional"
+        ))
+      }
+      ExprKind::Ident(ref name) => {
+        let target = self.target_funs.get(name).copied();
+
+        if target.is_none() && self.target != Some(Target::Wasm) {
+          *self.unresolved.entry(name.to_owned()).or_insert(0) += 1;
+        }
+
+        self.validate_target(name, target)
+      }
+      _ => {}
+    }
+
+    match expr.kind {
+      // the name of a struct literal is not a value e.g `Point { .x = 1 }`
+      ExprKind::StructLit { ref fields, .. } => {
+        for (_, value) in fields.iter() {
+          self.visit_expr(value);
+        }
+      }
+      _ => visit::walk_expr(self, expr),
+    }
+  }
+
+  // the loops of the enclosing function are not reachable from its body and
+  // a closure is not async even inside an async function
+  fn visit_fun(&mut self, fun: &'ast Fun) {
+    self.validate_foreign_fun(fun);
+
+    let loops = mem::replace(&mut self.loops, vec![]);
+    let is_async =
+      mem::replace(&mut self.is_async, fun.has_modifier(&FunModifier::Async));
+
+    let optionals = mem::replace(
+      &mut self.optionals,
+      fun
+        .args
+        .iter()
+        .filter(|arg| is_optional(&arg.ty.kind()))
+        .map(|arg| arg.expr.text())
+        .collect(),
+    );
+
+    let narrowed = mem::replace(&mut self.narrowed, vec![]);
+
+    // an inferred return type may turn out to be optional
+    let returns_optional = mem::replace(
+      &mut self.returns_optional,
+      match fun.ret_ty.kind() {
+        TyKind::Unknown => None,
+        ty => Some(is_optional(&ty)),
+      },
+    );
+
+    visit::walk_fun(self, fun);
+
+    self.loops = loops;
+    self.is_async = is_async;
+    self.optionals = optionals;
+    self.narrowed = narrowed;
+    self.returns_optional = returns_optional;
+  }
+
+  // the name of a parameter declares it, it is not a use of the name
+  fn visit_fun_arg(&mut self, arg: &'ast FunArg) {
+    self.visit_ty(&arg.ty);
+
+    if let Some(ref default) = arg.default {
+      self.visit_expr(default);
+    }
+  }
+
+  fn visit_local(&mut self, local: &'ast Local) {
+    visit::walk_local(self, local);
+    self.declare_local(local);
+  }
+
+  // the iterator of a `for` loop declares it, it is not a use of the name
+  fn visit_loop(&mut self, kind: &'ast LoopKind) {
+    let block = match *kind {
+      LoopKind::LoopFor {
+        ref iterable,
+        ref block,
+        ..
+      } => {
+        self.visit_expr(iterable);
+        block
+      }
+      LoopKind::LoopLoop { ref block, .. } => block,
+      LoopKind::LoopWhile {
+        ref condition,
+        ref block,
+        ..
+      } => {
+        self.visit_expr(condition);
+        block
+      }
+    };
+
+    self.loops.push(kind.label());
+    self.visit_block(block);
+    self.loops.pop();
+  }
+
+  fn visit_path(&mut self, path: &'ast Path) {
+    let target = path.segments.iter().find_map(|name| Target::from(name));
+
+    self.validate_target(&path.segments.join("::"), target)
+  }
+
+  fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+    match stmt.kind {
+      StmtKind::Break { ref label, .. } => {
+        self.validate_jump("break", label);
+        visit::walk_stmt(self, stmt);
+      }
+      StmtKind::Continue(ref label) => self.validate_jump("continue", label),
+      StmtKind::IfBlock {
+        ref conditions,
+        ref alternative,
+      } => {
+        for (condition, block) in conditions.iter() {
+          self.visit_expr(condition);
+          self.validate_narrowed_block(block, narrowed_by(condition, true));
+        }
+
+        // e.g `if foo == nil { } else { foo.bar; }`
+        let names = match conditions.as_slice() {
+          [(condition, _)] => narrowed_by(condition, false),
+          _ => vec![],
+        };
+
+        match alternative {
+          Some(ref block) => self.validate_narrowed_block(block, names),
+          // e.g `if foo == nil { ret nil; } foo.bar;`
+          None if conditions.iter().all(|(_, block)| exits(block)) => {
+            self.narrowed.extend(names)
+          }
+          None => {}
+        }
+      }
+      StmtKind::TargetBlock { target, .. }
+        if self.selected.map_or(false, |selected| selected != target) => {}
+      StmtKind::TargetBlock { target, ref body } => {
+        let enclosing = mem::replace(&mut self.target, Some(target));
+
+        self.visit_block(body);
+        self.target = enclosing;
+      }
+      _ => visit::walk_stmt(self, stmt),
+    }
+  }
+}
+
+fn is_optional(ty: &TyKind) -> bool {
+  match ty {
+    TyKind::OptionalTy(_) => true,
+    _ => false,
+  }
+}
+
+// the names known to hold a value when the condition is true, or false e.g
+// `foo`, `foo != nil` and `foo && bar` when true or `foo == nil` when false
+fn narrowed_by(condition: &Expr, when: bool) -> Vec<String> {
+  match condition.kind {
+    ExprKind::Ident(ref name) if when => vec![name.to_owned()],
+    ExprKind::BinOp {
+      ref lhs,
+      ref op,
+      ref rhs,
+      ..
+    } => match (op, when) {
+      (BinOpKind::And, true) | (BinOpKind::Or, false) => {
+        [narrowed_by(lhs, when), narrowed_by(rhs, when)].concat()
+      }
+      (BinOpKind::Ne, true) | (BinOpKind::EqEq, false) => {
+        match (&lhs.kind, &rhs.kind) {
+          (ExprKind::Ident(ref name), ExprKind::Lit(LitKind::Nil))
+          | (ExprKind::Lit(LitKind::Nil), ExprKind::Ident(ref name)) => {
+            vec![name.to_owned()]
+          }
+          _ => vec![],
+        }
+      }
+      _ => vec![],
+    },
+    ExprKind::UnOp {
+      operand: UnOpKind::Not,
+      ref rhs,
+    } => narrowed_by(rhs, !when),
+    _ => vec![],
+  }
+}
+
+// the statements after the block are not reached from it
+fn exits(block: &Block) -> bool {
+  match block.stmts.last().map(|stmt| &stmt.kind) {
+    Some(StmtKind::Break { .. })
+    | Some(StmtKind::Continue(_))
+    | Some(StmtKind::Ret(_)) => true,
+    _ => false,
+  }
+}
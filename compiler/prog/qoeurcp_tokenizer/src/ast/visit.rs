@@ -0,0 +1,469 @@
+use super::ast::*;
+
+// walks the tree by reference, a pass overrides the `visit_*` methods of the
+// nodes it cares about and calls the matching `walk_*` function to keep
+// descending e.g a linter counting the calls of a program
+//
+// ```
+// impl<'ast> Visitor<'ast> for Calls {
+//   fn visit_expr(&mut self, expr: &'ast Expr) {
+//     if let ExprKind::Call { .. } = expr.kind {
+//       self.count += 1;
+//     }
+//
+//     walk_expr(self, expr);
+//   }
+// }
+// ```
+pub trait Visitor<'ast>: Sized {
+  fn visit_ast(&mut self, ast: &'ast Ast) {
+    walk_ast(self, ast)
+  }
+
+  fn visit_attr(&mut self, attr: &'ast Attribute) {
+    walk_attr(self, attr)
+  }
+
+  fn visit_block(&mut self, block: &'ast Block) {
+    walk_block(self, block)
+  }
+
+  fn visit_capsule(&mut self, capsule: &'ast Capsule) {
+    walk_capsule(self, capsule)
+  }
+
+  fn visit_enum(&mut self, enumeration: &'ast Enum) {
+    walk_enum(self, enumeration)
+  }
+
+  fn visit_enum_variant(&mut self, variant: &'ast EnumVariant) {
+    walk_enum_variant(self, variant)
+  }
+
+  fn visit_expr(&mut self, expr: &'ast Expr) {
+    walk_expr(self, expr)
+  }
+
+  fn visit_field(&mut self, field: &'ast Field) {
+    walk_field(self, field)
+  }
+
+  fn visit_fun(&mut self, fun: &'ast Fun) {
+    walk_fun(self, fun)
+  }
+
+  fn visit_fun_arg(&mut self, arg: &'ast FunArg) {
+    walk_fun_arg(self, arg)
+  }
+
+  fn visit_lit(&mut self, _lit: &'ast LitKind) {}
+
+  fn visit_local(&mut self, local: &'ast Local) {
+    walk_local(self, local)
+  }
+
+  fn visit_loop(&mut self, kind: &'ast LoopKind) {
+    walk_loop(self, kind)
+  }
+
+  fn visit_macro_def(&mut self, _def: &'ast MacroDef) {}
+
+  fn visit_path(&mut self, _path: &'ast Path) {}
+
+  fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+    walk_stmt(self, stmt)
+  }
+
+  fn visit_struct(&mut self, structure: &'ast Struct) {
+    walk_struct(self, structure)
+  }
+
+  fn visit_suite(&mut self, suite: &'ast Suite) {
+    walk_suite(self, suite)
+  }
+
+  fn visit_ty(&mut self, ty: &'ast Ty) {
+    walk_ty(self, ty)
+  }
+
+  fn visit_ty_kind(&mut self, kind: &'ast TyKind) {
+    walk_ty_kind(self, kind)
+  }
+
+  fn visit_type_alias(&mut self, alias: &'ast TypeAlias) {
+    walk_type_alias(self, alias)
+  }
+
+  fn visit_use(&mut self, import: &'ast Use) {
+    walk_use(self, import)
+  }
+}
+
+pub fn walk_ast<'ast, V: Visitor<'ast>>(visitor: &mut V, ast: &'ast Ast) {
+  for stmt in ast.nodes.iter() {
+    visitor.visit_stmt(stmt);
+  }
+}
+
+pub fn walk_attr<'ast, V: Visitor<'ast>>(
+  visitor: &mut V,
+  attr: &'ast Attribute,
+) {
+  for arg in attr.args.iter() {
+    visitor.visit_expr(arg);
+  }
+}
+
+pub fn walk_block<'ast, V: Visitor<'ast>>(visitor: &mut V, block: &'ast Block) {
+  for stmt in block.stmts.iter() {
+    visitor.visit_stmt(stmt);
+  }
+}
+
+pub fn walk_capsule<'ast, V: Visitor<'ast>>(
+  visitor: &mut V,
+  capsule: &'ast Capsule,
+) {
+  if let Some(ref stmt) = capsule.stmt {
+    visitor.visit_stmt(stmt);
+  }
+
+  for arg in capsule.args.iter() {
+    visitor.visit_fun_arg(arg);
+  }
+
+  for member in capsule.members.iter() {
+    match *member {
+      TraitMember::Field(ref field) => visitor.visit_field(field),
+      TraitMember::Method(ref method) => visitor.visit_fun(method),
+    }
+  }
+}
+
+pub fn walk_enum<'ast, V: Visitor<'ast>>(
+  visitor: &mut V,
+  enumeration: &'ast Enum,
+) {
+  for variant in enumeration.variants.iter() {
+    visitor.visit_enum_variant(variant);
+  }
+}
+
+pub fn walk_enum_variant<'ast, V: Visitor<'ast>>(
+  visitor: &mut V,
+  variant: &'ast EnumVariant,
+) {
+  match variant.kind {
+    VariantKind::Unit => {}
+    VariantKind::Tuple(ref tys) => {
+      for ty in tys.iter() {
+        visitor.visit_ty(ty);
+      }
+    }
+    VariantKind::Struct(ref fields) => {
+      for field in fields.iter() {
+        visitor.visit_field(field);
+      }
+    }
+  }
+
+  if let Some(ref discriminant) = variant.discriminant {
+    visitor.visit_expr(discriminant);
+  }
+}
+
+pub fn walk_expr<'ast, V: Visitor<'ast>>(visitor: &mut V, expr: &'ast Expr) {
+  match expr.kind {
+    ExprKind::Empty
+    | ExprKind::Error
+    | ExprKind::Ident(_)
+    | ExprKind::LoopVar(_) => {}
+    ExprKind::Closure(ref fun) => visitor.visit_fun(fun),
+    ExprKind::Lit(ref lit) => visitor.visit_lit(lit),
+    ExprKind::Loop(ref kind) => visitor.visit_loop(kind),
+    ExprKind::Path(ref path) => visitor.visit_path(path),
+    ExprKind::Array { ref data, .. } | ExprKind::Tuple(ref data) => {
+      for expr in data.iter() {
+        visitor.visit_expr(expr);
+      }
+    }
+    ExprKind::ArrayRepeat { ref value, ref len } => {
+      visitor.visit_expr(value);
+      visitor.visit_expr(len);
+    }
+    ExprKind::Assign { ref lhs, ref rhs }
+    | ExprKind::BinOp {
+      ref lhs, ref rhs, ..
+    }
+    | ExprKind::CompoundAssign {
+      ref lhs, ref rhs, ..
+    } => {
+      visitor.visit_expr(lhs);
+      visitor.visit_expr(rhs);
+    }
+    ExprKind::Await(ref expr)
+    | ExprKind::Try(ref expr)
+    | ExprKind::MemberAccess { from: ref expr, .. }
+    | ExprKind::TupleIndex { from: ref expr, .. }
+    | ExprKind::UnOp { rhs: ref expr, .. } => visitor.visit_expr(expr),
+    ExprKind::Call {
+      ref callee,
+      ref args,
+      ..
+    } => {
+      visitor.visit_expr(callee);
+
+      for arg in args.iter() {
+        visitor.visit_expr(arg);
+      }
+    }
+    ExprKind::Cast { ref expr, ref ty } => {
+      visitor.visit_expr(expr);
+      visitor.visit_ty(ty);
+    }
+    ExprKind::Hash { ref data } => {
+      for (_, value) in data.iter() {
+        visitor.visit_expr(value);
+      }
+    }
+    ExprKind::IfElse {
+      ref conditions,
+      ref alternative,
+    } => {
+      for condition in conditions.iter() {
+        visitor.visit_expr(condition);
+      }
+
+      if let Some(ref alternative) = alternative {
+        visitor.visit_block(alternative);
+      }
+    }
+    ExprKind::Index {
+      ref index,
+      ref data,
+    } => {
+      visitor.visit_expr(data);
+      visitor.visit_expr(index);
+    }
+    ExprKind::MethodCall {
+      ref receiver,
+      ref args,
+      ..
+    } => {
+      visitor.visit_expr(receiver);
+
+      for arg in args.iter() {
+        visitor.visit_expr(arg);
+      }
+    }
+    ExprKind::Range {
+      ref start, ref end, ..
+    } => {
+      if let Some(ref start) = start {
+        visitor.visit_expr(start);
+      }
+
+      if let Some(ref end) = end {
+        visitor.visit_expr(end);
+      }
+    }
+    ExprKind::StructLit {
+      ref name,
+      ref fields,
+    } => {
+      visitor.visit_expr(name);
+
+      for (_, value) in fields.iter() {
+        visitor.visit_expr(value);
+      }
+    }
+  }
+}
+
+pub fn walk_field<'ast, V: Visitor<'ast>>(visitor: &mut V, field: &'ast Field) {
+  visitor.visit_ty(&field.ty);
+
+  if let Some(ref expr) = field.expr {
+    visitor.visit_expr(expr);
+  }
+}
+
+pub fn walk_fun<'ast, V: Visitor<'ast>>(visitor: &mut V, fun: &'ast Fun) {
+  for arg in fun.args.iter() {
+    visitor.visit_fun_arg(arg);
+  }
+
+  visitor.visit_ty(&fun.ret_ty);
+
+  if let Some(ref block) = fun.block {
+    visitor.visit_block(block);
+  }
+}
+
+pub fn walk_fun_arg<'ast, V: Visitor<'ast>>(
+  visitor: &mut V,
+  arg: &'ast FunArg,
+) {
+  visitor.visit_expr(&arg.expr);
+  visitor.visit_ty(&arg.ty);
+
+  if let Some(ref default) = arg.default {
+    visitor.visit_expr(default);
+  }
+}
+
+pub fn walk_local<'ast, V: Visitor<'ast>>(visitor: &mut V, local: &'ast Local) {
+  if let Some(ref ty) = local.ty {
+    visitor.visit_ty(ty);
+  }
+
+  visitor.visit_expr(&local.value);
+}
+
+pub fn walk_loop<'ast, V: Visitor<'ast>>(
+  visitor: &mut V,
+  kind: &'ast LoopKind,
+) {
+  match *kind {
+    LoopKind::LoopFor {
+      ref iterable,
+      ref iterator,
+      ref block,
+      ..
+    } => {
+      visitor.visit_expr(iterable);
+      visitor.visit_expr(iterator);
+      visitor.visit_block(block);
+    }
+    LoopKind::LoopLoop { ref block, .. } => visitor.visit_block(block),
+    LoopKind::LoopWhile {
+      ref condition,
+      ref block,
+      ..
+    } => {
+      visitor.visit_expr(condition);
+      visitor.visit_block(block);
+    }
+  }
+}
+
+pub fn walk_stmt<'ast, V: Visitor<'ast>>(visitor: &mut V, stmt: &'ast Stmt) {
+  for attr in stmt.attrs.iter() {
+    visitor.visit_attr(attr);
+  }
+
+  match stmt.kind {
+    StmtKind::Empty | StmtKind::Error | StmtKind::Continue(_) => {}
+    StmtKind::Bench(ref fun)
+    | StmtKind::Fun(ref fun)
+    | StmtKind::Mock(ref fun)
+    | StmtKind::Test(ref fun) => visitor.visit_fun(fun),
+    StmtKind::Capsule(ref capsule) => visitor.visit_capsule(capsule),
+    StmtKind::Enum(ref enumeration) => visitor.visit_enum(enumeration),
+    StmtKind::Expr(ref expr) => visitor.visit_expr(expr),
+    StmtKind::Macro(ref def) => visitor.visit_macro_def(def),
+    StmtKind::Mut(ref local) | StmtKind::Val(ref local) => {
+      visitor.visit_local(local)
+    }
+    StmtKind::Ret(ref value) | StmtKind::Break { ref value, .. } => {
+      if let Some(ref value) = value {
+        visitor.visit_expr(value);
+      }
+    }
+    StmtKind::Struct(ref structure) => visitor.visit_struct(structure),
+    StmtKind::Suite(ref suite) => visitor.visit_suite(suite),
+    StmtKind::TypeAlias(ref alias) => visitor.visit_type_alias(alias),
+    StmtKind::Use(ref import) => visitor.visit_use(import),
+    StmtKind::IfBlock {
+      ref conditions,
+      ref alternative,
+    } => {
+      for (condition, block) in conditions.iter() {
+        visitor.visit_expr(condition);
+        visitor.visit_block(block);
+      }
+
+      if let Some(ref alternative) = alternative {
+        visitor.visit_block(alternative);
+      }
+    }
+    StmtKind::TargetBlock { ref body, .. } => visitor.visit_block(body),
+  }
+}
+
+pub fn walk_struct<'ast, V: Visitor<'ast>>(
+  visitor: &mut V,
+  structure: &'ast Struct,
+) {
+  if let Some(ref node) = structure.node {
+    visitor.visit_stmt(node);
+  }
+
+  for param_ty in structure.param_tys.iter() {
+    visitor.visit_fun_arg(param_ty);
+  }
+
+  for member in structure.members.iter() {
+    match *member {
+      StructMember::Field(ref field) => visitor.visit_field(field),
+      StructMember::Method(ref method)
+      | StructMember::StaticMethod(ref method) => visitor.visit_fun(method),
+    }
+  }
+}
+
+pub fn walk_suite<'ast, V: Visitor<'ast>>(visitor: &mut V, suite: &'ast Suite) {
+  visitor.visit_block(&suite.block);
+}
+
+pub fn walk_ty<'ast, V: Visitor<'ast>>(visitor: &mut V, ty: &'ast Ty) {
+  visitor.visit_ty_kind(ty.kind());
+}
+
+pub fn walk_ty_kind<'ast, V: Visitor<'ast>>(
+  visitor: &mut V,
+  kind: &'ast TyKind,
+) {
+  match *kind {
+    TyKind::Unknown
+    | TyKind::Void
+    | TyKind::NameRefTy(_)
+    | TyKind::PathTy(_) => {}
+    TyKind::FunTy {
+      ref param_tys,
+      ref ret_ty,
+    } => {
+      for param_ty in param_tys.iter() {
+        visitor.visit_ty_kind(param_ty);
+      }
+
+      visitor.visit_ty_kind(ret_ty);
+    }
+    TyKind::GenericTy { ref param_tys, .. }
+    | TyKind::TupleTy(ref param_tys) => {
+      for param_ty in param_tys.iter() {
+        visitor.visit_ty_kind(param_ty);
+      }
+    }
+    TyKind::ArrayTy { ref ty, ref len } => {
+      visitor.visit_ty_kind(ty);
+      visitor.visit_expr(len);
+    }
+    TyKind::SliceTy(ref ty)
+    | TyKind::OptionalTy(ref ty)
+    | TyKind::RefTy(ref ty) => visitor.visit_ty_kind(ty),
+  }
+}
+
+pub fn walk_type_alias<'ast, V: Visitor<'ast>>(
+  visitor: &mut V,
+  alias: &'ast TypeAlias,
+) {
+  visitor.visit_ty(&alias.ty);
+}
+
+pub fn walk_use<'ast, V: Visitor<'ast>>(visitor: &mut V, import: &'ast Use) {
+  for stmt in import.stmts.iter() {
+    visitor.visit_stmt(stmt);
+  }
+}
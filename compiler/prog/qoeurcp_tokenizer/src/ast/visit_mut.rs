@@ -0,0 +1,475 @@
+use super::ast::*;
+
+// walks the tree by mutable reference, a pass rewrites the nodes it cares
+// about in place e.g renaming a local in every expression that reads it
+//
+// ```
+// impl VisitorMut for Rename {
+//   fn visit_expr_mut(&mut self, expr: &mut Expr) {
+//     if let ExprKind::Ident(ref mut name) = expr.kind {
+//       *name = self.to.to_owned();
+//     }
+//
+//     walk_expr_mut(self, expr);
+//   }
+// }
+// ```
+pub trait VisitorMut: Sized {
+  fn visit_ast_mut(&mut self, ast: &mut Ast) {
+    walk_ast_mut(self, ast)
+  }
+
+  fn visit_attr_mut(&mut self, attr: &mut Attribute) {
+    walk_attr_mut(self, attr)
+  }
+
+  fn visit_block_mut(&mut self, block: &mut Block) {
+    walk_block_mut(self, block)
+  }
+
+  fn visit_capsule_mut(&mut self, capsule: &mut Capsule) {
+    walk_capsule_mut(self, capsule)
+  }
+
+  fn visit_enum_mut(&mut self, enumeration: &mut Enum) {
+    walk_enum_mut(self, enumeration)
+  }
+
+  fn visit_enum_variant_mut(&mut self, variant: &mut EnumVariant) {
+    walk_enum_variant_mut(self, variant)
+  }
+
+  fn visit_expr_mut(&mut self, expr: &mut Expr) {
+    walk_expr_mut(self, expr)
+  }
+
+  fn visit_field_mut(&mut self, field: &mut Field) {
+    walk_field_mut(self, field)
+  }
+
+  fn visit_fun_mut(&mut self, fun: &mut Fun) {
+    walk_fun_mut(self, fun)
+  }
+
+  fn visit_fun_arg_mut(&mut self, arg: &mut FunArg) {
+    walk_fun_arg_mut(self, arg)
+  }
+
+  fn visit_lit_mut(&mut self, _lit: &mut LitKind) {}
+
+  fn visit_local_mut(&mut self, local: &mut Local) {
+    walk_local_mut(self, local)
+  }
+
+  fn visit_loop_mut(&mut self, kind: &mut LoopKind) {
+    walk_loop_mut(self, kind)
+  }
+
+  fn visit_macro_def_mut(&mut self, _def: &mut MacroDef) {}
+
+  fn visit_path_mut(&mut self, _path: &mut Path) {}
+
+  fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+    walk_stmt_mut(self, stmt)
+  }
+
+  fn visit_struct_mut(&mut self, structure: &mut Struct) {
+    walk_struct_mut(self, structure)
+  }
+
+  fn visit_suite_mut(&mut self, suite: &mut Suite) {
+    walk_suite_mut(self, suite)
+  }
+
+  fn visit_ty_mut(&mut self, ty: &mut Ty) {
+    walk_ty_mut(self, ty)
+  }
+
+  fn visit_ty_kind_mut(&mut self, kind: &mut TyKind) {
+    walk_ty_kind_mut(self, kind)
+  }
+
+  fn visit_type_alias_mut(&mut self, alias: &mut TypeAlias) {
+    walk_type_alias_mut(self, alias)
+  }
+
+  fn visit_use_mut(&mut self, import: &mut Use) {
+    walk_use_mut(self, import)
+  }
+}
+
+pub fn walk_ast_mut<V: VisitorMut>(visitor: &mut V, ast: &mut Ast) {
+  for stmt in ast.nodes.iter_mut() {
+    visitor.visit_stmt_mut(stmt);
+  }
+}
+
+pub fn walk_attr_mut<V: VisitorMut>(visitor: &mut V, attr: &mut Attribute) {
+  for arg in attr.args.iter_mut() {
+    visitor.visit_expr_mut(arg);
+  }
+}
+
+pub fn walk_block_mut<V: VisitorMut>(visitor: &mut V, block: &mut Block) {
+  for stmt in block.stmts.iter_mut() {
+    visitor.visit_stmt_mut(stmt);
+  }
+}
+
+pub fn walk_capsule_mut<V: VisitorMut>(visitor: &mut V, capsule: &mut Capsule) {
+  if let Some(ref mut stmt) = capsule.stmt {
+    visitor.visit_stmt_mut(stmt);
+  }
+
+  for arg in capsule.args.iter_mut() {
+    visitor.visit_fun_arg_mut(arg);
+  }
+
+  for member in capsule.members.iter_mut() {
+    match *member {
+      TraitMember::Field(ref mut field) => visitor.visit_field_mut(field),
+      TraitMember::Method(ref mut method) => visitor.visit_fun_mut(method),
+    }
+  }
+}
+
+pub fn walk_enum_mut<V: VisitorMut>(visitor: &mut V, enumeration: &mut Enum) {
+  for variant in enumeration.variants.iter_mut() {
+    visitor.visit_enum_variant_mut(variant);
+  }
+}
+
+pub fn walk_enum_variant_mut<V: VisitorMut>(
+  visitor: &mut V,
+  variant: &mut EnumVariant,
+) {
+  match variant.kind {
+    VariantKind::Unit => {}
+    VariantKind::Tuple(ref mut tys) => {
+      for ty in tys.iter_mut() {
+        visitor.visit_ty_mut(ty);
+      }
+    }
+    VariantKind::Struct(ref mut fields) => {
+      for field in fields.iter_mut() {
+        visitor.visit_field_mut(field);
+      }
+    }
+  }
+
+  if let Some(ref mut discriminant) = variant.discriminant {
+    visitor.visit_expr_mut(discriminant);
+  }
+}
+
+pub fn walk_expr_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Expr) {
+  match expr.kind {
+    ExprKind::Empty
+    | ExprKind::Error
+    | ExprKind::Ident(_)
+    | ExprKind::LoopVar(_) => {}
+    ExprKind::Closure(ref mut fun) => visitor.visit_fun_mut(fun),
+    ExprKind::Lit(ref mut lit) => visitor.visit_lit_mut(lit),
+    ExprKind::Loop(ref mut kind) => visitor.visit_loop_mut(kind),
+    ExprKind::Path(ref mut path) => visitor.visit_path_mut(path),
+    ExprKind::Array { ref mut data, .. } | ExprKind::Tuple(ref mut data) => {
+      for expr in data.iter_mut() {
+        visitor.visit_expr_mut(expr);
+      }
+    }
+    ExprKind::ArrayRepeat {
+      ref mut value,
+      ref mut len,
+    } => {
+      visitor.visit_expr_mut(value);
+      visitor.visit_expr_mut(len);
+    }
+    ExprKind::Assign {
+      ref mut lhs,
+      ref mut rhs,
+    }
+    | ExprKind::BinOp {
+      ref mut lhs,
+      ref mut rhs,
+      ..
+    }
+    | ExprKind::CompoundAssign {
+      ref mut lhs,
+      ref mut rhs,
+      ..
+    } => {
+      visitor.visit_expr_mut(lhs);
+      visitor.visit_expr_mut(rhs);
+    }
+    ExprKind::Await(ref mut expr)
+    | ExprKind::Try(ref mut expr)
+    | ExprKind::MemberAccess {
+      from: ref mut expr, ..
+    }
+    | ExprKind::TupleIndex {
+      from: ref mut expr, ..
+    }
+    | ExprKind::UnOp {
+      rhs: ref mut expr, ..
+    } => visitor.visit_expr_mut(expr),
+    ExprKind::Call {
+      ref mut callee,
+      ref mut args,
+      ..
+    } => {
+      visitor.visit_expr_mut(callee);
+
+      for arg in args.iter_mut() {
+        visitor.visit_expr_mut(arg);
+      }
+    }
+    ExprKind::Cast {
+      ref mut expr,
+      ref mut ty,
+    } => {
+      visitor.visit_expr_mut(expr);
+      visitor.visit_ty_mut(ty);
+    }
+    ExprKind::Hash { ref mut data } => {
+      for (_, value) in data.iter_mut() {
+        visitor.visit_expr_mut(value);
+      }
+    }
+    ExprKind::IfElse {
+      ref mut conditions,
+      ref mut alternative,
+    } => {
+      for condition in conditions.iter_mut() {
+        visitor.visit_expr_mut(condition);
+      }
+
+      if let Some(ref mut alternative) = alternative {
+        visitor.visit_block_mut(alternative);
+      }
+    }
+    ExprKind::Index {
+      ref mut index,
+      ref mut data,
+    } => {
+      visitor.visit_expr_mut(data);
+      visitor.visit_expr_mut(index);
+    }
+    ExprKind::MethodCall {
+      ref mut receiver,
+      ref mut args,
+      ..
+    } => {
+      visitor.visit_expr_mut(receiver);
+
+      for arg in args.iter_mut() {
+        visitor.visit_expr_mut(arg);
+      }
+    }
+    ExprKind::Range {
+      ref mut start,
+      ref mut end,
+      ..
+    } => {
+      if let Some(ref mut start) = start {
+        visitor.visit_expr_mut(start);
+      }
+
+      if let Some(ref mut end) = end {
+        visitor.visit_expr_mut(end);
+      }
+    }
+    ExprKind::StructLit {
+      ref mut name,
+      ref mut fields,
+    } => {
+      visitor.visit_expr_mut(name);
+
+      for (_, value) in fields.iter_mut() {
+        visitor.visit_expr_mut(value);
+      }
+    }
+  }
+}
+
+pub fn walk_field_mut<V: VisitorMut>(visitor: &mut V, field: &mut Field) {
+  visitor.visit_ty_mut(&mut field.ty);
+
+  if let Some(ref mut expr) = field.expr {
+    visitor.visit_expr_mut(expr);
+  }
+}
+
+pub fn walk_fun_mut<V: VisitorMut>(visitor: &mut V, fun: &mut Fun) {
+  for arg in fun.args.iter_mut() {
+    visitor.visit_fun_arg_mut(arg);
+  }
+
+  visitor.visit_ty_mut(&mut fun.ret_ty);
+
+  if let Some(ref mut block) = fun.block {
+    visitor.visit_block_mut(block);
+  }
+}
+
+pub fn walk_fun_arg_mut<V: VisitorMut>(visitor: &mut V, arg: &mut FunArg) {
+  visitor.visit_expr_mut(&mut arg.expr);
+  visitor.visit_ty_mut(&mut arg.ty);
+
+  if let Some(ref mut default) = arg.default {
+    visitor.visit_expr_mut(default);
+  }
+}
+
+pub fn walk_local_mut<V: VisitorMut>(visitor: &mut V, local: &mut Local) {
+  if let Some(ref mut ty) = local.ty {
+    visitor.visit_ty_mut(ty);
+  }
+
+  visitor.visit_expr_mut(&mut local.value);
+}
+
+pub fn walk_loop_mut<V: VisitorMut>(visitor: &mut V, kind: &mut LoopKind) {
+  match *kind {
+    LoopKind::LoopFor {
+      ref mut iterable,
+      ref mut iterator,
+      ref mut block,
+      ..
+    } => {
+      visitor.visit_expr_mut(iterable);
+      visitor.visit_expr_mut(iterator);
+      visitor.visit_block_mut(block);
+    }
+    LoopKind::LoopLoop { ref mut block, .. } => visitor.visit_block_mut(block),
+    LoopKind::LoopWhile {
+      ref mut condition,
+      ref mut block,
+      ..
+    } => {
+      visitor.visit_expr_mut(condition);
+      visitor.visit_block_mut(block);
+    }
+  }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut Stmt) {
+  for attr in stmt.attrs.iter_mut() {
+    visitor.visit_attr_mut(attr);
+  }
+
+  match stmt.kind {
+    StmtKind::Empty | StmtKind::Error | StmtKind::Continue(_) => {}
+    StmtKind::Bench(ref mut fun)
+    | StmtKind::Fun(ref mut fun)
+    | StmtKind::Mock(ref mut fun)
+    | StmtKind::Test(ref mut fun) => visitor.visit_fun_mut(fun),
+    StmtKind::Capsule(ref mut capsule) => visitor.visit_capsule_mut(capsule),
+    StmtKind::Enum(ref mut enumeration) => visitor.visit_enum_mut(enumeration),
+    StmtKind::Expr(ref mut expr) => visitor.visit_expr_mut(expr),
+    StmtKind::Macro(ref mut def) => visitor.visit_macro_def_mut(def),
+    StmtKind::Mut(ref mut local) | StmtKind::Val(ref mut local) => {
+      visitor.visit_local_mut(local)
+    }
+    StmtKind::Ret(ref mut value) | StmtKind::Break { ref mut value, .. } => {
+      if let Some(ref mut value) = value {
+        visitor.visit_expr_mut(value);
+      }
+    }
+    StmtKind::Struct(ref mut structure) => visitor.visit_struct_mut(structure),
+    StmtKind::Suite(ref mut suite) => visitor.visit_suite_mut(suite),
+    StmtKind::TypeAlias(ref mut alias) => visitor.visit_type_alias_mut(alias),
+    StmtKind::Use(ref mut import) => visitor.visit_use_mut(import),
+    StmtKind::IfBlock {
+      ref mut conditions,
+      ref mut alternative,
+    } => {
+      for (condition, block) in conditions.iter_mut() {
+        visitor.visit_expr_mut(condition);
+        visitor.visit_block_mut(block);
+      }
+
+      if let Some(ref mut alternative) = alternative {
+        visitor.visit_block_mut(alternative);
+      }
+    }
+    StmtKind::TargetBlock { ref mut body, .. } => visitor.visit_block_mut(body),
+  }
+}
+
+pub fn walk_struct_mut<V: VisitorMut>(visitor: &mut V, structure: &mut Struct) {
+  if let Some(ref mut node) = structure.node {
+    visitor.visit_stmt_mut(node);
+  }
+
+  for param_ty in structure.param_tys.iter_mut() {
+    visitor.visit_fun_arg_mut(param_ty);
+  }
+
+  for member in structure.members.iter_mut() {
+    match *member {
+      StructMember::Field(ref mut field) => visitor.visit_field_mut(field),
+      StructMember::Method(ref mut method)
+      | StructMember::StaticMethod(ref mut method) => {
+        visitor.visit_fun_mut(method)
+      }
+    }
+  }
+}
+
+pub fn walk_suite_mut<V: VisitorMut>(visitor: &mut V, suite: &mut Suite) {
+  visitor.visit_block_mut(&mut suite.block);
+}
+
+pub fn walk_ty_mut<V: VisitorMut>(visitor: &mut V, ty: &mut Ty) {
+  visitor.visit_ty_kind_mut(ty.kind_mut());
+}
+
+pub fn walk_ty_kind_mut<V: VisitorMut>(visitor: &mut V, kind: &mut TyKind) {
+  match *kind {
+    TyKind::Unknown
+    | TyKind::Void
+    | TyKind::NameRefTy(_)
+    | TyKind::PathTy(_) => {}
+    TyKind::FunTy {
+      ref mut param_tys,
+      ref mut ret_ty,
+    } => {
+      for param_ty in param_tys.iter_mut() {
+        visitor.visit_ty_kind_mut(param_ty);
+      }
+
+      visitor.visit_ty_kind_mut(ret_ty);
+    }
+    TyKind::GenericTy {
+      ref mut param_tys, ..
+    }
+    | TyKind::TupleTy(ref mut param_tys) => {
+      for param_ty in param_tys.iter_mut() {
+        visitor.visit_ty_kind_mut(param_ty);
+      }
+    }
+    TyKind::ArrayTy {
+      ref mut ty,
+      ref mut len,
+    } => {
+      visitor.visit_ty_kind_mut(ty);
+      visitor.visit_expr_mut(len);
+    }
+    TyKind::SliceTy(ref mut ty)
+    | TyKind::OptionalTy(ref mut ty)
+    | TyKind::RefTy(ref mut ty) => visitor.visit_ty_kind_mut(ty),
+  }
+}
+
+pub fn walk_type_alias_mut<V: VisitorMut>(
+  visitor: &mut V,
+  alias: &mut TypeAlias,
+) {
+  visitor.visit_ty_mut(&mut alias.ty);
+}
+
+pub fn walk_use_mut<V: VisitorMut>(visitor: &mut V, import: &mut Use) {
+  for stmt in import.stmts.iter_mut() {
+    visitor.visit_stmt_mut(stmt);
+  }
+}
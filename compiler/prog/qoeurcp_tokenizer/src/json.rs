@@ -0,0 +1,77 @@
+use crate::ast::{Ast, NodeIds};
+use crate::token::TokenQueue;
+
+use serde_json::Value;
+
+// the version of the json schema of the tokens and the tree, it is bumped
+// whenever a node is added, removed or renamed, the schema is described in
+// `src/doc/ast_json.md`
+pub static SCHEMA_VERSION: u64 = 1;
+
+// a document holds the tree of a program and the version of its schema e.g
+// `{ "version": 1, "ast": { "nodes": [...], "docs": [] } }`
+#[derive(Serialize)]
+struct AstDocument<'a> {
+  version: u64,
+  ast: &'a Ast,
+}
+
+#[derive(Serialize)]
+struct TokensDocument<'a> {
+  version: u64,
+  tokens: &'a TokenQueue,
+}
+
+pub fn ast_to_json(ast: &Ast) -> Result<String, String> {
+  let document = AstDocument {
+    version: SCHEMA_VERSION,
+    ast,
+  };
+
+  serde_json::to_string_pretty(&document)
+    .map_err(|error| format!("json:fn:ast_to_json:error: {}", error))
+}
+
+// the ids of the nodes are not trusted, a code generator can leave them out,
+// so the tree is numbered again as if it had been parsed
+pub fn ast_from_json(json: &str) -> Result<Ast, String> {
+  let mut document = serde_json::from_str::<Value>(json)
+    .map_err(|error| format!("json:fn:ast_from_json:error: {}", error))?;
+
+  match document.get("version").and_then(|version| version.as_u64()) {
+    Some(version) if version == SCHEMA_VERSION => {}
+    Some(version) => {
+      return Err(format!(
+        "json:fn:ast_from_json:error: the schema version {} is not supported, \
+         the version {} is expected",
+        version, SCHEMA_VERSION
+      ))
+    }
+    None => {
+      return Err(format!(
+        "json:fn:ast_from_json:error: the schema version is missing"
+      ))
+    }
+  }
+
+  let ast = document.get_mut("ast").map(Value::take).ok_or(format!(
+    "json:fn:ast_from_json:error: the `ast` field is missing"
+  ))?;
+
+  let mut ast = serde_json::from_value::<Ast>(ast)
+    .map_err(|error| format!("json:fn:ast_from_json:error: {}", error))?;
+
+  NodeIds::new().assign_ast(&mut ast);
+
+  Ok(ast)
+}
+
+pub fn tokens_to_json(tokens: &TokenQueue) -> Result<String, String> {
+  let document = TokensDocument {
+    version: SCHEMA_VERSION,
+    tokens,
+  };
+
+  serde_json::to_string_pretty(&document)
+    .map_err(|error| format!("json:fn:tokens_to_json:error: {}", error))
+}
@@ -17,6 +17,7 @@ extern crate serde_derive;
 
 pub mod ast;
 mod buffer_queue;
+mod json;
 
 #[macro_use]
 mod macros;
@@ -31,27 +32,21 @@ mod tokenizer;
 mod tree_builder;
 mod util;
 
+pub use self::json::{
+  ast_from_json, ast_to_json, tokens_to_json, SCHEMA_VERSION,
+};
 pub use self::token::{Token, TokenPrinter, TokenQueue, TokenSink};
 pub use self::tokenizer::{Tokenizer, TokenizerOpts};
 pub use self::tree_builder::{TreeBuilder, TreeSink};
 
 use self::ast::{Ast, Stmt};
 
+use qoeurcp_span::Span;
+
 use std::borrow::Cow;
-use std::ops::Deref;
 
 use tendril::StrTendril;
 
-#[derive(Clone, Debug)]
-pub struct Handle(Vec<Box<Stmt>>);
-
-impl Deref for Handle {
-  type Target = Vec<Box<Stmt>>;
-  fn deref(&self) -> &Vec<Box<Stmt>> {
-    &self.0
-  }
-}
-
 pub trait ParseResult {
   type Sink: TreeSink + Default;
   fn get_result(sink: Self::Sink) -> Self;
@@ -60,29 +55,29 @@ pub trait ParseResult {
 #[derive(Debug)]
 pub struct Tree {
   pub ast: Box<Ast>,
-  pub stmts: Handle,
+  pub errors: Vec<(String, Span)>,
 }
 
 impl Tree {
   pub fn new() -> Tree {
     Self {
       ast: box Ast::new(vec![]),
-      stmts: Handle(vec![]),
+      errors: vec![],
     }
   }
 }
 
 impl TreeSink for Tree {
-  type Handle = Handle;
-
-  fn get_stmts(&mut self) -> Self::Handle {
-    self.stmts.clone()
+  fn parse_error(&mut self, msg: Cow<'static, str>, span: Span) {
+    self.errors.push((msg.into_owned(), span));
   }
 
-  fn parse_error(&mut self, _msg: Cow<'static, str>) {}
+  fn append(&mut self, stmt: Box<Stmt>) {
+    self.ast.add(stmt);
+  }
 
-  fn ast(&mut self, ast: Box<Ast>) {
-    self.ast = ast;
+  fn append_docs(&mut self, docs: Vec<String>) {
+    self.ast.docs.extend(docs);
   }
 }
 
@@ -113,8 +108,8 @@ pub fn tokenize(file: &str) -> TokenQueue {
   };
 
   let buffer = StrTendril::from(file);
-  let token_printer = TokenPrinter::new();
-  let mut tokenizer = Tokenizer::new(token_printer, opts);
+  let token_queue = TokenQueue::new();
+  let mut tokenizer = Tokenizer::new(token_queue, opts);
   let _ = tokenizer.feed(buffer.try_reinterpret().unwrap());
   let _ = tokenizer.end();
 
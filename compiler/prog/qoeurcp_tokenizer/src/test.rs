@@ -1 +1,2325 @@
+use crate::ast::*;
+use crate::token::{BinaryKind, LiteralKind, TokenKind};
+use crate::{ast_from_json, ast_to_json, tokenize, tokens_to_json};
+use crate::{parse, Tokenizer, TokenizerOpts, Tree, TreeBuilder};
 
+use qoeurcp_span::Span;
+use tendril::StrTendril;
+
+// prints the tree of an expression as a s-expression e.g `(+ a (* b c))`
+fn shape(expr: &Expr) -> String {
+  match expr.kind {
+    ExprKind::Ident(ref name) => format!("{}", name),
+    ExprKind::Lit(ref lit) => format!("{}", lit.text()),
+    ExprKind::Array { ref data, .. } => format!("[{}]", shapes(data)),
+    ExprKind::ArrayRepeat { ref value, ref len } => {
+      format!("[{}; {}]", shape(value), shape(len))
+    }
+    ExprKind::Assign { ref lhs, ref rhs } => {
+      format!("(= {} {})", shape(lhs), shape(rhs))
+    }
+    ExprKind::Await(ref expr) => format!("(await {})", shape(expr)),
+    ExprKind::BinOp {
+      ref lhs,
+      ref op,
+      ref rhs,
+      ..
+    } => format!("({} {} {})", op.text(), shape(lhs), shape(rhs)),
+    ExprKind::Call {
+      ref callee,
+      ref args,
+      ..
+    } => {
+      let args = args
+        .iter()
+        .map(|arg| shape(arg))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+      format!("(call {} {})", shape(callee), args)
+    }
+    ExprKind::Cast { ref expr, ref ty } => {
+      format!("(as {} {})", shape(expr), ty.text())
+    }
+    ExprKind::Closure(ref fun) => {
+      let args = fun
+        .args
+        .iter()
+        .map(|arg| arg.expr.text())
+        .collect::<Vec<String>>()
+        .join(" ");
+      let stmts = fun
+        .block
+        .as_ref()
+        .map_or(vec![], |block| stmt_kinds(&block.stmts));
+
+      format!("(closure ({}) {})", args, stmts.join("; "))
+    }
+    ExprKind::CompoundAssign {
+      ref lhs,
+      ref op,
+      ref rhs,
+    } => format!("({}= {} {})", op.text(), shape(lhs), shape(rhs)),
+    ExprKind::Hash { ref data } => {
+      let data = data
+        .iter()
+        .map(|(key, value)| format!("{} = {}", key.text(), shape(value)))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+      format!(".{{{}}}", data)
+    }
+    ExprKind::Index {
+      ref index,
+      ref data,
+    } => format!("([] {} {})", shape(data), shape(index)),
+    ExprKind::Loop(LoopKind::LoopFor {
+      ref iterable,
+      ref iterator,
+      implicit,
+      ref block,
+      ..
+    }) => {
+      // an implicit loop variable is written between parentheses
+      let iterator = match implicit {
+        true => format!("({})", shape(iterator)),
+        false => shape(iterator),
+      };
+
+      format!(
+        "(for {} {} {})",
+        shape(iterable),
+        iterator,
+        stmt_kinds(&block.stmts).join("; ")
+      )
+    }
+    ExprKind::LoopVar(ref name) => format!("${}", name),
+    ExprKind::MemberAccess {
+      ref from,
+      ref access,
+    } => format!("(. {} {})", shape(from), access),
+    ExprKind::MethodCall {
+      ref receiver,
+      ref method,
+      ref args,
+    } => {
+      let args = args
+        .iter()
+        .map(|arg| shape(arg))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+      format!("(.call {} {} {})", shape(receiver), method, args)
+    }
+    ExprKind::Path(ref path) => path.text(),
+    ExprKind::Range {
+      ref start,
+      ref end,
+      inclusive,
+    } => {
+      let op = if inclusive { "..=" } else { ".." };
+      let start = start.as_ref().map_or(format!("_"), |start| shape(start));
+      let end = end.as_ref().map_or(format!("_"), |end| shape(end));
+
+      format!("({} {} {})", op, start, end)
+    }
+    ExprKind::StructLit {
+      ref name,
+      ref fields,
+    } => {
+      let fields = fields
+        .iter()
+        .map(|(field, value)| format!("({} {})", field, shape(value)))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+      format!("(struct {} {})", shape(name), fields)
+    }
+    ExprKind::Try(ref expr) => format!("(? {})", shape(expr)),
+    ExprKind::Tuple(ref exprs) => format!("(tuple {})", shapes(exprs)),
+    ExprKind::TupleIndex { ref from, index } => {
+      format!("(. {} {})", shape(from), index)
+    }
+    ExprKind::UnOp {
+      ref operand,
+      ref rhs,
+    } => format!("({} {})", UnOpKind::text(operand.to_owned()), shape(rhs)),
+    ref kind => format!("{:?}", kind),
+  }
+}
+
+fn shapes(exprs: &Vec<Box<Expr>>) -> String {
+  exprs
+    .iter()
+    .map(|expr| shape(expr))
+    .collect::<Vec<String>>()
+    .join(" ")
+}
+
+fn parse_expr_shape(src: &str) -> String {
+  let tree = parse(src);
+
+  match tree.ast.nodes.first().map(|stmt| &stmt.kind) {
+    Some(StmtKind::Expr(ref expr)) => shape(expr),
+    kind => panic!("an expression is expected, found {:?}", kind),
+  }
+}
+
+// the kinds of the tokens without the indentation and the end of file
+fn token_kinds(src: &str) -> Vec<TokenKind> {
+  tokenize(src)
+    .tokens
+    .into_iter()
+    .map(|token| token.kind)
+    .filter(|kind| match kind {
+      TokenKind::Indent(_) | TokenKind::EOF => false,
+      _ => true,
+    })
+    .collect()
+}
+
+#[test]
+fn tokenize_the_char_ending_a_number_or_an_ident() {
+  let int =
+    |value: &str| TokenKind::Literal(LiteralKind::IntNumber(value.into()));
+  let ident = |name: &str| TokenKind::Ident(name.into());
+
+  assert_eq!(
+    token_kinds("f(1,2);"),
+    vec![
+      ident("f"),
+      TokenKind::OpenParen,
+      int("1"),
+      TokenKind::Comma,
+      int("2"),
+      TokenKind::CloseParen,
+      TokenKind::Semicolon,
+    ]
+  );
+
+  assert_eq!(
+    token_kinds("a+b;"),
+    vec![
+      ident("a"),
+      TokenKind::Binary(BinaryKind::Add),
+      ident("b"),
+      TokenKind::Semicolon,
+    ]
+  );
+}
+
+#[test]
+fn parse_enums() {
+  let tree = parse(
+    "enum Shape {
+      Empty,
+        Circle(real) = 2,
+      Rect { .w: int, .h: int } = 4,
+    }
+    Shape::Circle(1.5);",
+  );
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+
+  let shape_enum = match tree.ast.nodes[0].kind {
+    StmtKind::Enum(ref shape_enum) => shape_enum,
+    ref kind => panic!("an enum is expected, found {:?}", kind),
+  };
+
+  let variants = shape_enum
+    .variants
+    .iter()
+    .map(|variant| {
+      let discriminant = variant
+        .discriminant
+        .as_ref()
+        .map_or(format!("_"), |discriminant| shape(discriminant));
+
+      format!("{}{} {}", variant.name, variant.kind.text(), discriminant)
+    })
+    .collect::<Vec<String>>();
+
+  assert_eq!(
+    variants,
+    vec!["Empty _", "Circle(real) 2", "Rect { .w: int, .h: int } 4"]
+  );
+
+  assert_eq!(
+    stmt_kinds(&tree.ast.nodes)[1..],
+    ["(call Shape::Circle 1.5)"]
+  );
+}
+
+#[test]
+fn parse_closures() {
+  let tree = parse(
+    "val add: (int, int -> int) = (x, y) { x + y };
+    val one := () { 1 };
+    val sq := (x) -> x * x;
+    val h := (x: int, y) { val z := x; z + y };
+    map(xs, (x) { x + 1 });
+    fun f: (int -> (int -> int)) = (n) { (x) { x + n } }",
+  );
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+  assert_eq!(
+    stmt_kinds(&tree.ast.nodes)[..5],
+    [
+      "val add = (closure (x y) (+ x y))",
+      "val one = (closure () 1)",
+      "val sq = (closure (x) (* x x))",
+      "val h = (closure (x y) val z = x; (+ z y))",
+      "(call map xs (closure (x) (+ x 1)))",
+    ]
+  );
+
+  match tree.ast.nodes[3].kind {
+    StmtKind::Val(ref local) => match local.value.kind {
+      ExprKind::Closure(ref fun) => {
+        assert_eq!(fun.args[0].ty.text(), "int");
+        assert_eq!(fun.args[1].ty.text(), "Unknown");
+      }
+      ref kind => panic!("a closure is expected, found {:?}", kind),
+    },
+    ref kind => panic!("a value is expected, found {:?}", kind),
+  }
+
+  match tree.ast.nodes[5].kind {
+    StmtKind::Fun(ref fun) => {
+      let block = fun.block.as_ref().unwrap();
+
+      assert_eq!(fun.ret_ty.text(), "(int -> int)");
+      assert_eq!(stmt_kinds(&block.stmts), vec!["(closure (x) (+ x n))"]);
+    }
+    ref kind => panic!("a function is expected, found {:?}", kind),
+  }
+}
+
+fn fun_args(stmt: &Stmt) -> Vec<String> {
+  match stmt.kind {
+    StmtKind::Fun(ref fun) => fun
+      .args
+      .iter()
+      .map(|arg| {
+        let default = arg
+          .default
+          .as_ref()
+          .map_or(format!(""), |default| format!(" = {}", shape(default)));
+
+        format!("{}: {}{}", arg.expr.text(), arg.ty.text(), default)
+      })
+      .collect(),
+    ref kind => panic!("a function is expected, found {:?}", kind),
+  }
+}
+
+#[test]
+fn make_fun_stmt_merges_the_signature() {
+  let tree = parse(
+    "fun scale: (-> int | x: int, factor: int = 2) = (x, factor) { }
+    fun f: (x: int) = (x: real, y) { }",
+  );
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+  assert_eq!(
+    fun_args(&tree.ast.nodes[0]),
+    vec!["x: int", "factor: int = 2"]
+  );
+  assert_eq!(fun_args(&tree.ast.nodes[1]), vec!["x: real", "y: Unknown"]);
+
+  match tree.ast.nodes[1].kind {
+    StmtKind::Fun(ref fun) => assert_eq!(fun.ret_ty.text(), "void"),
+    ref kind => panic!("a function is expected, found {:?}", kind),
+  }
+
+  // the default value of an argument is kept over the one of the signature
+  let sig = make_fun_sig(
+    vec![make_fun_sig_arg(
+      Some("x".into()),
+      make_name_ref_ty("int"),
+      Some(make_lit_int_expr(1)),
+    )],
+    make_name_ref_ty("int"),
+  );
+  let mut arg =
+    make_fun_arg(make_ident_expr("x"), make_unknown_ty(TyKind::Unknown));
+
+  arg.default = Some(make_lit_int_expr(5));
+
+  let stmt = make_fun_stmt(make_ident_expr("g"), vec![], sig, vec![arg], None);
+
+  assert_eq!(fun_args(&stmt), vec!["x: int = 5"]);
+}
+
+fn ty_shape(kind: &TyKind) -> String {
+  let tys = |kinds: &Vec<TyKind>| {
+    kinds
+      .iter()
+      .map(|kind| ty_shape(kind))
+      .collect::<Vec<String>>()
+      .join(" ")
+  };
+
+  match *kind {
+    TyKind::NameRefTy(ref name) => format!("{}", name),
+    TyKind::PathTy(ref segments) => segments.join("::"),
+    TyKind::RefTy(ref ty) => format!("(ref {})", ty_shape(ty)),
+    TyKind::OptionalTy(ref ty) => format!("(? {})", ty_shape(ty)),
+    TyKind::SliceTy(ref ty) => format!("(slice {})", ty_shape(ty)),
+    TyKind::ArrayTy { ref ty, ref len } => {
+      format!("(array {} {})", ty_shape(ty), shape(len))
+    }
+    TyKind::TupleTy(ref kinds) => format!("(tuple {})", tys(kinds)),
+    TyKind::GenericTy {
+      ref name,
+      ref param_tys,
+    } => format!("({} {})", name, tys(param_tys)),
+    TyKind::FunTy {
+      ref param_tys,
+      ref ret_ty,
+    } => format!("(fun ({}) {})", tys(param_tys), ty_shape(ret_ty)),
+    ref kind => kind.text(),
+  }
+}
+
+#[test]
+fn parse_ty_exprs() {
+  let tree = parse(
+    "val a: ref vec<int> = x;
+    val b: ?A = x;
+    val c: [int] = x;
+    val d: [int; 3] = x;
+    val e: (int, real) = x;
+    val f: () = x;
+    val g: map<str, [real]> = x;
+    val h: matrix::mat4 = x;
+    val i: (int, int -> int) = x;
+    val j: (-> ?int) = x;
+    val k: ?ref [map<str, (int, ?real)>; 2] = x;",
+  );
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+
+  let tys = tree
+    .ast
+    .nodes
+    .iter()
+    .map(|stmt| match stmt.kind {
+      StmtKind::Val(ref local) => ty_shape(local.ty.as_ref().unwrap().kind()),
+      ref kind => panic!("a value is expected, found {:?}", kind),
+    })
+    .collect::<Vec<String>>();
+
+  assert_eq!(
+    tys,
+    vec![
+      "(ref (vec int))",
+      "(? A)",
+      "(slice int)",
+      "(array int 3)",
+      "(tuple int real)",
+      "(tuple )",
+      "(map str (slice real))",
+      "matrix::mat4",
+      "(fun (int int) int)",
+      "(fun () (? int))",
+      "(? (ref (array (map str (tuple int (? real))) 2)))",
+    ]
+  );
+}
+
+#[test]
+fn parse_ty_aliases_and_bindings() {
+  let tree = parse(
+    "type Id = int;
+    type Pair = (int, ?str);
+    val x y z: int = 0;
+    mut a b := 1 + 2;
+    val p: = 1;",
+  );
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+
+  let items = tree
+    .ast
+    .nodes
+    .iter()
+    .map(|stmt| match stmt.kind {
+      StmtKind::TypeAlias(ref alias) => alias.text(),
+      StmtKind::Mut(ref local) | StmtKind::Val(ref local) => {
+        let kw = if local.immutable { "val" } else { "mut" };
+        let ty = local.ty.as_ref().map_or(format!("_"), |ty| ty.text());
+
+        format!(
+          "{} {}: {} = {} ({})",
+          kw,
+          local.names().join(" "),
+          ty,
+          shape(&local.value),
+          local.is_shared()
+        )
+      }
+      ref kind => panic!("an item is expected, found {:?}", kind),
+    })
+    .collect::<Vec<String>>();
+
+  assert_eq!(
+    items,
+    vec![
+      "type Id = int;",
+      "type Pair = (int, ?str);",
+      "val x y z: int = 0 (true)",
+      "mut a b: _ = (+ 1 2) (true)",
+      "val p: _ = 1 (false)",
+    ]
+  );
+
+  assert_eq!(parse("type = int;").errors.len(), 1);
+  assert_eq!(parse("val x y int = 0;").errors.len(), 1);
+}
+
+#[test]
+fn parse_for_loops_and_ranges() {
+  let tree = parse(
+    "for 0..3 { f($it); }
+    for 0..=3 $i { f($i); }
+    for 0..3 = $it { }
+    for a..b + 1 { }
+    for elmts { f($elmt); }
+    for elmts $e { }
+    1..;
+    ..3;
+    ..=3;
+    ..;",
+  );
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+  assert_eq!(
+    stmt_kinds(&tree.ast.nodes),
+    vec![
+      "(for (.. 0 3) ($it) (call f $it))",
+      "(for (..= 0 3) $i (call f $i))",
+      "(for (.. 0 3) $it )",
+      "(for (.. a (+ b 1)) ($it) )",
+      "(for elmts ($elmt) (call f $elmt))",
+      "(for elmts $e )",
+      "(.. 1 _)",
+      "(.. _ 3)",
+      "(..= _ 3)",
+      "(.. _ _)",
+    ]
+  );
+}
+
+#[test]
+fn validate_jumps() {
+  let errors = |src: &str| validate(&parse(src).ast, None);
+
+  assert_eq!(
+    errors("break;"),
+    vec!["validator:fn:validate_jump:error: `break` outside of a loop"]
+  );
+  assert_eq!(
+    errors(r"loop { break \inner; }"),
+    vec![
+      "validator:fn:validate_jump:error: `break` to an unknown label `inner`"
+    ]
+  );
+
+  // the body of a closure does not see the loops around it
+  assert_eq!(
+    errors("loop { val f := () { break; }; }"),
+    vec!["validator:fn:validate_jump:error: `break` outside of a loop"]
+  );
+  assert_eq!(
+    errors(r"\outer loop { val f := () { loop { continue \outer; } }; }"),
+    vec![
+      "validator:fn:validate_jump:error: `continue` to an unknown label \
+       `outer`"
+    ]
+  );
+
+  assert!(errors(r"\outer loop { loop { break \outer 1; } }").is_empty());
+  assert!(errors("for 0..3 { continue; }").is_empty());
+}
+
+#[test]
+fn parse_assignment_places() {
+  assert_eq!(parse_expr_shape("x = 1;"), "(= x 1)");
+  assert_eq!(parse_expr_shape("v.x += 2;"), "(+= (. v x) 2)");
+  assert_eq!(parse_expr_shape("out[0] = 3;"), "(= ([] out 0) 3)");
+  assert_eq!(parse_expr_shape("t.0 = 4;"), "(= (. t 0) 4)");
+
+  for src in ["1 = 2;", "f() = 3;", "a + b -= 1;"].iter() {
+    let errors = parse(src).errors;
+
+    assert_eq!(errors.len(), 1, "{}", src);
+    assert!(errors[0].0.starts_with(
+      "parser:fn:parse_assign_expr:error: invalid assignment target"
+    ));
+  }
+}
+
+#[test]
+fn parse_struct_lits() {
+  assert_eq!(parse_expr_shape("A { .bar = 1 };"), "(struct A (bar 1))");
+  assert_eq!(
+    parse_expr_shape("f(gl::vec2 { .x = 1, .y = b });"),
+    "(call f (struct gl::vec2 (x 1) (y b)))"
+  );
+
+  // the block of a condition is not taken for a struct literal
+  let tree = parse("if a { b; }");
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+  assert!(matches!(tree.ast.nodes[0].kind, StmtKind::IfBlock { .. }));
+}
+
+#[test]
+fn parse_arithmetic_precedence() {
+  assert_eq!(
+    parse_expr_shape("a + b * c - d / e % f;"),
+    "(- (+ a (* b c)) (% (/ d e) f))"
+  );
+}
+
+#[test]
+fn parse_left_associativity() {
+  assert_eq!(parse_expr_shape("a - b - c;"), "(- (- a b) c)");
+  assert_eq!(parse_expr_shape("a != b == c;"), "(== (!= a b) c)");
+  assert_eq!(parse_expr_shape("a >> b >> c;"), "(>> (>> a b) c)");
+}
+
+#[test]
+fn parse_logical_precedence() {
+  assert_eq!(
+    parse_expr_shape("a < b && c == d || e >= f;"),
+    "(|| (&& (< a b) (== c d)) (>= e f))"
+  );
+}
+
+#[test]
+fn parse_bitwise_precedence() {
+  assert_eq!(
+    parse_expr_shape("a | b & c << 1 + d;"),
+    "(| a (& b (<< c (+ 1 d))))"
+  );
+  assert_eq!(parse_expr_shape("a & b == c;"), "(== (& a b) c)");
+}
+
+#[test]
+fn parse_unary_and_cast_precedence() {
+  assert_eq!(parse_expr_shape("-a as int * b;"), "(* (as (- a) int) b)");
+  assert_eq!(parse_expr_shape("- -a;"), "(- (- a))");
+  assert_eq!(parse_expr_shape("!a && b;"), "(&& (! a) b)");
+  assert_eq!(parse_expr_shape("-a.b;"), "(- (. a b))");
+}
+
+#[test]
+fn parse_unary_operator_in_infix_position() {
+  // `!` only starts an expression, the statement ends before it
+  assert_eq!(
+    stmt_kinds(&parse("val a := b !c;").ast.nodes),
+    vec!["val a = b", "(! c)"]
+  );
+
+  let tree = parse("val a := (b !c); d;");
+
+  assert_eq!(tree.errors.len(), 1, "{:?}", tree.errors);
+  assert_eq!(stmt_kinds(&tree.ast.nodes).last().unwrap(), "d");
+}
+
+#[test]
+fn parse_postfix_precedence() {
+  assert_eq!(
+    parse_expr_shape("a.b[0](c) + d;"),
+    "(+ (call ([] (. a b) 0) c) d)"
+  );
+  assert_eq!(parse_expr_shape("(a + b) * c;"), "(* (+ a b) c)");
+}
+
+#[test]
+fn parse_range_precedence() {
+  assert_eq!(parse_expr_shape("a + 1..b * 2;"), "(.. (+ a 1) (* b 2))");
+  assert_eq!(parse_expr_shape("..=a || b;"), "(..= _ (|| a b))");
+}
+
+#[test]
+fn parse_assignment_right_associativity() {
+  assert_eq!(
+    parse_expr_shape("x = y += a || b;"),
+    "(= x (+= y (|| a b)))"
+  );
+  assert_eq!(
+    parse_expr_shape("out[0] = a..b;"),
+    "(= ([] out 0) (.. a b))"
+  );
+}
+
+#[test]
+fn parse_member_access_chaining() {
+  assert_eq!(
+    parse_expr_shape("a.b(c).d[0].e(f, g);"),
+    "(.call ([] (. (.call a b c) d) 0) e f g)"
+  );
+  assert_eq!(
+    parse_expr_shape("t.0.1 + t.2;"),
+    "(+ (. (. t 0) 1) (. t 2))"
+  );
+  assert_eq!(parse_expr_shape("t.0.x;"), "(. (. t 0) x)");
+  assert_eq!(parse_expr_shape("t.0.1.x;"), "(. (. (. t 0) 1) x)");
+  assert_eq!(parse_expr_shape("self.x = y;"), "(= (. self x) y)");
+}
+
+#[test]
+fn tokenize_a_number_before_a_dot() {
+  let int =
+    |value: &str| TokenKind::Literal(LiteralKind::IntNumber(value.into()));
+  let real =
+    |value: &str| TokenKind::Literal(LiteralKind::RealNumber(value.into()));
+  let dot = TokenKind::Binary(BinaryKind::Dot);
+  let semi = TokenKind::Semicolon;
+  let t = TokenKind::Ident("t".into());
+
+  assert_eq!(
+    token_kinds("t.0.1;"),
+    vec![t.to_owned(), dot.to_owned(), real("0.1"), semi.to_owned()]
+  );
+  assert_eq!(
+    token_kinds("t.0.x;"),
+    vec![
+      t.to_owned(),
+      dot.to_owned(),
+      int("0"),
+      dot.to_owned(),
+      TokenKind::Ident("x".into()),
+      semi.to_owned(),
+    ]
+  );
+  assert_eq!(
+    token_kinds("1.5.0;"),
+    vec![real("1.5"), dot, int("0"), semi.to_owned()]
+  );
+  assert_eq!(
+    token_kinds("0..3;"),
+    vec![
+      int("0"),
+      TokenKind::Binary(BinaryKind::DotDot),
+      int("3"),
+      semi
+    ]
+  );
+}
+
+#[test]
+fn parse_path_roots() {
+  assert_eq!(
+    parse_expr_shape("matrix::mat4::identity(matrix::world_matrix);"),
+    "(call matrix::mat4::identity matrix::world_matrix)"
+  );
+  assert_eq!(
+    parse_expr_shape("@std::math::sqrt(x);"),
+    "(call @std::math::sqrt x)"
+  );
+  assert_eq!(parse_expr_shape("@panic(x);"), "(call @panic x)");
+  assert_eq!(parse_expr_shape("self::add;"), "self::add");
+  assert_eq!(
+    parse_expr_shape("super::super::add.x;"),
+    "(. super::super::add x)"
+  );
+}
+
+#[test]
+fn parse_collection_literals() {
+  assert_eq!(
+    parse_expr_shape(".{ firstname = \"a\", 1 = b + c, };"),
+    ".{firstname = \"a\" 1 = (+ b c)}"
+  );
+  assert_eq!(parse_expr_shape(".{};"), ".{}");
+}
+
+#[test]
+fn parse_tuple_exprs() {
+  assert_eq!(parse_expr_shape("();"), "(tuple )");
+  assert_eq!(parse_expr_shape("(a,);"), "(tuple a)");
+  assert_eq!(parse_expr_shape("(a, b + c,);"), "(tuple a (+ b c))");
+  assert_eq!(parse_expr_shape("(a);"), "a");
+}
+
+#[test]
+fn parse_array_literals() {
+  assert_eq!(parse_expr_shape("[10, 23, 12, 3,];"), "[10 23 12 3]");
+  assert_eq!(parse_expr_shape("[];"), "[]");
+  assert_eq!(parse_expr_shape("[0; 4 * n];"), "[0; (* 4 n)]");
+  assert_eq!(parse_expr_shape("f(a, b,);"), "(call f a b)");
+}
+
+fn stmt_kinds(stmts: &Vec<Box<Stmt>>) -> Vec<String> {
+  stmts
+    .iter()
+    .map(|stmt| match stmt.kind {
+      StmtKind::Error => format!("error"),
+      StmtKind::Expr(ref expr) => shape(expr),
+      StmtKind::Fun(ref fun) => format!("fun {}", fun.name),
+      StmtKind::Val(ref local) => {
+        format!("val {} = {}", local.names().join(" "), shape(&local.value))
+      }
+      ref kind => format!("{:?}", kind),
+    })
+    .collect()
+}
+
+#[test]
+fn parse_recovery_at_semicolon() {
+  let tree = parse("a + ; b * c; val x: = 1 + ; d;");
+
+  assert_eq!(
+    stmt_kinds(&tree.ast.nodes),
+    vec!["error", "(* b c)", "val x = Error", "d"]
+  );
+  assert_eq!(tree.errors.len(), 2);
+}
+
+#[test]
+fn parse_recovery_at_unmatched_closer() {
+  // the `)` does not close the block, the item ends at the `}`
+  let tree = parse("fun f: () = () { val x := ); val y := 3; } g();");
+
+  assert_eq!(stmt_kinds(&tree.ast.nodes), ["fun f", "(call g )"]);
+  assert_eq!(tree.errors.len(), 1, "{:?}", tree.errors);
+
+  match tree.ast.nodes[0].kind {
+    StmtKind::Fun(ref fun) => assert_eq!(
+      stmt_kinds(&fun.block.as_ref().unwrap().stmts),
+      ["val x = Error", "val y = 3"]
+    ),
+    ref kind => panic!("a function is expected, found {:?}", kind),
+  }
+}
+
+#[test]
+fn parse_recovery_at_item_keyword() {
+  let tree = parse("val x = ) 2 fun f: () = () { } f();");
+
+  assert_eq!(
+    stmt_kinds(&tree.ast.nodes),
+    vec!["error", "fun f", "(call f )"]
+  );
+  assert_eq!(tree.errors.len(), 1);
+}
+
+#[test]
+fn parse_recovery_in_block() {
+  let tree = parse("fun f: () = () { a + ; b; c * } g;");
+
+  match tree.ast.nodes.first().map(|stmt| &stmt.kind) {
+    Some(StmtKind::Fun(ref fun)) => {
+      let block = fun.block.as_ref().unwrap();
+
+      assert_eq!(stmt_kinds(&block.stmts), vec!["error", "b", "error"]);
+    }
+    kind => panic!("a function is expected, found {:?}", kind),
+  }
+
+  assert_eq!(stmt_kinds(&tree.ast.nodes)[1..], ["g"]);
+  assert_eq!(tree.errors.len(), 2);
+}
+
+#[test]
+fn parse_items_while_feeding() {
+  let opts = TokenizerOpts {
+    exact_errors: true,
+    ..Default::default()
+  };
+
+  let tree_builder = TreeBuilder::new(Tree::new());
+  let mut tokenizer = Tokenizer::new(tree_builder, opts);
+  let items = |tokenizer: &Tokenizer<TreeBuilder<Tree>>| {
+    stmt_kinds(&tokenizer.sink().sink().ast.nodes)
+  };
+
+  tokenizer.feed(StrTendril::from("val a: = 1; fun f: () = () { a }\n"));
+  assert_eq!(items(&tokenizer), vec!["val a = 1"]);
+
+  tokenizer.feed(StrTendril::from("f(); fun g: () = () { }"));
+  assert_eq!(items(&tokenizer), vec!["val a = 1", "fun f", "(call f )"]);
+
+  tokenizer.end();
+  assert_eq!(
+    items(&tokenizer),
+    vec!["val a = 1", "fun f", "(call f )", "fun g"]
+  );
+}
+
+#[test]
+fn parse_trailing_comment_while_feeding() {
+  let opts = TokenizerOpts {
+    exact_errors: true,
+    ..Default::default()
+  };
+
+  let tree_builder = TreeBuilder::new(Tree::new());
+  let mut tokenizer = Tokenizer::new(tree_builder, opts);
+
+  tokenizer.feed(StrTendril::from("val a := 1;"));
+  tokenizer.feed(StrTendril::from(" # note\nfun b: () = () { }"));
+  tokenizer.end();
+
+  let nodes = &tokenizer.sink().sink().ast.nodes;
+
+  assert_eq!(stmt_kinds(nodes), vec!["val a = 1", "fun b"]);
+  assert!(nodes[1].docs().is_empty(), "{:?}", nodes[1].docs());
+}
+
+#[test]
+fn parse_use_reports_the_unresolved_module() {
+  let tree = parse("use @std::gl::matrix; use @std::math::(sqrt, sin); f();");
+
+  assert_eq!(
+    tree
+      .errors
+      .iter()
+      .map(|(error, _)| error)
+      .collect::<Vec<_>>(),
+    vec![
+      "parser:fn:parse_use_stmt:error: `use @std::gl::matrix` is not \
+       resolved yet",
+      "parser:fn:parse_use_stmt:error: `use @std::math::(sqrt, sin)` is not \
+       resolved yet",
+    ]
+  );
+
+  let modules = tree
+    .ast
+    .nodes
+    .iter()
+    .filter_map(|stmt| match stmt.kind {
+      StmtKind::Use(ref module) if module.stmts.is_empty() => {
+        Some(module.name.to_owned())
+      }
+      _ => None,
+    })
+    .collect::<Vec<_>>();
+
+  assert_eq!(modules, vec!["std::gl::matrix", "std::math::(sqrt, sin)"]);
+  assert_eq!(stmt_kinds(&tree.ast.nodes)[2..], ["(call f )"]);
+}
+
+#[test]
+fn parse_attrs() {
+  let tree = parse(
+    "|> derive: clone, debug. |> inline. fun f: () = () { }
+    |> cfg: test. val x: = 1;
+    g();",
+  );
+
+  let fun = &tree.ast.nodes[0];
+  let attrs = fun.attrs.iter().map(|attr| attr.text()).collect::<Vec<_>>();
+
+  assert_eq!(attrs, vec!["|> derive: clone, debug.", "|> inline."]);
+  assert!(fun.attr("derive").unwrap().has_arg("debug"));
+  assert!(fun.has_attr("inline") && !fun.has_attr("cfg"));
+
+  let items = tree.ast.items_with_attr("cfg");
+
+  assert_eq!(items.len(), 1);
+  assert_eq!(items[0].attrs_by_name("cfg")[0].text(), "|> cfg: test.");
+  assert!(tree.ast.nodes[2].attrs.is_empty());
+
+  // the dot ending an attribute is not read as a fraction
+  let tree = parse("|> align: 16. val y: = 2;");
+  let attrs = tree.ast.nodes[0].attrs_by_name("align");
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+  assert_eq!(attrs[0].text(), "|> align: 16.");
+  assert_eq!(stmt_kinds(&tree.ast.nodes), vec!["val y = 2"]);
+}
+
+#[test]
+fn parse_attrs_on_structs_and_capsules() {
+  let tree = parse(
+    "|> cfg: test.
+    capsule Vec2 {
+      fun mul: (-> int | x: int) = (x) -> x * x;
+    }
+    |> derive: clone, debug.
+    struct Foo { .x: int }
+    pub struct Button {
+      name: str,
+      id: int,
+      static fun zero: int = () { 0 }
+    }",
+  );
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+  assert_eq!(tree.ast.nodes.len(), 3);
+
+  let capsule = &tree.ast.nodes[0];
+  let structure = &tree.ast.nodes[1];
+
+  assert!(capsule.has_attr("cfg"));
+  assert!(structure.attr("derive").unwrap().has_arg("clone"));
+
+  match (&capsule.kind, &structure.kind, &tree.ast.nodes[2].kind) {
+    (
+      StmtKind::Capsule(ref capsule),
+      StmtKind::Struct(ref foo),
+      StmtKind::Struct(ref button),
+    ) => {
+      assert_eq!(capsule.name, "Vec2");
+      assert!(!capsule.visibility);
+      assert!(match capsule.members[..] {
+        [TraitMember::Method(ref method)] => method.name == "mul",
+        _ => false,
+      });
+
+      assert_eq!(foo.name, "Foo");
+      assert!(match foo.members[..] {
+        [StructMember::Field(ref field)] => field.name == "x",
+        _ => false,
+      });
+
+      assert!(button.visibility);
+      assert_eq!(button.members.len(), 3);
+      assert!(match button.members[2] {
+        StructMember::StaticMethod(ref method) => method.modifiers.is_empty(),
+        _ => false,
+      });
+    }
+    kinds => {
+      panic!("a capsule and two structs are expected, found {:?}", kinds)
+    }
+  }
+
+  // a capsule declares no static method
+  let tree = parse("capsule Vec2 { static fun zero: int = () { 0 } }");
+
+  assert_eq!(tree.errors.len(), 1);
+}
+
+#[test]
+fn parse_test_declarations() {
+  let tree = parse(
+    "|> cfg: test.
+    suite lexer {
+      mock tokens_mock = () { }
+      test tokens_test = () {
+        val x y := true;
+        must!(x be y);
+        must!(x == y)
+      }
+    }
+    bench tokens_bench = () { }",
+  );
+
+  let items = tree
+    .ast
+    .test_items()
+    .iter()
+    .map(|item| match item.kind {
+      StmtKind::Bench(ref fun) => format!("bench {}", fun.name),
+      StmtKind::Mock(ref fun) => format!("mock {}", fun.name),
+      StmtKind::Test(ref fun) => format!("test {}", fun.name),
+      ref kind => format!("{:?}", kind),
+    })
+    .collect::<Vec<String>>();
+
+  assert_eq!(
+    items,
+    ["mock tokens_mock", "test tokens_test", "bench tokens_bench"]
+  );
+
+  let test = match tree.ast.test_items()[1].kind {
+    StmtKind::Test(ref fun) => fun.block.as_ref().unwrap().stmts.to_vec(),
+    _ => unreachable!(),
+  };
+
+  assert_eq!(
+    stmt_kinds(&test)[1..],
+    [
+      "(call @std::testing::must_be x y)",
+      "(call @std::testing::must (== x y))"
+    ]
+  );
+  assert!(tree.ast.nodes[0].has_attr("cfg"));
+  assert!(tree.errors.is_empty());
+}
+
+#[test]
+fn parse_macro_expansion() {
+  let tree = parse(
+    "macro square {
+      ($x:expr) => { $x * $x }
+    }
+    square!(a + 1) * 2;
+    print!(\"{} {}\", a, square!(b));",
+  );
+
+  assert_eq!(
+    stmt_kinds(&tree.ast.nodes)[1..],
+    [
+      "(* (* (+ a 1) (+ a 1)) 2)",
+      "(call @std::io::print \"{} {}\" a (* b b))"
+    ]
+  );
+  assert!(tree.errors.is_empty());
+}
+
+#[test]
+fn parse_macro_statements() {
+  let tree = parse(
+    "macro swap {
+      ($a:ident, $b:ident) => { val tmp := $a; $a = $b; $b = tmp; }
+    }
+    macro bail {
+      ($e:expr) => { ret $e }
+    }
+    fun f: (-> int | a: int, b: int) = (a, b) {
+      swap!(a, b);
+      bail!(a - b)
+    }
+    val c := swap!(a, b);",
+  );
+
+  let body = match tree.ast.nodes[2].kind {
+    StmtKind::Fun(ref fun) => fun.block.as_ref().unwrap().stmts.to_vec(),
+    ref kind => panic!("a function is expected, found {:?}", kind),
+  };
+
+  // an invocation ending a statement is replaced by the statements of the
+  // expansion, the `val` of the template is renamed
+  assert_eq!(
+    stmt_kinds(&body[..3].to_vec()),
+    ["val tmp#1 = a", "(= a b)", "(= b tmp#1)"]
+  );
+
+  match body[3].kind {
+    StmtKind::Ret(Some(ref value)) => assert_eq!(shape(value), "(- a b)"),
+    ref kind => panic!("a return is expected, found {:?}", kind),
+  }
+
+  // statements can not stand for an expression
+  assert_eq!(body.len(), 4);
+  assert!(tree.errors[0].0.ends_with("error: val"));
+
+  // an invocation expanding into itself stops at the expansion limit
+  let tree = parse("macro again { () => { again!(); } } again!(); a;");
+
+  assert_eq!(tree.errors.len(), 1);
+  assert!(tree.errors[0].0.contains("exceeds the expansion limit"));
+  assert_eq!(stmt_kinds(&tree.ast.nodes).last().unwrap(), "a");
+}
+
+#[test]
+fn parse_macro_hygiene() {
+  let tree = parse(
+    "macro add_tmp {
+      ($e:expr) => { ((tmp) -> tmp + $e)(1) }
+    }
+    add_tmp!(tmp);",
+  );
+
+  match tree.ast.nodes[1].kind {
+    StmtKind::Expr(ref expr) => match expr.kind {
+      ExprKind::Call { ref callee, .. } => match callee.kind {
+        ExprKind::Closure(ref fun) => {
+          let body = fun.block.as_ref().unwrap().stmts.to_vec();
+
+          assert_eq!(fun.args[0].name, Some("tmp#1".into()));
+          assert_eq!(stmt_kinds(&body), ["(+ tmp#1 tmp)"]);
+        }
+        ref kind => panic!("a closure is expected, found {:?}", kind),
+      },
+      ref kind => panic!("a call is expected, found {:?}", kind),
+    },
+    ref kind => panic!("an expression is expected, found {:?}", kind),
+  }
+}
+
+#[test]
+fn parse_macro_errors() {
+  let tree = parse("nope!(a); macro one { () => { 1 } } one!(2); one!();");
+
+  assert_eq!(tree.errors.len(), 2);
+  assert_eq!(stmt_kinds(&tree.ast.nodes)[3..], ["1"]);
+
+  // a malformed prelude is reported instead of aborting the parse
+  let opts = TokenizerOpts {
+    exact_errors: true,
+    ..Default::default()
+  };
+  let tree_builder = TreeBuilder::with_prelude(Tree::new(), "macro m { ( => }");
+  let mut tokenizer = Tokenizer::new(tree_builder, opts);
+
+  tokenizer.feed(StrTendril::from("val a: = 1;"));
+  tokenizer.end();
+
+  let tree = tokenizer.unwrap().unwrap();
+
+  assert_eq!(tree.errors.len(), 1);
+  assert!(tree.errors[0]
+    .0
+    .starts_with("parser:fn:load_prelude:error:"));
+  assert_eq!(stmt_kinds(&tree.ast.nodes), vec!["val a = 1"]);
+}
+
+#[test]
+fn parse_foreign_funs() {
+  let tree = parse(
+    "ext fun sqrt: (-> int | x: int);
+    exp fun cos: (-> int | x: int);
+    mod fun puts: (-> int | s: str);
+    fun f: () = () { sqrt(4) }",
+  );
+
+  let funs = tree
+    .ast
+    .foreign_funs()
+    .iter()
+    .map(|fun| (fun.name(), fun.abi().unwrap(), fun.block.is_none()))
+    .collect::<Vec<_>>();
+
+  assert_eq!(
+    funs,
+    [
+      ("sqrt".to_string(), Abi::C, true),
+      ("cos".to_string(), Abi::JavaScript, true),
+      ("puts".to_string(), Abi::Rust, true),
+    ]
+  );
+
+  assert!(tree.errors.is_empty());
+
+  let tree = parse("ext fun sqrt: (-> int | x: int) = (x) { x }");
+
+  assert_eq!(tree.errors.len(), 1);
+}
+
+#[test]
+fn parse_target_blocks() {
+  let tree = parse(
+    "exp fun alert: (-> void | s: str);
+    wasm = {
+      fun greet: () = () { alert(\"hi\"); }
+    }
+    native = { fun greet: () = () { } }
+    fun main: () = () { wasm::log(1); alert(\"no\"); }",
+  );
+
+  let targets = tree
+    .ast
+    .nodes
+    .iter()
+    .filter_map(|node| match node.kind {
+      StmtKind::TargetBlock { target, ref body } => {
+        Some((target, body.stmts.len()))
+      }
+      _ => None,
+    })
+    .collect::<Vec<_>>();
+
+  assert_eq!(targets, [(Target::Wasm, 1), (Target::Native, 1)]);
+
+  let errors = tree
+    .errors
+    .iter()
+    .map(|(error, _)| error.to_owned())
+    .collect::<Vec<_>>();
+
+  assert_eq!(errors.len(), 2);
+  assert!(errors[0].contains("`wasm::log`"));
+  assert!(errors[1].contains("`alert`"));
+  assert_eq!(validate(&tree.ast, None), errors);
+  assert_eq!(validate(&tree.ast, Some(Target::Native)), errors);
+
+  assert_eq!(tree.ast.for_target(Target::Wasm).nodes.len(), 3);
+  assert!(tree.ast.for_target(Target::Native).nodes.iter().all(
+    |node| match node.kind {
+      StmtKind::TargetBlock { .. } => false,
+      _ => true,
+    }
+  ));
+
+  // a function used by a previous item is reported as in `validate`
+  let tree = parse(
+    "fun main: () = () { alert(\"no\"); }
+    exp fun alert: (-> void | s: str);",
+  );
+
+  assert_eq!(tree.errors.len(), 1);
+  assert!(tree.errors[0].0.contains("`alert`"));
+  assert_eq!(validate(&tree.ast, None), [tree.errors[0].0.to_owned()]);
+
+  // the block of an other target is not compiled
+  let tree = parse("wasm = { fun f: () = () { break; } }");
+
+  assert_eq!(tree.errors.len(), 1);
+  assert_eq!(validate(&tree.ast, Some(Target::Wasm)).len(), 1);
+  assert!(validate(&tree.ast, Some(Target::Native)).is_empty());
+
+  // the validator walks every node e.g the discriminant of a variant, but a
+  // parameter is not a use of its name
+  let tree = parse(
+    "enum Level { Low = wasm::low }
+    fun f: (-> void | alert: int) = (alert) { }
+    exp fun alert: (-> void | s: str);",
+  );
+
+  assert_eq!(tree.errors.len(), 1);
+  assert!(tree.errors[0].0.contains("`wasm::low`"));
+}
+
+#[test]
+fn parse_await_exprs() {
+  let tree = parse(
+    "async fun load: () = () {
+      val a := fetch(1).await + 1;
+      val b := await fetch(a) * 2;
+      a.b.await
+    }
+    fun sync: () = () { fetch(1).await }",
+  );
+
+  let body = match tree.ast.nodes[0].kind {
+    StmtKind::Fun(ref fun) => fun.block.as_ref().unwrap().stmts.to_vec(),
+    _ => unreachable!(),
+  };
+
+  assert_eq!(
+    stmt_kinds(&body),
+    [
+      "val a = (+ (await (call fetch 1)) 1)",
+      "val b = (* (await (call fetch a)) 2)",
+      "(await (. a b))"
+    ]
+  );
+
+  assert_eq!(tree.errors.len(), 1);
+  assert!(tree.errors[0]
+    .0
+    .contains("`await` outside of an async function"));
+}
+
+#[test]
+fn parse_optionals() {
+  assert_eq!(parse_expr_shape("a? + 1;"), "(+ (? a) 1)");
+  assert_eq!(parse_expr_shape("a?.b?;"), "(? (. (? a) b))");
+  assert_eq!(parse_expr_shape("a != nil;"), "(!= a nil)");
+
+  let tree = parse(
+    "fun f: (-> ?int | x: ?int) = (x) {
+      if x == nil {
+        ret nil;
+      }
+
+      val y := x + 1;
+      x? * 2
+    }
+    fun g: (-> int | x: ?int, y: ?int) = (x, y) {
+      if x && y != nil {
+        x + y;
+      } else if x {
+        -x;
+      } else {
+        x + 1;
+      }
+
+      val z: int = nil;
+      x?
+    }",
+  );
+
+  let body = match tree.ast.nodes[0].kind {
+    StmtKind::Fun(ref fun) => fun.block.as_ref().unwrap().stmts.to_vec(),
+    _ => unreachable!(),
+  };
+
+  match body[0].kind {
+    StmtKind::IfBlock {
+      ref conditions,
+      ref alternative,
+    } => {
+      assert_eq!(shape(&conditions[0].0), "(== x nil)");
+      assert!(alternative.is_none());
+    }
+    ref kind => panic!("an if statement is expected, found {:?}", kind),
+  }
+
+  let errors = tree
+    .errors
+    .iter()
+    .map(|(error, _)| error.to_owned())
+    .collect::<Vec<_>>();
+
+  assert_eq!(errors.len(), 3);
+  assert!(errors[0].contains("`x` may be nil"));
+  assert!(errors[1].contains("`nil` can not be assigned to `z`"));
+  assert!(errors[2].contains("`?` in a function that does not return"));
+}
+
+#[test]
+fn parse_doc_comments() {
+  let tree = parse(
+    "#!+
+    #! vectors of two numbers
+    #!-
+
+    #+
+    dropped
+    #-
+
+    # adds two vec2
+    # into out
+    pub fun add: (-> int | a: int) = (a) {
+      # not a doc
+      a + 1 # not a doc either
+    }
+
+    |> inline.
+    #! subtracts
+    fun sub: () = () { }
+
+    # detached
+
+    fun mul: () = () { }
+    enum Shape {
+      Rect {
+        # the width
+        w: int,
+        h: int,
+      },
+    }",
+  );
+
+  let docs = tree
+    .ast
+    .nodes
+    .iter()
+    .map(|node| node.docs())
+    .collect::<Vec<_>>();
+
+  assert!(tree.errors.is_empty());
+  assert_eq!(tree.ast.docs, ["vectors of two numbers"]);
+  assert_eq!(
+    docs,
+    vec![
+      vec!["adds two vec2", "into out"],
+      vec!["subtracts"],
+      vec![],
+      vec![]
+    ]
+  );
+
+  match tree.ast.nodes[3].kind {
+    StmtKind::Enum(ref enm) => match enm.variants[0].kind {
+      VariantKind::Struct(ref fields) => {
+        assert_eq!(fields[0].docs, ["the width"]);
+        assert!(fields[1].docs.is_empty());
+      }
+      ref kind => panic!("a struct variant is expected, found {:?}", kind),
+    },
+    ref kind => panic!("an enum is expected, found {:?}", kind),
+  }
+}
+
+#[test]
+fn parse_doc_comments_on_members() {
+  let tree = parse(
+    "# a point
+    struct Point {
+      # the abscissa
+      x: real,
+      y: real,
+      # the origin
+      static fun zero: int = () { 0 }
+    }
+
+    # the products
+    pub capsule Vec2 {
+      # the length
+      len: real,
+      # the square
+      fun mul: (-> int | x: int) = (x) -> x * x;
+    }",
+  );
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+  assert_eq!(tree.ast.nodes[0].docs(), ["a point"]);
+  assert_eq!(tree.ast.nodes[1].docs(), ["the products"]);
+
+  match (&tree.ast.nodes[0].kind, &tree.ast.nodes[1].kind) {
+    (StmtKind::Struct(ref point), StmtKind::Capsule(ref vec2)) => {
+      let docs = point
+        .members
+        .iter()
+        .map(|member| match member {
+          StructMember::Field(ref field) => field.docs.to_vec(),
+          StructMember::Method(ref method) => method.docs.to_vec(),
+          StructMember::StaticMethod(ref method) => method.docs.to_vec(),
+        })
+        .collect::<Vec<_>>();
+
+      assert_eq!(docs, vec![vec!["the abscissa"], vec![], vec!["the origin"]]);
+
+      let docs = vec2
+        .members
+        .iter()
+        .map(|member| match member {
+          TraitMember::Field(ref field) => field.docs.to_vec(),
+          TraitMember::Method(ref method) => method.docs.to_vec(),
+        })
+        .collect::<Vec<_>>();
+
+      assert_eq!(docs, vec![vec!["the length"], vec!["the square"]]);
+    }
+    kinds => panic!("a struct and a capsule are expected, found {:?}", kinds),
+  }
+}
+
+#[test]
+fn parse_comments_inside_lookahead() {
+  // the comments are skipped by every lookahead as they are by the parser
+  let tree = parse(
+    "val f := (x # the first
+      , y) { x };
+    val g := print! # prints
+      (\"x\");
+    val t: (int # the count
+      -> int) = f;",
+  );
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+
+  let values = tree
+    .ast
+    .nodes
+    .iter()
+    .map(|node| match node.kind {
+      StmtKind::Val(ref local) => local.to_owned(),
+      ref kind => panic!("a local is expected, found {:?}", kind),
+    })
+    .collect::<Vec<_>>();
+
+  assert!(matches!(values[0].value.kind, ExprKind::Closure(_)));
+  assert_eq!(shape(&values[1].value), "(call @std::io::print \"x\")");
+  assert!(matches!(
+    values[2].ty.as_ref().map(|ty| ty.kind()),
+    Some(TyKind::FunTy { .. })
+  ));
+}
+
+#[test]
+fn visit_and_fold_the_tree() {
+  // collects the names read by a function, in the order of the tree
+  struct Names<'ast> {
+    names: Vec<&'ast str>,
+  }
+
+  impl<'ast> Visitor<'ast> for Names<'ast> {
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+      if let ExprKind::Ident(ref name) = expr.kind {
+        self.names.push(name);
+      }
+
+      visit::walk_expr(self, expr);
+    }
+  }
+
+  struct Rename;
+
+  impl VisitorMut for Rename {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+      match expr.kind {
+        ExprKind::Ident(ref mut name) if name == "x" => *name = "n".into(),
+        _ => visit_mut::walk_expr_mut(self, expr),
+      }
+    }
+  }
+
+  // desugars `a += b` into `a = a + b`
+  struct Desugar;
+
+  impl Fold for Desugar {
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+      let span = expr.span.to_owned();
+
+      match fold::walk_expr(self, expr).kind {
+        ExprKind::CompoundAssign { lhs, op, rhs } => {
+          let rhs = ExprKind::BinOp {
+            lhs: lhs.to_owned(),
+            op,
+            rhs,
+            span: span.to_owned(),
+          };
+
+          let rhs = Box::new(Expr::new(rhs, span.to_owned()));
+
+          Expr::new(ExprKind::Assign { lhs, rhs }, span)
+        }
+        kind => Expr::new(kind, span),
+      }
+    }
+  }
+
+  let tree = parse(
+    "fun f: (-> int | x: int) = (x) {
+      mut a := x;
+      a += x * 2;
+      if a > 0 {
+        g(a, x);
+      }
+      a
+    }",
+  );
+
+  assert!(tree.errors.is_empty());
+
+  let mut names = Names { names: vec![] };
+
+  names.visit_ast(&tree.ast);
+  assert_eq!(names.names, ["x", "x", "a", "x", "a", "g", "a", "x", "a"]);
+
+  let mut ast = tree.ast.to_owned();
+
+  Rename.visit_ast_mut(&mut ast);
+
+  let mut names = Names { names: vec![] };
+
+  names.visit_ast(&ast);
+  assert!(!names.names.contains(&"x"));
+
+  let ast = Desugar.fold_ast(*ast);
+
+  let body = match ast.nodes[0].kind {
+    StmtKind::Fun(ref fun) => fun.block.as_ref().unwrap().stmts.to_vec(),
+    _ => unreachable!(),
+  };
+
+  match body[1].kind {
+    StmtKind::Expr(ref expr) => {
+      assert_eq!(shape(expr), "(= a (+ a (* n 2)))")
+    }
+    ref kind => panic!("an expression is expected, found {:?}", kind),
+  }
+}
+
+#[test]
+fn number_the_nodes() {
+  let src = "fun f: (-> int | x: int) = (x) {
+      val a := x + 1;
+      a * 2
+    }
+    fun g: () = () {
+      f(3);
+    }";
+
+  let tree = parse(src);
+  let nodes = Arena::new(*tree.ast.clone());
+  let ids = (0..nodes.len()).map(NodeId::new).collect::<Vec<_>>();
+
+  // the ids are dense and the same from one parse to the next
+  assert!(tree.errors.is_empty());
+  assert!(!nodes.is_empty());
+  assert!(ids.iter().all(|&id| nodes.get(id).is_some()));
+  assert_eq!(parse(src).ast, tree.ast);
+
+  let (stmt, fun) = tree
+    .ast
+    .nodes
+    .iter()
+    .find_map(|node| match node.kind() {
+      StmtKind::Fun(ref fun) if fun.name() == "f" => Some((node, fun)),
+      _ => None,
+    })
+    .unwrap();
+
+  assert_eq!(nodes.fun(fun.id).map(|fun| fun.name()), Some("f".into()));
+  assert_eq!(nodes.parent(fun.id), Some(stmt.id));
+  assert_eq!(nodes.items().len(), 2);
+  assert_eq!(nodes.items()[0], stmt.id);
+
+  // the children of a node of the arena are holes keeping their ids e.g the
+  // operands of `x + 1`
+  let sum = (0..nodes.len())
+    .filter_map(|index| nodes.expr(NodeId::new(index)))
+    .find_map(|expr| match expr.kind() {
+      ExprKind::BinOp {
+        ref lhs, ref rhs, ..
+      } => Some((lhs, rhs)),
+      _ => None,
+    });
+
+  match sum {
+    Some((lhs, rhs)) => {
+      assert_eq!(lhs.kind(), &ExprKind::Empty);
+      assert_eq!(nodes.expr(lhs.id).map(|lhs| lhs.text()), Some("x".into()));
+      assert_eq!(nodes.expr(rhs.id).map(|rhs| rhs.text()), Some("1".into()));
+    }
+    None => panic!("a binary expression is expected"),
+  }
+
+  // the type of a literal is known from its kind, a pass stores it in a side
+  // table keyed by the id of the expression
+  struct Literals {
+    tys: NodeMap<&'static str>,
+  }
+
+  impl<'ast> Visitor<'ast> for Literals {
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+      if let ExprKind::Lit(LitKind::Int(_)) = expr.kind() {
+        self.tys.insert(expr.id, "int");
+      }
+
+      visit::walk_expr(self, expr);
+    }
+  }
+
+  let mut literals = Literals {
+    tys: NodeMap::new(),
+  };
+
+  literals.visit_ast(&tree.ast);
+
+  let lits = literals
+    .tys
+    .iter()
+    .map(|(id, ty)| (nodes.expr(id).unwrap().text(), *ty))
+    .collect::<Vec<_>>();
+
+  assert_eq!(
+    lits,
+    [
+      ("1".into(), "int"),
+      ("2".into(), "int"),
+      ("3".into(), "int")
+    ]
+  );
+
+  // the statement holding a literal is found by walking up its parents
+  let (id, _) = literals.tys.iter().last().unwrap();
+  let mut parent = nodes.parent(id);
+
+  while let Some(id) = parent.filter(|&id| nodes.stmt(id).is_none()) {
+    parent = nodes.parent(id);
+  }
+
+  match parent.and_then(|id| nodes.stmt(id)).map(|stmt| stmt.kind()) {
+    Some(StmtKind::Expr(ref expr)) => {
+      match nodes.expr(expr.id).map(|expr| expr.kind()) {
+        Some(ExprKind::Call { ref callee, .. }) => {
+          assert_eq!(nodes.expr(callee.id).map(|f| f.text()), Some("f".into()))
+        }
+        kind => panic!("a call is expected, found {:?}", kind),
+      }
+    }
+    kind => panic!("a statement is expected, found {:?}", kind),
+  }
+}
+
+#[test]
+fn number_the_items() {
+  let tree = parse(
+    "|> inline. enum Shape { Empty, Circle(real) }
+    type Id = int;
+    Shape::Circle(1.5);",
+  );
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+
+  let nodes = Arena::new(*tree.ast);
+
+  // the items and their parts are numbered like the expressions
+  let kinds = (0..nodes.len())
+    .filter_map(|index| match nodes.get(NodeId::new(index))? {
+      Node::Attribute(_) => Some("attr"),
+      Node::Enum(_) => Some("enum"),
+      Node::Path(_) => Some("path"),
+      Node::TypeAlias(_) => Some("type"),
+      _ => None,
+    })
+    .collect::<Vec<_>>();
+
+  assert_eq!(kinds, ["attr", "enum", "type", "path"]);
+
+  // the side table counts its entries as they are inserted and removed
+  let mut tys = NodeMap::new();
+
+  tys.insert(NodeId::new(3), "int");
+  tys.insert(NodeId::new(3), "real");
+  tys.insert(NodeId::new(0), "bool");
+
+  assert_eq!(tys.len(), 2);
+  assert_eq!(tys.remove(NodeId::new(3)), Some("real"));
+  assert_eq!(tys.remove(NodeId::new(3)), None);
+  assert_eq!(tys.len(), 1);
+}
+
+#[test]
+fn export_and_import_json() {
+  let tree = parse(
+    "# adds one
+    pub fun inc: (-> int | x: int) = (x) {
+      val y: ?int = x + 1;
+      y?
+    }
+    |> inline.
+    enum Shape { Circle(real), Rect { w: int, h: int } = 2 }",
+  );
+
+  let json = ast_to_json(&tree.ast).unwrap();
+
+  assert!(json.starts_with("{\n  \"version\": 1,"));
+  assert_eq!(ast_from_json(&json).unwrap(), *tree.ast);
+
+  // a code generator can leave out the ids, the spans and the docs
+  let ast = ast_from_json(
+    r#"{
+      "version": 1,
+      "ast": {
+        "nodes": [
+          {
+            "kind": {
+              "Expr": {
+                "kind": {
+                  "BinOp": {
+                    "lhs": { "kind": { "Ident": "a" } },
+                    "op": "Add",
+                    "rhs": { "kind": { "Lit": { "Int": 1 } } }
+                  }
+                }
+              }
+            }
+          }
+        ]
+      }
+    }"#,
+  )
+  .unwrap();
+
+  match ast.nodes[0].kind {
+    StmtKind::Expr(ref expr) => {
+      assert_eq!(shape(expr), "(+ a 1)");
+      assert_eq!((ast.nodes[0].id.index(), expr.id.index()), (0, 1));
+    }
+    ref kind => panic!("an expression is expected, found {:?}", kind),
+  }
+
+  assert_eq!(
+    ast_from_json(r#"{ "version": 0, "ast": { "nodes": [] } }"#),
+    Err(
+      "json:fn:ast_from_json:error: the schema version 0 is not supported, \
+       the version 1 is expected"
+        .into()
+    )
+  );
+
+  assert!(ast_from_json(r#"{ "version": 1 }"#).is_err());
+
+  let json = tokens_to_json(&tokenize("val a := 1;")).unwrap();
+
+  assert!(json.contains("\"Val\""));
+  assert!(json.contains("\"IntNumber\": \"1\""));
+}
+
+fn unparse_source(source: &str) -> String {
+  let tree = parse(source);
+
+  assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+
+  unparse(&tree.ast)
+}
+
+#[test]
+fn unparse_canonical_source() {
+  assert_eq!(
+    unparse_source(
+      "# adds one
+      pub fun inc: (-> ?int | x: int) = (x) { val y: ?int = x+1; y? }
+      |> inline.
+      enum Shape { Circle(real), Rect { w: int, h: int } = 2 }
+      fun main = () { for 0..3 { if $it==1 { continue; } else { f($it) } } }",
+    ),
+    "# adds one
+pub fun inc: ?int = (x: int) {
+  val y: ?int = x + 1;
+  y?
+}
+
+|> inline.
+enum Shape {
+  Circle(real),
+  Rect { w: int, h: int } = 2,
+}
+
+fun main = () {
+  for 0..3 {
+    if $it == 1 {
+      continue;
+    } else {
+      f($it);
+    }
+  }
+}
+"
+  );
+}
+
+#[test]
+fn unparse_minimal_parentheses() {
+  let exprs = [
+    ("((a + b)) * c;", "(a + b) * c"),
+    ("(a * b) + c;", "a * b + c"),
+    ("a - (b - c);", "a - (b - c)"),
+    ("(a - b) - c;", "a - b - c"),
+    ("x = (y = a || b);", "x = y = a || b"),
+    ("(-a).b;", "(-a).b"),
+    ("- (-a);", "- -a"),
+    ("(a..b).len();", "(a..b).len()"),
+    ("(t.0).1;", "t.0.1"),
+    ("(f)(x)(y);", "f(x)(y)"),
+    ("(a, b) == c;", "(a, b) == c"),
+  ];
+
+  for (source, expected) in exprs.iter() {
+    let tree = parse(source);
+
+    match tree.ast.nodes[0].kind {
+      StmtKind::Expr(ref expr) => assert_eq!(unparse_expr(expr), *expected),
+      ref kind => panic!("an expression is expected, found {:?}", kind),
+    }
+  }
+
+  // a few trees are only built by a tool, their source needs parentheses
+  let cast = make_cast_expr(make_ident_expr("x"), make_name_ref_ty("int"));
+
+  assert_eq!(
+    unparse_expr(&make_binop_expr(cast, BinOpKind::Lt, make_ident_expr("y"))),
+    "(x as int) < y"
+  );
+  assert_eq!(
+    unparse_expr(&make_call_expr(
+      make_member_access_expr(*make_ident_expr("a"), "f"),
+      vec![make_ident_expr("x")],
+    )),
+    "(a.f)(x)"
+  );
+  assert_eq!(
+    unparse_expr(&make_member_access_expr(*make_lit_int_expr(1), "len")),
+    "(1).len"
+  );
+  assert_eq!(
+    unparse_ty(&make_fun_ty(
+      vec![TyKind::NameRefTy("int".into())],
+      TyKind::TupleTy(vec![])
+    )),
+    "(int -> ())"
+  );
+}
+
+#[test]
+fn unparse_hygiene_names() {
+  // the renamed `tmp#1` can not be spelled `tmp_1`, a user already took it
+  let source = unparse_source(
+    "macro add_tmp {
+      ($e:expr) => { ((tmp) -> tmp + $e)(1) }
+    }
+    val tmp_1 := 2;
+    add_tmp!(tmp_1);",
+  );
+
+  assert!(
+    source.contains("((tmp_1_) -> tmp_1_ + tmp_1)(1)"),
+    "{}",
+    source
+  );
+  assert!(parse(&source).errors.is_empty());
+}
+
+// a xorshift generator, the random trees are the same on every run
+struct Rng(u64);
+
+impl Rng {
+  fn below(&mut self, n: usize) -> usize {
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 7;
+    self.0 ^= self.0 << 17;
+
+    (self.0 % n as u64) as usize
+  }
+
+  fn name(&mut self) -> String {
+    ["a", "b", "xs", "foo"][self.below(4)].into()
+  }
+
+  fn label(&mut self) -> Option<String> {
+    [None, Some("l".into())][self.below(2)].to_owned()
+  }
+}
+
+fn random_ty(rng: &mut Rng, depth: usize) -> TyKind {
+  let param_tys = |rng: &mut Rng| {
+    (0..rng.below(3))
+      .map(|_| random_ty(rng, depth - 1))
+      .collect::<Vec<TyKind>>()
+  };
+
+  match rng.below(if depth == 0 { 2 } else { 10 }) {
+    0 => TyKind::NameRefTy(["int", "real", "str"][rng.below(3)].into()),
+    1 => TyKind::PathTy(vec!["gl".into(), "vec2".into()]),
+    2 => TyKind::GenericTy {
+      name: "vec".into(),
+      param_tys: vec![random_ty(rng, depth - 1)],
+    },
+    3 => TyKind::OptionalTy(box random_ty(rng, depth - 1)),
+    4 => TyKind::RefTy(box random_ty(rng, depth - 1)),
+    5 => TyKind::SliceTy(box random_ty(rng, depth - 1)),
+    6 => TyKind::ArrayTy {
+      ty: box random_ty(rng, depth - 1),
+      len: make_lit_int_expr(3),
+    },
+    7 => TyKind::TupleTy(param_tys(rng)),
+    8 => TyKind::FunTy {
+      param_tys: param_tys(rng),
+      ret_ty: box TyKind::Void,
+    },
+    _ => TyKind::FunTy {
+      param_tys: param_tys(rng),
+      ret_ty: box random_ty(rng, depth - 1),
+    },
+  }
+}
+
+fn random_atom(rng: &mut Rng) -> Box<Expr> {
+  match rng.below(8) {
+    0 => make_lit_int_expr(rng.below(100) as i64),
+    1 => make_lit_real_expr([0.5, 1.25, 3.0][rng.below(3)]),
+    2 => make_lit_str_expr(["", "hello", "a b"][rng.below(3)].into()),
+    3 => make_lit_nil_expr(),
+    4 => make_loop_var_expr("it"),
+    5 => make_path_expr(
+      [
+        PathRoot::Relative,
+        PathRoot::Package("std".into()),
+        PathRoot::Current,
+      ][rng.below(3)]
+      .to_owned(),
+      vec!["gl".into(), rng.name()],
+    ),
+    _ => make_ident_expr(&rng.name()),
+  }
+}
+
+fn random_exprs(rng: &mut Rng, depth: usize) -> Vec<Box<Expr>> {
+  (0..rng.below(3)).map(|_| random_expr(rng, depth)).collect()
+}
+
+fn random_place(rng: &mut Rng, depth: usize) -> Box<Expr> {
+  match rng.below(4) {
+    0 => make_member_access_expr(*random_expr(rng, depth), &rng.name()),
+    1 => make_index_expr(random_expr(rng, depth), random_expr(rng, depth)),
+    2 => make_tuple_index_expr(random_expr(rng, depth), rng.below(3)),
+    _ => make_ident_expr(&rng.name()),
+  }
+}
+
+fn random_binop(rng: &mut Rng) -> BinOpKind {
+  let ops = [
+    BinOpKind::Add,
+    BinOpKind::Sub,
+    BinOpKind::Mul,
+    BinOpKind::Div,
+    BinOpKind::Mod,
+    BinOpKind::Lt,
+    BinOpKind::Gt,
+    BinOpKind::Le,
+    BinOpKind::Ge,
+    BinOpKind::Ne,
+    BinOpKind::EqEq,
+    BinOpKind::And,
+    BinOpKind::Or,
+    BinOpKind::BitAnd,
+    BinOpKind::BitOr,
+    BinOpKind::Shl,
+    BinOpKind::Shr,
+  ];
+
+  ops[rng.below(ops.len())].to_owned()
+}
+
+fn random_loop(rng: &mut Rng, depth: usize) -> LoopKind {
+  let block = random_block(rng, depth);
+  let label = rng.label();
+
+  match rng.below(3) {
+    0 => LoopKind::LoopLoop { block, label },
+    1 => LoopKind::LoopWhile {
+      condition: random_expr(rng, depth),
+      block,
+      label,
+    },
+    _ => {
+      let iterable = random_expr(rng, depth);
+      let implicit = rng.below(2) == 0;
+
+      let iterator = match (implicit, &iterable.kind) {
+        (true, ExprKind::Range { .. }) => make_loop_var_expr("it"),
+        (true, _) => make_loop_var_expr("elmt"),
+        (false, _) => make_loop_var_expr("x"),
+      };
+
+      LoopKind::LoopFor {
+        iterable,
+        iterator,
+        implicit,
+        block,
+        label,
+      }
+    }
+  }
+}
+
+fn random_expr(rng: &mut Rng, depth: usize) -> Box<Expr> {
+  if depth == 0 {
+    return random_atom(rng);
+  }
+
+  let depth = depth - 1;
+
+  match rng.below(22) {
+    0 | 1 | 2 => make_binop_expr(
+      random_expr(rng, depth),
+      random_binop(rng),
+      random_expr(rng, depth),
+    ),
+    3 => make_unop_expr(["-", "!"][rng.below(2)], random_expr(rng, depth)),
+    4 => make_cast_expr(
+      random_expr(rng, depth),
+      make_unknown_ty(random_ty(rng, 2)),
+    ),
+    5 => make_call_expr(random_expr(rng, depth), random_exprs(rng, depth)),
+    6 => make_method_call_expr(
+      random_expr(rng, depth),
+      &rng.name(),
+      random_exprs(rng, depth),
+    ),
+    7 | 8 => random_place(rng, depth),
+    9 => make_try_expr(random_expr(rng, depth)),
+    10 => make_await_expr(random_expr(rng, depth)),
+    11 => {
+      // a range can not start with a range e.g `a..b..c`
+      let start = match random_expr(rng, depth) {
+        expr if matches!(expr.kind, ExprKind::Range { .. }) => None,
+        expr => Some(expr),
+      };
+
+      match rng.below(3) {
+        0 => make_range_expr(start, None, false),
+        _ => make_range_expr(
+          start,
+          Some(random_expr(rng, depth)),
+          rng.below(2) == 0,
+        ),
+      }
+    }
+    12 => make_assign_expr(random_place(rng, depth), random_expr(rng, depth)),
+    13 => make_compound_assign_expr(
+      random_place(rng, depth),
+      [BinOpKind::Add, BinOpKind::Mul, BinOpKind::Mod][rng.below(3)].to_owned(),
+      random_expr(rng, depth),
+    ),
+    14 => make_tuple_expr(random_exprs(rng, depth)),
+    15 => match rng.below(2) {
+      0 => make_array_expr(random_exprs(rng, depth)),
+      _ => {
+        make_array_repeat_expr(random_expr(rng, depth), random_expr(rng, depth))
+      }
+    },
+    16 => make_hash_expr(
+      (0..rng.below(3))
+        .map(|index| {
+          let key = match index {
+            0 => make_ident_expr(&rng.name()),
+            1 => make_lit_str_expr("key".into()),
+            _ => make_lit_int_expr(index as i64),
+          };
+
+          make_hash_data_expr(key, random_expr(rng, depth))
+        })
+        .collect(),
+    ),
+    17 => make_struct_lit_expr(
+      make_path_expr(PathRoot::Relative, vec!["gl".into(), "vec2".into()]),
+      vec![
+        ("x".into(), random_expr(rng, depth)),
+        ("y".into(), random_expr(rng, depth)),
+      ],
+    ),
+    18 => {
+      let args = (0..rng.below(3))
+        .map(|index| {
+          let ty = match rng.below(2) {
+            0 => TyKind::Unknown,
+            _ => random_ty(rng, 1),
+          };
+
+          let mut arg = make_fun_arg(
+            make_ident_expr(&format!("x{}", index)),
+            make_unknown_ty(ty),
+          );
+
+          if rng.below(3) == 0 {
+            arg.default = Some(random_atom(rng));
+          }
+
+          arg
+        })
+        .collect();
+
+      make_closure_expr(args, random_block(rng, depth))
+    }
+    19 => make_expr(ExprKind::Loop(random_loop(rng, depth))),
+    _ => random_atom(rng),
+  }
+}
+
+fn random_stmt(rng: &mut Rng, depth: usize) -> Box<Stmt> {
+  let names = |rng: &mut Rng| {
+    (0..rng.below(2) + 1)
+      .map(|_| make_ident_expr(&rng.name()))
+      .collect::<Vec<Box<Expr>>>()
+  };
+
+  let ty = |rng: &mut Rng| match rng.below(2) {
+    0 => None,
+    _ => Some(make_unknown_ty(random_ty(rng, 1))),
+  };
+
+  match rng.below(10) {
+    0 => make_val_stmt(names(rng), ty(rng), random_expr(rng, depth)),
+    1 => make_mut_stmt(names(rng), ty(rng), random_expr(rng, depth)),
+    2 => make_ret_stmt(match rng.below(2) {
+      0 => None,
+      _ => Some(random_expr(rng, depth)),
+    }),
+    3 => make_break_stmt(
+      rng.label(),
+      match rng.below(2) {
+        0 => None,
+        _ => Some(random_expr(rng, depth)),
+      },
+    ),
+    4 => make_continue_stmt(rng.label()),
+    5 if depth > 0 => make_if_block_stmt(
+      (0..rng.below(2) + 1)
+        .map(|_| (random_expr(rng, depth), random_block(rng, depth - 1)))
+        .collect(),
+      match rng.below(2) {
+        0 => None,
+        _ => Some(random_block(rng, depth - 1)),
+      },
+    ),
+    _ => make_expr_stmt(random_expr(rng, depth)),
+  }
+}
+
+fn random_block(rng: &mut Rng, depth: usize) -> Box<Block> {
+  make_block_expr((0..rng.below(3)).map(|_| random_stmt(rng, depth)).collect())
+}
+
+fn random_item(rng: &mut Rng) -> Box<Stmt> {
+  let mut stmt = match rng.below(4) {
+    0 => {
+      let ret_ty = match rng.below(2) {
+        0 => TyKind::Void,
+        _ => random_ty(rng, 2),
+      };
+
+      let args = (0..rng.below(3))
+        .map(|index| {
+          make_fun_arg(
+            make_ident_expr(&format!("x{}", index)),
+            make_unknown_ty(random_ty(rng, 1)),
+          )
+        })
+        .collect();
+
+      let mut stmt = make_fun_stmt(
+        make_ident_expr(&rng.name()),
+        vec![],
+        make_fun_sig(vec![], make_unknown_ty(ret_ty)),
+        args,
+        Some(random_block(rng, 3)),
+      );
+
+      if let StmtKind::Fun(ref mut fun) = stmt.kind {
+        fun.visibility = rng.below(2) == 0;
+        fun.docs = match rng.below(3) {
+          0 => vec![],
+          1 => vec!["adds one".into()],
+          _ => vec!["adds one".into(), "".into(), "e.g `inc(1)`".into()],
+        };
+      }
+
+      stmt
+    }
+    1 => make_type_alias_stmt(
+      make_ident_expr("point"),
+      make_unknown_ty(random_ty(rng, 3)),
+    ),
+    _ => random_stmt(rng, 3),
+  };
+
+  if rng.below(4) == 0 {
+    stmt.attrs = vec![Attribute::new(
+      "align",
+      vec![make_lit_int_expr(16)],
+      Span::zero(),
+    )];
+  }
+
+  stmt
+}
+
+// the ids and the spans are not written in the source
+fn without_positions(ast: &Ast) -> serde_json::Value {
+  fn strip(value: &mut serde_json::Value) {
+    match value {
+      serde_json::Value::Object(map) => {
+        map.remove("id");
+        map.remove("span");
+        map.values_mut().for_each(strip);
+      }
+      serde_json::Value::Array(values) => values.iter_mut().for_each(strip),
+      _ => {}
+    }
+  }
+
+  let mut value = serde_json::to_value(ast).unwrap();
+
+  strip(&mut value);
+  value
+}
+
+#[test]
+fn unparse_round_trip_random_trees() {
+  let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+  for _ in 0..500 {
+    let ast = Ast {
+      nodes: (0..rng.below(3) + 1)
+        .map(|_| random_item(&mut rng))
+        .collect(),
+      ..Default::default()
+    };
+
+    let source = unparse(&ast);
+    let tree = parse(&source);
+
+    assert_eq!(
+      without_positions(&tree.ast),
+      without_positions(&ast),
+      "the source is parsed into another tree:\n{}",
+      source
+    );
+  }
+}
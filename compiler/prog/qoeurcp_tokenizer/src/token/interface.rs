@@ -33,7 +33,7 @@ macro symbols {
   },
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum BinaryKind {
   Add,
   Sub,
@@ -53,6 +53,9 @@ pub enum BinaryKind {
   OrOr,
   Dot,
   DotDot,
+  DotDotEq,
+  Shl,
+  Shr,
 }
 
 symbols! {
@@ -75,22 +78,52 @@ symbols! {
     OrOr: "||",
     Dot: ".",
     DotDot: "..",
+    DotDotEq: "..=",
+    Shl: "<<",
+    Shr: ">>",
   }
 }
 
-#[derive(Debug, PartialEq, PartialOrd)]
+// the binding power of the operators from the lowest to the highest
+//
+// | precedence  | operators                    | associativity |
+// |-------------|------------------------------|---------------|
+// | Assignement | `=` `+=` `-=` `*=` `/=` `%=` | right         |
+// | Range       | `..` `..=`                   | none          |
+// | LogicalOr   | `||`                         | left          |
+// | LogicalAnd  | `&&`                         | left          |
+// | Conditional | `==` `!=` `<` `>` `<=` `>=`  | left          |
+// | BitOr       | `|`                          | left          |
+// | BitAnd      | `&`                          | left          |
+// | Shift       | `<<` `>>`                    | left          |
+// | Sum         | `+` `-`                      | left          |
+// | Exponent    | `*` `/` `%`                  | left          |
+// | Cast        | `as`                         | left          |
+// | Unary       | `!` `-` (prefix)             | right         |
+// | Calling     | `(`                          | left          |
+// | Index       | `[`                          | left          |
+// | Access      | `.`                          | left          |
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
 pub enum PrecedenceKind {
   Lowest,
   Assignement,
+  Range,
+  LogicalOr,
+  LogicalAnd,
   Conditional,
+  BitOr,
+  BitAnd,
+  Shift,
   Sum,
   Exponent,
+  Cast,
   Unary,
   Calling,
   Index,
+  Access,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum NumberBase {
   Int,
   Bin,
@@ -98,7 +131,7 @@ pub enum NumberBase {
   Hex,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum LiteralKind {
   RealNumber(String),
   IntNumber(String),
@@ -115,7 +148,7 @@ symbols! {
   }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum TokenKind {
   EOF,
   EOL,
@@ -141,12 +174,15 @@ pub enum TokenKind {
   Comma,
   Dollar,
   DollarDotDot,
+  // the text of the comments above an item e.g `# adds two vec2`
+  DocComment(String),
   QuestionMark,
   Shebang,
   Semicolon,
   As,
   Async,
   Await,
+  Bench,
   Break,
   Capsule,
   Continue,
@@ -160,9 +196,12 @@ pub enum TokenKind {
   If,
   Load,
   Loop,
+  Macro,
   Match,
+  Mock,
   Module,
   Mut,
+  Nil,
   Pub,
   Ref,
   Ret,
@@ -171,7 +210,9 @@ pub enum TokenKind {
   Set,
   Static,
   Struct,
+  Suite,
   Super,
+  Test,
   True,
   Type,
   Typeof,
@@ -194,10 +235,39 @@ impl fmt::Display for TokenKind {
 impl TokenKind {
   pub fn keyword(name: &str) -> TokenKind {
     match name {
+      "as" => Self::As,
+      "async" => Self::Async,
+      "await" => Self::Await,
+      "bench" => Self::Bench,
+      "break" => Self::Break,
+      "capsule" => Self::Capsule,
+      "continue" => Self::Continue,
+      "else" => Self::Else,
+      "enum" => Self::Enum,
+      "exp" => Self::Exp,
+      "ext" => Self::Ext,
+      "for" => Self::For,
       "fun" => Self::Fun,
+      "if" => Self::If,
+      "loop" => Self::Loop,
+      "macro" => Self::Macro,
+      "mock" => Self::Mock,
+      "mod" => Self::Module,
       "mut" => Self::Mut,
+      "nil" => Self::Nil,
+      "pub" => Self::Pub,
+      "ref" => Self::Ref,
+      "ret" => Self::Ret,
+      "self" => Self::SelfLower,
+      "static" => Self::Static,
+      "struct" => Self::Struct,
+      "suite" => Self::Suite,
+      "super" => Self::Super,
+      "test" => Self::Test,
+      "type" => Self::Type,
       "use" => Self::Use,
       "val" => Self::Val,
+      "while" => Self::While,
       _ => Self::Ident(name.into()),
     }
   }
@@ -212,38 +282,103 @@ impl TokenKind {
       "<" => Self::Binary(Lt),
       ">" => Self::Binary(Gt),
       "<=" => Self::Binary(Le),
+      "<<" => Self::Binary(Shl),
+      ">>" => Self::Binary(Shr),
       ">=" => Self::Binary(Ge),
       "=" => Self::AssignOp(Eq),
-      "+=" => Self::AssignOp(Eq),
+      "+=" => Self::AssignOp(Add),
       "-=" => Self::AssignOp(Sub),
       "*=" => Self::AssignOp(Mul),
       "/=" => Self::AssignOp(Div),
+      "%=" => Self::AssignOp(Mod),
       "==" => Self::Binary(EqEq),
       "!" => Self::Unary(UnaryKind::Not),
       "!=" => Self::Binary(Ne),
-      "&&" => Self::Binary(And),
-      "||" => Self::Binary(Or),
+      "&" => Self::Binary(And),
+      "&&" => Self::Binary(AndAnd),
+      "||" => Self::Binary(OrOr),
       "->" => Self::Arrow,
       "=>" => Self::ArrowFat,
       ":" => Self::Colon,
       "::" => Self::ColonColon,
       "|" => Self::Binary(Or),
+      "|>" => Self::Attr,
       "." => Self::Binary(Dot),
+      ".." => Self::Binary(DotDot),
+      "..=" => Self::Binary(DotDotEq),
       _ => Self::Unknown,
     }
   }
 
+  // the tokens that can start a statement but can not continue an expression
+  pub fn is_item_start(&self) -> bool {
+    match *self {
+      Self::Ident(_)
+      | Self::Literal(_)
+      | Self::At
+      | Self::Attr
+      | Self::BackSlash
+      | Self::Dollar
+      | Self::DocComment(_)
+      | Self::Async
+      | Self::Await
+      | Self::Bench
+      | Self::Break
+      | Self::Capsule
+      | Self::Continue
+      | Self::Enum
+      | Self::Exp
+      | Self::Ext
+      | Self::False
+      | Self::For
+      | Self::Fun
+      | Self::If
+      | Self::Loop
+      | Self::Macro
+      | Self::Mock
+      | Self::Module
+      | Self::Mut
+      | Self::Nil
+      | Self::Pub
+      | Self::Ret
+      | Self::SelfLower
+      | Self::Static
+      | Self::Struct
+      | Self::Suite
+      | Self::Super
+      | Self::Test
+      | Self::True
+      | Self::Type
+      | Self::Use
+      | Self::Val
+      | Self::While => true,
+      _ => false,
+    }
+  }
+
   pub fn precedence(kind: &TokenKind) -> PrecedenceKind {
     match kind {
-      Self::Binary(Mul) | Self::Binary(Div) => PrecedenceKind::Exponent,
-      Self::Binary(Add) | Self::Binary(Sub) => PrecedenceKind::Sum,
-      Self::Binary(Lt)
+      Self::Binary(Eq) | Self::AssignOp(_) => PrecedenceKind::Assignement,
+      Self::Binary(DotDot) | Self::Binary(DotDotEq) => PrecedenceKind::Range,
+      Self::Binary(OrOr) => PrecedenceKind::LogicalOr,
+      Self::Binary(AndAnd) => PrecedenceKind::LogicalAnd,
+      Self::Binary(EqEq)
+      | Self::Binary(Ne)
+      | Self::Binary(Lt)
       | Self::Binary(Le)
       | Self::Binary(Gt)
       | Self::Binary(Ge) => PrecedenceKind::Conditional,
-      Self::Binary(Eq) | Self::Binary(Ne) => PrecedenceKind::Assignement,
-      Self::OpenParen => PrecedenceKind::Calling,
+      Self::Binary(Or) => PrecedenceKind::BitOr,
+      Self::Binary(And) => PrecedenceKind::BitAnd,
+      Self::Binary(Shl) | Self::Binary(Shr) => PrecedenceKind::Shift,
+      Self::Binary(Add) | Self::Binary(Sub) => PrecedenceKind::Sum,
+      Self::Binary(Mul) | Self::Binary(Div) | Self::Binary(Mod) => {
+        PrecedenceKind::Exponent
+      }
+      Self::As => PrecedenceKind::Cast,
+      Self::OpenParen | Self::QuestionMark => PrecedenceKind::Calling,
       Self::OpenBracket => PrecedenceKind::Index,
+      Self::Binary(Dot) => PrecedenceKind::Access,
       _ => PrecedenceKind::Lowest,
     }
   }
@@ -274,6 +409,7 @@ impl TokenKind {
       Self::As => format!("as"),
       Self::Async => format!("async"),
       Self::Await => format!("await"),
+      Self::Bench => format!("bench"),
       Self::Break => format!("break"),
       Self::Capsule => format!("capsule"),
       Self::Continue => format!("continue"),
@@ -287,9 +423,12 @@ impl TokenKind {
       Self::If => format!("if"),
       Self::Load => format!("load"),
       Self::Loop => format!("loop"),
+      Self::Macro => format!("macro"),
       Self::Match => format!("match"),
+      Self::Mock => format!("mock"),
       Self::Module => format!("mod"),
       Self::Mut => format!("mut"),
+      Self::Nil => format!("nil"),
       Self::Pub => format!("pub"),
       Self::Ref => format!("ref"),
       Self::Ret => format!("ret"),
@@ -298,7 +437,9 @@ impl TokenKind {
       Self::Set => format!("set"),
       Self::Static => format!("static"),
       Self::Struct => format!("struct"),
+      Self::Suite => format!("suite"),
       Self::Super => format!("super"),
+      Self::Test => format!("test"),
       Self::True => format!("true"),
       Self::Type => format!("type"),
       Self::Typeof => format!("typeof"),
@@ -311,6 +452,7 @@ impl TokenKind {
       Self::Unknown => format!("UNKNOWN"),
       Self::AssignOp(ref kind) => format!("{}", kind),
       Self::Binary(ref kind) => format!("{}", kind),
+      Self::DocComment(ref doc) => format!("#! {}", doc),
       Self::Ident(ref ident) => format!("{}", ident),
       Self::Indent(ref indent) => format!("{}", indent),
       Self::Literal(ref lit) => format!("{}", lit),
@@ -331,7 +473,7 @@ pub trait TokenizeResult {
   fn get_result(sink: Self::Sink) -> Self;
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum UnaryKind {
   Not,
   Neg,
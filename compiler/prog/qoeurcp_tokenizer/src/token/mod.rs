@@ -19,7 +19,7 @@ use qoeurcp_span::Span;
 
 use std::fmt;
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Token {
   pub kind: TokenKind,
   pub span: Span,
@@ -14,7 +14,7 @@ impl TokenSink for TokenPrinter {
   fn print(&self, _level: usize) {}
 
   fn process_token(&mut self, token: Token) {
-    println!("token_printer: {}", token);
+    eprintln!("token_printer: {}", token);
     match token.kind {
       _ => {}
     }
@@ -1,4 +1,4 @@
-use super::interface::{BinaryKind, TokenKind};
+use super::interface::{BinaryKind, TokenKind, TokenSink};
 use super::Token;
 
 use qoeurcp_span::{ColumnIndex, LineIndex, Loc, Span};
@@ -7,7 +7,7 @@ use std::collections::VecDeque;
 
 pub type CompileResult<T> = Result<T, String>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TokenQueue {
   pub tokens: VecDeque<Token>,
   pub last_loc: Loc,
@@ -21,6 +21,16 @@ impl Iterator for TokenQueue {
   }
 }
 
+impl TokenSink for TokenQueue {
+  fn end(&mut self) {}
+
+  fn print(&self, _level: usize) {}
+
+  fn process_token(&mut self, token: Token) {
+    self.push_back(token);
+  }
+}
+
 impl TokenQueue {
   pub fn new() -> TokenQueue {
     Self {
@@ -37,10 +47,6 @@ impl TokenQueue {
     self.last_loc
   }
 
-  pub fn dump(&self) {
-    self.tokens.iter().for_each(|t| println!("{:?}", t));
-  }
-
   pub fn push_front(&mut self, token: Token) {
     self.tokens.push_front(token)
   }
@@ -2,8 +2,7 @@ use super::buffer_queue::{BufferQueue, FromSet, NotFromSet, SetResult};
 use super::state::TokenizerState;
 
 use super::token::{
-  LiteralKind, NumberBase, Token, TokenKind, TokenPrinter, TokenQueue,
-  TokenSink,
+  LiteralKind, NumberBase, Token, TokenKind, TokenQueue, TokenSink,
 };
 
 use super::util::ascii::*;
@@ -58,8 +57,6 @@ pub fn tokenize(source: &str) -> Result<TokenQueue, String> {
   match f {
     None => Err(format!("")),
     Some(file) => {
-      println!("\n{}", file);
-
       let opts = TokenizerOpts {
         profile: true,
         exact_errors: true,
@@ -67,7 +64,7 @@ pub fn tokenize(source: &str) -> Result<TokenQueue, String> {
       };
 
       let buffer = StrTendril::from(file);
-      let sink = TokenPrinter::new();
+      let sink = TokenQueue::new();
       let mut tokenizer = Tokenizer::new(sink, opts);
       let _ = tokenizer.feed(buffer.try_reinterpret().unwrap());
       let _ = tokenizer.end();
@@ -168,6 +165,10 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
     self.sink
   }
 
+  pub fn sink(&self) -> &Sink {
+    &self.sink
+  }
+
   fn add(&mut self, kind: TokenKind, span: Span) {
     self
       .token_queue
@@ -194,6 +195,27 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
     }
   }
 
+  // a comment is kept as a token when it can document the item below it e.g
+  // `# adds two vec2` or `#!+ ... #!-`, a `#+ ... #-` block is dropped
+  fn emit_comment(&mut self) {
+    let data = mem::replace(&mut self.data, String::new());
+    let lines = data.lines().map(|line| line.trim()).collect::<Vec<&str>>();
+
+    let doc = match lines[0] {
+      "#+" => return,
+      "#!+" => lines[1..lines.len() - 1]
+        .iter()
+        .map(|line| strip_comment(line))
+        .collect::<Vec<String>>()
+        .join("\n"),
+      line => strip_comment(line),
+    };
+
+    let span = self.current_span();
+
+    self.add(TokenKind::DocComment(doc), span);
+  }
+
   fn emit_error(&mut self, error: Cow<'static, str>) {
     let span = self.current_span();
     let error = TokenKind::ParseError(error);
@@ -271,6 +293,19 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
     }
   }
 
+  // a block comment spans the lines until its closing line e.g `#-`
+  fn is_block_comment_open(&self) -> bool {
+    let mut lines = self.data.lines().map(|line| line.trim());
+
+    let close = match lines.next() {
+      Some("#+") => "#-",
+      Some("#!+") => "#!-",
+      _ => return false,
+    };
+
+    lines.last() != Some(close)
+  }
+
   fn peek(&mut self) -> Option<char> {
     if self.reconsume {
       Some(self.current_char)
@@ -331,6 +366,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
           }
           FromSet('\n') => {
             self.indent_level += INDENT_LEVEL_NEWLINE;
+            self.loc.line += LineOffset(1);
 
             return true;
           }
@@ -363,10 +399,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             return true;
           }
           '#' => {
-            self.state = TokenizerState::Quiescent;
-            let span = self.current_span();
-
-            self.add(TokenKind::Comma, span);
+            self.start(self.current_char, TokenizerState::Comment);
             return true;
           }
           ',' => {
@@ -418,6 +451,13 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             self.add(TokenKind::CloseBracket, span);
             return true;
           }
+          '\\' => {
+            self.state = TokenizerState::Quiescent;
+            let span = self.current_span();
+
+            self.add(TokenKind::BackSlash, span);
+            return true;
+          }
           '$' => {
             self.state = TokenizerState::Quiescent;
             let span = self.current_span();
@@ -494,11 +534,16 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
       },
       TokenizerState::Comment => loop {
         match get_char!(self) {
-          c if c == '\n' => {
+          '\n' if self.is_block_comment_open() => {
+            self.loc.line += LineOffset(1);
+            self.data.push('\n');
+          }
+          '\n' => {
+            self.emit_comment();
             self.start_newline();
             return true;
           }
-          _ => {}
+          c => self.data.push(c),
         }
       },
       TokenizerState::Char => loop {
@@ -551,7 +596,12 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
             self.data.push(c);
             return true;
           }
-          c if c == '.' => {
+          // a fraction is a dot followed by a digit, otherwise the dot
+          // starts a range or a member access e.g `0..3`, `t.0.x`, `16.`
+          c if c == '.'
+            && !self.data.contains('.')
+            && self.peek().map_or(false, is_number) =>
+          {
             self.current_base_number = NumberBase::Dec;
             self.state = TokenizerState::Number;
 
@@ -567,6 +617,7 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
           }
           _ => {
             self.state = TokenizerState::Quiescent;
+            self.reconsume = true;
             let span = self.current_span();
             let num = mem::replace(&mut self.data, String::new());
 
@@ -582,19 +633,9 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
       },
       TokenizerState::Op => loop {
         match get_char!(self) {
-          c if c.is_whitespace()
-            || c.is_alphanumeric()
-            || c == '{'
-            || c == '('
-            || c == '['
-            || c == '}'
-            || c == ')'
-            || c == ']'
-            || c == '$'
-            || c == ','
-            || c == '_' =>
-          {
+          c if !is_operator(c) => {
             self.state = TokenizerState::Quiescent;
+            self.reconsume = true;
             let span = self.current_span();
             let kind = TokenKind::glue(&self.data[..]);
 
@@ -627,3 +668,13 @@ impl<Sink: TokenSink> Tokenizer<Sink> {
     }
   }
 }
+
+// e.g `#! adds two vec2` is `adds two vec2`
+fn strip_comment(line: &str) -> String {
+  let line = line
+    .strip_prefix("#!")
+    .or_else(|| line.strip_prefix('#'))
+    .unwrap_or(line);
+
+  line.strip_prefix(' ').unwrap_or(line).to_string()
+}
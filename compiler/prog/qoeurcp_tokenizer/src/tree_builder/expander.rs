@@ -0,0 +1,600 @@
+use crate::ast::{MacroDef, TokenTree};
+use crate::token::*;
+
+use std::collections::{HashMap, HashSet};
+
+type Bindings = HashMap<String, Binding>;
+
+// a variable of a pattern is bound to a fragment of the arguments or, under
+// a repetition, to one binding per repetition
+#[derive(Clone, Debug)]
+enum Binding {
+  Fragment(FragmentKind, Vec<TokenTree>),
+  Repeat(Vec<Binding>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FragmentKind {
+  Expr,
+  Ident,
+  Lit,
+  Tt,
+  Ty,
+}
+
+impl FragmentKind {
+  fn from(name: &str) -> Result<FragmentKind, String> {
+    match name {
+      "expr" => Ok(Self::Expr),
+      "ident" => Ok(Self::Ident),
+      "lit" => Ok(Self::Lit),
+      "tt" => Ok(Self::Tt),
+      "ty" => Ok(Self::Ty),
+      _ => Err(format!(
+        "expander:fn:from:error: unknown fragment kind `{}`",
+        name
+      )),
+    }
+  }
+}
+
+enum Matcher {
+  Token(TokenKind),
+  Delimited(TokenKind, Vec<Matcher>),
+  Fragment(String, FragmentKind),
+  Repeat {
+    matchers: Vec<Matcher>,
+    separator: Option<TokenKind>,
+    at_least_one: bool,
+  },
+}
+
+impl Matcher {
+  fn first_token(&self) -> Option<&TokenKind> {
+    match *self {
+      Self::Token(ref kind) | Self::Delimited(ref kind, _) => Some(kind),
+      _ => None,
+    }
+  }
+
+  fn names(matchers: &[Matcher]) -> Vec<String> {
+    let mut names = vec![];
+
+    for matcher in matchers.iter() {
+      match *matcher {
+        Self::Delimited(_, ref matchers)
+        | Self::Repeat { ref matchers, .. } => {
+          names.extend(Self::names(matchers))
+        }
+        Self::Fragment(ref name, _) => names.push(name.to_owned()),
+        Self::Token(_) => {}
+      }
+    }
+
+    names
+  }
+}
+
+// checks the patterns of a macro when it is declared
+pub fn check(mac: &MacroDef) -> Result<(), String> {
+  for rule in mac.rules.iter() {
+    compile(&rule.pattern)?;
+  }
+
+  Ok(())
+}
+
+// expands the first rule whose pattern matches the arguments, the names
+// bound by the template are renamed with the number of the expansion so
+// they can not capture or shadow the names of the invocation site
+pub fn expand(
+  mac: &MacroDef,
+  args: &[TokenTree],
+  expansion: usize,
+) -> Result<Vec<Token>, String> {
+  for rule in mac.rules.iter() {
+    let matchers = compile(&rule.pattern)?;
+
+    if let Some(bindings) = match_all(&matchers, args) {
+      let mut renames = HashSet::new();
+      let mut trees = vec![];
+
+      binders(&rule.template, &mut renames);
+      transcribe(&rule.template, &bindings, &renames, expansion, &mut trees)?;
+
+      return Ok(TokenTree::flatten(trees));
+    }
+  }
+
+  Err(format!(
+    "expander:fn:expand:error: no rule of `{}!` matches `{}`",
+    mac.name,
+    TokenTree::text_of(args)
+  ))
+}
+
+// the names introduced by `val`, `mut` or the parameters of a closure
+fn binders(trees: &[TokenTree], names: &mut HashSet<String>) {
+  for (index, tree) in trees.iter().enumerate() {
+    match *tree {
+      TokenTree::Token(Token {
+        kind: TokenKind::Mut,
+        ..
+      })
+      | TokenTree::Token(Token {
+        kind: TokenKind::Val,
+        ..
+      }) => {
+        for tree in trees[index + 1..].iter() {
+          match *tree {
+            TokenTree::Token(Token {
+              kind: TokenKind::Ident(ref name),
+              ..
+            }) => names.insert(name.to_owned()),
+            _ => break,
+          };
+        }
+      }
+      TokenTree::Delimited {
+        ref open,
+        trees: ref params,
+        ..
+      } if open.kind == OpenParen && is_closure_params(trees, index) => {
+        for (index, param) in params.iter().enumerate() {
+          if let TokenTree::Token(Token {
+            kind: TokenKind::Ident(ref name),
+            ..
+          }) = *param
+          {
+            match params.get(index + 1) {
+              None
+              | Some(TokenTree::Token(Token { kind: Comma, .. }))
+              | Some(TokenTree::Token(Token { kind: Colon, .. })) => {
+                names.insert(name.to_owned());
+              }
+              _ => {}
+            }
+          }
+
+          if let TokenTree::Delimited { ref trees, .. } = *param {
+            binders(trees, names);
+          }
+        }
+      }
+      TokenTree::Delimited { ref trees, .. } => binders(trees, names),
+      _ => {}
+    }
+  }
+}
+
+fn compile(trees: &[TokenTree]) -> Result<Vec<Matcher>, String> {
+  let mut matchers = vec![];
+  let mut index = 0;
+
+  while index < trees.len() {
+    match (&trees[index], trees.get(index + 1)) {
+      (
+        TokenTree::Token(Token { kind: Dollar, .. }),
+        Some(TokenTree::Token(Token {
+          kind: TokenKind::Ident(ref name),
+          ..
+        })),
+      ) => match (trees.get(index + 2), trees.get(index + 3)) {
+        (
+          Some(TokenTree::Token(Token { kind: Colon, .. })),
+          Some(TokenTree::Token(Token {
+            kind: TokenKind::Ident(ref kind),
+            ..
+          })),
+        ) => {
+          matchers.push(Matcher::Fragment(
+            name.to_owned(),
+            FragmentKind::from(kind)?,
+          ));
+          index += 4;
+        }
+        _ => {
+          return Err(format!(
+            "expander:fn:compile:error: `${}` has no fragment kind",
+            name
+          ))
+        }
+      },
+      (
+        TokenTree::Token(Token { kind: Dollar, .. }),
+        Some(TokenTree::Delimited {
+          ref open,
+          trees: ref inner,
+          ..
+        }),
+      ) if open.kind == OpenParen => {
+        let (separator, at_least_one, len) = repetition(trees, index + 2)?;
+
+        matchers.push(Matcher::Repeat {
+          matchers: compile(inner)?,
+          separator,
+          at_least_one,
+        });
+
+        index += 2 + len;
+      }
+      (
+        TokenTree::Delimited {
+          ref open,
+          trees: ref inner,
+          ..
+        },
+        _,
+      ) => {
+        matchers.push(Matcher::Delimited(open.kind(), compile(inner)?));
+        index += 1;
+      }
+      (TokenTree::Token(ref token), _) => {
+        matchers.push(Matcher::Token(token.kind()));
+        index += 1;
+      }
+    }
+  }
+
+  Ok(matchers)
+}
+
+fn is_closure_params(trees: &[TokenTree], index: usize) -> bool {
+  match trees.get(index + 1) {
+    Some(TokenTree::Token(Token { kind: Arrow, .. })) => true,
+    Some(TokenTree::Delimited { ref open, .. }) => open.kind == OpenBrace,
+    _ => false,
+  }
+}
+
+// the fragments are greedy, an expression or a type runs until the token
+// that follows it in the pattern or until a separator
+fn match_fragment(
+  kind: FragmentKind,
+  trees: &[TokenTree],
+  stop: Option<&TokenKind>,
+) -> Option<usize> {
+  let is_stop = |tree: &TokenTree| match *tree {
+    TokenTree::Token(ref token) => {
+      Some(&token.kind) == stop
+        || token.kind == Comma
+        || token.kind == Semicolon
+        || token.kind == ArrowFat
+    }
+    TokenTree::Delimited { ref open, .. } => Some(&open.kind) == stop,
+  };
+
+  match (kind, trees.first()) {
+    (_, None) => None,
+    (
+      FragmentKind::Ident,
+      Some(TokenTree::Token(Token {
+        kind: TokenKind::Ident(_),
+        ..
+      })),
+    )
+    | (
+      FragmentKind::Lit,
+      Some(TokenTree::Token(Token {
+        kind: TokenKind::Literal(_),
+        ..
+      })),
+    )
+    | (FragmentKind::Tt, Some(_)) => Some(1),
+    (FragmentKind::Expr, _) | (FragmentKind::Ty, _) => {
+      match trees.iter().take_while(|tree| !is_stop(tree)).count() {
+        0 => None,
+        len => Some(len),
+      }
+    }
+    _ => None,
+  }
+}
+
+fn match_all(matchers: &[Matcher], trees: &[TokenTree]) -> Option<Bindings> {
+  let mut bindings = HashMap::new();
+
+  match match_prefix(matchers, trees, None, &mut bindings)? {
+    len if len == trees.len() => Some(bindings),
+    _ => None,
+  }
+}
+
+// matches the matchers against the first trees, returns the number of trees
+// consumed
+fn match_prefix(
+  matchers: &[Matcher],
+  trees: &[TokenTree],
+  stop: Option<&TokenKind>,
+  bindings: &mut Bindings,
+) -> Option<usize> {
+  let mut pos = 0;
+
+  for (index, matcher) in matchers.iter().enumerate() {
+    let next = matchers
+      .get(index + 1)
+      .and_then(|matcher| matcher.first_token())
+      .or(stop);
+
+    match *matcher {
+      Matcher::Token(ref kind) => match trees.get(pos) {
+        Some(TokenTree::Token(ref token)) if token.kind == *kind => pos += 1,
+        _ => return None,
+      },
+      Matcher::Delimited(ref kind, ref matchers) => match trees.get(pos) {
+        Some(TokenTree::Delimited {
+          ref open,
+          ref trees,
+          ..
+        }) if open.kind == *kind => {
+          if match_prefix(matchers, trees, None, bindings)? != trees.len() {
+            return None;
+          }
+
+          pos += 1;
+        }
+        _ => return None,
+      },
+      Matcher::Fragment(ref name, kind) => {
+        let len = match_fragment(kind, &trees[pos..], next)?;
+        let fragment = trees[pos..pos + len].to_vec();
+
+        bindings.insert(name.to_owned(), Binding::Fragment(kind, fragment));
+        pos += len;
+      }
+      Matcher::Repeat {
+        ref matchers,
+        ref separator,
+        at_least_one,
+      } => {
+        let mut repeats: Vec<Bindings> = vec![];
+
+        loop {
+          let mut start = pos;
+
+          if let (false, Some(separator)) = (repeats.is_empty(), separator) {
+            match trees.get(pos) {
+              Some(TokenTree::Token(ref token)) if token.kind == *separator => {
+                start += 1
+              }
+              _ => break,
+            }
+          }
+
+          match trees.get(start) {
+            None => break,
+            Some(TokenTree::Token(ref token)) if Some(&token.kind) == next => {
+              break
+            }
+            _ => {}
+          }
+
+          let mut repeat = HashMap::new();
+          let stop = separator.as_ref().or(next);
+
+          match match_prefix(matchers, &trees[start..], stop, &mut repeat) {
+            Some(len) if len > 0 => {
+              pos = start + len;
+              repeats.push(repeat);
+            }
+            _ => break,
+          }
+        }
+
+        if at_least_one && repeats.is_empty() {
+          return None;
+        }
+
+        for name in Matcher::names(matchers) {
+          let binding = repeats
+            .iter_mut()
+            .filter_map(|repeat| repeat.remove(&name))
+            .collect();
+
+          bindings.insert(name, Binding::Repeat(binding));
+        }
+      }
+    }
+  }
+
+  Some(pos)
+}
+
+// reads the separator and the operator of a repetition e.g `),*`, `)+`,
+// returns them with the number of trees they span
+fn repetition(
+  trees: &[TokenTree],
+  index: usize,
+) -> Result<(Option<TokenKind>, bool, usize), String> {
+  let op = |tree: Option<&TokenTree>| match tree {
+    Some(TokenTree::Token(Token {
+      kind: TokenKind::Binary(BinaryKind::Mul),
+      ..
+    })) => Some(false),
+    Some(TokenTree::Token(Token {
+      kind: TokenKind::Binary(BinaryKind::Add),
+      ..
+    })) => Some(true),
+    _ => None,
+  };
+
+  if let Some(at_least_one) = op(trees.get(index)) {
+    return Ok((None, at_least_one, 1));
+  }
+
+  match (trees.get(index), op(trees.get(index + 1))) {
+    (Some(TokenTree::Token(ref separator)), Some(at_least_one)) => {
+      Ok((Some(separator.kind()), at_least_one, 2))
+    }
+    _ => Err(format!(
+      "expander:fn:repetition:error: a repetition ends with `*` or `+`"
+    )),
+  }
+}
+
+fn transcribe(
+  template: &[TokenTree],
+  bindings: &Bindings,
+  renames: &HashSet<String>,
+  expansion: usize,
+  trees: &mut Vec<TokenTree>,
+) -> Result<(), String> {
+  let mut index = 0;
+
+  while index < template.len() {
+    match (&template[index], template.get(index + 1)) {
+      (
+        TokenTree::Token(Token { kind: Dollar, .. }),
+        Some(TokenTree::Token(Token {
+          kind: TokenKind::Ident(ref name),
+          ..
+        })),
+      ) if bindings.contains_key(name) => {
+        match bindings[name] {
+          // an expression keeps its precedence e.g `$x * 2` with `a + 1`
+          Binding::Fragment(FragmentKind::Expr, ref fragment)
+            if fragment.len() > 1 =>
+          {
+            let first = TokenTree::flatten(fragment[..1].to_vec()).remove(0);
+
+            trees.push(TokenTree::Delimited {
+              open: Token::new(OpenParen, first.span.to_owned()),
+              trees: fragment.to_vec(),
+              close: Token::new(CloseParen, first.span),
+            });
+          }
+          Binding::Fragment(_, ref fragment) => trees.extend(fragment.to_vec()),
+          Binding::Repeat(_) => {
+            return Err(format!(
+              "expander:fn:transcribe:error: `${}` is repeated, it must be \
+               used in a repetition",
+              name
+            ))
+          }
+        }
+
+        index += 2;
+      }
+      (
+        TokenTree::Token(Token { kind: Dollar, .. }),
+        Some(TokenTree::Delimited {
+          ref open,
+          trees: ref inner,
+          ..
+        }),
+      ) if open.kind == OpenParen => {
+        let (separator, _, len) = repetition(template, index + 2)?;
+        let separator = match separator {
+          Some(_) => template.get(index + 2).cloned(),
+          None => None,
+        };
+
+        let mut names = HashSet::new();
+
+        variables(inner, &mut names);
+
+        let repeats = names
+          .iter()
+          .filter_map(|name| match bindings.get(name) {
+            Some(Binding::Repeat(ref repeats)) => Some((name, repeats)),
+            _ => None,
+          })
+          .collect::<Vec<_>>();
+
+        let count = match repeats.first() {
+          Some((_, ref first)) => first.len(),
+          None => {
+            return Err(format!(
+              "expander:fn:transcribe:error: a repetition has no repeated \
+               variable"
+            ))
+          }
+        };
+
+        if repeats.iter().any(|(_, repeats)| repeats.len() != count) {
+          return Err(format!(
+            "expander:fn:transcribe:error: the repeated variables do not \
+             repeat the same number of times"
+          ));
+        }
+
+        for n in 0..count {
+          let mut bindings = bindings.to_owned();
+
+          for (name, repeats) in repeats.iter() {
+            bindings.insert(name.to_string(), repeats[n].to_owned());
+          }
+
+          if let (true, Some(separator)) = (n > 0, separator.as_ref()) {
+            trees.push(separator.to_owned());
+          }
+
+          transcribe(inner, &bindings, renames, expansion, trees)?;
+        }
+
+        index += 2 + len;
+      }
+      (
+        TokenTree::Delimited {
+          ref open,
+          trees: ref inner,
+          ref close,
+        },
+        _,
+      ) => {
+        let mut inner_trees = vec![];
+
+        transcribe(inner, bindings, renames, expansion, &mut inner_trees)?;
+
+        trees.push(TokenTree::Delimited {
+          open: open.to_owned(),
+          trees: inner_trees,
+          close: close.to_owned(),
+        });
+
+        index += 1;
+      }
+      (
+        TokenTree::Token(Token {
+          kind: TokenKind::Ident(ref name),
+          ref span,
+        }),
+        _,
+      ) if renames.contains(name) => {
+        let name = format!("{}#{}", name, expansion);
+
+        trees.push(TokenTree::Token(Token::new(
+          TokenKind::Ident(name),
+          span.to_owned(),
+        )));
+
+        index += 1;
+      }
+      (tree, _) => {
+        trees.push(tree.to_owned());
+        index += 1;
+      }
+    }
+  }
+
+  Ok(())
+}
+
+// the variables used by a template e.g `$x`
+fn variables(trees: &[TokenTree], names: &mut HashSet<String>) {
+  for (index, tree) in trees.iter().enumerate() {
+    match (tree, trees.get(index + 1)) {
+      (
+        TokenTree::Token(Token { kind: Dollar, .. }),
+        Some(TokenTree::Token(Token {
+          kind: TokenKind::Ident(ref name),
+          ..
+        })),
+      ) => {
+        names.insert(name.to_owned());
+      }
+      (TokenTree::Delimited { ref trees, .. }, _) => variables(trees, names),
+      _ => {}
+    }
+  }
+}
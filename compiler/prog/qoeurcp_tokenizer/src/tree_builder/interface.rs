@@ -1,4 +1,6 @@
-use crate::ast::{Ast, Stmt};
+use crate::ast::Stmt;
+
+use qoeurcp_span::Span;
 
 use std::borrow::Cow;
 
@@ -7,11 +9,9 @@ pub trait TreeBuilderPrinter {
 }
 
 pub trait TreeSink {
-  type Handle: Clone;
-
-  fn ast(&mut self, ast: Box<Ast>);
-  fn get_stmts(&mut self) -> Self::Handle;
-  fn parse_error(&mut self, msg: Cow<'static, str>);
+  fn append(&mut self, stmt: Box<Stmt>);
+  fn append_docs(&mut self, docs: Vec<String>);
+  fn parse_error(&mut self, msg: Cow<'static, str>, span: Span);
 }
 
 pub trait TreePrinter {
@@ -1,48 +1,80 @@
+mod expander;
 mod interface;
 
 pub use self::interface::{TreeBuilderPrinter, TreePrinter, TreeSink};
 
 use crate::ast::*;
 use crate::token::*;
+use crate::tokenizer::{Tokenizer, TokenizerOpts};
 
-use qoeurcp_span::Span;
+use qoeurcp_span::{LineIndex, Span};
 
-use std::collections::VecDeque;
+use tendril::StrTendril;
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::iter;
 use std::mem;
 
-pub struct TreeBuilder<Handle, Sink> {
-  handle: Handle,
-  nodes: Vec<Handle>,
+// the macros of the library e.g `print!`, `must!`
+static PRELUDE: &str = include_str!("prelude.q5");
+
+// the nesting of expansions is bounded to stop a macro expanding into itself
+static EXPANSION_LIMIT: usize = 64;
+
+pub struct TreeBuilder<Sink> {
   tokens: VecDeque<Token>,
   sink: Sink,
-  errors: Vec<String>,
-  stmts: Vec<Box<Stmt>>,
+  blocks: usize,
+  // the closing delimiters awaited by the buffered item, innermost last
+  delimiters: Vec<TokenKind>,
+  expansions: usize,
+  expansion_depth: usize,
+  macros: HashMap<String, MacroDef>,
+  ids: NodeIds,
   token: Box<Token>,
   first: Box<Token>,
+  // the doc comments above the current and the next token
+  docs: Vec<String>,
+  first_docs: Vec<String>,
+  // the line the last token read ends on, it outlives the batches of tokens
+  // parsed while the input is fed
+  last_token_line: Option<LineIndex>,
+  is_module_start: bool,
+  validator: Validator,
 }
 
-impl<Handle, Sink> TreeBuilder<Handle, Sink>
+impl<Sink> TreeBuilder<Sink>
 where
-  Handle: Clone,
-  Sink: TreeSink<Handle = Handle>,
+  Sink: TreeSink,
 {
-  pub fn new(mut sink: Sink) -> TreeBuilder<Handle, Sink> {
-    let handle = sink.get_stmts();
+  pub fn new(sink: Sink) -> TreeBuilder<Sink> {
+    Self::with_prelude(sink, PRELUDE)
+  }
 
-    Self {
-      handle: handle,
+  pub(crate) fn with_prelude(sink: Sink, prelude: &str) -> TreeBuilder<Sink> {
+    let mut tree_builder = Self {
       sink: sink,
-      stmts: vec![],
-      nodes: vec![],
-      errors: vec![],
+      blocks: 0,
+      delimiters: vec![],
+      expansions: 0,
+      expansion_depth: 0,
+      macros: HashMap::new(),
+      ids: NodeIds::new(),
       tokens: VecDeque::new(),
       token: box Token::new(TokenKind::EOF, Span::zero()),
       first: box Token::new(TokenKind::EOF, Span::zero()),
-    }
-  }
-
-  pub fn stmts(&self) -> Vec<Box<Stmt>> {
-    self.stmts.to_vec()
+      docs: vec![],
+      first_docs: vec![],
+      last_token_line: None,
+      is_module_start: false,
+      validator: Validator::new(),
+    };
+
+    tree_builder.load_prelude(prelude);
+    tree_builder.last_token_line = None;
+    tree_builder.is_module_start = true;
+    tree_builder
   }
 
   pub fn unwrap(self) -> Sink {
@@ -58,23 +90,71 @@ where
   }
 
   pub fn next_token<'a>(&mut self) {
-    self.token = mem::replace(
-      &mut self.first,
-      box self
+    self.docs = mem::replace(&mut self.first_docs, vec![]);
+
+    let first = self.pop_token();
+
+    self.token = mem::replace(&mut self.first, box first);
+  }
+
+  // the comments above a token are set aside as its docs, they document the
+  // item of the token only when no blank line separates them from it, the
+  // comments at the top of a file separated this way document the module
+  fn pop_token(&mut self) -> Token {
+    let mut docs = vec![];
+    let mut last_line = None;
+
+    loop {
+      let token = self
         .tokens
         .pop_front()
-        .unwrap_or(Token::new(TokenKind::EOF, Span::zero()))
-        .to_owned(),
-    );
+        .unwrap_or(Token::new(TokenKind::EOF, Span::zero()));
+
+      if let Some(line) = last_line {
+        if token.span.start.line.0 > line + 1 {
+          let detached = mem::replace(&mut docs, vec![]);
+
+          if self.is_module_start {
+            self.sink.append_docs(detached);
+          }
+        }
+      }
+
+      match token.kind {
+        // a comment that follows a token on its line is not a doc
+        // e.g `a + 1 # note`
+        TokenKind::DocComment(_)
+          if Some(token.span.start.line) == self.last_token_line => {}
+        TokenKind::DocComment(doc) => {
+          docs.push(doc);
+          last_line = Some(token.span.end.line.0);
+        }
+        // the end of a batch is not a token of the input
+        TokenKind::EOF => {
+          self.first_docs = docs;
+
+          return token;
+        }
+        _ => {
+          self.first_docs = docs;
+          self.last_token_line = Some(token.span.end.line);
+          self.is_module_start = false;
+
+          return token;
+        }
+      }
+    }
   }
 
   fn current_precedence(&self) -> PrecedenceKind {
     TokenKind::precedence(&self.token.kind())
   }
 
-  fn emit(&mut self, ast: Box<Ast>) {
-    println!("{:#?}", ast);
-    self.sink.ast(ast);
+  // an item is numbered once it is complete, so the ids of the items parsed
+  // before it never change
+  fn emit(&mut self, mut stmt: Box<Stmt>) {
+    self.ids.assign(&mut stmt);
+    self.sink.append(stmt);
   }
 
   fn expect_first(&mut self, kind: &TokenKind) -> Result<(), String> {
@@ -89,18 +169,228 @@ where
     ))
   }
 
+  // an invocation is a name followed by a bang and a delimited argument e.g
+  // `print!("{}", x)`
+  fn is_macro_call_ahead(&self) -> bool {
+    self.first_is(&TokenKind::Unary(UnaryKind::Not))
+      && (self.second_is(&OpenParen)
+        || self.second_is(&OpenBracket)
+        || self.second_is(&OpenBrace))
+  }
+
+  // an invocation is a statement when its arguments are followed by the end
+  // of a statement e.g `print!(x);`, but not `square!(x) * 2`
+  fn is_macro_stmt_ahead(&self) -> bool {
+    match self.token.kind() {
+      TokenKind::Ident(_) if self.is_macro_call_ahead() => {}
+      _ => return false,
+    }
+
+    let mut depth = 0;
+    let mut tokens = self.tokens_ahead();
+
+    while let Some(token) = tokens.next() {
+      match token.kind {
+        OpenBrace | OpenBracket | OpenParen => depth += 1,
+        CloseBrace | CloseBracket | CloseParen if depth > 1 => depth -= 1,
+        CloseBrace | CloseBracket | CloseParen => {
+          return match tokens.next().map(|token| token.kind()) {
+            None | Some(Semicolon) | Some(CloseBrace) | Some(EOF) => true,
+            _ => false,
+          };
+        }
+        _ => {}
+      }
+    }
+
+    false
+  }
+
   fn first_is(&self, kind: &TokenKind) -> bool {
     self.first.kind() == *kind
   }
 
+  // a parenthesized list is a closure when it is followed by an arrow, or by
+  // a block when it only holds parameters e.g `(x) -> x * x`, `(x, y) { }`
+  fn is_closure_ahead(&self) -> bool {
+    let is_params = self.first_is(&CloseParen)
+      || match (self.first.kind(), self.tokens_ahead().next()) {
+        (TokenKind::Binary(BinaryKind::Dot), _) => true,
+        (TokenKind::Ident(_), Some(token)) => match token.kind {
+          Comma | Colon | CloseParen => true,
+          _ => false,
+        },
+        _ => false,
+      };
+
+    let mut depth = 0;
+    let mut tokens = iter::once(&*self.first).chain(self.tokens_ahead());
+
+    while let Some(token) = tokens.next() {
+      match token.kind {
+        OpenParen => depth += 1,
+        CloseParen if depth > 0 => depth -= 1,
+        CloseParen => {
+          return match tokens.next().map(|token| token.kind()) {
+            Some(TokenKind::Arrow) => true,
+            Some(TokenKind::OpenBrace) => is_params,
+            _ => false,
+          };
+        }
+        _ => {}
+      }
+    }
+
+    false
+  }
+
+  // a parenthesized type is a function type when it holds an arrow, named or
+  // untyped parameters e.g `(int -> int)`, `(x: int)`, `(\x -> int)`
+  fn is_fun_ty_ahead(&self) -> bool {
+    let mut depth = 0;
+    let tokens = iter::once(&*self.first).chain(self.tokens_ahead());
+
+    for token in tokens {
+      match token.kind {
+        OpenParen | OpenBracket => depth += 1,
+        CloseParen | CloseBracket if depth > 0 => depth -= 1,
+        CloseParen => return false,
+        Arrow | Colon | BackSlash if depth == 0 => return true,
+        _ => {}
+      }
+    }
+
+    false
+  }
+
+  // a semicolon after the first element makes a repeat array e.g `[0; 16]`
   fn parse_array_expr(&mut self) -> Result<Box<Expr>, String> {
-    let data = self.parse_until(&CloseBracket)?;
+    if self.first_is(&CloseBracket) {
+      self.next_token();
+
+      return Ok(make_array_expr(vec![]));
+    }
+
+    self.next_token();
+
+    let value = self.parse_expr_by_precedence(&Lowest)?;
+
+    if self.first_is(&Semicolon) {
+      self.next_token();
+      self.next_token();
+
+      let len = self.parse_expr_by_precedence(&Lowest)?;
+
+      self.expect_first(&CloseBracket)?;
+
+      return Ok(make_array_repeat_expr(value, len));
+    }
+
+    let mut data = vec![value];
+
+    data.extend(self.parse_rest_until(&CloseBracket)?);
+
     Ok(make_array_expr(data))
   }
 
+  // the assignment is right associative e.g `x = y = 0` is `x = (y = 0)`
+  fn parse_assign_expr(&mut self, lhs: Box<Expr>) -> Result<Box<Expr>, String> {
+    if !lhs.is_place() {
+      return Err(format!(
+        "parser:fn:parse_assign_expr:error: invalid assignment target {:?}",
+        lhs.kind
+      ));
+    }
+
+    let op = BinOpKind::from_assign_op(&self.token);
+
+    self.next_token();
+
+    let rhs = self.parse_expr_by_precedence(&Lowest)?;
+
+    Ok(match op {
+      Some(op) => make_compound_assign_expr(lhs, op, rhs),
+      None => make_assign_expr(lhs, rhs),
+    })
+  }
+
+  // an attribute has a name and optional arguments e.g `|> inline.`,
+  // `|> derive: clone, debug.`
+  fn parse_attr(&mut self) -> Result<Attribute, String> {
+    let span = self.token.span.to_owned();
+    let mut args = vec![];
+
+    self.next_token();
+
+    let name = self.parse_attr_name()?;
+
+    if self.first_is(&Colon) {
+      self.next_token();
+
+      loop {
+        self.next_token();
+        args.push(self.parse_attr_arg()?);
+
+        if !self.first_is(&Comma) {
+          break;
+        }
+
+        self.next_token();
+      }
+    }
+
+    self.expect_first(&TokenKind::Binary(BinaryKind::Dot))?;
+
+    Ok(Attribute::new(&name, args, span))
+  }
+
+  fn parse_attr_arg(&mut self) -> Result<Box<Expr>, String> {
+    match self.token.kind() {
+      TokenKind::Literal(_)
+      | TokenKind::At
+      | TokenKind::SelfLower
+      | TokenKind::Super => self.parse_expr(),
+      TokenKind::Ident(_) if self.first_is(&ColonColon) => self.parse_expr(),
+      _ => Ok(make_ident_expr(&self.parse_attr_name()?)),
+    }
+  }
+
+  // the keywords are names in an attribute e.g `|> cfg: test.`
+  fn parse_attr_name(&mut self) -> Result<String, String> {
+    match self.token.kind() {
+      TokenKind::Ident(name) => Ok(name),
+      kind if TokenKind::keyword(&kind.text()) == kind => Ok(kind.text()),
+      _ => Err(format!(
+        "parser:fn:parse_attr_name:error: {}",
+        self.token.text()
+      )),
+    }
+  }
+
+  // the attributes are attached to the item that follows them
+  fn parse_attr_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let attr = self.parse_attr()?;
+
+    if self.first_is(&EOF) {
+      return Err(format!(
+        "parser:fn:parse_attr_stmt:error: an item is expected after `{}`",
+        attr.text()
+      ));
+    }
+
+    self.next_token();
+
+    let mut stmt = self.parse_stmt()?;
+
+    stmt.attrs.insert(0, attr);
+
+    Ok(stmt)
+  }
+
   fn parse_binop_expr(&mut self, lhs: Box<Expr>) -> Result<Box<Expr>, String> {
     let precedence = self.current_precedence();
-    let op = BinOpKind::from(&self.token);
+    let op = BinOpKind::try_from(&*self.token)
+      .map_err(|e| format!("parser:fn:parse_binop_expr:error: {}", e))?;
 
     self.next_token();
 
@@ -114,29 +404,59 @@ where
     lhs: Box<Expr>,
   ) -> Result<Box<Expr>, String> {
     match self.token.kind() {
+      TokenKind::AssignOp(_) => self.parse_assign_expr(lhs),
+      TokenKind::As => self.parse_cast_expr(lhs),
+      TokenKind::Binary(BinaryKind::Dot) => self.parse_member_access_expr(lhs),
       TokenKind::OpenBracket => self.parse_index_expr(lhs),
       TokenKind::OpenParen => self.parse_call_expr(lhs),
+      TokenKind::QuestionMark => Ok(make_try_expr(lhs)),
+      TokenKind::Binary(BinaryKind::DotDot)
+      | TokenKind::Binary(BinaryKind::DotDotEq) => {
+        self.parse_range_expr(Some(lhs))
+      }
       _ => self.parse_binop_expr(lhs),
     }
   }
 
+  // the prefix form binds like a unary operator e.g `await f() + 1` is
+  // `f().await + 1`
+  fn parse_await_expr(&mut self) -> Result<Box<Expr>, String> {
+    self.next_token();
+
+    let expr = self.parse_expr_by_precedence(&PrecedenceKind::Unary)?;
+
+    Ok(make_await_expr(expr))
+  }
+
   fn parse_block(&mut self) -> Result<Box<Block>, String> {
     let mut stmts = vec![];
 
+    self.blocks += 1;
     self.next_token();
 
     while !self.token_is(&CloseBrace) {
-      match self.parse_stmt() {
-        Err(_) => break,
-        Ok(stmt) => stmts.push(stmt),
-      };
+      if self.token_is(&EOF) {
+        self.blocks -= 1;
+
+        return Err(format!("parser:fn:parse_block:error: unclosed block"));
+      }
 
+      stmts.extend(self.parse_stmts_or_error());
       self.next_token();
     }
 
+    self.blocks -= 1;
+
     Ok(make_block_expr(stmts))
   }
 
+  fn parse_break_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let label = self.parse_label()?;
+    let value = self.parse_jump_value()?;
+
+    Ok(make_break_stmt(label, value))
+  }
+
   fn parse_bool_expr(&mut self) -> Result<Box<Expr>, String> {
     let expr = self.token_is(&True);
     Ok(make_bool_expr(expr))
@@ -151,6 +471,44 @@ where
     Ok(make_call_expr(callee, args))
   }
 
+  fn parse_cast_expr(&mut self, expr: Box<Expr>) -> Result<Box<Expr>, String> {
+    self.next_token();
+
+    let ty = self.parse_ty()?;
+
+    Ok(make_cast_expr(expr, ty))
+  }
+
+  // a capsule declares the members shared by structs e.g
+  // `capsule Vec2 { fun mul: (-> int | x: int) = (x) -> x * x; }`
+  fn parse_capsule_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+    let name = self.parse_ident_expr()?;
+    let mut members = vec![];
+
+    for member in self.parse_members()? {
+      members.push(match member {
+        StructMember::Field(field) => TraitMember::Field(field),
+        StructMember::Method(method) => TraitMember::Method(method),
+        StructMember::StaticMethod(method) => {
+          return Err(format!(
+            "parser:fn:parse_capsule_stmt:error: static {}",
+            method.name()
+          ))
+        }
+      });
+    }
+
+    Ok(make_capsule_stmt(name, members))
+  }
+
+  fn parse_closure_expr(&mut self) -> Result<Box<Expr>, String> {
+    let (args, block) = self.parse_fun_body()?;
+
+    Ok(make_closure_expr(args, block))
+  }
+
   // TODO: implements Comment for ast
   // fn parse_comment_expr(&mut self) -> Result<Box<Expr>, String> {
   //   match self.token.kind {
@@ -165,14 +523,101 @@ where
   //   }
   // }
 
+  fn parse_enum_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+    let name = self.parse_ident_expr()?;
+
+    self.expect_first(&OpenBrace)?;
+
+    let mut variants = vec![];
+
+    while !self.first_is(&CloseBrace) {
+      self.next_token();
+
+      variants.push(self.parse_enum_variant()?);
+
+      if !self.first_is(&CloseBrace) {
+        self.expect_first(&Comma)?;
+      }
+    }
+
+    self.expect_first(&CloseBrace)?;
+
+    Ok(make_enum_stmt(name, variants))
+  }
+
+  fn parse_enum_variant(&mut self) -> Result<EnumVariant, String> {
+    let name = self.parse_ident_expr()?;
+
+    let kind = if self.first_is(&OpenParen) {
+      self.next_token();
+      VariantKind::Tuple(self.parse_tys_until(&CloseParen)?)
+    } else if self.first_is(&OpenBrace) {
+      self.next_token();
+      VariantKind::Struct(self.parse_fields_until(&CloseBrace)?)
+    } else {
+      VariantKind::Unit
+    };
+
+    let mut discriminant = None;
+
+    if self.first_is(&TokenKind::AssignOp(BinaryKind::Eq)) {
+      self.next_token();
+      self.next_token();
+
+      discriminant = Some(self.parse_expr_by_precedence(&Lowest)?);
+    }
+
+    Ok(make_enum_variant(name, kind, discriminant))
+  }
+
+  fn parse_continue_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let label = self.parse_label()?;
+
+    if self.first_is(&Semicolon) {
+      self.next_token();
+    }
+
+    Ok(make_continue_stmt(label))
+  }
+
   fn parse_expr(&mut self) -> Result<Box<Expr>, String> {
     match self.token.kind() {
-      TokenKind::OpenBrace => self.parse_hash_expr(),
+      TokenKind::Binary(BinaryKind::Dot) if self.first_is(&OpenBrace) => {
+        self.parse_hash_expr()
+      }
       TokenKind::OpenBracket => self.parse_array_expr(),
+      TokenKind::OpenParen if self.is_closure_ahead() => {
+        self.parse_closure_expr()
+      }
       TokenKind::OpenParen => self.parse_group_expr(),
+      TokenKind::Ident(_) if self.is_macro_call_ahead() => {
+        self.parse_macro_call_expr()
+      }
+      TokenKind::Ident(_) if self.first_is(&ColonColon) => {
+        self.parse_path_expr()
+      }
+      // a block never starts with a `.`, so `A { .` is a struct literal
+      TokenKind::Ident(_)
+        if self.first_is(&OpenBrace)
+          && self.second_is(&TokenKind::Binary(BinaryKind::Dot)) =>
+      {
+        self.parse_path_expr()
+      }
+      TokenKind::SelfLower if self.first_is(&ColonColon) => {
+        self.parse_path_expr()
+      }
+      TokenKind::SelfLower => Ok(make_ident_expr("self")),
+      TokenKind::At | TokenKind::Super => self.parse_path_expr(),
       TokenKind::Ident(_) => self.parse_ident_expr(),
+      TokenKind::Await => self.parse_await_expr(),
+      TokenKind::BackSlash => self.parse_labeled_loop_expr(),
+      TokenKind::Dollar => self.parse_loop_var_expr(),
       TokenKind::False | TokenKind::True => self.parse_bool_expr(),
+      TokenKind::Nil => Ok(make_lit_nil_expr()),
       TokenKind::For => self.parse_loop_for_expr(),
+      TokenKind::Fun => self.parse_fun_expr(),
       // TokenKind::If => self.parse_if_else_expr(),
       TokenKind::Loop => self.parse_loop_loop_expr(),
       TokenKind::While => self.parse_loop_while_expr(),
@@ -182,6 +627,8 @@ where
       TokenKind::Binary(BinaryKind::Sub) | TokenKind::Unary(UnaryKind::Not) => {
         self.parse_unop_expr()
       }
+      TokenKind::Binary(BinaryKind::DotDot)
+      | TokenKind::Binary(BinaryKind::DotDotEq) => self.parse_range_expr(None),
       _ => Err(format!(
         "parser:fn:parse_expr_stmt:error: {}",
         self.token.text()
@@ -206,6 +653,22 @@ where
     Ok(node)
   }
 
+  // an erroneous expression is kept in the tree as an error node, which keeps
+  // the enclosing statement e.g the names of `val x = 1 +;` stay declared
+  fn parse_expr_or_error(&mut self, precedence: &PrecedenceKind) -> Box<Expr> {
+    match self.parse_expr_by_precedence(precedence) {
+      Ok(expr) => expr,
+      Err(error) => {
+        let span = self.token.span.to_owned();
+
+        self.report(error);
+        self.synchronize();
+
+        make_error_expr(span)
+      }
+    }
+  }
+
   fn parse_expr_stmt(&mut self) -> Result<Box<Stmt>, String> {
     let expr = self.parse_expr_by_precedence(&Lowest)?;
 
@@ -216,317 +679,1435 @@ where
     Ok(make_expr_stmt(expr))
   }
 
-  fn parse_fun_stmt(&mut self) -> Result<Box<Stmt>, String> {
-    self.expect_first(&TokenKind::Ident(self.first.text()))?;
+  fn parse_field(&mut self) -> Result<Field, String> {
+    let docs = mem::replace(&mut self.docs, vec![]);
+
+    if self.token_is(&TokenKind::Binary(BinaryKind::Dot)) {
+      self.next_token();
+    }
 
     let name = self.parse_ident_expr()?;
 
-    let ty;
+    self.expect_first(&Colon)?;
+    self.next_token();
 
-    if self.first_is(&&Semicolon) {
-      self.next_token();
+    let ty = self.parse_ty()?;
+    let mut field = make_field(name, ty);
 
-      ty = self.parse_ident_expr()?;
-    } else {
-      // TODO: void type
-      ty = self.parse_ident_expr()?;
-    }
+    field.docs = docs;
 
-    self.expect_first(&TokenKind::AssignOp(BinaryKind::Eq))?;
+    Ok(field)
+  }
 
-    self.expect_first(&OpenParen)?;
+  fn parse_fields_until(
+    &mut self,
+    kind: &TokenKind,
+  ) -> Result<Vec<Field>, String> {
+    let mut fields = vec![];
 
-    let args = self.parse_fun_arg_exprs()?;
+    while !self.first_is(kind) {
+      self.next_token();
 
-    self.expect_first(&OpenBrace)?;
+      fields.push(self.parse_field()?);
 
-    let block = self.parse_block()?;
+      if !self.first_is(kind) {
+        self.expect_first(&Comma)?;
+      }
+    }
 
-    Ok(make_fun_stmt(name, args, ty, block))
+    self.expect_first(kind)?;
+
+    Ok(fields)
   }
 
-  fn parse_fun_arg_expr(&mut self) -> Result<Box<FunArg>, String> {
-    self.next_token();
+  // a member is a field or a method, a static method is marked by its
+  // modifier e.g `static fun new: Self = () { ... }`
+  fn parse_member(&mut self) -> Result<StructMember, String> {
+    match self.token.kind() {
+      TokenKind::Async
+      | TokenKind::Exp
+      | TokenKind::Ext
+      | TokenKind::Fun
+      | TokenKind::Module
+      | TokenKind::Static => {}
+      _ => return Ok(StructMember::Field(self.parse_field()?)),
+    }
 
-    let expr = self.parse_ident_expr()?;
+    let docs = mem::replace(&mut self.docs, vec![]);
+
+    let mut method = match *self.parse_fun_stmt()? {
+      Stmt {
+        kind: StmtKind::Fun(box fun),
+        ..
+      } => fun,
+      _ => {
+        return Err(format!(
+          "parser:fn:parse_member:error: {}",
+          self.token.text()
+        ))
+      }
+    };
 
-    self.expect_first(&Colon)?;
-    self.next_token();
+    method.docs = docs;
+
+    if !method.has_modifier(&FunModifier::Static) {
+      return Ok(StructMember::Method(method));
+    }
 
-    let ty = self.parse_ident_expr()?;
+    method
+      .modifiers
+      .retain(|modifier| *modifier != FunModifier::Static);
 
-    Ok(make_fun_arg(expr, ty))
+    Ok(StructMember::StaticMethod(method))
   }
 
-  fn parse_fun_arg_exprs(&mut self) -> Result<Vec<Box<FunArg>>, String> {
-    let mut args = vec![];
+  // the members between the braces, the fields are separated by commas
+  fn parse_members(&mut self) -> Result<Vec<StructMember>, String> {
+    self.expect_first(&OpenBrace)?;
 
-    if self.first_is(&CloseParen) {
+    let mut members = vec![];
+
+    while !self.first_is(&CloseBrace) {
       self.next_token();
-      return Ok(args);
-    }
 
-    args.push(self.parse_fun_arg_expr()?);
+      let member = self.parse_member()?;
 
-    while self.first_is(&Comma) {
-      self.next_token();
-      args.push(self.parse_fun_arg_expr()?);
+      if let StructMember::Field(_) = member {
+        if !self.first_is(&CloseBrace) {
+          self.expect_first(&Comma)?;
+        }
+      }
+
+      members.push(member);
     }
 
-    self.expect_first(&CloseParen)?;
+    self.expect_first(&CloseBrace)?;
 
-    Ok(args)
+    Ok(members)
   }
 
-  fn parse_group_expr(&mut self) -> Result<Box<Expr>, String> {
-    self.next_token();
+  fn parse_fun_expr(&mut self) -> Result<Box<Expr>, String> {
+    self.expect_first(&TokenKind::AssignOp(BinaryKind::Eq))?;
+    self.expect_first(&OpenParen)?;
 
-    let expr = self.parse_expr_by_precedence(&Lowest)?;
+    self.parse_closure_expr()
+  }
 
-    self.expect_first(&CloseParen)?;
+  // parses the arguments and the body of a function from the opening paren,
+  // the body is a block or an expression after an arrow
+  fn parse_fun_body(
+    &mut self,
+  ) -> Result<(Vec<Box<FunArg>>, Box<Block>), String> {
+    let args = self.parse_fun_arg_exprs()?;
+
+    let block = if self.first_is(&Arrow) && !self.second_is(&OpenBrace) {
+      self.next_token();
+      self.next_token();
+
+      let body = self.parse_expr_by_precedence(&Lowest)?;
+
+      make_block_expr(vec![make_expr_stmt(body)])
+    } else {
+      if self.first_is(&Arrow) {
+        self.next_token();
+      }
+
+      self.expect_first(&OpenBrace)?;
+      self.parse_block()?
+    };
+
+    Ok((args, block))
+  }
+
+  fn parse_fun_modifiers(&mut self) -> Result<Vec<FunModifier>, String> {
+    let mut modifiers = vec![];
+
+    loop {
+      match self.token.kind() {
+        TokenKind::Async => modifiers.push(FunModifier::Async),
+        TokenKind::Exp => modifiers.push(FunModifier::Exp),
+        TokenKind::Ext => modifiers.push(FunModifier::Ext),
+        TokenKind::Module => modifiers.push(FunModifier::Mod),
+        TokenKind::Static => modifiers.push(FunModifier::Static),
+        TokenKind::Fun => return Ok(modifiers),
+        _ => {
+          return Err(format!(
+            "parser:fn:parse_fun_modifiers:error: {}",
+            self.token.text()
+          ))
+        }
+      }
+
+      self.next_token();
+    }
+  }
+
+  // parses the signature forms of a function:
+  // `vec2`, `(x: int, y: int = 0)`, `(int, int -> int)`, `(-> int | x: int)`
+  fn parse_fun_sig(&mut self) -> Result<Box<FunSig>, String> {
+    if !self.token_is(&OpenParen) {
+      let ret_ty = self.parse_ty()?;
+
+      return Ok(make_fun_sig(vec![], ret_ty));
+    }
+
+    let mut args = vec![];
+    let mut ret_ty = box Ty::void();
+
+    if self.first_is(&Arrow) {
+      self.next_token();
+
+      if !self.first_is(&TokenKind::Binary(BinaryKind::Or))
+        && !self.first_is(&CloseParen)
+      {
+        self.next_token();
+
+        ret_ty = self.parse_ty()?;
+      }
+
+      if self.first_is(&TokenKind::Binary(BinaryKind::Or)) {
+        self.next_token();
+
+        args = self.parse_fun_sig_args()?;
+      }
+    } else {
+      args = self.parse_fun_sig_args()?;
+
+      if self.first_is(&Arrow) {
+        self.next_token();
+        self.next_token();
+
+        ret_ty = self.parse_ty()?;
+      }
+    }
+
+    self.expect_first(&CloseParen)?;
+
+    Ok(make_fun_sig(args, ret_ty))
+  }
+
+  fn parse_fun_sig_arg(&mut self) -> Result<FunArg, String> {
+    let mut name = None;
+    let mut ty = box Ty::unknown();
+
+    if self.token_is(&BackSlash) {
+      self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+      name = Some(self.token.text());
+    } else if self.first_is(&Colon) {
+      name = Some(self.token.text());
+
+      self.next_token();
+      self.next_token();
+
+      ty = self.parse_ty()?;
+    } else {
+      ty = self.parse_ty()?;
+    }
+
+    let default = self.parse_fun_arg_default()?;
+
+    Ok(make_fun_sig_arg(name, ty, default))
+  }
+
+  fn parse_fun_sig_args(&mut self) -> Result<Vec<FunArg>, String> {
+    let mut args = vec![];
+
+    while !self.first_is(&Arrow) && !self.first_is(&CloseParen) {
+      self.next_token();
+
+      args.push(self.parse_fun_sig_arg()?);
+
+      if !self.first_is(&Arrow) && !self.first_is(&CloseParen) {
+        self.expect_first(&Comma)?;
+      }
+    }
+
+    Ok(args)
+  }
+
+  fn parse_fun_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let modifiers = self.parse_fun_modifiers()?;
+
+    self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+    let name = self.parse_ident_expr()?;
+
+    let mut sig = make_fun_sig(vec![], box Ty::void());
+
+    // the signature is inferred e.g `fun add := (x, y) { x + y }`
+    if self.first_is(&Colon)
+      && self.second_is(&TokenKind::AssignOp(BinaryKind::Eq))
+    {
+      self.next_token();
+
+      sig = make_fun_sig(vec![], box Ty::unknown());
+    } else if self.first_is(&Colon) {
+      self.next_token();
+      self.next_token();
+
+      sig = self.parse_fun_sig()?;
+    }
+
+    // a declaration without body e.g `ext fun sqrt: (-> int | x: int);`
+    if self.first_is(&Semicolon) {
+      self.next_token();
+
+      let args = sig.args.iter().map(|arg| box arg.to_owned()).collect();
+
+      return Ok(make_fun_stmt(name, modifiers, sig, args, None));
+    }
+
+    self.expect_first(&TokenKind::AssignOp(BinaryKind::Eq))?;
+    self.expect_first(&OpenParen)?;
+
+    let (args, block) = self.parse_fun_body()?;
+
+    if self.first_is(&Semicolon) {
+      self.next_token();
+    }
+
+    Ok(make_fun_stmt(name, modifiers, sig, args, Some(block)))
+  }
+
+  fn parse_fun_arg_default(&mut self) -> Result<Option<Box<Expr>>, String> {
+    if !self.first_is(&TokenKind::AssignOp(BinaryKind::Eq)) {
+      return Ok(None);
+    }
+
+    self.next_token();
+    self.next_token();
+
+    Ok(Some(self.parse_expr_by_precedence(&Lowest)?))
+  }
+
+  fn parse_fun_arg_expr(&mut self) -> Result<Box<FunArg>, String> {
+    self.next_token();
+
+    let expr = self.parse_ident_expr()?;
+
+    let mut ty = box Ty::unknown();
+
+    if self.first_is(&Colon) {
+      self.next_token();
+      self.next_token();
+
+      ty = self.parse_ty()?;
+    }
+
+    let mut arg = make_fun_arg(expr, ty);
+
+    arg.default = self.parse_fun_arg_default()?;
+
+    Ok(arg)
+  }
+
+  fn parse_fun_arg_exprs(&mut self) -> Result<Vec<Box<FunArg>>, String> {
+    let mut args = vec![];
+
+    if self.first_is(&CloseParen) {
+      self.next_token();
+      return Ok(args);
+    }
+
+    args.push(self.parse_fun_arg_expr()?);
+
+    while self.first_is(&Comma) {
+      self.next_token();
+      args.push(self.parse_fun_arg_expr()?);
+    }
+
+    self.expect_first(&CloseParen)?;
+
+    Ok(args)
+  }
+
+  // a comma makes a tuple e.g `()`, `(a,)`, `(a, b)`, otherwise the
+  // parentheses are used for grouping
+  fn parse_group_expr(&mut self) -> Result<Box<Expr>, String> {
+    if self.first_is(&CloseParen) {
+      self.next_token();
+
+      return Ok(make_tuple_expr(vec![]));
+    }
+
+    self.next_token();
+
+    let expr = self.parse_expr_by_precedence(&Lowest)?;
+
+    if !self.first_is(&Comma) {
+      self.expect_first(&CloseParen)?;
+
+      return Ok(expr);
+    }
+
+    let mut exprs = vec![expr];
+
+    exprs.extend(self.parse_rest_until(&CloseParen)?);
+
+    Ok(make_tuple_expr(exprs))
+  }
+
+  fn parse_ident_expr(&mut self) -> Result<Box<Expr>, String> {
+    match self.token.kind() {
+      TokenKind::Ident(ref ident) => Ok(make_ident_expr(ident)),
+      _ => Err(format!(
+        "parser::fn::parse_ident_expr: {}",
+        &self.token.text()
+      )),
+    }
+  }
+
+  // the condition of a branch is followed by its block e.g
+  // `if a { } else if b { } else { }`
+  fn parse_if_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let mut conditions = vec![];
+    let mut alternative = None;
+
+    loop {
+      self.next_token();
+
+      let condition = self.parse_expr_by_precedence(&Lowest)?;
+
+      self.expect_first(&OpenBrace)?;
+      conditions.push((condition, self.parse_block()?));
+
+      if !self.first_is(&Else) {
+        break;
+      }
+
+      self.next_token();
+
+      if self.first_is(&If) {
+        self.next_token();
+        continue;
+      }
+
+      self.expect_first(&OpenBrace)?;
+      alternative = Some(self.parse_block()?);
+
+      break;
+    }
+
+    Ok(make_if_block_stmt(conditions, alternative))
+  }
+
+  // TODO: implements IfElse for ast
+  // fn parse_if_else_expr(&mut self) -> Result<Box<Expr>, String> {
+  //   self.next_token();
+
+  //   let condition = self.parse_expr_by_precedence(&Lowest)?;
+
+  //   self.expect_first(&OpenBrace)?;
+
+  //   let consequence = self.parse_block()?;
+
+  //   let alternative = if self.first_is(&Else) {
+  //     self.next_token();
+  //     self.expect_first(&OpenBrace)?;
+
+  //     Some(self.parse_block()?)
+  //   } else {
+  //     None
+  //   };
+
+  //   Ok(make_if_else_expr(condition, consequence, alternative))
+  // }
+
+  pub fn parse_index_expr(
+    &mut self,
+    lhs: Box<Expr>,
+  ) -> Result<Box<Expr>, String> {
+    self.next_token();
+
+    let rhs = self.parse_expr_by_precedence(&Lowest)?;
+
+    self.expect_first(&CloseBracket)?;
+
+    Ok(make_index_expr(lhs, rhs))
+  }
+
+  fn parse_lit_int_expr(&mut self) -> Result<Box<Expr>, String> {
+    match self.token.text().replace("_", "").parse() {
+      Err(_) => Err(format!("parser:parse_lit_int_expr:error")),
+      Ok(expr) => Ok(make_lit_int_expr(expr)),
+    }
+  }
+
+  fn parse_lit_real_expr(&mut self) -> Result<Box<Expr>, String> {
+    match self.token.text().parse() {
+      Err(_) => Err(format!("parser:parse_lit_real_expr:error")),
+      Ok(expr) => Ok(make_lit_real_expr(expr)),
+    }
+  }
+
+  fn parse_lit_str_expr(&mut self) -> Result<Box<Expr>, String> {
+    let expr = self.token.text();
+    Ok(make_lit_str_expr(expr))
+  }
+
+  // several names share the same initializer e.g `val x y z: int = 0;` and
+  // the type is inferred when it is omitted e.g `val a := true;`
+  fn parse_local_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let from_kw = self.token.kind();
+
+    self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+    let mut names = vec![self.parse_ident_expr()?];
+
+    while let TokenKind::Ident(_) = self.first.kind() {
+      self.next_token();
+
+      names.push(self.parse_ident_expr()?);
+    }
+
+    self.expect_first(&Colon)?;
+
+    let mut ty = None;
+
+    if !self.first_is(&TokenKind::AssignOp(BinaryKind::Eq)) {
+      self.next_token();
+
+      ty = Some(self.parse_ty()?);
+    }
+
+    self.expect_first(&TokenKind::AssignOp(BinaryKind::Eq))?;
+    self.next_token();
+
+    let value = self.parse_expr_or_error(&Lowest);
+
+    if self.first_is(&Semicolon) {
+      self.next_token();
+    }
+
+    Ok(match from_kw {
+      TokenKind::Mut => make_mut_stmt(names, ty, value),
+      TokenKind::Val => make_val_stmt(names, ty, value),
+      _ => unreachable!(),
+    })
+  }
+
+  // a jump is followed by an optional value e.g `ret;`, `break \outer x;`
+  fn parse_jump_value(&mut self) -> Result<Option<Box<Expr>>, String> {
+    let mut value = None;
+
+    if !self.first_is(&Semicolon) && !self.first_is(&CloseBrace) {
+      self.next_token();
+
+      value = Some(self.parse_expr_by_precedence(&Lowest)?);
+    }
+
+    if self.first_is(&Semicolon) {
+      self.next_token();
+    }
+
+    Ok(value)
+  }
+
+  fn parse_label(&mut self) -> Result<Option<String>, String> {
+    if !self.first_is(&BackSlash) {
+      return Ok(None);
+    }
+
+    self.next_token();
+    self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+    Ok(Some(self.token.text()))
+  }
+
+  // a label is attached to the loop written after it e.g `\outer loop { }`
+  fn parse_labeled_loop_expr(&mut self) -> Result<Box<Expr>, String> {
+    self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+    let label = self.token.text();
+
+    self.next_token();
+
+    let mut expr = match self.token.kind() {
+      TokenKind::For => self.parse_loop_for_expr()?,
+      TokenKind::Loop => self.parse_loop_loop_expr()?,
+      TokenKind::While => self.parse_loop_while_expr()?,
+      _ => {
+        return Err(format!(
+          "parser:fn:parse_labeled_loop_expr:error: {}",
+          self.token.text()
+        ))
+      }
+    };
+
+    if let ExprKind::Loop(ref mut kind) = expr.kind {
+      kind.set_label(&label);
+    }
 
     Ok(expr)
   }
 
-  fn parse_ident_expr(&mut self) -> Result<Box<Expr>, String> {
-    match self.token.kind() {
-      TokenKind::Ident(ref ident) => Ok(make_ident_expr(ident)),
-      _ => Err(format!(
-        "parser::fn::parse_ident_expr: {}",
-        &self.token.text()
-      )),
+  // the iterator is named `$it` for ranges and `$elmt` for the other
+  // iterables when it is omitted e.g `for 0..3 { $it }`, `for xs $x { }`
+  fn parse_loop_for_expr(&mut self) -> Result<Box<Expr>, String> {
+    self.next_token();
+
+    let iterable = self.parse_expr_by_precedence(&Assignement)?;
+    let mut iterator = None;
+
+    if self.first_is(&TokenKind::AssignOp(BinaryKind::Eq)) {
+      self.next_token();
+      self.expect_first(&Dollar)?;
+    } else if self.first_is(&Dollar) {
+      self.next_token();
+    }
+
+    if self.token_is(&Dollar) {
+      iterator = Some(self.parse_loop_var_expr()?);
+    }
+
+    let implicit = iterator.is_none();
+
+    let iterator = iterator.unwrap_or_else(|| match iterable.kind {
+      ExprKind::Range { .. } => make_loop_var_expr("it"),
+      _ => make_loop_var_expr("elmt"),
+    });
+
+    self.expect_first(&OpenBrace)?;
+
+    let block = self.parse_block()?;
+
+    Ok(make_loop_for_expr(iterable, iterator, implicit, block))
+  }
+
+  fn parse_loop_loop_expr(&mut self) -> Result<Box<Expr>, String> {
+    self.expect_first(&OpenBrace)?;
+
+    let block = self.parse_block()?;
+
+    Ok(make_loop_loop_expr(block))
+  }
+
+  fn parse_loop_var_expr(&mut self) -> Result<Box<Expr>, String> {
+    self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+    Ok(make_loop_var_expr(&self.token.text()))
+  }
+
+  fn parse_loop_while_expr(&mut self) -> Result<Box<Expr>, String> {
+    self.next_token();
+
+    let condition = self.parse_expr_by_precedence(&Lowest)?;
+
+    self.expect_first(&OpenBrace)?;
+
+    let block = self.parse_block()?;
+
+    Ok(make_loop_while_expr(condition, block))
+  }
+
+  // a collection starts with a dot to not be confused with a block e.g
+  // `.{ firstname = "", lastname = "" }`
+  fn parse_hash_expr(&mut self) -> Result<Box<Expr>, String> {
+    let mut data = vec![];
+
+    self.expect_first(&OpenBrace)?;
+
+    while !self.first_is(&CloseBrace) {
+      self.next_token();
+
+      let key = self.parse_expr()?;
+
+      if !HashKind::is_key(&key) {
+        return Err(format!(
+          "parser:fn:parse_hash_expr:error: invalid collection key {:?}",
+          key.kind
+        ));
+      }
+
+      self.expect_first(&TokenKind::AssignOp(BinaryKind::Eq))?;
+      self.next_token();
+
+      let value = self.parse_expr_by_precedence(&Lowest)?;
+
+      data.push(make_hash_data_expr(key, value));
+
+      if !self.first_is(&CloseBrace) {
+        self.expect_first(&Comma)?;
+      }
+    }
+
+    self.expect_first(&CloseBrace)?;
+
+    Ok(make_hash_expr(data))
+  }
+
+  // a member is a field, a method or a tuple index e.g `v.x`, `v.len()`,
+  // `t.0`, the tokenizer reads nested tuple indexes as a real e.g `t.0.1`
+  fn parse_member_access_expr(
+    &mut self,
+    from: Box<Expr>,
+  ) -> Result<Box<Expr>, String> {
+    self.next_token();
+
+    match self.token.kind() {
+      TokenKind::Await => Ok(make_await_expr(from)),
+      TokenKind::Ident(ref method) if self.first_is(&OpenParen) => {
+        self.next_token();
+
+        let args = self.parse_until(&CloseParen)?;
+
+        Ok(make_method_call_expr(from, method, args))
+      }
+      TokenKind::Ident(ref access) => {
+        Ok(make_member_access_expr(*from, access))
+      }
+      TokenKind::Literal(IntNumber(ref index))
+      | TokenKind::Literal(RealNumber(ref index)) => {
+        index.split('.').try_fold(from, |from, index| {
+          match index.parse::<usize>() {
+            Ok(index) => Ok(make_tuple_index_expr(from, index)),
+            Err(_) => Err(format!(
+              "parser:fn:parse_member_access_expr:error: {}",
+              index
+            )),
+          }
+        })
+      }
+      _ => Err(format!(
+        "parser:fn:parse_member_access_expr:error: {}",
+        self.token.text()
+      )),
+    }
+  }
+
+  // the prelude declares the builtin macros e.g `print!`, `must!`, a
+  // malformed prelude is reported as a parse error of the source
+  fn load_prelude(&mut self, prelude: &str) {
+    let opts = TokenizerOpts {
+      exact_errors: true,
+      ..Default::default()
+    };
+
+    let mut tokenizer = Tokenizer::new(TokenQueue::new(), opts);
+
+    tokenizer.feed(StrTendril::from(prelude));
+    tokenizer.end();
+
+    self.tokens = tokenizer
+      .unwrap()
+      .tokens
+      .into_iter()
+      .filter(|token| match token.kind {
+        TokenKind::Indent(_) | TokenKind::EOF => false,
+        _ => true,
+      })
+      .collect();
+
+    self.next_token();
+    self.next_token();
+
+    while !self.token_is(&EOF) {
+      if let Err(error) = self.parse_macro_stmt() {
+        self.report(format!("parser:fn:load_prelude:error: {}", error));
+        self.tokens.clear();
+        self.next_token();
+        self.next_token();
+
+        break;
+      }
+
+      self.next_token();
+    }
+  }
+
+  // parses the buffered top-level items, each item is checked and handed
+  // over to the sink once it is complete
+  fn parse_items(&mut self) {
+    self.next_token();
+    self.next_token();
+
+    while !self.token_is(&EOF) {
+      for stmt in self.parse_stmts_or_error() {
+        for error in self.validator.validate_item(&stmt) {
+          self.sink.parse_error(error.into(), stmt.span.to_owned());
+        }
+
+        self.emit(stmt);
+      }
+
+      self.next_token();
+    }
+  }
+
+  // the invocation is replaced by the expansion of the macro between
+  // parentheses, which is parsed in place e.g `twice!(a + 1)` is parsed as
+  // `((a + 1) * 2)`
+  fn parse_macro_call_expr(&mut self) -> Result<Box<Expr>, String> {
+    let tokens = self.expand_macro_call()?;
+    let span = self.token.span.to_owned();
+    let first = mem::replace(&mut self.first, box Token::new(OpenParen, span));
+
+    self.tokens.push_front(*first);
+    self
+      .tokens
+      .push_front(Token::new(CloseParen, self.token.span.to_owned()));
+
+    for token in tokens.into_iter().rev() {
+      self.tokens.push_front(token);
+    }
+
+    self.next_token();
+    self.expansion_depth += 1;
+
+    let expr = self.parse_group_expr();
+
+    self.expansion_depth -= 1;
+
+    expr
+  }
+
+  // the invocation is replaced by the statements of the expansion, which are
+  // parsed in the enclosing block up to a closing brace put after them e.g
+  // `swap!(a, b);` declares the `val` of its template next to `a` and `b`
+  fn parse_macro_call_stmts(&mut self) -> Result<Vec<Box<Stmt>>, String> {
+    let tokens = self.expand_macro_call()?;
+    let span = self.token.span.to_owned();
+    let first = mem::replace(&mut self.first, box Token::new(EOF, span));
+    let mut stmts = vec![];
+
+    self.tokens.push_front(*first);
+    self
+      .tokens
+      .push_front(Token::new(CloseBrace, self.token.span.to_owned()));
+
+    for token in tokens.into_iter().rev() {
+      self.tokens.push_front(token);
+    }
+
+    self.next_token();
+    self.next_token();
+    self.blocks += 1;
+    self.expansion_depth += 1;
+
+    while !self.token_is(&CloseBrace) && !self.token_is(&EOF) {
+      stmts.extend(self.parse_stmts_or_error());
+      self.next_token();
+    }
+
+    self.expansion_depth -= 1;
+    self.blocks -= 1;
+
+    if self.first_is(&Semicolon) {
+      self.next_token();
+    }
+
+    Ok(stmts)
+  }
+
+  // reads the arguments of the invocation and expands the macro, the current
+  // token is left on the closing delimiter of the arguments
+  fn expand_macro_call(&mut self) -> Result<Vec<Token>, String> {
+    let name = self.token.text();
+
+    self.next_token();
+    self.next_token();
+
+    let args = self.parse_delimited_token_trees()?;
+
+    if self.expansion_depth == EXPANSION_LIMIT {
+      return Err(format!(
+        "parser:fn:expand_macro_call:error: `{}!` exceeds the expansion limit",
+        name
+      ));
+    }
+
+    self.expansions += 1;
+
+    let mac = self.macros.get(&name).ok_or(format!(
+      "parser:fn:expand_macro_call:error: unknown macro `{}!`",
+      name
+    ))?;
+
+    expander::expand(mac, &args, self.expansions)
+  }
+
+  // a rule maps a pattern to a template, the rules are separated by an
+  // optional `;` e.g `macro twice { ($x:expr) => { $x * 2 } }`
+  fn parse_macro_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let mut rules = vec![];
+
+    self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+    let name = self.token.text();
+
+    self.expect_first(&OpenBrace)?;
+
+    while !self.first_is(&CloseBrace) {
+      self.expect_first(&OpenParen)?;
+
+      let pattern = self.parse_delimited_token_trees()?;
+
+      self.expect_first(&ArrowFat)?;
+      self.expect_first(&OpenBrace)?;
+
+      let template = self.parse_delimited_token_trees()?;
+
+      rules.push(MacroRule { pattern, template });
+
+      if self.first_is(&Semicolon) {
+        self.next_token();
+      }
+    }
+
+    self.expect_first(&CloseBrace)?;
+
+    let stmt = make_macro_stmt(&name, rules);
+
+    if let StmtKind::Macro(ref mac) = stmt.kind {
+      expander::check(mac)?;
+      self.macros.insert(name, *mac.to_owned());
+    }
+
+    Ok(stmt)
+  }
+
+  fn parse_path_expr(&mut self) -> Result<Box<Expr>, String> {
+    let root = self.parse_path_root()?;
+    let mut segments = vec![];
+
+    if root == PathRoot::Relative {
+      segments.push(self.token.text());
+    }
+
+    while self.first_is(&ColonColon) {
+      self.next_token();
+      self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+      segments.push(self.token.text());
+    }
+
+    match root {
+      PathRoot::Current | PathRoot::Super(_) if segments.is_empty() => {
+        return Err(format!(
+          "parser:fn:parse_path_expr:error: {}",
+          self.token.text()
+        ))
+      }
+      _ => {}
+    }
+
+    let path = make_path_expr(root, segments);
+
+    if self.first_is(&OpenBrace)
+      && self.second_is(&TokenKind::Binary(BinaryKind::Dot))
+    {
+      self.next_token();
+
+      return self.parse_struct_lit_expr(path);
+    }
+
+    Ok(path)
+  }
+
+  // a package root may be used alone e.g `@panic("msg")`
+  fn parse_path_root(&mut self) -> Result<PathRoot, String> {
+    match self.token.kind() {
+      TokenKind::At => {
+        self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+        Ok(PathRoot::Package(self.token.text()))
+      }
+      TokenKind::SelfLower => Ok(PathRoot::Current),
+      TokenKind::Super => {
+        let mut depth = 1;
+
+        while self.first_is(&ColonColon) && self.second_is(&Super) {
+          self.next_token();
+          self.next_token();
+
+          depth += 1;
+        }
+
+        Ok(PathRoot::Super(depth))
+      }
+      _ => Ok(PathRoot::Relative),
+    }
+  }
+
+  fn parse_pub_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    self.next_token();
+
+    let mut stmt = self.parse_stmt()?;
+
+    match stmt.kind {
+      StmtKind::Capsule(ref mut capsule) => capsule.visibility = true,
+      StmtKind::Enum(ref mut enm) => enm.visibility = true,
+      StmtKind::Fun(ref mut fun) => fun.visibility = true,
+      StmtKind::Struct(ref mut structure) => structure.visibility = true,
+      StmtKind::TypeAlias(ref mut alias) => alias.visibility = true,
+      _ => {
+        return Err(format!(
+          "parser:fn:parse_pub_stmt:error: {}",
+          self.token.text()
+        ))
+      }
+    }
+
+    Ok(stmt)
+  }
+
+  // a bound is omitted when the range is open e.g `0..`, `..3`, `..`
+  fn parse_range_expr(
+    &mut self,
+    start: Option<Box<Expr>>,
+  ) -> Result<Box<Expr>, String> {
+    if let Some(ExprKind::Range { .. }) = start.as_ref().map(|lhs| &lhs.kind) {
+      return Err(format!(
+        "parser:fn:parse_range_expr:error: ranges cannot be chained"
+      ));
+    }
+
+    let inclusive = self.token_is(&TokenKind::Binary(BinaryKind::DotDotEq));
+    let precedence = self.current_precedence();
+    let mut end = None;
+
+    let is_open = match self.first.kind() {
+      OpenBrace | CloseBrace | CloseBracket | CloseParen | Comma
+      | Semicolon | EOF => true,
+      _ => false,
+    };
+
+    if !is_open {
+      self.next_token();
+
+      end = Some(self.parse_expr_by_precedence(&precedence)?);
+    } else if inclusive {
+      return Err(format!(
+        "parser:fn:parse_range_expr:error: inclusive range without end"
+      ));
+    }
+
+    Ok(make_range_expr(start, end, inclusive))
+  }
+
+  fn parse_ret_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let value = self.parse_jump_value()?;
+
+    Ok(make_ret_stmt(value))
+  }
+
+  // the docs above the first token of an item are attached to it, which is
+  // also the case when the item starts with an attribute or `pub`
+  fn parse_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let docs = mem::replace(&mut self.docs, vec![]);
+
+    let mut stmt = match self.token.kind() {
+      TokenKind::Use => self.parse_use_stmt(),
+      TokenKind::Attr => self.parse_attr_stmt(),
+      TokenKind::Break => self.parse_break_stmt(),
+      TokenKind::Capsule => self.parse_capsule_stmt(),
+      TokenKind::Continue => self.parse_continue_stmt(),
+      TokenKind::Enum => self.parse_enum_stmt(),
+      TokenKind::If => self.parse_if_stmt(),
+      TokenKind::Fun if self.first_is(&TokenKind::AssignOp(BinaryKind::Eq)) => {
+        self.parse_expr_stmt()
+      }
+      TokenKind::Async
+      | TokenKind::Exp
+      | TokenKind::Ext
+      | TokenKind::Fun
+      | TokenKind::Module
+      | TokenKind::Static => self.parse_fun_stmt(),
+      TokenKind::Pub => self.parse_pub_stmt(),
+      TokenKind::Mut | TokenKind::Val => self.parse_local_stmt(),
+      TokenKind::Ret => self.parse_ret_stmt(),
+      TokenKind::Macro => self.parse_macro_stmt(),
+      TokenKind::Struct => self.parse_struct_stmt(),
+      TokenKind::Suite => self.parse_suite_stmt(),
+      TokenKind::Ident(ref name)
+        if Target::from(name).is_some()
+          && self.first_is(&TokenKind::AssignOp(BinaryKind::Eq))
+          && self.second_is(&OpenBrace) =>
+      {
+        self.parse_target_block_stmt()
+      }
+      TokenKind::Bench | TokenKind::Mock | TokenKind::Test => {
+        self.parse_test_stmt()
+      }
+      TokenKind::Type => self.parse_type_alias_stmt(),
+      _ => self.parse_expr_stmt(),
+    }?;
+
+    if !docs.is_empty() {
+      stmt.set_docs(docs);
     }
+
+    Ok(stmt)
   }
 
-  // TODO: implements IfElse for ast
-  // fn parse_if_else_expr(&mut self) -> Result<Box<Expr>, String> {
-  //   self.next_token();
+  // an invocation ending a statement stands for the statements of its
+  // expansion e.g `print!(x);`, the other ones are expressions
+  fn parse_stmts_or_error(&mut self) -> Vec<Box<Stmt>> {
+    let stmts = if self.is_macro_stmt_ahead() {
+      self.parse_macro_call_stmts()
+    } else {
+      self.parse_stmt().map(|stmt| vec![stmt])
+    };
 
-  //   let condition = self.parse_expr_by_precedence(&Lowest)?;
+    match stmts {
+      Ok(stmts) => stmts,
+      Err(error) => {
+        let span = self.token.span.to_owned();
 
-  //   self.expect_first(&OpenBrace)?;
+        self.report(error);
+        self.synchronize();
 
-  //   let consequence = self.parse_block()?;
+        vec![make_error_stmt(span)]
+      }
+    }
+  }
 
-  //   let alternative = if self.first_is(&Else) {
-  //     self.next_token();
-  //     self.expect_first(&OpenBrace)?;
+  fn parse_struct_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    self.expect_first(&TokenKind::Ident(self.first.text()))?;
 
-  //     Some(self.parse_block()?)
-  //   } else {
-  //     None
-  //   };
+    let name = self.parse_ident_expr()?;
+    let members = self.parse_members()?;
 
-  //   Ok(make_if_else_expr(condition, consequence, alternative))
-  // }
+    Ok(make_struct_stmt(name, members))
+  }
 
-  pub fn parse_index_expr(
+  fn parse_struct_lit_expr(
     &mut self,
-    lhs: Box<Expr>,
+    name: Box<Expr>,
   ) -> Result<Box<Expr>, String> {
-    self.next_token();
+    let mut fields = vec![];
 
-    let rhs = self.parse_expr_by_precedence(&Lowest)?;
+    while !self.first_is(&CloseBrace) {
+      self.expect_first(&TokenKind::Binary(BinaryKind::Dot))?;
+      self.expect_first(&TokenKind::Ident(self.first.text()))?;
 
-    self.expect_first(&CloseBracket)?;
+      let field = self.token.text();
 
-    Ok(make_index_expr(rhs, lhs))
-  }
+      self.expect_first(&TokenKind::AssignOp(BinaryKind::Eq))?;
+      self.next_token();
 
-  fn parse_lit_int_expr(&mut self) -> Result<Box<Expr>, String> {
-    match self.token.text().replace("_", "").parse() {
-      Err(_) => Err(format!("parser:parse_lit_int_expr:error")),
-      Ok(expr) => Ok(make_lit_int_expr(expr)),
+      fields.push((field, self.parse_expr_by_precedence(&Lowest)?));
+
+      if !self.first_is(&CloseBrace) {
+        self.expect_first(&Comma)?;
+      }
     }
+
+    self.expect_first(&CloseBrace)?;
+
+    Ok(make_struct_lit_expr(name, fields))
   }
 
-  fn parse_lit_real_expr(&mut self) -> Result<Box<Expr>, String> {
-    match self.token.text().parse() {
-      Err(_) => Err(format!("parser:parse_lit_real_expr:error")),
-      Ok(expr) => Ok(make_lit_real_expr(expr)),
+  fn parse_ty(&mut self) -> Result<Box<Ty>, String> {
+    match self.token.kind() {
+      TokenKind::Ident(_) => self.parse_name_ty(),
+      TokenKind::OpenBracket => self.parse_array_ty(),
+      TokenKind::OpenParen if self.is_fun_ty_ahead() => self.parse_fun_ty(),
+      TokenKind::OpenParen => self.parse_tuple_ty(),
+      TokenKind::QuestionMark => self.parse_optional_ty(),
+      TokenKind::Ref => self.parse_ref_ty(),
+      _ => Err(format!("parser:fn:parse_ty:error: {}", self.token.text())),
     }
   }
 
-  fn parse_lit_str_expr(&mut self) -> Result<Box<Expr>, String> {
-    let expr = self.token.text();
-    Ok(make_lit_str_expr(expr))
-  }
+  fn parse_array_ty(&mut self) -> Result<Box<Ty>, String> {
+    self.next_token();
 
-  fn parse_local_stmt(&mut self) -> Result<Box<Stmt>, String> {
-    let from_kw = self.token.kind();
+    let ty = self.parse_ty()?;
 
-    self.expect_first(&TokenKind::Ident(self.first.text()))?;
+    if !self.first_is(&Semicolon) {
+      self.expect_first(&CloseBracket)?;
 
-    let name = self.parse_ident_expr()?;
+      return Ok(make_slice_ty(ty.into_kind()));
+    }
 
-    self.expect_first(&Colon)?;
+    self.next_token();
     self.next_token();
 
-    let ty = self.parse_ident_expr()?;
+    let len = self.parse_expr_by_precedence(&Lowest)?;
 
-    self.expect_first(&TokenKind::AssignOp(BinaryKind::Eq))?;
-    self.next_token();
+    self.expect_first(&CloseBracket)?;
 
-    let value = self.parse_expr_by_precedence(&Lowest)?;
-    self.next_token();
+    Ok(make_array_ty(ty.into_kind(), len))
+  }
 
-    Ok(match from_kw {
-      TokenKind::Mut => make_mut_stmt(name, ty, value),
-      TokenKind::Val => make_val_stmt(name, ty, value),
-      _ => unreachable!(),
-    })
+  fn parse_fun_ty(&mut self) -> Result<Box<Ty>, String> {
+    let sig = self.parse_fun_sig()?;
+
+    Ok(box sig.ty)
   }
 
-  fn parse_loop_for_expr(&mut self) -> Result<Box<Expr>, String> {
-    let iterable;
+  fn parse_name_ty(&mut self) -> Result<Box<Ty>, String> {
+    let mut segments = vec![self.token.text()];
 
-    if self.first_is(&OpenBracket) {
-      iterable = self.parse_array_expr()?;
-    } else {
-      iterable = self.parse_ident_expr()?;
+    while self.first_is(&ColonColon) {
+      self.next_token();
+      self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+      segments.push(self.token.text());
     }
 
-    self.next_token();
-    self.expect_first(&OpenParen)?;
-    self.expect_first(&TokenKind::Ident(self.first.text()))?;
+    if self.first_is(&TokenKind::Binary(BinaryKind::Lt)) {
+      self.next_token();
 
-    let iterator = self.parse_ident_expr()?;
+      let param_tys = self
+        .parse_tys_until(&TokenKind::Binary(BinaryKind::Gt))?
+        .into_iter()
+        .map(|ty| ty.into_kind())
+        .collect::<Vec<TyKind>>();
 
-    self.expect_first(&CloseParen)?;
+      return Ok(make_generics_ty(&segments.join("::"), param_tys));
+    }
+
+    match segments.len() {
+      1 if segments[0] == "void" => Ok(box Ty::void()),
+      1 => Ok(make_name_ref_ty(&segments[0])),
+      _ => Ok(make_path_ty(segments)),
+    }
+  }
+
+  fn parse_optional_ty(&mut self) -> Result<Box<Ty>, String> {
     self.next_token();
-    self.expect_first(&OpenBrace)?;
 
-    let block = self.parse_block()?;
+    let ty = self.parse_ty()?;
 
-    Ok(make_loop_for_expr(iterable, iterator, block))
+    Ok(make_optional_ty(ty.into_kind()))
   }
 
-  fn parse_loop_loop_expr(&mut self) -> Result<Box<Expr>, String> {
-    self.expect_first(&OpenBrace)?;
+  fn parse_ref_ty(&mut self) -> Result<Box<Ty>, String> {
+    self.next_token();
 
-    let block = self.parse_block()?;
+    let ty = self.parse_ty()?;
 
-    Ok(make_loop_loop_expr(block))
+    Ok(make_ref_ty(ty.into_kind()))
   }
 
-  fn parse_loop_while_expr(&mut self) -> Result<Box<Expr>, String> {
-    self.next_token();
+  fn parse_suite_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let mut name = None;
 
-    let condition = self.parse_expr_by_precedence(&Lowest)?;
+    if let TokenKind::Ident(_) = self.first.kind() {
+      self.next_token();
+
+      name = Some(self.token.text());
+    }
 
     self.expect_first(&OpenBrace)?;
 
     let block = self.parse_block()?;
 
-    Ok(make_loop_while_expr(condition, block))
+    Ok(make_suite_stmt(name, block))
   }
 
-  fn parse_hash_expr(&mut self) -> Result<Box<Expr>, String> {
-    let mut data = vec![];
+  // the block is only compiled for its target e.g `wasm = { }`
+  fn parse_target_block_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let target = match Target::from(&self.token.text()) {
+      Some(target) => target,
+      None => {
+        return Err(format!(
+          "parser:fn:parse_target_block_stmt:error: unknown target {}",
+          self.token.text()
+        ))
+      }
+    };
 
-    while !self.first_is(&CloseBrace) {
-      self.next_token();
+    self.next_token();
+    self.expect_first(&OpenBrace)?;
 
-      let key = self.parse_expr_by_precedence(&Lowest)?;
+    let body = self.parse_block()?;
 
-      self.expect_first(&Colon)?;
+    if self.first_is(&Semicolon) {
       self.next_token();
+    }
 
-      let value = self.parse_expr_by_precedence(&Lowest)?;
+    Ok(make_target_block_stmt(target, body))
+  }
 
-      data.push(make_hash_data_expr(key, value));
+  // the tests, mocks and benches are declared like closures bound to a name
+  // e.g `test lexer_test = () { }`
+  fn parse_test_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let kind = self.token.kind();
 
-      if !self.first_is(&CloseBrace) {
-        self.expect_first(&Comma)?;
-      }
-    }
+    self.expect_first(&TokenKind::Ident(self.first.text()))?;
 
-    self.expect_first(&CloseBrace)?;
+    let name = self.token.text();
 
-    Ok(make_hash_expr(data))
+    self.expect_first(&TokenKind::AssignOp(BinaryKind::Eq))?;
+    self.expect_first(&OpenParen)?;
+
+    let (args, block) = self.parse_fun_body()?;
+
+    Ok(match kind {
+      TokenKind::Bench => make_bench_stmt(&name, args, block),
+      TokenKind::Mock => make_mock_stmt(&name, args, block),
+      TokenKind::Test => make_test_stmt(&name, args, block),
+      _ => unreachable!(),
+    })
   }
 
-  fn parse_nodes_ast(&mut self) -> Result<Box<Ast>, String> {
-    let mut ast = Ast::new(vec![]);
+  fn parse_delimited_token_trees(&mut self) -> Result<Vec<TokenTree>, String> {
+    match self.parse_token_tree()? {
+      TokenTree::Delimited { trees, .. } => Ok(trees),
+      TokenTree::Token(token) => Err(format!(
+        "parser:fn:parse_delimited_token_trees:error: {}",
+        token.text()
+      )),
+    }
+  }
 
-    while self.token.kind() != EOF {
-      match self.token.kind() {
-        TokenKind::EOF => break,
-        TokenKind::Indent(_) => {
-          self.next_token();
-          continue;
-        }
-        _ => match self.parse_stmt() {
-          Err(error) => self.errors.push(error),
-          Ok(stmt) => ast.add(stmt),
-        },
+  // a token tree is a token or tokens between delimiters, a delimited tree
+  // ends on its closing token
+  fn parse_token_tree(&mut self) -> Result<TokenTree, String> {
+    let close = match self.token.kind() {
+      OpenBrace => CloseBrace,
+      OpenBracket => CloseBracket,
+      OpenParen => CloseParen,
+      CloseBrace | CloseBracket | CloseParen | EOF => {
+        return Err(format!(
+          "parser:fn:parse_token_tree:error: {}",
+          self.token.text()
+        ))
+      }
+      _ => return Ok(TokenTree::Token(*self.token.to_owned())),
+    };
+
+    let open = *self.token.to_owned();
+    let mut trees = vec![];
+
+    while !self.first_is(&close) {
+      if self.first_is(&EOF) {
+        return Err(format!(
+          "parser:fn:parse_token_tree:error: unclosed `{}`",
+          open.text()
+        ));
       }
 
       self.next_token();
+      trees.push(self.parse_token_tree()?);
     }
 
-    Ok(box ast)
-  }
-
-  fn parse_ret_stmt(&mut self) -> Result<Box<Stmt>, String> {
     self.next_token();
 
-    let expr = self.parse_expr_by_precedence(&Lowest)?;
+    Ok(TokenTree::Delimited {
+      open,
+      trees,
+      close: *self.token.to_owned(),
+    })
+  }
+
+  // a single type between parentheses is only a tuple with a trailing comma
+  // e.g `(int,)`, otherwise the parentheses are used for grouping
+  fn parse_tuple_ty(&mut self) -> Result<Box<Ty>, String> {
+    let mut tys = vec![];
+    let mut has_trailing_comma = false;
 
-    while self.token.kind() != Semicolon && self.token.kind() != EOL {
+    while !self.first_is(&CloseParen) {
       self.next_token();
+
+      tys.push(self.parse_ty()?.into_kind());
+      has_trailing_comma = self.first_is(&Comma);
+
+      if !self.first_is(&CloseParen) {
+        self.expect_first(&Comma)?;
+      }
     }
 
-    Ok(make_ret_stmt(expr))
+    self.expect_first(&CloseParen)?;
+
+    if tys.len() == 1 && !has_trailing_comma {
+      return Ok(box Ty::new(tys.remove(0), Span::zero()));
+    }
+
+    Ok(make_tuple_ty(tys))
   }
 
-  fn parse_stmt(&mut self) -> Result<Box<Stmt>, String> {
-    match self.token.kind() {
-      // TokenKind::Use => self.parse_use_stmt(),
-      TokenKind::Fun => self.parse_fun_stmt(),
-      TokenKind::Mut | TokenKind::Val => self.parse_local_stmt(),
-      TokenKind::Ret => self.parse_ret_stmt(),
-      _ => self.parse_expr_stmt(),
+  fn parse_tys_until(&mut self, kind: &TokenKind) -> Result<Vec<Ty>, String> {
+    let mut tys = vec![];
+
+    while !self.first_is(kind) {
+      self.next_token();
+
+      tys.push(*self.parse_ty()?);
+      self.split_first_shr();
+
+      if !self.first_is(kind) {
+        self.expect_first(&Comma)?;
+      }
     }
+
+    self.expect_first(kind)?;
+
+    Ok(tys)
+  }
+
+  fn parse_type_alias_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    self.expect_first(&TokenKind::Ident(self.first.text()))?;
+
+    let name = self.parse_ident_expr()?;
+
+    self.expect_first(&TokenKind::AssignOp(BinaryKind::Eq))?;
+    self.next_token();
+
+    let ty = self.parse_ty()?;
+
+    self.expect_first(&Semicolon)?;
+
+    Ok(make_type_alias_stmt(name, ty))
   }
 
+  // the modules are not loaded yet, the import is kept without the items of
+  // the module and reported e.g `use @std::math::(sqrt);`
   fn parse_use_stmt(&mut self) -> Result<Box<Stmt>, String> {
+    let span = self.token.span.to_owned();
     let name = self.parse_use_path_stmt()?;
 
-    self.next_token();
-    self.parse_until(&Semicolon)?;
+    self.expect_first(&Semicolon)?;
 
-    let nodes = self.parse_nodes_ast()?;
+    self.sink.parse_error(
+      format!(
+        "parser:fn:parse_use_stmt:error: `use @{}` is not resolved yet",
+        name
+      )
+      .into(),
+      span,
+    );
 
-    Ok(make_use_stmt(&name, nodes))
+    Ok(make_use_stmt(&name, box Ast::new(vec![])))
   }
 
+  // the path ends with a module or with a group of its items e.g
+  // `@std::gl::matrix`, `@std::math::(sqrt, sin)`
   fn parse_use_path_stmt(&mut self) -> Result<String, String> {
-    let mut use_path = vec![];
-    let mut use_path_items = vec![];
-
     self.expect_first(&TokenKind::At)?;
-    self.next_token();
+    self.expect_first(&TokenKind::Ident(self.first.text()))?;
 
-    let parent_path = self.token.text();
-    use_path.push(parent_path);
+    let mut use_path = vec![self.token.text()];
 
     while self.first_is(&ColonColon) {
-      self.parse_until(&ColonColon)?;
       self.next_token();
 
-      let path = self.token.text();
+      if self.first_is(&OpenParen) {
+        self.next_token();
 
-      use_path.push(path);
-    }
+        let mut use_path_items = vec![];
 
-    if self.token.kind() == TokenKind::OpenParen {
-      while self.first_is(&TokenKind::Ident(self.first.text())) {
-        let use_name = self.token.text();
+        while !self.first_is(&CloseParen) {
+          self.expect_first(&TokenKind::Ident(self.first.text()))?;
+          use_path_items.push(self.token.text());
 
-        use_path_items.push(use_name);
-        self.next_token();
+          if !self.first_is(&CloseParen) {
+            self.expect_first(&Comma)?;
+          }
+        }
+
+        self.expect_first(&CloseParen)?;
+        use_path.push(format!("({})", use_path_items.join(", ")));
+
+        break;
       }
+
+      self.expect_first(&TokenKind::Ident(self.first.text()))?;
+      use_path.push(self.token.text());
     }
 
     Ok(use_path.join("::"))
@@ -537,11 +2118,35 @@ where
 
     self.next_token();
 
-    let rhs = self.parse_expr_by_precedence(&Unary)?;
+    let rhs = self.parse_expr_by_precedence(&PrecedenceKind::Unary)?;
 
     Ok(make_unop_expr(&operand, rhs))
   }
 
+  // parses the comma separated elements after the first one, a trailing
+  // comma is allowed before the closing token e.g `(a, b,)`
+  fn parse_rest_until(
+    &mut self,
+    kind: &TokenKind,
+  ) -> Result<Vec<Box<Expr>>, String> {
+    let mut exprs: Vec<Box<Expr>> = vec![];
+
+    while !self.first_is(kind) {
+      self.expect_first(&Comma)?;
+
+      if self.first_is(kind) {
+        break;
+      }
+
+      self.next_token();
+      exprs.push(self.parse_expr_by_precedence(&Lowest)?);
+    }
+
+    self.expect_first(kind)?;
+
+    Ok(exprs)
+  }
+
   fn parse_until(
     &mut self,
     kind: &TokenKind,
@@ -557,52 +2162,162 @@ where
     self.next_token();
 
     exprs.push(self.parse_expr_by_precedence(&Lowest)?);
-
-    while self.first_is(&Comma) {
-      self.next_token();
-      self.next_token();
-      exprs.push(self.parse_expr_by_precedence(&Lowest)?);
-    }
-
-    self.expect_first(&kind)?;
+    exprs.extend(self.parse_rest_until(kind)?);
 
     Ok(exprs)
   }
 
+  // the tokens are buffered until they hold complete top-level items, which
+  // are parsed right away so the items are emitted while the input is fed
   fn process_to_completion(&mut self, token: Token) {
+    match token.kind {
+      // blocks are delimited by braces, the indentation is not meaningful
+      TokenKind::Indent(_) | TokenKind::EOF => return,
+      TokenKind::ParseError(error) => {
+        return self.sink.parse_error(error, token.span);
+      }
+      _ => {}
+    }
+
+    // a closing brace ends an item when the next token can not continue it
+    // e.g `fun f: () = () { }` but not `val f: = () { };`
+    if self.delimiters.is_empty()
+      && self
+        .tokens
+        .back()
+        .map_or(false, |last| last.kind == CloseBrace)
+      && token.kind.is_item_start()
+    {
+      self.parse_items();
+    }
+
+    // a closer that does not match the innermost opener is left for the
+    // parser to report e.g the `)` of `{ val x := ); }` does not close `{`
+    match token.kind {
+      OpenBrace => self.delimiters.push(CloseBrace),
+      OpenBracket => self.delimiters.push(CloseBracket),
+      OpenParen => self.delimiters.push(CloseParen),
+      CloseBrace | CloseBracket | CloseParen
+        if self.delimiters.last() == Some(&token.kind) =>
+      {
+        self.delimiters.pop();
+      }
+      _ => {}
+    }
+
+    let is_item_end = self.delimiters.is_empty() && token.kind == Semicolon;
+
     self.tokens.push_back(token);
+
+    if is_item_end {
+      self.parse_items();
+    }
+  }
+
+  // nested generic types are closed by a single `>>` token e.g `vec<vec<int>>`
+  fn split_first_shr(&mut self) {
+    if self.first_is(&TokenKind::Binary(BinaryKind::Shr)) {
+      let span = self.first.span.to_owned();
+
+      self.first.kind = TokenKind::Binary(BinaryKind::Gt);
+      self.tokens.push_front(Token::new(self.first.kind(), span));
+    }
+  }
+
+  fn second_is(&self, kind: &TokenKind) -> bool {
+    match self.tokens_ahead().next() {
+      Some(token) => token.kind == *kind,
+      None => false,
+    }
+  }
+
+  // the tokens after the first one without the comments, which are set aside
+  // by `pop_token` when the tokens are read
+  fn tokens_ahead(&self) -> impl Iterator<Item = &Token> {
+    self.tokens.iter().filter(|token| match token.kind {
+      TokenKind::DocComment(_) => false,
+      _ => true,
+    })
+  }
+
+  fn report(&mut self, error: String) {
+    let span = self.token.span.to_owned();
+
+    self.sink.parse_error(error.into(), span);
   }
 
   fn should_precedence_has_priority(&self, kind: &PrecedenceKind) -> bool {
     kind < &TokenKind::precedence(&self.first.kind())
   }
 
+  // panic-mode recovery, skips the tokens of an erroneous construct up to the
+  // next `;`, `}` or item keyword, the current token is left on the last
+  // skipped token so the caller moves on as after a well-formed construct
+  fn synchronize(&mut self) {
+    if self.token_is(&CloseBrace) && self.blocks > 0 {
+      // the brace closes the enclosing block, it is handed back to it
+      let first = mem::replace(&mut self.first, self.token.to_owned());
+
+      self.tokens.push_front(*first);
+
+      return;
+    }
+
+    let mut depth = if self.token_is(&OpenBrace) { 1 } else { 0 };
+
+    while depth > 0 || !self.token_is(&Semicolon) {
+      match self.first.kind() {
+        EOF => break,
+        OpenBrace => depth += 1,
+        CloseBrace if depth > 0 => depth -= 1,
+        CloseBrace => break,
+        TokenKind::Async
+        | TokenKind::Bench
+        | TokenKind::Capsule
+        | TokenKind::Enum
+        | TokenKind::Exp
+        | TokenKind::Ext
+        | TokenKind::Fun
+        | TokenKind::Macro
+        | TokenKind::Mock
+        | TokenKind::Module
+        | TokenKind::Pub
+        | TokenKind::Static
+        | TokenKind::Struct
+        | TokenKind::Suite
+        | TokenKind::Test
+        | TokenKind::Type
+        | TokenKind::Use
+          if depth == 0 =>
+        {
+          break
+        }
+        _ => {}
+      }
+
+      self.next_token();
+    }
+  }
+
   fn token_is(&self, kind: &TokenKind) -> bool {
     self.token.kind() == *kind
   }
 }
 
-impl<Handle, Sink> TreeBuilderPrinter for TreeBuilder<Handle, Sink>
+impl<Sink> TreeBuilderPrinter for TreeBuilder<Sink>
 where
-  Handle: Clone,
-  Sink: TreeSink<Handle = Handle>,
+  Sink: TreeSink,
 {
   fn print(&mut self, _stmt: Box<Stmt>) {}
 }
 
-impl<Handle, Sink> TokenSink for TreeBuilder<Handle, Sink>
+impl<Sink> TokenSink for TreeBuilder<Sink>
 where
-  Handle: Clone,
-  Sink: TreeSink<Handle = Handle>,
+  Sink: TreeSink,
 {
   // runs the parsing after the scanning end
   fn end(&mut self) {
-    self.next_token();
-    self.next_token();
-
-    let ast = self.parse_nodes_ast().unwrap();
-
-    self.emit(ast);
+    self.parse_items();
   }
 
   fn print(&self, _level: usize) {}
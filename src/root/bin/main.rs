@@ -1,37 +1,107 @@
+use qoeurcp::tokenizer::ast::{self, Ast};
 use qoeurcp::BackendKind;
+use root::doc;
+use root::options::{CompileMode, InputKind, Options};
 
-pub enum CompileMode {
-  Tokens,
-  Ast,
-  Jit,
-}
+use std::path::Path;
 
 fn main() {
-  let pathname = "data/code/add.q5";
-  let path = std::path::Path::new(&pathname);
+  let args = std::env::args().skip(1).collect::<Vec<String>>();
+
+  // e.g `root doc data/code/gl`
+  if args.first().map_or(false, |arg| arg == "doc") {
+    return generate_doc(args.get(1).map_or(".", |path| path));
+  }
+
+  let options = match Options::parse(&args) {
+    Ok(options) => options,
+    Err(error) => return eprintln!("{}", error),
+  };
 
-  let file_name = format!("{}", path.file_name().unwrap().to_str().unwrap())
-    .replace(".q5", "");
+  let f = match std::fs::read_to_string(&options.path) {
+    Ok(file) => file,
+    Err(error) => {
+      return eprintln!(
+        "root:fn:main:error: `{}` can not be read, {}",
+        options.path.display(),
+        error
+      )
+    }
+  };
 
-  let f = match std::fs::read_to_string(&path) {
-    Err(_) => None,
-    Ok(file) => Some(file),
+  let result = match options.mode {
+    CompileMode::Tokens => qoeurcp::tokens_to_json(&qoeurcp::tokenize(&f))
+      .map(|json| println!("{}", json)),
+    CompileMode::Ast => load_ast(&options, &f)
+      .and_then(|ast| qoeurcp::ast_to_json(&ast))
+      .map(|json| println!("{}", json)),
+    CompileMode::Source => {
+      load_ast(&options, &f).map(|ast| print!("{}", ast::unparse(&ast)))
+    }
+    CompileMode::Jit => load_ast(&options, &f).and_then(|ast| {
+      qoeurcp::compile_ast(
+        &options.file_name(),
+        &ast,
+        &BackendKind::Llvm,
+        options.target,
+      )
+    }),
   };
 
-  let f = f.unwrap();
+  if let Err(error) = result {
+    eprintln!("{}", error);
+  }
+}
 
-  // TODO: tmp
-  let compile_mode = CompileMode::Jit;
+// a json tree is checked as the parser would check a source, it may have
+// been written by hand
+fn load_ast(options: &Options, f: &str) -> Result<Ast, String> {
+  match options.input {
+    InputKind::Source => {
+      let tree = qoeurcp::parse(f);
 
-  match compile_mode {
-    CompileMode::Tokens => {
-      qoeurcp::tokenize(&f);
+      if tree.errors.is_empty() {
+        Ok(*tree.ast)
+      } else {
+        Err(
+          tree
+            .errors
+            .iter()
+            .map(|(error, _)| error.to_owned())
+            .collect::<Vec<String>>()
+            .join("\n"),
+        )
+      }
     }
-    CompileMode::Ast => {
-      qoeurcp::parse(&f);
+    InputKind::Json => {
+      let ast = qoeurcp::ast_from_json(f)?;
+      let errors = ast::validate(&ast, Some(options.target));
+
+      if errors.is_empty() {
+        Ok(ast)
+      } else {
+        Err(errors.join("\n"))
+      }
     }
-    CompileMode::Jit => {
-      qoeurcp::compile(&file_name, &f, &BackendKind::Llvm);
+  }
+}
+
+fn generate_doc(pathname: &str) {
+  let path = Path::new(pathname);
+
+  match doc::generate(path) {
+    Ok(package) => {
+      for error in package.errors.iter() {
+        eprintln!("{}", error);
+      }
+
+      println!(
+        "{} {} documented in {}",
+        package.name,
+        package.version,
+        path.join("out").join("doc").display()
+      );
     }
+    Err(error) => eprintln!("{}", error),
   }
 }
@@ -0,0 +1,508 @@
+use qoeurcp::tokenizer::ast::*;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// the manifest of a package e.g `data/code/gl/qoeur.config.toml`
+static CONFIG: &str = "qoeur.config.toml";
+
+#[derive(Debug, Deserialize)]
+struct Config {
+  package: ConfigPackage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConfigPackage {
+  name: String,
+  version: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DocFormat {
+  Html,
+  Markdown,
+}
+
+impl DocFormat {
+  pub fn extension(&self) -> &'static str {
+    match *self {
+      Self::Html => "html",
+      Self::Markdown => "md",
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Package {
+  pub name: String,
+  pub version: String,
+  pub modules: Vec<Module>,
+  // the parse errors of the sources, the items that parsed are documented
+  pub errors: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Module {
+  pub name: String,
+  pub docs: Vec<String>,
+  pub items: Vec<Item>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Item {
+  pub kind: &'static str,
+  pub name: String,
+  pub signature: String,
+  pub docs: Vec<String>,
+  pub fields: Vec<Field>,
+}
+
+impl Item {
+  // e.g `fun.add`
+  pub fn anchor(&self) -> String {
+    format!("{}.{}", self.kind, self.name)
+  }
+}
+
+// writes a page per module and an index in `out/doc` of the package, in html
+// and in markdown
+pub fn generate(root: &Path) -> Result<Package, String> {
+  let package = load_package(root)?;
+  let out = root.join("out").join("doc");
+
+  fs::create_dir_all(&out)
+    .map_err(|e| format!("doc:fn:generate:error: {}: {}", out.display(), e))?;
+
+  for format in [DocFormat::Html, DocFormat::Markdown].iter() {
+    let mut pages = vec![(
+      format!("index.{}", format.extension()),
+      render_index(&package, *format),
+    )];
+
+    for module in package.modules.iter() {
+      pages.push((
+        page(&module.name, *format),
+        render_module(&package, module, *format),
+      ));
+    }
+
+    for (name, content) in pages {
+      let path = out.join(name);
+
+      fs::write(&path, content).map_err(|e| {
+        format!("doc:fn:generate:error: {}: {}", path.display(), e)
+      })?;
+    }
+  }
+
+  Ok(package)
+}
+
+// the modules of a package are the sources of its `src` directory, `lib.q5`
+// and `main.q5` are the module named after the package
+pub fn load_package(root: &Path) -> Result<Package, String> {
+  let path = root.join(CONFIG);
+
+  let config = fs::read_to_string(&path).map_err(|e| {
+    format!("doc:fn:load_package:error: {}: {}", path.display(), e)
+  })?;
+
+  let config = toml::from_str::<Config>(&config).map_err(|e| {
+    format!("doc:fn:load_package:error: {}: {}", path.display(), e)
+  })?;
+
+  let src = root.join("src");
+  let mut sources = vec![];
+  let mut modules = vec![];
+  let mut errors = vec![];
+
+  find_sources(&src, &mut sources)?;
+  sources.sort();
+
+  for source in sources.iter() {
+    let input = fs::read_to_string(source).map_err(|e| {
+      format!("doc:fn:load_package:error: {}: {}", source.display(), e)
+    })?;
+
+    let tree = qoeurcp::parse(&input);
+
+    for (error, span) in tree.errors.iter() {
+      errors.push(format!("{}:{}: {}", source.display(), span, error));
+    }
+
+    let name = module_name(&config.package.name, &src, source);
+
+    modules.push(document(&name, &tree.ast));
+  }
+
+  Ok(Package {
+    name: config.package.name,
+    version: config.package.version,
+    modules,
+    errors,
+  })
+}
+
+fn find_sources(dir: &Path, sources: &mut Vec<PathBuf>) -> Result<(), String> {
+  let entries = fs::read_dir(dir).map_err(|e| {
+    format!("doc:fn:find_sources:error: {}: {}", dir.display(), e)
+  })?;
+
+  for entry in entries {
+    let path = entry.map_err(|e| e.to_string())?.path();
+
+    if path.is_dir() {
+      find_sources(&path, sources)?;
+    } else if path.extension().map_or(false, |ext| ext == "q5") {
+      sources.push(path);
+    }
+  }
+
+  Ok(())
+}
+
+// e.g `src/shapes/circle.q5` is `shapes::circle`
+fn module_name(package: &str, src: &Path, source: &Path) -> String {
+  let path = source
+    .strip_prefix(src)
+    .unwrap_or(source)
+    .with_extension("");
+
+  let segments = path
+    .iter()
+    .map(|segment| segment.to_string_lossy().to_string())
+    .collect::<Vec<String>>();
+
+  match segments.as_slice() {
+    [name] if name == "lib" || name == "main" => package.to_string(),
+    _ => segments.join("::"),
+  }
+}
+
+// the public items of the module
+pub fn document(name: &str, ast: &Ast) -> Module {
+  let items = ast
+    .nodes
+    .iter()
+    .filter_map(|node| match node.kind {
+      StmtKind::Capsule(ref capsule) if capsule.visibility => Some(Item {
+        kind: "capsule",
+        name: capsule.name.to_owned(),
+        signature: format!("capsule {}", capsule.name),
+        docs: capsule.docs.to_vec(),
+        fields: capsule
+          .members
+          .iter()
+          .filter_map(|member| match member {
+            TraitMember::Field(ref field) => Some(field.to_owned()),
+            _ => None,
+          })
+          .collect(),
+      }),
+      StmtKind::Enum(ref enm) if enm.visibility => Some(Item {
+        kind: "enum",
+        name: enm.name.to_owned(),
+        signature: enm.text(),
+        docs: vec![],
+        fields: enm
+          .variants
+          .iter()
+          .flat_map(|variant| match variant.kind {
+            VariantKind::Struct(ref fields) => fields.to_vec(),
+            _ => vec![],
+          })
+          .collect(),
+      }),
+      StmtKind::Fun(ref fun) if fun.visibility => Some(Item {
+        kind: "fun",
+        name: fun.name(),
+        signature: fun.sig().text(),
+        docs: fun.docs.to_vec(),
+        fields: vec![],
+      }),
+      StmtKind::Struct(ref strukt) if strukt.visibility => Some(Item {
+        kind: "struct",
+        name: strukt.name.to_owned(),
+        signature: format!("struct {}", strukt.name),
+        docs: strukt.docs.to_vec(),
+        fields: strukt
+          .members
+          .iter()
+          .filter_map(|member| match member {
+            StructMember::Field(ref field) => Some(field.to_owned()),
+            _ => None,
+          })
+          .collect(),
+      }),
+      StmtKind::TypeAlias(ref alias) if alias.visibility => Some(Item {
+        kind: "type",
+        name: alias.name.to_owned(),
+        signature: alias.text(),
+        docs: vec![],
+        fields: vec![],
+      }),
+      _ => None,
+    })
+    .collect();
+
+  Module {
+    name: name.to_string(),
+    docs: ast.docs.to_vec(),
+    items,
+  }
+}
+
+pub fn render_index(package: &Package, format: DocFormat) -> String {
+  let title = format!("{} {}", package.name, package.version);
+
+  let rows = package.modules.iter().map(|module| {
+    let summary = module.docs.first().map_or(String::new(), |doc| {
+      doc.lines().next().unwrap_or("").to_string()
+    });
+
+    (module.name.to_owned(), page(&module.name, format), summary)
+  });
+
+  match format {
+    DocFormat::Html => {
+      let rows = rows
+        .map(|(name, page, summary)| {
+          format!(
+            "<li><a href=\"{}\">{}</a> {}</li>\n",
+            page,
+            escape(&name),
+            escape(&summary)
+          )
+        })
+        .collect::<String>();
+
+      html_page(
+        &title,
+        &format!("<h1>{}</h1>\n<ul>\n{}</ul>\n", title, rows),
+      )
+    }
+    DocFormat::Markdown => {
+      let rows = rows
+        .map(|(name, page, summary)| {
+          format!("* [{}]({}) {}\n", name, page, summary)
+        })
+        .collect::<String>();
+
+      format!("# {}\n\n{}", title, rows)
+    }
+  }
+}
+
+pub fn render_module(
+  package: &Package,
+  module: &Module,
+  format: DocFormat,
+) -> String {
+  match format {
+    DocFormat::Html => {
+      let mut body = format!(
+        "<p><a href=\"index.html\">{}</a></p>\n<h1>{}</h1>\n",
+        escape(&package.name),
+        escape(&module.name)
+      );
+
+      body.push_str(&render_html_docs(&module.docs));
+
+      for item in module.items.iter() {
+        body.push_str(&format!(
+          "<h2 id=\"{}\">{} {}</h2>\n<pre><code>{}</code></pre>\n",
+          item.anchor(),
+          item.kind,
+          escape(&item.name),
+          link_html(package, module, item)
+        ));
+
+        body.push_str(&render_html_docs(&item.docs));
+
+        if !item.fields.is_empty() {
+          body.push_str("<ul>\n");
+
+          for field in item.fields.iter() {
+            body.push_str(&format!(
+              "<li><code>{}: {}</code> {}</li>\n",
+              escape(&field.name),
+              escape(&field.ty.text()),
+              escape(&field.docs.join(" "))
+            ));
+          }
+
+          body.push_str("</ul>\n");
+        }
+      }
+
+      html_page(&module.name, &body)
+    }
+    DocFormat::Markdown => {
+      let mut body =
+        format!("[{}](index.md)\n\n# {}\n\n", package.name, module.name);
+
+      if !module.docs.is_empty() {
+        body.push_str(&format!("{}\n\n", module.docs.join("\n\n")));
+      }
+
+      for item in module.items.iter() {
+        body.push_str(&format!(
+          "<a id=\"{}\"></a>\n\n## {} {}\n\n```\n{}\n```\n\n",
+          item.anchor(),
+          item.kind,
+          item.name,
+          item.signature
+        ));
+
+        let links = links(package, module, item)
+          .into_iter()
+          .map(|(name, href)| format!("[{}]({})", name, href))
+          .collect::<Vec<String>>();
+
+        if !links.is_empty() {
+          body.push_str(&format!("see: {}\n\n", links.join(", ")));
+        }
+
+        if !item.docs.is_empty() {
+          body.push_str(&format!("{}\n\n", item.docs.join("\n\n")));
+        }
+
+        for field in item.fields.iter() {
+          body.push_str(&format!(
+            "* `{}: {}` {}\n",
+            field.name,
+            field.ty.text(),
+            field.docs.join(" ")
+          ));
+        }
+
+        if !item.fields.is_empty() {
+          body.push('\n');
+        }
+      }
+
+      body
+    }
+  }
+}
+
+// e.g `shapes::circle` is `shapes.circle.html`
+fn page(module: &str, format: DocFormat) -> String {
+  format!("{}.{}", module.replace("::", "."), format.extension())
+}
+
+// the item named in a signature, the items of the same module come first
+fn resolve<'a>(
+  package: &'a Package,
+  module: &'a Module,
+  name: &str,
+) -> Option<(&'a Module, &'a Item)> {
+  let find = |module: &'a Module| {
+    module
+      .items
+      .iter()
+      .find(|item| item.name == name)
+      .map(|item| (module, item))
+  };
+
+  find(module).or_else(|| package.modules.iter().find_map(find))
+}
+
+fn href(
+  module: &Module,
+  target: &Module,
+  item: &Item,
+  format: DocFormat,
+) -> String {
+  if target.name == module.name {
+    format!("#{}", item.anchor())
+  } else {
+    format!("{}#{}", page(&target.name, format), item.anchor())
+  }
+}
+
+// splits a signature into names and the text between them e.g
+// `fun add: (-> vec2 | ...)` is `fun`, ` `, `add`, `: (-> `, `vec2`...
+fn words(signature: &str) -> Vec<(bool, String)> {
+  let mut words: Vec<(bool, String)> = vec![];
+
+  for c in signature.chars() {
+    let is_name = c.is_alphanumeric() || c == '_';
+
+    match words.last_mut() {
+      Some((last, word)) if *last == is_name => word.push(c),
+      _ => words.push((is_name, c.to_string())),
+    }
+  }
+
+  words
+}
+
+// the items named in the signature of another item, once each
+fn links(
+  package: &Package,
+  module: &Module,
+  item: &Item,
+) -> Vec<(String, String)> {
+  let mut links: Vec<(String, String)> = vec![];
+
+  for (is_name, word) in words(&item.signature) {
+    let is_linked = links.iter().any(|(name, _)| *name == word);
+
+    if !is_name || is_linked || word == item.name {
+      continue;
+    }
+
+    if let Some((target, linked)) = resolve(package, module, &word) {
+      links.push((word, href(module, target, linked, DocFormat::Markdown)));
+    }
+  }
+
+  links
+}
+
+fn link_html(package: &Package, module: &Module, item: &Item) -> String {
+  words(&item.signature)
+    .into_iter()
+    .map(|(is_name, word)| {
+      let target = if is_name && word != item.name {
+        resolve(package, module, &word)
+      } else {
+        None
+      };
+
+      match target {
+        Some((target, linked)) => format!(
+          "<a href=\"{}\">{}</a>",
+          href(module, target, linked, DocFormat::Html),
+          escape(&word)
+        ),
+        None => escape(&word),
+      }
+    })
+    .collect()
+}
+
+fn render_html_docs(docs: &[String]) -> String {
+  docs
+    .iter()
+    .map(|doc| format!("<p>{}</p>\n", escape(doc).replace('\n', "<br>\n")))
+    .collect()
+}
+
+fn html_page(title: &str, body: &str) -> String {
+  format!(
+    "<!doctype html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+     <title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+    escape(title),
+    body
+  )
+}
+
+fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
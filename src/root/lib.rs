@@ -2,5 +2,11 @@
 #![feature(box_syntax)]
 #![recursion_limit = "256"]
 
+#[macro_use]
+extern crate serde_derive;
+
+pub mod doc;
+pub mod options;
+
 #[cfg(test)]
 mod test;
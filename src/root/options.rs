@@ -0,0 +1,104 @@
+use qoeurcp::tokenizer::ast::Target;
+
+use std::path::PathBuf;
+
+// the source compiled when no path is given
+static DEFAULT_PATH: &str = "data/code/add.q5";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompileMode {
+  Tokens,
+  Ast,
+  Source,
+  Jit,
+}
+
+// a json input holds a tree written by `--dump-ast` or by another tool
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputKind {
+  Source,
+  Json,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+  pub mode: CompileMode,
+  pub input: InputKind,
+  pub path: PathBuf,
+  pub target: Target,
+}
+
+impl Options {
+  // e.g `root --dump-ast data/code/add.q5`, `root --from-json out/add.json`,
+  // `root --dump-source out/add.json`, `root --target=wasm data/code/add.q5`
+  //
+  // a path ending with `.json` is read as a json tree
+  pub fn parse(args: &[String]) -> Result<Options, String> {
+    let mut mode = CompileMode::Jit;
+    let mut input = None;
+    let mut path = None;
+    let mut target = Target::Native;
+
+    for arg in args.iter() {
+      match arg.as_str() {
+        "--dump-tokens" => mode = CompileMode::Tokens,
+        "--dump-ast" => mode = CompileMode::Ast,
+        "--dump-source" => mode = CompileMode::Source,
+        "--from-json" => input = Some(InputKind::Json),
+        flag if flag.starts_with("--target=") => {
+          let name = &flag["--target=".len()..];
+
+          target = Target::from(name).ok_or(format!(
+            "options:fn:parse:error: the target `{}` is unknown",
+            name
+          ))?;
+        }
+        flag if flag.starts_with("--") => {
+          return Err(format!(
+            "options:fn:parse:error: the option `{}` is unknown",
+            flag
+          ))
+        }
+        pathname if path.is_none() => path = Some(PathBuf::from(pathname)),
+        pathname => {
+          return Err(format!(
+            "options:fn:parse:error: the path `{}` is given twice",
+            pathname
+          ))
+        }
+      }
+    }
+
+    let path = path.unwrap_or_else(|| PathBuf::from(DEFAULT_PATH));
+
+    let input = input.unwrap_or_else(|| {
+      match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => InputKind::Json,
+        _ => InputKind::Source,
+      }
+    });
+
+    if mode == CompileMode::Tokens && input == InputKind::Json {
+      return Err(format!(
+        "options:fn:parse:error: the tokens of a json tree can not be dumped"
+      ));
+    }
+
+    Ok(Self {
+      mode,
+      input,
+      path,
+      target,
+    })
+  }
+
+  // the name of the compiled module e.g `add` for `data/code/add.q5`
+  pub fn file_name(&self) -> String {
+    self
+      .path
+      .file_stem()
+      .and_then(|stem| stem.to_str())
+      .unwrap_or_default()
+      .to_string()
+  }
+}
@@ -1 +1,173 @@
+use super::doc::{self, DocFormat, Package};
+use super::options::{CompileMode, InputKind, Options};
 
+use qoeurcp::tokenizer::ast::Target;
+
+use std::fs;
+use std::path::PathBuf;
+
+fn package(sources: &[(&str, &str)]) -> Package {
+  Package {
+    name: "gl".into(),
+    version: "0.1.0".into(),
+    modules: sources
+      .iter()
+      .map(|(name, src)| doc::document(name, &qoeurcp::parse(src).ast))
+      .collect(),
+    errors: vec![],
+  }
+}
+
+#[test]
+fn document_public_items() {
+  let package = package(&[
+    (
+      "matrix",
+      "#! matrices and vectors
+
+      # adds two vec2
+      pub fun add: (-> vec2 | a: vec2, b: vec2) = (a, b) { a }
+      fun hidden: () = () { }
+      pub type vec2 = [real; 2];
+
+      # a point of the plane
+      pub struct Point {
+        # the abscissa
+        x: real,
+        y: real,
+      }
+      struct Hidden { }
+
+      # the products of vectors
+      pub capsule Mul {
+        # the scale
+        scale: real,
+      }",
+    ),
+    ("shapes", "pub fun scale: (-> vec2 | v: vec2) = (v) { v }"),
+  ]);
+
+  let matrix = &package.modules[0];
+
+  assert_eq!(matrix.docs, ["matrices and vectors"]);
+  assert_eq!(
+    matrix
+      .items
+      .iter()
+      .map(|item| item.anchor())
+      .collect::<Vec<_>>(),
+    ["fun.add", "type.vec2", "struct.Point", "capsule.Mul"]
+  );
+  assert_eq!(matrix.items[0].docs, ["adds two vec2"]);
+  assert_eq!(matrix.items[2].docs, ["a point of the plane"]);
+  assert_eq!(matrix.items[2].fields[0].docs, ["the abscissa"]);
+  assert_eq!(matrix.items[3].docs, ["the products of vectors"]);
+
+  let html = doc::render_module(&package, matrix, DocFormat::Html);
+
+  assert!(html.contains("<h2 id=\"fun.add\">fun add</h2>"));
+  assert!(html.contains("(-&gt; <a href=\"#type.vec2\">vec2</a> |"));
+  assert!(html.contains("<p>adds two vec2</p>"));
+  assert!(!html.contains("hidden") && !html.contains("Hidden"));
+  assert!(html.contains("<li><code>x: real</code> the abscissa</li>"));
+  assert!(html.contains("<li><code>scale: real</code> the scale</li>"));
+
+  let markdown =
+    doc::render_module(&package, &package.modules[1], DocFormat::Markdown);
+
+  assert!(markdown.contains("## fun scale"));
+  assert!(markdown.contains("see: [vec2](matrix.md#type.vec2)"));
+
+  let index = doc::render_index(&package, DocFormat::Markdown);
+
+  assert!(index.contains("* [matrix](matrix.md) matrices and vectors"));
+}
+
+#[test]
+fn generate_package_docs() {
+  let root = std::env::temp_dir().join("qoeur-doc-test");
+  let _ = fs::remove_dir_all(&root);
+
+  fs::create_dir_all(root.join("src")).unwrap();
+  fs::write(
+    root.join("qoeur.config.toml"),
+    "[package]\nname = \"gl\"\nversion = \"0.1.0\"\n",
+  )
+  .unwrap();
+  fs::write(
+    root.join("src").join("matrix.q5"),
+    "# adds two vec2\npub fun add: (-> int | a: int) = (a) { a }\n",
+  )
+  .unwrap();
+  fs::write(root.join("src").join("lib.q5"), "pub load matrix;\n").unwrap();
+
+  let package = doc::generate(&root).unwrap();
+  let out = root.join("out").join("doc");
+
+  assert_eq!(
+    package
+      .modules
+      .iter()
+      .map(|module| module.name.to_owned())
+      .collect::<Vec<_>>(),
+    ["gl", "matrix"]
+  );
+  assert_eq!(package.errors.len(), 1);
+
+  for page in ["index", "gl", "matrix"].iter() {
+    assert!(out.join(format!("{}.html", page)).exists());
+    assert!(out.join(format!("{}.md", page)).exists());
+  }
+
+  let matrix = fs::read_to_string(out.join("matrix.md")).unwrap();
+
+  assert!(matrix.contains("adds two vec2"));
+
+  fs::remove_dir_all(&root).unwrap();
+}
+
+fn options(args: &[&str]) -> Result<Options, String> {
+  Options::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+}
+
+#[test]
+fn parse_driver_options() {
+  let default = options(&[]).unwrap();
+
+  assert_eq!(default.mode, CompileMode::Jit);
+  assert_eq!(default.input, InputKind::Source);
+  assert_eq!(default.path, PathBuf::from("data/code/add.q5"));
+  assert_eq!(default.file_name(), "add");
+  assert_eq!(default.target, Target::Native);
+
+  let tokens = options(&["--dump-tokens", "a.q5"]).unwrap();
+
+  assert_eq!(tokens.mode, CompileMode::Tokens);
+  assert_eq!(tokens.path, PathBuf::from("a.q5"));
+
+  let json = options(&["--dump-ast", "out/add.json"]).unwrap();
+
+  assert_eq!(json.mode, CompileMode::Ast);
+  assert_eq!(json.input, InputKind::Json);
+  assert_eq!(json.file_name(), "add");
+
+  let forced = options(&["--from-json", "add.tree"]).unwrap();
+
+  assert_eq!(forced.mode, CompileMode::Jit);
+  assert_eq!(forced.input, InputKind::Json);
+
+  let source = options(&["--dump-source", "add.json"]).unwrap();
+
+  assert_eq!(source.mode, CompileMode::Source);
+  assert_eq!(source.input, InputKind::Json);
+
+  let wasm = options(&["--target=wasm", "a.q5"]).unwrap();
+
+  assert_eq!(wasm.target, Target::Wasm);
+  assert_eq!(wasm.path, PathBuf::from("a.q5"));
+
+  assert!(options(&["--emit-wasm"]).unwrap_err().contains("unknown"));
+  assert!(options(&["--target=arm"]).unwrap_err().contains("unknown"));
+  assert!(options(&["a.q5", "b.q5"]).unwrap_err().contains("twice"));
+  assert!(options(&["--dump-tokens", "add.json"]).is_err());
+}